@@ -59,6 +59,19 @@ impl Formatter for JsonFormatter {
                     "type": "reference",
                     "path": path.to_string()
                 })
+            },
+            Value::Vector(vector) => {
+                json!({
+                    "type": "vector",
+                    "data": vector
+                })
+            },
+            Value::GeoPoint { lat, lon } => {
+                json!({
+                    "type": "geopoint",
+                    "lat": lat,
+                    "lon": lon
+                })
             }
         };
         
@@ -70,11 +83,7 @@ impl Formatter for JsonFormatter {
     }
     
     fn format_entity(&self, entity: &Entity) -> Result<String> {
-        if self.pretty {
-            Ok(serde_json::to_string_pretty(entity)?)
-        } else {
-            Ok(serde_json::to_string(entity)?)
-        }
+        self.format_json(&entity.to_json())
     }
     
     fn format_paths(&self, paths: &[String]) -> Result<String> {
@@ -69,6 +69,12 @@ impl Formatter for TableFormatter {
                         Ok(table.to_string())
                     },
                     serde_json::Value::Array(items) => {
+                        // Un tableau d'objets (résultat d'un filtre) donne une
+                        // ligne par objet et une colonne par champ
+                        if !items.is_empty() && items.iter().all(|item| item.is_object()) {
+                            return Ok(records_table(items));
+                        }
+                        
                         let mut table = Table::new();
                         
                         // En-têtes
@@ -80,41 +86,16 @@ impl Formatter for TableFormatter {
                             
                             // Ajouter chaque élément
                             for (i, item) in items.iter().enumerate() {
-                                let type_name = match item {
-                                    serde_json::Value::Null => "null",
-                                    serde_json::Value::Bool(_) => "boolean",
-                                    serde_json::Value::Number(_) => "number",
-                                    serde_json::Value::String(_) => "string",
-                                    serde_json::Value::Array(_) => "array",
-                                    serde_json::Value::Object(_) => "object",
-                                };
-                                
-                                let value_str = match item {
-                                    serde_json::Value::Null => "null".to_string(),
-                                    serde_json::Value::Bool(b) => b.to_string(),
-                                    serde_json::Value::Number(n) => n.to_string(),
-                                    serde_json::Value::String(s) => {
-                                        // Tronquer les chaînes longues
-                                        if s.len() > 50 {
-                                            format!("\"{}...\"", &s[..47])
-                                        } else {
-                                            format!("\"{}\"", s)
-                                        }
-                                    },
-                                    serde_json::Value::Array(_) => "[array]".to_string(),
-                                    serde_json::Value::Object(_) => "{object}".to_string(),
-                                };
-                                
                                 table.add_row(Row::new(vec![
                                     Cell::new(&i.to_string()),
-                                    Cell::new(type_name),
-                                    Cell::new(&value_str)
+                                    Cell::new(json_type_name(item)),
+                                    Cell::new(&json_cell(item))
                                     ]));
                                 }
                                 
                                 Ok(table.to_string())
                             },
-                            serde_json::Value::Object(map) => {
+                            serde_json::Value::Object(_) => {
                                 let mut table = Table::new();
                                 
                                 // En-têtes
@@ -124,37 +105,16 @@ impl Formatter for TableFormatter {
                                     Cell::new("Valeur")
                                     ]));
                                     
-                                    // Ajouter chaque paire clé-valeur
-                                    for (key, value) in map {
-                                        let type_name = match value {
-                                            serde_json::Value::Null => "null",
-                                            serde_json::Value::Bool(_) => "boolean",
-                                            serde_json::Value::Number(_) => "number",
-                                            serde_json::Value::String(_) => "string",
-                                            serde_json::Value::Array(_) => "array",
-                                            serde_json::Value::Object(_) => "object",
-                                        };
-                                        
-                                        let value_str = match value {
-                                            serde_json::Value::Null => "null".to_string(),
-                                            serde_json::Value::Bool(b) => b.to_string(),
-                                            serde_json::Value::Number(n) => n.to_string(),
-                                            serde_json::Value::String(s) => {
-                                                // Tronquer les chaînes longues
-                                                if s.len() > 50 {
-                                                    format!("\"{}...\"", &s[..47])
-                                                } else {
-                                                    format!("\"{}\"", s)
-                                                }
-                                            },
-                                            serde_json::Value::Array(_) => "[array]".to_string(),
-                                            serde_json::Value::Object(_) => "{object}".to_string(),
-                                        };
-                                        
+                                    // Ajouter chaque champ, les objets imbriqués
+                                    // étant dépliés en chemins
+                                    let mut fields = Vec::new();
+                                    flatten_json("", json, &mut fields);
+                                    
+                                    for (key, value) in fields {
                                         table.add_row(Row::new(vec![
-                                            Cell::new(key),
-                                            Cell::new(type_name),
-                                            Cell::new(&value_str)
+                                            Cell::new(&key),
+                                            Cell::new(json_type_name(value)),
+                                            Cell::new(&json_cell(value))
                                             ]));
                                         }
                                         
@@ -196,7 +156,7 @@ impl Formatter for TableFormatter {
                                                     for (key, value) in map {
                                                         table.add_row(Row::new(vec![
                                                             Cell::new(key),
-                                                            Cell::new(&format!("{:?}", value)),
+                                                            Cell::new(value.type_name()),
                                                             Cell::new(&entity_value_to_string(value))
                                                             ]));
                                                         }
@@ -217,7 +177,7 @@ impl Formatter for TableFormatter {
                                                             for (i, item) in items.iter().enumerate() {
                                                                 table.add_row(Row::new(vec![
                                                                     Cell::new(&i.to_string()),
-                                                                    Cell::new(&format!("{:?}", item)),
+                                                                    Cell::new(item.type_name()),
                                                                     Cell::new(&entity_value_to_string(item))
                                                                     ]));
                                                                 }
@@ -289,7 +249,98 @@ impl Formatter for TableFormatter {
                                                                     }
                                                                 },
                                                                 Entity::Reference(path) => format!("@{}", path),
+                                                                Entity::Vector(vector) => format!("[vector: {} dimensions]", vector.len()),
+                                                                Entity::GeoPoint { lat, lon } => format!("geopoint({}, {})", lat, lon),
                                                                 Entity::Object(_) => "[object]".to_string(),
                                                                 Entity::Array(_) => "[array]".to_string(),
                                                             }
-                                                        }
\ No newline at end of file
+                                                        }
+
+/// Aplatit un objet JSON en paires (chemin, valeur), les objets imbriqués
+/// donnant des chemins séparés par des points
+fn flatten_json<'a>(prefix: &str, json: &'a serde_json::Value, fields: &mut Vec<(String, &'a serde_json::Value)>) {
+    match json {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_json(&path, value, fields);
+            }
+        },
+        _ => fields.push((prefix.to_string(), json)),
+    }
+}
+
+/// Construit un tableau avec une ligne par objet et une colonne par champ
+fn records_table(records: &[serde_json::Value]) -> String {
+    let rows: Vec<Vec<(String, &serde_json::Value)>> = records.iter()
+        .map(|record| {
+            let mut fields = Vec::new();
+            flatten_json("", record, &mut fields);
+            fields
+        })
+        .collect();
+    
+    // Les colonnes, dans l'ordre où elles apparaissent
+    let mut columns: Vec<&str> = Vec::new();
+    for fields in &rows {
+        for (key, _) in fields {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+    
+    let mut table = Table::new();
+    
+    let mut header = vec![Cell::new("Index")];
+    header.extend(columns.iter().map(|column| Cell::new(column)));
+    table.add_row(Row::new(header));
+    
+    for (i, fields) in rows.iter().enumerate() {
+        let mut cells = vec![Cell::new(&i.to_string())];
+        for column in &columns {
+            let value = fields.iter()
+                .find(|(key, _)| key == column)
+                .map_or(String::new(), |(_, value)| json_cell(value));
+            cells.push(Cell::new(&value));
+        }
+        table.add_row(Row::new(cells));
+    }
+    
+    table.to_string()
+}
+
+/// Donne le type d'une valeur JSON pour l'affichage
+fn json_type_name(json: &serde_json::Value) -> &'static str {
+    match json {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Convertit une valeur JSON en texte de cellule
+fn json_cell(json: &serde_json::Value) -> String {
+    match json {
+        serde_json::Value::Null => "null".to_string(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => {
+            // Tronquer les chaînes longues
+            if s.chars().count() > 50 {
+                format!("\"{}...\"", s.chars().take(47).collect::<String>())
+            } else {
+                format!("\"{}\"", s)
+            }
+        },
+        serde_json::Value::Array(items) => format!("[array: {} items]", items.len()),
+        serde_json::Value::Object(_) => "{object}".to_string(),
+    }
+}
@@ -2,7 +2,7 @@
 use clap::Parser;
 use std::path::PathBuf;
 use tokio::runtime::Runtime;
-use hyperion::Hyperion;
+use hyperion::{Hyperion, RetentionPolicy, IndexConsistency};
 use hyperion::server::{HyperionServer, ServerConfig};
 
 #[derive(Parser)]
@@ -20,6 +20,35 @@ struct Cli {
     /// Adresse d'écoute
     #[arg(short = 'H', long, default_value = "127.0.0.1")]
     host: String,
+
+    /// Migrer la base de données vers le format de clés actuel avant de l'ouvrir
+    #[arg(long)]
+    migrate: bool,
+
+    /// Mettre à jour les index dans la transaction des données : une
+    /// requête voit toujours les écritures qui la précèdent
+    #[arg(long)]
+    sync_indexes: bool,
+
+    /// Vérifier les index au démarrage et réparer les divergences
+    #[arg(long)]
+    verify_indexes: bool,
+
+    /// Nombre de changements conservés pour la reprise de /api/watch
+    #[arg(long, default_value_t = 1024)]
+    replay_events: usize,
+
+    /// Conserver l'historique des valeurs (history() et snapshot())
+    #[arg(long)]
+    versioning: bool,
+
+    /// Nombre maximal de versions conservées par chemin
+    #[arg(long, requires = "versioning")]
+    keep_versions: Option<usize>,
+
+    /// Durée de conservation des versions remplacées, en jours
+    #[arg(long, requires = "versioning", conflicts_with = "keep_versions")]
+    keep_days: Option<i64>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -41,12 +70,44 @@ async fn async_main(args: Cli) -> Result<(), Box<dyn std::error::Error>> {
     let config = ServerConfig {
         port: args.port,
         host: args.host.clone(),
+        replay_capacity: args.replay_events,
     };
     
-    // Ouvrir la base de données de manière asynchrone
+    // Ouvrir la base de données de manière asynchrone, en la migrant
+    // vers le format de clés actuel si demandé
     println!("Ouverture de la base de données: {:?}", args.db_path);
-    let store = hyperion::storage::PersistentStore::open_async(&args.db_path).await?;
-    let hyperion = Hyperion::from_store(Box::new(store));
+    let store = if args.migrate {
+        hyperion::storage::PersistentStore::open_with_migration_async(&args.db_path).await?
+    } else {
+        hyperion::storage::PersistentStore::open_async(&args.db_path).await?
+    };
+    let mut hyperion = Hyperion::from_store(Box::new(store));
+    
+    // Vérifier les index si demandé (le worker a fini de les restaurer)
+    if args.verify_indexes {
+        match hyperion.verify_indexes()? {
+            Some(report) if !report.is_consistent() => log::warn!("Index réparés: {:?}", report),
+            Some(report) => log::info!("Index vérifiés: {} chemins", report.checked_paths),
+            None => {},
+        }
+    }
+    
+    // Mettre à jour les index de manière synchrone si demandé
+    if args.sync_indexes {
+        log::info!("Index synchrones activés");
+        hyperion.set_index_consistency(IndexConsistency::Synchronous)?;
+    }
+    
+    // Activer le versioning si demandé
+    if args.versioning {
+        let policy = match (args.keep_versions, args.keep_days) {
+            (Some(max), _) => RetentionPolicy::MaxVersions(max),
+            (None, Some(days)) => RetentionPolicy::MaxAge(chrono::Duration::days(days)),
+            (None, None) => RetentionPolicy::KeepAll,
+        };
+        log::info!("Versioning activé: {:?}", policy);
+        hyperion.set_versioning(Some(policy))?;
+    }
     
     // Créer et démarrer le serveur
     let server = HyperionServer::new(hyperion, config);
@@ -0,0 +1,345 @@
+//! Change feed for Hyperion
+//!
+//! Stores publish a change event for every endpoint they write. Callers
+//! subscribe with a path pattern and receive the events of the matching
+//! endpoints as a stream, in the order the writes were applied.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::path::Path;
+use super::value::Value;
+
+/// Kind of write that produced a change event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeOperation {
+    /// A value was set
+    Set,
+    /// A value was deleted
+    Delete,
+}
+
+impl ChangeOperation {
+    /// Get the name of the operation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOperation::Set => "set",
+            ChangeOperation::Delete => "delete",
+        }
+    }
+}
+
+/// A write applied to an endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Sequence number of the write (increasing within a store)
+    pub sequence: u64,
+    /// The endpoint that was written
+    pub path: Path,
+    /// The value before the write (`None` if the endpoint did not exist)
+    pub old_value: Option<Value>,
+    /// The value after the write (`None` if the endpoint was deleted)
+    pub new_value: Option<Value>,
+    /// The kind of write
+    pub operation: ChangeOperation,
+}
+
+/// A predicate selecting the events a subscriber receives
+pub type ChangeFilter = Box<dyn Fn(&ChangeEvent) -> bool + Send + Sync>;
+
+/// A registered subscriber
+struct Subscriber {
+    /// Pattern the written paths must match
+    pattern: Path,
+    /// Additional predicate on the events
+    filter: Option<ChangeFilter>,
+    /// Channel to the subscription
+    sender: UnboundedSender<ChangeEvent>,
+}
+
+impl Subscriber {
+    /// Check whether the subscriber wants an event
+    ///
+    /// An event matches if the written path, or one of its ancestors,
+    /// matches the pattern: subscribing to an entity path receives the
+    /// writes of all its endpoints.
+    fn wants(&self, event: &ChangeEvent) -> bool {
+        let segments = event.path.segments();
+        let path_matches = (1..=segments.len())
+            .any(|len| Path::from_segments(segments[..len].to_vec()).matches(&self.pattern));
+
+        path_matches && self.filter.as_ref().is_none_or(|filter| filter(event))
+    }
+}
+
+/// Mutable state of a change feed
+#[derive(Default)]
+struct FeedState {
+    /// Registered subscribers
+    subscribers: Vec<Subscriber>,
+    /// Most recent events, kept to let subscribers resume
+    replay: VecDeque<ChangeEvent>,
+    /// Maximum number of events kept for replay (0 disables replay)
+    replay_capacity: usize,
+    /// Every event with a greater sequence number is in the replay
+    /// buffer (`None` until the buffer has caught up with the store)
+    complete_after: Option<u64>,
+}
+
+/// Publisher of the change events of a store
+///
+/// Cloning the feed gives another handle on the same subscribers.
+#[derive(Clone, Default)]
+pub struct ChangeFeed {
+    /// Subscribers and replay buffer
+    state: Arc<Mutex<FeedState>>,
+}
+
+impl fmt::Debug for ChangeFeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("ChangeFeed")
+            .field("subscribers", &state.subscribers.len())
+            .field("replay_capacity", &state.replay_capacity)
+            .finish()
+    }
+}
+
+impl ChangeFeed {
+    /// Create a feed without subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the writes of the paths matching a pattern
+    pub fn subscribe(&self, pattern: Path) -> Subscription {
+        self.register(pattern, None, None)
+    }
+
+    /// Subscribe to the writes matching a pattern and a predicate
+    pub fn subscribe_filtered(&self, pattern: Path, filter: ChangeFilter) -> Subscription {
+        self.register(pattern, Some(filter), None)
+    }
+
+    /// Subscribe to the writes matching a pattern, starting with the
+    /// buffered events that followed the given sequence number
+    ///
+    /// If some of these events are no longer buffered, the subscription
+    /// reports it with `missed_events`.
+    pub fn subscribe_since(&self, pattern: Path, filter: Option<ChangeFilter>, since: u64) -> Subscription {
+        self.register(pattern, filter, Some(since))
+    }
+
+    fn register(&self, pattern: Path, filter: Option<ChangeFilter>, since: Option<u64>) -> Subscription {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let subscriber = Subscriber {
+            pattern: pattern.clone(),
+            filter,
+            sender,
+        };
+
+        // Replaying under the lock keeps the events in order with the
+        // ones published concurrently
+        let mut state = self.state.lock().unwrap();
+        let mut missed_events = false;
+
+        if let Some(since) = since {
+            missed_events = state.complete_after.is_none_or(|complete_after| since < complete_after);
+
+            for event in state.replay.iter().filter(|event| event.sequence > since) {
+                if subscriber.wants(event) {
+                    let _ = subscriber.sender.send(event.clone());
+                }
+            }
+        }
+
+        state.subscribers.push(subscriber);
+
+        Subscription { pattern, receiver, missed_events }
+    }
+
+    /// Keep the most recent events so that subscribers can resume
+    ///
+    /// Only the writes made from now on can be replayed.
+    pub fn enable_replay(&self, capacity: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        state.replay_capacity = capacity;
+        while state.replay.len() > capacity {
+            state.replay.pop_front();
+        }
+        if capacity == 0 {
+            state.complete_after = None;
+        }
+    }
+
+    /// Check whether anyone is listening
+    pub fn has_subscribers(&self) -> bool {
+        !self.state.lock().unwrap().subscribers.is_empty()
+    }
+
+    /// Check whether the stores need to publish their writes
+    pub fn is_active(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !state.subscribers.is_empty() || state.replay_capacity > 0
+    }
+
+    /// Send events to the subscribers that want them
+    pub fn publish(&self, events: Vec<ChangeEvent>) {
+        if events.is_empty() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        for event in &events {
+            for subscriber in state.subscribers.iter() {
+                if subscriber.wants(event) {
+                    // A closed channel is cleaned up below
+                    let _ = subscriber.sender.send(event.clone());
+                }
+            }
+        }
+
+        // Drop the subscribers whose subscription was dropped
+        state.subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+
+        if state.replay_capacity == 0 {
+            return;
+        }
+
+        // Writes made before the buffer was enabled cannot be replayed
+        if state.complete_after.is_none() {
+            state.complete_after = Some(events[0].sequence.saturating_sub(1));
+        }
+
+        for event in events {
+            if state.replay.len() == state.replay_capacity {
+                if let Some(evicted) = state.replay.pop_front() {
+                    state.complete_after = Some(evicted.sequence);
+                }
+            }
+            state.replay.push_back(event);
+        }
+    }
+}
+
+/// A stream of the change events matching a pattern
+///
+/// Events are buffered until read, and the subscription ends when it is
+/// dropped.
+pub struct Subscription {
+    /// Pattern the subscription was made with
+    pattern: Path,
+    /// Channel receiving the events
+    receiver: UnboundedReceiver<ChangeEvent>,
+    /// Whether events to replay were no longer available
+    missed_events: bool,
+}
+
+impl Subscription {
+    /// Get the pattern of the subscription
+    pub fn pattern(&self) -> &Path {
+        &self.pattern
+    }
+
+    /// Check whether some of the events requested when resuming could
+    /// not be replayed, in which case the caller should reload its state
+    pub fn missed_events(&self) -> bool {
+        self.missed_events
+    }
+
+    /// Wait for the next event
+    ///
+    /// Returns `None` once the store and every handle on its feed have
+    /// been dropped.
+    pub async fn recv(&mut self) -> Option<ChangeEvent> {
+        self.receiver.recv().await
+    }
+
+    /// Get the next event if one is already available
+    pub fn try_recv(&mut self) -> Option<ChangeEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = ChangeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChangeEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn event(path: &str, new_value: Option<&str>) -> ChangeEvent {
+        ChangeEvent {
+            sequence: 1,
+            path: Path::from_str(path).unwrap(),
+            old_value: None,
+            new_value: new_value.map(Value::from),
+            operation: if new_value.is_some() { ChangeOperation::Set } else { ChangeOperation::Delete },
+        }
+    }
+
+    #[test]
+    fn test_subscribers_receive_matching_events() {
+        let feed = ChangeFeed::new();
+        let mut entity = feed.subscribe(Path::from_str("users.u-1").unwrap());
+        let mut online = feed.subscribe_filtered(
+            Path::from_str("users.*.status").unwrap(),
+            Box::new(|event| event.new_value == Some(Value::from("online"))),
+        );
+
+        feed.publish(vec![
+            event("users.u-1.status", Some("online")),
+            event("users.u-2.status", Some("away")),
+            event("users.u-2.email", None),
+        ]);
+
+        assert_eq!(entity.try_recv().unwrap().path.to_string(), "users.u-1.status");
+        assert!(entity.try_recv().is_none());
+        assert_eq!(online.try_recv().unwrap().path.to_string(), "users.u-1.status");
+        assert!(online.try_recv().is_none());
+
+        // Dropped subscriptions are removed on the next publication
+        drop(entity);
+        drop(online);
+        feed.publish(vec![event("users.u-1.status", None)]);
+        assert!(!feed.has_subscribers());
+    }
+
+    #[test]
+    fn test_resume_from_sequence() {
+        let feed = ChangeFeed::new();
+        feed.enable_replay(2);
+
+        let events: Vec<ChangeEvent> = (1..=3)
+            .map(|sequence| ChangeEvent { sequence, ..event("users.u-1.status", Some("online")) })
+            .collect();
+        feed.publish(events);
+
+        // Events 2 and 3 are still buffered
+        let mut resumed = feed.subscribe_since(Path::from_str("users.**").unwrap(), None, 1);
+        assert!(!resumed.missed_events());
+        assert_eq!(resumed.try_recv().unwrap().sequence, 2);
+        assert_eq!(resumed.try_recv().unwrap().sequence, 3);
+        assert!(resumed.try_recv().is_none());
+
+        // Event 1 was evicted
+        let mut late = feed.subscribe_since(Path::from_str("users.**").unwrap(), None, 0);
+        assert!(late.missed_events());
+        assert_eq!(late.try_recv().unwrap().sequence, 2);
+    }
+}
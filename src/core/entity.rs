@@ -4,11 +4,13 @@
 //! individual endpoints that share a common path prefix.
 
 use std::collections::HashMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::{Serialize, Deserialize};
-use super::path::Path;
+use super::path::{Path, PathSegment};
 use super::value::Value;
 use super::errors::{Result, StoreError};
-use super::store::Store;
+use super::store::ReadStore;
 
 /// A reconstructed entity from the database
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -27,6 +29,13 @@ pub enum Entity {
     Binary(Vec<u8>, Option<String>),
     /// Reference to another path
     Reference(Path),
+    /// Embedding vector
+    Vector(Vec<f32>),
+    /// Geographic point, in degrees
+    GeoPoint {
+        lat: f64,
+        lon: f64,
+    },
     /// Object with named fields
     Object(HashMap<String, Entity>),
     /// Array of values
@@ -43,11 +52,197 @@ impl From<Value> for Entity {
             Value::String(s) => Entity::String(s),
             Value::Binary(data, mime) => Entity::Binary(data, mime),
             Value::Reference(path) => Entity::Reference(path),
+            Value::Vector(vector) => Entity::Vector(vector),
+            Value::GeoPoint { lat, lon } => Entity::GeoPoint { lat, lon },
         }
     }
 }
 
 impl Entity {
+    /// Get a string representation of the entity's type
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Entity::Null => "null",
+            Entity::Boolean(_) => "boolean",
+            Entity::Integer(_) => "integer",
+            Entity::Float(_) => "float",
+            Entity::String(_) => "string",
+            Entity::Binary(_, _) => "binary",
+            Entity::Reference(_) => "reference",
+            Entity::Vector(_) => "vector",
+            Entity::GeoPoint { .. } => "geopoint",
+            Entity::Object(_) => "object",
+            Entity::Array(_) => "array",
+        }
+    }
+    
+    /// Convert a scalar entity back to a value
+    ///
+    /// Returns `None` for objects and arrays, which are stored as
+    /// several endpoints.
+    pub fn into_value(self) -> Option<Value> {
+        match self {
+            Entity::Null => Some(Value::Null),
+            Entity::Boolean(b) => Some(Value::Boolean(b)),
+            Entity::Integer(i) => Some(Value::Integer(i)),
+            Entity::Float(f) => Some(Value::Float(f)),
+            Entity::String(s) => Some(Value::String(s)),
+            Entity::Binary(data, mime) => Some(Value::Binary(data, mime)),
+            Entity::Reference(path) => Some(Value::Reference(path)),
+            Entity::Vector(vector) => Some(Value::Vector(vector)),
+            Entity::GeoPoint { lat, lon } => Some(Value::GeoPoint { lat, lon }),
+            Entity::Object(_) | Entity::Array(_) => None,
+        }
+    }
+    
+    /// Split the entity into the endpoints that store it under a path
+    ///
+    /// Object fields and array items become child paths, array items
+    /// using index segments (`tags.[0]`). Empty objects and arrays have
+    /// no endpoint.
+    pub fn to_endpoints(&self, path: &Path) -> Vec<(Path, Value)> {
+        let mut endpoints = Vec::new();
+        collect_endpoints(self, path.clone(), &mut endpoints);
+        endpoints
+    }
+    
+    /// Convert the entity to plain JSON
+    ///
+    /// Binary data, references, vectors and geographic points, which JSON
+    /// cannot represent, become objects tagged with a `type` field.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Entity::Null => serde_json::Value::Null,
+            Entity::Boolean(b) => serde_json::Value::Bool(*b),
+            Entity::Integer(i) => serde_json::Value::from(*i),
+            Entity::Float(f) => {
+                // JSON cannot represent NaN and infinities
+                serde_json::Number::from_f64(*f)
+                    .map(serde_json::Value::Number)
+                    .unwrap_or_else(|| serde_json::Value::String(f.to_string()))
+            },
+            Entity::String(s) => serde_json::Value::String(s.clone()),
+            Entity::Binary(data, mime) => {
+                let mut obj = serde_json::Map::new();
+                obj.insert("type".to_string(), serde_json::Value::from("binary"));
+                obj.insert("data".to_string(), serde_json::Value::from(BASE64.encode(data)));
+                if let Some(m) = mime {
+                    obj.insert("mime".to_string(), serde_json::Value::from(m.clone()));
+                }
+                serde_json::Value::Object(obj)
+            },
+            Entity::Reference(path) => {
+                let mut obj = serde_json::Map::new();
+                obj.insert("type".to_string(), serde_json::Value::from("reference"));
+                obj.insert("path".to_string(), serde_json::Value::from(path.to_string()));
+                serde_json::Value::Object(obj)
+            },
+            Entity::Vector(vector) => {
+                let mut obj = serde_json::Map::new();
+                obj.insert("type".to_string(), serde_json::Value::from("vector"));
+                obj.insert("data".to_string(), serde_json::Value::from(vector.clone()));
+                serde_json::Value::Object(obj)
+            },
+            Entity::GeoPoint { lat, lon } => {
+                let mut obj = serde_json::Map::new();
+                obj.insert("type".to_string(), serde_json::Value::from("geopoint"));
+                obj.insert("lat".to_string(), serde_json::Value::from(*lat));
+                obj.insert("lon".to_string(), serde_json::Value::from(*lon));
+                serde_json::Value::Object(obj)
+            },
+            Entity::Object(map) => {
+                serde_json::Value::Object(map.iter()
+                    .map(|(key, value)| (key.clone(), value.to_json()))
+                    .collect())
+            },
+            Entity::Array(items) => {
+                serde_json::Value::Array(items.iter().map(Entity::to_json).collect())
+            },
+        }
+    }
+    
+    /// Build an entity from plain JSON
+    ///
+    /// Objects tagged with a `type` of `binary`, `reference`, `vector` or
+    /// `geopoint` are read back as binary data, references, vectors and
+    /// geographic points, as written by `to_json`. The data of a vector is
+    /// an array of numbers, or the base64 of its little-endian 32-bit
+    /// floats.
+    pub fn from_json(json: serde_json::Value) -> Result<Entity> {
+        match json {
+            serde_json::Value::Null => Ok(Entity::Null),
+            serde_json::Value::Bool(b) => Ok(Entity::Boolean(b)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Ok(Entity::Integer(i)),
+                None => Ok(Entity::Float(n.as_f64().unwrap_or(0.0))),
+            },
+            serde_json::Value::String(s) => Ok(Entity::String(s)),
+            serde_json::Value::Array(items) => {
+                let items = items.into_iter()
+                    .map(Entity::from_json)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Entity::Array(items))
+            },
+            serde_json::Value::Object(obj) => {
+                match obj.get("type").and_then(|t| t.as_str()) {
+                    Some("binary") => {
+                        let data = obj.get("data")
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| invalid_json("Missing data for binary type"))?;
+                        let decoded = BASE64.decode(data)
+                            .map_err(|e| invalid_json(&format!("Invalid base64 data: {}", e)))?;
+                        let mime = obj.get("mime").and_then(|v| v.as_str()).map(String::from);
+                        Ok(Entity::Binary(decoded, mime))
+                    },
+                    Some("reference") => {
+                        let path_str = obj.get("path")
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| invalid_json("Missing path for reference type"))?;
+                        Ok(Entity::Reference(path_str.parse()?))
+                    },
+                    Some("vector") => {
+                        let vector = match obj.get("data") {
+                            Some(serde_json::Value::Array(items)) => items.iter()
+                                .map(|item| item.as_f64().map(|x| x as f32))
+                                .collect::<Option<Vec<f32>>>()
+                                .ok_or_else(|| invalid_json("Vector data must only contain numbers"))?,
+                            Some(serde_json::Value::String(data)) => {
+                                let decoded = BASE64.decode(data)
+                                    .map_err(|e| invalid_json(&format!("Invalid base64 data: {}", e)))?;
+                                if !decoded.len().is_multiple_of(4) {
+                                    return Err(invalid_json("Vector data must hold 32-bit floats"));
+                                }
+                                decoded.chunks_exact(4)
+                                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                                    .collect()
+                            },
+                            _ => return Err(invalid_json("Missing data for vector type")),
+                        };
+                        Ok(Entity::Vector(vector))
+                    },
+                    Some("geopoint") => {
+                        let coordinate = |name: &str| obj.get(name)
+                            .and_then(|value| value.as_f64())
+                            .ok_or_else(|| invalid_json(&format!("Missing {} for geopoint type", name)));
+                        let (lat, lon) = (coordinate("lat")?, coordinate("lon")?);
+                        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                            return Err(invalid_json("Geopoint coordinates out of range"));
+                        }
+                        Ok(Entity::GeoPoint { lat, lon })
+                    },
+                    _ => {
+                        let mut map = HashMap::with_capacity(obj.len());
+                        for (key, value) in obj {
+                            check_field_name(&key)?;
+                            map.insert(key, Entity::from_json(value)?);
+                        }
+                        Ok(Entity::Object(map))
+                    },
+                }
+            },
+        }
+    }
+    
     /// Convert the entity to a debug string representation
     pub fn to_string_pretty(&self, indent: usize) -> String {
         match self {
@@ -64,6 +259,8 @@ impl Entity {
                 }
             },
             Entity::Reference(path) => format!("@{}", path),
+            Entity::Vector(vector) => format!("[vector: {} dimensions]", vector.len()),
+            Entity::GeoPoint { lat, lon } => format!("geopoint({}, {})", lat, lon),
             Entity::Object(map) => {
                 if map.is_empty() {
                     return "{}".to_string();
@@ -118,107 +315,113 @@ impl Entity {
     }
 }
 
+/// Error for JSON that cannot be stored as an entity
+fn invalid_json(message: &str) -> StoreError {
+    StoreError::InvalidOperation(format!("Invalid JSON value: {}", message))
+}
+
+/// Check that an object field can be used as a path segment
+fn check_field_name(name: &str) -> Result<()> {
+    let segment = PathSegment::new(name);
+    
+    if name.is_empty() || name.contains('.') || segment.is_wildcard() || segment.is_array_index() {
+        return Err(invalid_json(&format!("'{}' cannot be used as a field name", name)));
+    }
+    
+    Ok(())
+}
+
+/// Collect the endpoints of an entity stored at a path
+fn collect_endpoints(entity: &Entity, path: Path, endpoints: &mut Vec<(Path, Value)>) {
+    match entity {
+        Entity::Object(map) => {
+            for (key, value) in map {
+                let mut child = path.clone();
+                child.push(key.clone());
+                collect_endpoints(value, child, endpoints);
+            }
+        },
+        Entity::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                let mut child = path.clone();
+                child.push(format!("[{}]", index));
+                collect_endpoints(item, child, endpoints);
+            }
+        },
+        scalar => {
+            // Only objects and arrays have no value of their own
+            if let Some(value) = scalar.clone().into_value() {
+                endpoints.push((path, value));
+            }
+        },
+    }
+}
+
 /// Insert a value into the appropriate place in the entity
+///
+/// Missing objects and arrays along the way are created, depending on
+/// whether the next segment is a field name or an array index.
 fn insert_into_entity(
-    entity: &mut HashMap<String, Entity>,
-    segments: &[String],
+    entity: &mut Entity,
+    segments: &[PathSegment],
     value: Value
 ) -> Result<()> {
-    if segments.is_empty() {
-        return Err(StoreError::InvalidOperation("Empty segments".to_string()));
-    }
-    
-    let segment = &segments[0];
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => {
+            *entity = Entity::from(value);
+            return Ok(());
+        }
+    };
     
-    // Check if this is an array index
-    if let Some(index_str) = segment.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
-        // Parse the index
-        let index = index_str.parse::<usize>().map_err(|_| {
-            StoreError::InvalidOperation(format!("Invalid array index: {}", index_str))
-        })?;
-        
-        // Get or create the array
-        let array = entity
-            .entry("".to_string())
-            .or_insert_with(|| Entity::Array(Vec::new()));
-        
-        // Ensure we have an array
-        if let Entity::Array(items) = array {
+    let child = match segment.as_index() {
+        Some(index) => {
+            if let Entity::Null = entity {
+                *entity = Entity::Array(Vec::new());
+            }
+            
+            let items = match entity {
+                Entity::Array(items) => items,
+                _ => return Err(StoreError::InvalidOperation(
+                    format!("Cannot insert at path: expected array, found {}", segment.as_str())
+                )),
+            };
+            
             // Ensure the array is large enough
             while items.len() <= index {
                 items.push(Entity::Null);
             }
             
-            if segments.len() == 1 {
-                // This is the last segment, set the value directly
-                items[index] = Entity::from(value);
-            } else {
-                // More segments to process
-                let next_segments = &segments[1..];
-                
-                // Get or create an object at this index
-                if let Entity::Null = items[index] {
-                    items[index] = Entity::Object(HashMap::new());
-                }
-                
-                if let Entity::Object(ref mut obj) = items[index] {
-                    insert_into_entity(obj, next_segments, value)?;
-                } else {
-                    return Err(StoreError::InvalidOperation(
-                        format!("Cannot insert at path: expected object, found {}", segment)
-                    ));
-                }
+            &mut items[index]
+        },
+        None => {
+            if let Entity::Null = entity {
+                *entity = Entity::Object(HashMap::new());
             }
-        } else {
-            return Err(StoreError::InvalidOperation(
-                format!("Cannot insert at path: expected array, found {}", segment)
-            ));
-        }
-        
-        return Ok(());
-    }
-    
-    if segments.len() == 1 {
-        // This is the last segment, set the value directly
-        entity.insert(segment.clone(), Entity::from(value));
-    } else {
-        // More segments to process
-        let next_segments = &segments[1..];
-        
-        // Get or create an object at this key
-        let nested = entity
-            .entry(segment.clone())
-            .or_insert_with(|| Entity::Object(HashMap::new()));
-        
-        if let Entity::Object(ref mut obj) = nested {
-            insert_into_entity(obj, next_segments, value)?;
-        } else {
-            return Err(StoreError::InvalidOperation(
-                format!("Cannot insert at path: expected object, found {}", segment)
-            ));
-        }
-    }
+            
+            match entity {
+                Entity::Object(map) => map.entry(segment.as_str()).or_insert(Entity::Null),
+                _ => return Err(StoreError::InvalidOperation(
+                    format!("Cannot insert at path: expected object, found {}", segment.as_str())
+                )),
+            }
+        },
+    };
     
-    Ok(())
-}
-
-/// Get the remaining path segments after the prefix
-fn get_remaining_segments(path: &Path, prefix: &Path) -> Vec<String> {
-    let path_segments = path.segments();
-    let prefix_segments = prefix.segments();
-    
-    path_segments[prefix_segments.len()..]
-        .iter()
-        .map(|s| s.as_str().to_string())
-        .collect()
+    insert_into_entity(child, rest, value)
 }
 
 /// Reconstruct an entity from a collection of endpoints in a store
-pub fn reconstruct_entity<S: Store + ?Sized>(store: &S, prefix: &Path) -> Result<Entity> {
+pub fn reconstruct_entity<S: ReadStore + ?Sized>(store: &S, prefix: &Path) -> Result<Entity> {
     // Get all endpoints under the prefix
     let endpoints = store.get_prefix(prefix)?;
     println!("Endpoints: {:?}", endpoints);
     
+    entity_from_endpoints(endpoints, prefix)
+}
+
+/// Build an entity from the endpoints found under a prefix
+pub fn entity_from_endpoints(endpoints: Vec<(Path, Value)>, prefix: &Path) -> Result<Entity> {
     if endpoints.is_empty() {
         return Err(StoreError::NotFound(prefix.clone()));
     }
@@ -232,22 +435,71 @@ pub fn reconstruct_entity<S: Store + ?Sized>(store: &S, prefix: &Path) -> Result
         }
     }
     
-    // Start with an empty object
-    let mut result = HashMap::new();
+    // Start with an empty entity, which becomes an object or an array
+    // depending on the first segment below the prefix
+    let mut result = Entity::Null;
     
     // Process each endpoint
     for (path, value) in endpoints {
-        // Skip paths that don't start with the prefix
-        if !path.starts_with(prefix) {
+        // Skip paths that don't start with the prefix, and the prefix
+        // itself since it also has children
+        if !path.starts_with(prefix) || path.len() == prefix.len() {
             continue;
         }
         
-        // Get the remaining segments after the prefix
-        let remaining_segments = get_remaining_segments(&path, prefix);
-        
         // Insert the value into the appropriate place in the result
-        insert_into_entity(&mut result, &remaining_segments, value)?;
+        insert_into_entity(&mut result, &path.segments()[prefix.len()..], value)?;
     }
     
-    Ok(Entity::Object(result))
-}
\ No newline at end of file
+    match result {
+        Entity::Null => Ok(Entity::Object(HashMap::new())),
+        entity => Ok(entity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    
+    #[test]
+    fn test_json_round_trip_through_endpoints() {
+        let json = serde_json::json!({
+            "name": "Alice",
+            "profile": { "age": 30, "verified": true },
+            "tags": ["admin", { "since": 2020 }],
+            "manager": { "type": "reference", "path": "users.u-2" },
+            "home": { "type": "geopoint", "lat": 48.8566, "lon": 2.3522 }
+        });
+        
+        let prefix = Path::from_str("users.u-1").unwrap();
+        let entity = Entity::from_json(json.clone()).unwrap();
+        let endpoints = entity.to_endpoints(&prefix);
+        
+        let paths: Vec<String> = endpoints.iter().map(|(path, _)| path.to_string()).collect();
+        assert_eq!(endpoints.len(), 7);
+        assert!(paths.contains(&"users.u-1.profile.age".to_string()));
+        assert!(paths.contains(&"users.u-1.tags.[1].since".to_string()));
+        assert!(endpoints.contains(&(
+            Path::from_str("users.u-1.manager").unwrap(),
+            Value::Reference(Path::from_str("users.u-2").unwrap()),
+        )));
+        assert!(endpoints.contains(&(
+            Path::from_str("users.u-1.home").unwrap(),
+            Value::GeoPoint { lat: 48.8566, lon: 2.3522 },
+        )));
+        
+        let rebuilt = entity_from_endpoints(endpoints, &prefix).unwrap();
+        assert_eq!(rebuilt, entity);
+        assert_eq!(rebuilt.to_json(), json);
+        
+        // Points out of range are refused
+        assert!(Entity::from_json(serde_json::json!({ "type": "geopoint", "lat": 91.0, "lon": 0.0 })).is_err());
+    }
+    
+    #[test]
+    fn test_field_names_must_be_path_segments() {
+        assert!(Entity::from_json(serde_json::json!({ "a.b": 1 })).is_err());
+        assert!(Entity::from_json(serde_json::json!({ "*": 1 })).is_err());
+    }
+}
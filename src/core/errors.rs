@@ -26,6 +26,9 @@ pub enum StoreError {
     
     #[error("Deserialization error: {0}")]
     DeserializationError(String),
+    
+    #[error("Constraint violation: {0}")]
+    ConstraintViolation(String),
 }
 
 /// Result type for database operations
@@ -0,0 +1,556 @@
+// src/core/index/composite_index.rs
+//! Index composites sur plusieurs champs d'une entité
+//!
+//! Un index composite est déclaré sur un motif d'entités (`orders.*`) et
+//! une liste de champs (`customer`, `status`). Chaque entité y a une clé
+//! formée du tuple de ses valeurs, dans l'ordre des champs : les clés
+//! sont triées par la première colonne, puis la deuxième, etc. Une
+//! recherche fixe les premières colonnes par égalité et parcourt la
+//! suivante dans l'ordre.
+//!
+//! L'arbre de l'index contient deux sortes d'entrées, préfixées par
+//! l'identifiant de l'index :
+//! - `id | ENTRY_TAG | colonnes | entité` : l'entrée du tuple d'une entité ;
+//! - `id | TUPLE_TAG | entité` : le tuple courant de l'entité, qui permet
+//!   de retrouver son entrée quand un seul champ change.
+
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bincode::{serialize, deserialize};
+use serde::{Serialize, Deserialize};
+use sled::Db;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use super::types::{IndexImplementation, ValueRange, compare_values};
+use super::tree::{IndexTree, TreeResult, abort, into_store_error};
+use super::value_index::ValueIndex;
+
+/// Préfixe des entrées de tuples
+const ENTRY_TAG: u8 = 0x00;
+/// Préfixe des tuples courants des entités
+const TUPLE_TAG: u8 = 0x01;
+/// Octet d'échappement d'un 0x00 dans une colonne
+const ESCAPED_ZERO: u8 = 0xFF;
+
+/// Colonnes d'un tuple : la clé de valeur de chaque champ, ou `None`
+/// pour un champ absent
+type Columns = Vec<Option<Vec<u8>>>;
+
+/// Déclaration d'un index composite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CompositeDefinition {
+    /// Identifiant de l'index, préfixe de ses clés
+    id: u32,
+    /// Motif des entités indexées
+    pattern: Path,
+    /// Champs indexés, relatifs à l'entité, dans l'ordre des colonnes
+    fields: Vec<Path>,
+    /// Les données existantes sont indexées
+    ready: bool,
+}
+
+impl CompositeDefinition {
+    /// Trouve l'entité et la colonne d'un chemin indexé par cet index
+    fn column_of(&self, path: &Path) -> Option<(Path, usize)> {
+        let entity_len = self.pattern.len();
+        if path.len() <= entity_len {
+            return None;
+        }
+
+        let entity = Path::from_segments(path.segments()[..entity_len].to_vec());
+        if !entity.matches(&self.pattern) {
+            return None;
+        }
+
+        let field = &path.segments()[entity_len..];
+        let column = self.fields.iter().position(|candidate| candidate.segments() == field)?;
+        Some((entity, column))
+    }
+
+    /// Début des clés de l'index
+    fn prefix(&self, tag: u8) -> Vec<u8> {
+        let mut prefix = self.id.to_be_bytes().to_vec();
+        prefix.push(tag);
+        prefix
+    }
+
+    /// Clé du tuple courant d'une entité
+    fn tuple_key(&self, entity: &[u8]) -> Vec<u8> {
+        let mut key = self.prefix(TUPLE_TAG);
+        key.extend_from_slice(entity);
+        key
+    }
+
+    /// Clé de l'entrée d'un tuple
+    fn entry_key(&self, columns: &Columns, entity: &[u8]) -> Vec<u8> {
+        let mut key = self.prefix(ENTRY_TAG);
+        for column in columns {
+            push_column(&mut key, column.as_deref().unwrap_or(&[]));
+        }
+        key.extend_from_slice(entity);
+        key
+    }
+
+    /// Décode l'entrée d'un tuple : ses colonnes et son entité
+    fn decode_entry(&self, key: &[u8]) -> Result<(Vec<Option<Value>>, Path)> {
+        let mut rest = &key[5..];
+        let mut values = Vec::with_capacity(self.fields.len());
+
+        for _ in &self.fields {
+            let (column, tail) = split_column(rest)
+                .ok_or_else(|| StoreError::DeserializationError("Invalid composite index key".to_string()))?;
+            values.push(match column.is_empty() {
+                true => None,
+                false => Some(ValueIndex::decode_value_key(&column)?),
+            });
+            rest = tail;
+        }
+
+        let entity = deserialize(rest)
+            .map_err(|e| StoreError::Internal(format!("Failed to deserialize entity path: {}", e)))?;
+        Ok((values, entity))
+    }
+}
+
+/// Ajoute une colonne à une clé : les 0x00 sont échappés et la colonne
+/// se termine par deux 0x00, ce qui conserve l'ordre des colonnes (une
+/// colonne vide, pour un champ absent, passe avant toutes les valeurs)
+fn push_column(key: &mut Vec<u8>, column: &[u8]) {
+    push_escaped(key, column);
+    key.extend_from_slice(&[0x00, 0x00]);
+}
+
+/// Ajoute des octets échappés à une clé, sans fin de colonne
+fn push_escaped(key: &mut Vec<u8>, bytes: &[u8]) {
+    for &byte in bytes {
+        key.push(byte);
+        if byte == 0x00 {
+            key.push(ESCAPED_ZERO);
+        }
+    }
+}
+
+/// Sépare la première colonne d'une clé du reste de la clé
+fn split_column(key: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut column = Vec::new();
+    let mut i = 0;
+
+    while i < key.len() {
+        if key[i] == 0x00 {
+            match key.get(i + 1) {
+                Some(&ESCAPED_ZERO) => column.push(0x00),
+                Some(0x00) => return Some((column, &key[i + 2..])),
+                _ => return None,
+            }
+            i += 2;
+        } else {
+            column.push(key[i]);
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Index des tuples de champs des entités
+pub struct CompositeIndex {
+    /// La base de données sled
+    db: Arc<Db>,
+    /// Nom de l'arbre pour stocker l'index
+    index_tree_name: String,
+    /// Nom de l'arbre pour stocker les déclarations
+    metadata_tree_name: String,
+    /// Cache mémoire des index déclarés
+    definitions: Vec<CompositeDefinition>,
+}
+
+impl CompositeIndex {
+    /// Crée un nouvel index composite
+    pub fn new(db: Arc<Db>, base_name: &str) -> Result<Self> {
+        let mut index = CompositeIndex {
+            db,
+            index_tree_name: format!("{}_entries", base_name),
+            metadata_tree_name: format!("{}_metadata", base_name),
+            definitions: Vec::new(),
+        };
+
+        // Charger les index déclarés depuis le stockage
+        for item in index.get_metadata_tree()?.iter() {
+            let (_, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
+            let definition = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize definition: {}", e)))?;
+            index.definitions.push(definition);
+        }
+
+        Ok(index)
+    }
+
+    /// Obtient l'arbre pour l'index
+    fn get_index_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.index_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open composite index tree: {}", e)))
+    }
+
+    /// Obtient l'arbre pour les déclarations
+    fn get_metadata_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.metadata_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open metadata tree: {}", e)))
+    }
+
+    /// Trouve la déclaration d'un index
+    fn definition(&self, pattern: &Path, fields: &[Path]) -> Option<&CompositeDefinition> {
+        self.definitions.iter().find(|definition| definition.pattern == *pattern && definition.fields == fields)
+    }
+
+    /// Enregistre une déclaration
+    fn write_definition(&mut self, definition: CompositeDefinition) -> Result<()> {
+        let tree = self.get_metadata_tree()?;
+        let data = serialize(&definition).map_err(|e| StoreError::Internal(format!("Failed to serialize definition: {}", e)))?;
+
+        tree.insert(definition.id.to_be_bytes(), data).map_err(|e| StoreError::Internal(format!("Failed to insert definition into metadata tree: {}", e)))?;
+        tree.flush().map_err(|e| StoreError::Internal(format!("Failed to flush metadata tree: {}", e)))?;
+
+        // Mettre à jour le cache
+        self.definitions.retain(|existing| existing.id != definition.id);
+        self.definitions.push(definition);
+
+        Ok(())
+    }
+
+    /// Déclare un index, en construction jusqu'à `mark_ready` : les
+    /// écritures sont indexées, mais l'index ne répond pas encore aux
+    /// requêtes
+    pub fn begin_index(&mut self, pattern: &Path, fields: &[Path]) -> Result<()> {
+        if pattern.segments().iter().any(|segment| segment.is_multi_wildcard()) {
+            return Err(StoreError::InvalidOperation(
+                "A composite index pattern cannot contain '**'".to_string()
+            ));
+        }
+        if fields.is_empty() || fields.iter().any(|field| field.is_empty() || field.has_wildcards()) {
+            return Err(StoreError::InvalidOperation(
+                "A composite index needs one or more fields without wildcards".to_string()
+            ));
+        }
+
+        let id = self.definitions.iter().map(|definition| definition.id + 1).max().unwrap_or(0);
+        self.write_definition(CompositeDefinition {
+            id,
+            pattern: pattern.clone(),
+            fields: fields.to_vec(),
+            ready: false,
+        })
+    }
+
+    /// Marque un index comme prêt, une fois les données existantes indexées
+    pub fn mark_ready(&mut self, pattern: &Path, fields: &[Path]) -> Result<()> {
+        match self.definition(pattern, fields) {
+            Some(definition) => {
+                let definition = CompositeDefinition { ready: true, ..definition.clone() };
+                self.write_definition(definition)
+            },
+            // L'index a pu être supprimé pendant la construction
+            None => Ok(()),
+        }
+    }
+
+    /// Supprime un index et ses entrées
+    pub fn remove_index(&mut self, pattern: &Path, fields: &[Path]) -> Result<()> {
+        let id = match self.definition(pattern, fields) {
+            Some(definition) => definition.id,
+            None => return Ok(()),
+        };
+
+        let metadata = self.get_metadata_tree()?;
+        metadata.remove(id.to_be_bytes()).map_err(|e| StoreError::Internal(format!("Failed to remove definition from metadata tree: {}", e)))?;
+        self.definitions.retain(|definition| definition.id != id);
+
+        let tree = self.get_index_tree()?;
+        for key in tree.scan_prefix(id.to_be_bytes()).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            tree.remove(key).map_err(|e| StoreError::Internal(format!("Failed to remove from index tree: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Liste les index déclarés : motif, champs et état (prêt ou non)
+    pub fn indexes(&self) -> Vec<(Path, Vec<Path>, bool)> {
+        self.definitions.iter()
+            .map(|definition| (definition.pattern.clone(), definition.fields.clone(), definition.ready))
+            .collect()
+    }
+
+    /// Compte les entités indexées par un index
+    pub fn count_entities(&self, pattern: &Path, fields: &[Path]) -> Result<usize> {
+        let definition = match self.definition(pattern, fields) {
+            Some(definition) => definition,
+            None => return Ok(0),
+        };
+
+        Ok(self.get_index_tree()?.scan_prefix(definition.prefix(TUPLE_TAG)).count())
+    }
+
+    /// Trouve les entités dont les premiers champs d'un index sont égaux
+    /// à des valeurs, et dont le champ suivant appartient éventuellement à
+    /// un intervalle
+    ///
+    /// Les entités sont triées par le champ de l'intervalle (ou le premier
+    /// champ non fixé). Renvoie `None` si aucun index prêt ne commence par
+    /// ces champs.
+    pub fn find(
+        &self,
+        pattern: &Path,
+        equals: &[(Path, Value)],
+        range: Option<(&Path, &ValueRange)>,
+    ) -> Result<Option<Vec<Path>>> {
+        let definition = self.definitions.iter().find(|definition| {
+            let fixed = equals.len();
+            definition.ready
+                && pattern.matches(&definition.pattern)
+                && definition.fields.len() >= fixed + range.is_some() as usize
+                && definition.fields[..fixed].iter().all(|field| equals.iter().any(|(path, _)| path == field))
+                && range.is_none_or(|(field, _)| definition.fields[fixed] == *field)
+        });
+        let definition = match definition {
+            Some(definition) => definition,
+            None => return Ok(None),
+        };
+
+        // Les colonnes fixées forment le début des clés parcourues. Un
+        // nombre n'y est fixé que par son début commun à l'entier et au
+        // flottant égal : les colonnes suivantes sont alors filtrées
+        let mut prefix = definition.prefix(ENTRY_TAG);
+        let mut exact = true;
+        for field in &definition.fields[..equals.len()] {
+            let value = &equals.iter().find(|(path, _)| path == field).unwrap().1;
+            let key = match ValueIndex::indexable_key(value) {
+                Some(key) => key,
+                None => return Ok(Some(Vec::new())),
+            };
+
+            if matches!(value, Value::Integer(_) | Value::Float(_)) {
+                push_escaped(&mut prefix, &ValueIndex::bound_prefix(value)?);
+                exact = false;
+                break;
+            }
+            push_column(&mut prefix, &key);
+        }
+
+        // Les bornes de l'intervalle délimitent le parcours quand les
+        // colonnes qui le précèdent sont exactes
+        let bound_key = |bound: &Bound<Value>| -> Result<Option<Vec<u8>>> {
+            match bound {
+                Bound::Included(value) | Bound::Excluded(value) if exact => {
+                    let mut key = prefix.clone();
+                    push_escaped(&mut key, &ValueIndex::bound_prefix(value)?);
+                    Ok(Some(key))
+                },
+                _ => Ok(None),
+            }
+        };
+        let (start, limit) = match range {
+            Some((_, range)) => (bound_key(&range.lower)?, bound_key(&range.upper)?),
+            None => (None, None),
+        };
+        let start = start.unwrap_or_else(|| prefix.clone());
+        // Aucune colonne ne contient l'octet 0xFF juste après le début
+        // d'une borne
+        let limit = limit.map(|mut limit| {
+            limit.push(0xFF);
+            limit
+        });
+
+        let tree = self.get_index_tree()?;
+        let mut entities = Vec::new();
+
+        for key in tree.range(start..).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            if !key.starts_with(&prefix) || limit.as_ref().is_some_and(|limit| *key >= limit[..]) {
+                break;
+            }
+
+            let (values, entity) = definition.decode_entry(&key)?;
+            if !entity.matches(pattern) {
+                continue;
+            }
+
+            // Les bornes et les nombres sont vérifiés sur les valeurs
+            let mut holds = equals.iter().all(|(field, value)| {
+                let column = definition.fields.iter().position(|candidate| candidate == field).unwrap();
+                values[column].as_ref()
+                    .is_some_and(|actual| compare_values(actual, value) == Some(Ordering::Equal))
+            });
+            if let Some((_, range)) = range {
+                holds = holds && values[equals.len()].as_ref().is_some_and(|actual| range.contains(actual));
+            }
+
+            if holds {
+                entities.push(entity);
+            }
+        }
+
+        Ok(Some(entities))
+    }
+
+    /// Vérifie qu'un index prêt porte sur ces champs
+    pub fn covers(&self, pattern: &Path, fields: &[Path]) -> bool {
+        self.definition(pattern, fields).is_some_and(|definition| definition.ready)
+    }
+
+    /// Remplace une colonne du tuple d'une entité, et son entrée
+    ///
+    /// Avec `expected`, la colonne n'est vidée que si elle contient
+    /// encore cette valeur.
+    fn update_column(
+        definition: &CompositeDefinition,
+        tree: &dyn IndexTree,
+        entity: &Path,
+        column: usize,
+        value: Option<Vec<u8>>,
+        expected: Option<&[u8]>,
+    ) -> TreeResult<()> {
+        let entity = serialize(entity)
+            .map_err(|e| abort(format!("Failed to serialize entity path: {}", e)))?;
+        let tuple_key = definition.tuple_key(&entity);
+
+        let mut columns: Columns = match tree.get(&tuple_key)? {
+            Some(data) => deserialize(&data)
+                .map_err(|e| abort(format!("Failed to deserialize tuple: {}", e)))?,
+            None => vec![None; definition.fields.len()],
+        };
+        if columns[column] == value || expected.is_some_and(|expected| columns[column].as_deref() != Some(expected)) {
+            return Ok(());
+        }
+
+        if columns.iter().any(Option::is_some) {
+            tree.remove(&definition.entry_key(&columns, &entity))?;
+        }
+        columns[column] = value;
+
+        // L'entité disparaît de l'index avec son dernier champ
+        if columns.iter().all(Option::is_none) {
+            return tree.remove(&tuple_key);
+        }
+
+        tree.insert(&definition.entry_key(&columns, &entity), Vec::new())?;
+        let data = serialize(&columns)
+            .map_err(|e| abort(format!("Failed to serialize tuple: {}", e)))?;
+        tree.insert(&tuple_key, data)
+    }
+}
+
+impl IndexImplementation for CompositeIndex {
+    fn add_path(&mut self, _path: &Path) -> Result<()> {
+        // Les tuples ont besoin des valeurs
+        Ok(())
+    }
+
+    fn remove_path(&mut self, path: &Path) -> Result<()> {
+        let tree = self.get_index_tree()?;
+
+        for definition in &self.definitions {
+            if let Some((entity, column)) = definition.column_of(path) {
+                Self::update_column(definition, &tree, &entity, column, None, None).map_err(into_store_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_by_prefix(&self, _prefix: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn find_by_pattern(&self, _pattern: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_index_tree()?])
+    }
+
+    fn indexes_values(&self) -> bool {
+        true
+    }
+
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        for definition in &self.definitions {
+            if let Some((entity, column)) = definition.column_of(path) {
+                // Une valeur binaire n'est pas indexable : le champ est absent
+                let key = ValueIndex::indexable_key(value);
+                Self::update_column(definition, trees[0], &entity, column, key, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        let key = match ValueIndex::indexable_key(value) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+
+        for definition in &self.definitions {
+            if let Some((entity, column)) = definition.column_of(path) {
+                Self::update_column(definition, trees[0], &entity, column, None, Some(&key))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        // Les déclarations sont conservées
+        self.get_index_tree()?.clear().map_err(|e| StoreError::Internal(format!("Failed to clear index tree: {}", e)))?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "CompositeIndex"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::index::{IndexDefinition, IndexKind};
+    use crate::core::index::testing::TestIndexes;
+
+    #[tokio::test]
+    async fn test_tuples_follow_field_changes() {
+        let indexes = TestIndexes::new();
+        let path = |s: &str| Path::from_str(s).unwrap();
+        let definition = IndexDefinition::new(path("orders.*"), IndexKind::Composite(vec![path("customer"), path("total")]));
+        indexes.declare(&definition);
+
+        let orders = [("o-1", "c-1", 30), ("o-2", "c-1", 5), ("o-3", "c-2", 10), ("o-4", "c-1\u{0}", 1)];
+        for (id, customer, total) in orders {
+            indexes.write(&format!("orders.{}.customer", id), None, Some(Value::String(customer.to_string())));
+            indexes.write(&format!("orders.{}.total", id), None, Some(Value::Integer(total)));
+        }
+        // A field changes: the entry of the entity follows
+        indexes.write("orders.o-3.customer", Some(Value::String("c-2".to_string())), Some(Value::String("c-1".to_string())));
+        // Stale removals are ignored
+        indexes.write("orders.o-2.total", Some(Value::Integer(6)), None);
+
+        let customer = |id: &str| (path("customer"), Value::String(id.to_string()));
+        let found = indexes.system.find_by_fields(&path("orders.*"), &[customer("c-1")], None).unwrap().unwrap();
+        assert_eq!(found, vec![path("orders.o-2"), path("orders.o-3"), path("orders.o-1")]);
+
+        let range = ValueRange::new().above(Value::Float(5.0), false).below(Value::Integer(30), true);
+        let found = indexes.system.find_by_fields(&path("orders.*"), &[customer("c-1")], Some((&path("total"), &range))).unwrap().unwrap();
+        assert_eq!(found, vec![path("orders.o-3"), path("orders.o-1")]);
+
+        // Only a prefix of the fields can be fixed
+        assert_eq!(indexes.system.find_by_fields(&path("orders.*"), &[(path("total"), Value::Integer(5))], None).unwrap(), None);
+
+        indexes.write("orders.o-1.customer", Some(Value::String("c-1".to_string())), None);
+        indexes.write("orders.o-1.total", Some(Value::Integer(30)), None);
+        assert_eq!(indexes.info(&definition).entries, 3);
+    }
+}
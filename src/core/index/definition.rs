@@ -0,0 +1,138 @@
+// src/core/index/definition.rs
+//! Index secondaires déclarés par motif de chemin
+//!
+//! Un index est déclaré sur un motif (`users.*.email`) : seuls les
+//! chemins qui y correspondent sont indexés. Un nouvel index est d'abord
+//! en construction, le temps qu'une tâche de fond indexe les données
+//! existantes ; il ne répond aux requêtes qu'une fois prêt.
+//!
+//! Un index composite est déclaré sur un motif d'entités (`orders.*`) :
+//! ses champs sont relatifs aux entités du motif. Un index plein texte
+//! est déclaré sur un motif de chaînes (`posts.*.content`), un index de
+//! vecteurs sur un motif de vecteurs (`docs.*.embedding`), un index
+//! géographique sur un motif de points (`devices.*.location`).
+
+use std::fmt;
+
+use crate::core::path::Path;
+use super::vector_index::VectorMetric;
+
+/// Type d'un index secondaire
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    /// Index des valeurs, pour les recherches par égalité et par plage
+    Value,
+    /// Index des tuples de plusieurs champs des entités, dans l'ordre des
+    /// colonnes
+    Composite(Vec<Path>),
+    /// Index inversé des termes des chaînes, pour les recherches classées
+    FullText,
+    /// Index des vecteurs, pour les recherches par similarité ; un index
+    /// approximatif passe par un graphe HNSW
+    Vector {
+        metric: VectorMetric,
+        approximate: bool,
+    },
+    /// Index des points géographiques par geohash, pour les recherches
+    /// dans une zone
+    Geo,
+}
+
+impl IndexKind {
+    /// Obtient le nom du type d'index
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexKind::Value => "value",
+            IndexKind::Composite(_) => "composite",
+            IndexKind::FullText => "fulltext",
+            IndexKind::Vector { .. } => "vector",
+            IndexKind::Geo => "geo",
+        }
+    }
+    
+    /// Obtient les champs d'un index composite (aucun pour les autres)
+    pub fn fields(&self) -> &[Path] {
+        match self {
+            IndexKind::Value | IndexKind::FullText | IndexKind::Vector { .. } | IndexKind::Geo => &[],
+            IndexKind::Composite(fields) => fields,
+        }
+    }
+}
+
+impl fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexKind::Value | IndexKind::FullText | IndexKind::Geo => f.write_str(self.name()),
+            IndexKind::Composite(fields) => {
+                let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+                write!(f, "{} ({})", self.name(), fields.join(", "))
+            },
+            IndexKind::Vector { metric, approximate: false } => write!(f, "{} ({})", self.name(), metric.name()),
+            IndexKind::Vector { metric, approximate: true } => write!(f, "{} ({}, hnsw)", self.name(), metric.name()),
+        }
+    }
+}
+
+/// Déclaration d'un index secondaire
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IndexDefinition {
+    /// Motif des chemins indexés
+    pub pattern: Path,
+    /// Type de l'index
+    pub kind: IndexKind,
+}
+
+impl IndexDefinition {
+    /// Crée une déclaration d'index
+    pub fn new(pattern: Path, kind: IndexKind) -> Self {
+        IndexDefinition { pattern, kind }
+    }
+    
+    /// Indique si un chemin est indexé par cet index
+    pub fn indexes(&self, path: &Path) -> bool {
+        match &self.kind {
+            IndexKind::Value | IndexKind::FullText | IndexKind::Vector { .. } | IndexKind::Geo => {
+                path.matches(&self.pattern)
+            },
+            IndexKind::Composite(fields) => {
+                let entity_len = self.pattern.len();
+                path.len() > entity_len
+                    && Path::from_segments(path.segments()[..entity_len].to_vec()).matches(&self.pattern)
+                    && fields.iter().any(|field| field.segments() == &path.segments()[entity_len..])
+            },
+        }
+    }
+}
+
+impl fmt::Display for IndexDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} index on {}", self.kind, self.pattern)
+    }
+}
+
+/// État de construction d'un index
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexStatus {
+    /// Les données existantes sont en cours d'indexation
+    Building {
+        /// Nombre de chemins déjà indexés
+        indexed: usize,
+        /// Nombre de chemins à indexer (0 tant qu'il n'est pas connu)
+        total: usize,
+    },
+    /// L'index est complet et répond aux requêtes
+    Ready,
+}
+
+/// Description d'un index existant
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexInfo {
+    /// Déclaration de l'index
+    pub definition: IndexDefinition,
+    /// État de construction
+    pub status: IndexStatus,
+    /// Nombre de chemins indexés (d'entités pour un index composite, de
+    /// chaînes non vides pour un index plein texte, de vecteurs ou de
+    /// points pour un index de vecteurs ou géographique)
+    pub entries: usize,
+}
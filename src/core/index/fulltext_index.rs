@@ -0,0 +1,643 @@
+// src/core/index/fulltext_index.rs
+//! Index plein texte des chaînes
+//!
+//! Un index plein texte est déclaré sur un motif (`posts.*.content`) : le
+//! texte des chaînes qui y correspondent est découpé en termes (mis en
+//! minuscules, sans mots vides, réduits à leur racine), puis rangé dans
+//! un index inversé. Une recherche classe les chemins par score BM25.
+//!
+//! L'arbre de l'index contient trois sortes d'entrées, préfixées par
+//! l'identifiant de l'index :
+//! - `id | DOCUMENT_TAG | chemin` : les termes indexés d'un chemin, qui
+//!   permettent de retirer ses entrées quand sa valeur change ;
+//! - `id | POSTING_TAG | terme | 0x00 | chemin` : la fréquence du terme
+//!   dans le chemin et la longueur du chemin, en termes ;
+//! - `id | CORPUS_TAG` : le nombre de chemins indexés et leur longueur
+//!   totale, pour la longueur moyenne de BM25.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use bincode::{serialize, deserialize};
+use serde::{Serialize, Deserialize};
+use sled::Db;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use super::types::IndexImplementation;
+use super::tree::{IndexTree, TreeResult, abort, into_store_error};
+
+/// Préfixe des termes indexés des chemins
+const DOCUMENT_TAG: u8 = 0x00;
+/// Préfixe des entrées de l'index inversé
+const POSTING_TAG: u8 = 0x01;
+/// Clé des statistiques du corpus
+const CORPUS_TAG: u8 = 0x02;
+
+/// Saturation de la fréquence d'un terme (BM25)
+const K1: f64 = 1.2;
+/// Normalisation par la longueur des chemins (BM25)
+const B: f64 = 0.75;
+
+/// Mots ignorés à l'indexation et à la recherche
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from",
+    "has", "have", "in", "is", "it", "its", "of", "on", "or", "that", "the",
+    "this", "to", "was", "were", "will", "with",
+];
+
+/// Déclaration d'un index plein texte
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FullTextDefinition {
+    /// Identifiant de l'index, préfixe de ses clés
+    id: u32,
+    /// Motif des chemins indexés
+    pattern: Path,
+    /// Les données existantes sont indexées
+    ready: bool,
+}
+
+impl FullTextDefinition {
+    /// Début des clés de l'index
+    fn prefix(&self, tag: u8) -> Vec<u8> {
+        let mut prefix = self.id.to_be_bytes().to_vec();
+        prefix.push(tag);
+        prefix
+    }
+
+    /// Clé des termes indexés d'un chemin
+    fn document_key(&self, path: &[u8]) -> Vec<u8> {
+        let mut key = self.prefix(DOCUMENT_TAG);
+        key.extend_from_slice(path);
+        key
+    }
+
+    /// Début des entrées d'un terme
+    fn term_prefix(&self, term: &str) -> Vec<u8> {
+        let mut key = self.prefix(POSTING_TAG);
+        key.extend_from_slice(term.as_bytes());
+        key.push(0x00);
+        key
+    }
+
+    /// Clé de l'entrée d'un terme dans un chemin
+    fn posting_key(&self, term: &str, path: &[u8]) -> Vec<u8> {
+        let mut key = self.term_prefix(term);
+        key.extend_from_slice(path);
+        key
+    }
+}
+
+/// Termes indexés d'un chemin, avec leur fréquence
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Document {
+    terms: Vec<(String, u32)>,
+    length: u32,
+}
+
+/// Statistiques du corpus d'un index
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct Corpus {
+    /// Nombre de chemins indexés
+    documents: u64,
+    /// Nombre total de termes des chemins indexés
+    total_length: u64,
+}
+
+impl Corpus {
+    /// Score BM25 d'un terme dans un chemin
+    ///
+    /// `frequency` est le nombre d'occurrences du terme dans le chemin,
+    /// `length` la longueur du chemin et `document_frequency` le nombre de
+    /// chemins contenant le terme.
+    fn score(&self, frequency: u32, length: u32, document_frequency: usize) -> f64 {
+        let documents = self.documents as f64;
+        let document_frequency = document_frequency as f64;
+        let idf = (1.0 + (documents - document_frequency + 0.5) / (document_frequency + 0.5)).ln();
+
+        let average_length = match self.documents {
+            0 => 1.0,
+            documents => (self.total_length as f64 / documents as f64).max(1.0),
+        };
+        let frequency = frequency as f64;
+        let norm = K1 * (1.0 - B + B * length as f64 / average_length);
+
+        idf * frequency * (K1 + 1.0) / (frequency + norm)
+    }
+}
+
+/// Découpe un texte en termes indexables
+///
+/// Le texte est découpé sur tout ce qui n'est ni une lettre ni un
+/// chiffre, mis en minuscules ; les mots vides sont retirés et les
+/// autres réduits à leur racine.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+/// Réduit un mot anglais à sa racine, par quelques règles de suffixes
+/// (inspirées de l'algorithme de Porter)
+///
+/// Les formes d'un même mot (`database`, `databases`, `stored`,
+/// `storing`) donnent la même racine ; la racine n'est pas forcément un
+/// mot.
+fn stem(word: &str) -> String {
+    if !word.is_ascii() || word.len() <= 3 {
+        return word.to_string();
+    }
+
+    let has_vowel = |stem: &str| stem.chars().any(|c| "aeiouy".contains(c));
+    let mut stem = word.to_string();
+
+    // Pluriels
+    if stem.ends_with("sses") || stem.ends_with("ies") {
+        stem.truncate(stem.len() - 2);
+    } else if stem.ends_with('s') && !stem.ends_with("ss") {
+        stem.pop();
+    }
+
+    // Participes et formes en -ing
+    if stem.ends_with("eed") {
+        stem.pop();
+    } else {
+        for suffix in ["ing", "ed"] {
+            let base = &stem[..stem.len().saturating_sub(suffix.len())];
+            if stem.ends_with(suffix) && base.len() >= 3 && has_vowel(base) {
+                stem.truncate(base.len());
+
+                // running -> runn -> run
+                let bytes = stem.as_bytes();
+                let last = bytes[bytes.len() - 1];
+                if bytes[bytes.len() - 2] == last && !b"aeiouylsz".contains(&last) {
+                    stem.pop();
+                }
+                break;
+            }
+        }
+    }
+
+    // query -> queri, comme queries
+    if stem.ends_with('y') && has_vowel(&stem[..stem.len() - 1]) {
+        stem.pop();
+        stem.push('i');
+    }
+
+    // store -> stor, comme stored
+    if stem.ends_with('e') && stem.len() > 4 {
+        stem.pop();
+    }
+
+    stem
+}
+
+/// Compte les occurrences des termes d'un texte
+fn term_frequencies(terms: &[String]) -> BTreeMap<&str, u32> {
+    let mut frequencies = BTreeMap::new();
+    for term in terms {
+        *frequencies.entry(term.as_str()).or_insert(0) += 1;
+    }
+    frequencies
+}
+
+/// Termes distincts d'une recherche
+fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tokenize(query).into_iter().filter(|term| seen.insert(term.clone())).collect()
+}
+
+/// Garde les meilleurs scores, du plus haut au plus bas (partagé par la
+/// recherche de l'index et par `rank`)
+fn top(scores: HashMap<Path, f64>, limit: usize) -> Vec<(Path, f64)> {
+    let mut ranked: Vec<(Path, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|(left_path, left), (right_path, right)| {
+        right.partial_cmp(left).unwrap_or(Ordering::Equal).then_with(|| left_path.cmp(right_path))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Classe des chemins par score BM25 sans index, sur leur seul texte
+///
+/// Donne les mêmes scores qu'un index plein texte sur ces chemins : sert
+/// à l'évaluateur pour les recherches qu'aucun index prêt ne couvre.
+pub fn rank(documents: &[(Path, String)], query: &str, limit: usize) -> Vec<(Path, f64)> {
+    let documents: Vec<(&Path, Vec<String>)> = documents.iter()
+        .map(|(path, text)| (path, tokenize(text)))
+        .filter(|(_, terms)| !terms.is_empty())
+        .collect();
+    let corpus = Corpus {
+        documents: documents.len() as u64,
+        total_length: documents.iter().map(|(_, terms)| terms.len() as u64).sum(),
+    };
+    let frequencies: Vec<BTreeMap<&str, u32>> = documents.iter().map(|(_, terms)| term_frequencies(terms)).collect();
+
+    let mut scores = HashMap::new();
+    for term in query_terms(query) {
+        let document_frequency = frequencies.iter().filter(|frequencies| frequencies.contains_key(term.as_str())).count();
+
+        for ((path, terms), frequencies) in documents.iter().zip(&frequencies) {
+            if let Some(&frequency) = frequencies.get(term.as_str()) {
+                let score = corpus.score(frequency, terms.len() as u32, document_frequency);
+                *scores.entry((*path).clone()).or_insert(0.0) += score;
+            }
+        }
+    }
+
+    top(scores, limit)
+}
+
+/// Index inversé des termes des chaînes
+pub struct FullTextIndex {
+    /// La base de données sled
+    db: Arc<Db>,
+    /// Nom de l'arbre pour stocker l'index
+    index_tree_name: String,
+    /// Nom de l'arbre pour stocker les déclarations
+    metadata_tree_name: String,
+    /// Cache mémoire des index déclarés
+    definitions: Vec<FullTextDefinition>,
+}
+
+impl FullTextIndex {
+    /// Crée un nouvel index plein texte
+    pub fn new(db: Arc<Db>, base_name: &str) -> Result<Self> {
+        let mut index = FullTextIndex {
+            db,
+            index_tree_name: format!("{}_entries", base_name),
+            metadata_tree_name: format!("{}_metadata", base_name),
+            definitions: Vec::new(),
+        };
+
+        // Charger les index déclarés depuis le stockage
+        for item in index.get_metadata_tree()?.iter() {
+            let (_, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
+            let definition = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize definition: {}", e)))?;
+            index.definitions.push(definition);
+        }
+
+        Ok(index)
+    }
+
+    /// Obtient l'arbre pour l'index
+    fn get_index_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.index_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open full-text index tree: {}", e)))
+    }
+
+    /// Obtient l'arbre pour les déclarations
+    fn get_metadata_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.metadata_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open metadata tree: {}", e)))
+    }
+
+    /// Trouve la déclaration d'un index
+    fn definition(&self, pattern: &Path) -> Option<&FullTextDefinition> {
+        self.definitions.iter().find(|definition| definition.pattern == *pattern)
+    }
+
+    /// Enregistre une déclaration
+    fn write_definition(&mut self, definition: FullTextDefinition) -> Result<()> {
+        let tree = self.get_metadata_tree()?;
+        let data = serialize(&definition).map_err(|e| StoreError::Internal(format!("Failed to serialize definition: {}", e)))?;
+
+        tree.insert(definition.id.to_be_bytes(), data).map_err(|e| StoreError::Internal(format!("Failed to insert definition into metadata tree: {}", e)))?;
+        tree.flush().map_err(|e| StoreError::Internal(format!("Failed to flush metadata tree: {}", e)))?;
+
+        // Mettre à jour le cache
+        self.definitions.retain(|existing| existing.id != definition.id);
+        self.definitions.push(definition);
+
+        Ok(())
+    }
+
+    /// Déclare un index, en construction jusqu'à `mark_ready` : les
+    /// écritures sont indexées, mais l'index ne répond pas encore aux
+    /// recherches
+    pub fn begin_index(&mut self, pattern: &Path) -> Result<()> {
+        let id = self.definitions.iter().map(|definition| definition.id + 1).max().unwrap_or(0);
+        self.write_definition(FullTextDefinition {
+            id,
+            pattern: pattern.clone(),
+            ready: false,
+        })
+    }
+
+    /// Marque un index comme prêt, une fois les données existantes indexées
+    pub fn mark_ready(&mut self, pattern: &Path) -> Result<()> {
+        match self.definition(pattern) {
+            Some(definition) => {
+                let definition = FullTextDefinition { ready: true, ..definition.clone() };
+                self.write_definition(definition)
+            },
+            // L'index a pu être supprimé pendant la construction
+            None => Ok(()),
+        }
+    }
+
+    /// Supprime un index et ses entrées
+    pub fn remove_index(&mut self, pattern: &Path) -> Result<()> {
+        let id = match self.definition(pattern) {
+            Some(definition) => definition.id,
+            None => return Ok(()),
+        };
+
+        let metadata = self.get_metadata_tree()?;
+        metadata.remove(id.to_be_bytes()).map_err(|e| StoreError::Internal(format!("Failed to remove definition from metadata tree: {}", e)))?;
+        self.definitions.retain(|definition| definition.id != id);
+
+        let tree = self.get_index_tree()?;
+        for key in tree.scan_prefix(id.to_be_bytes()).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            tree.remove(key).map_err(|e| StoreError::Internal(format!("Failed to remove from index tree: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Liste les index déclarés : motif et état (prêt ou non)
+    pub fn indexes(&self) -> Vec<(Path, bool)> {
+        self.definitions.iter()
+            .map(|definition| (definition.pattern.clone(), definition.ready))
+            .collect()
+    }
+
+    /// Compte les chemins indexés par un index
+    pub fn count_documents(&self, pattern: &Path) -> Result<usize> {
+        let definition = match self.definition(pattern) {
+            Some(definition) => definition,
+            None => return Ok(0),
+        };
+
+        Ok(self.get_index_tree()?.scan_prefix(definition.prefix(DOCUMENT_TAG)).count())
+    }
+
+    /// Recherche les chemins d'un motif contenant les termes d'un texte,
+    /// classés par score BM25 décroissant
+    ///
+    /// Renvoie au plus `limit` chemins, ou `None` si aucun index prêt ne
+    /// couvre le motif.
+    pub fn search(&self, pattern: &Path, query: &str, limit: usize) -> Result<Option<Vec<(Path, f64)>>> {
+        let definition = self.definitions.iter()
+            .find(|definition| definition.ready && pattern.matches(&definition.pattern));
+        let definition = match definition {
+            Some(definition) => definition,
+            None => return Ok(None),
+        };
+
+        let tree = self.get_index_tree()?;
+        let corpus = Self::read_corpus(definition, &tree).map_err(into_store_error)?;
+        let mut scores = HashMap::new();
+
+        for term in query_terms(query) {
+            let prefix = definition.term_prefix(&term);
+            let mut postings = Vec::new();
+
+            for item in tree.scan_prefix(&prefix) {
+                let (key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+                let path: Path = deserialize(&key[prefix.len()..])
+                    .map_err(|e| StoreError::Internal(format!("Failed to deserialize path: {}", e)))?;
+                let (frequency, length) = decode_posting(&data)?;
+                postings.push((path, frequency, length));
+            }
+
+            // La fréquence documentaire porte sur tout l'index, pas
+            // seulement sur les chemins du motif recherché
+            let document_frequency = postings.len();
+            for (path, frequency, length) in postings {
+                if path.matches(pattern) {
+                    *scores.entry(path).or_insert(0.0) += corpus.score(frequency, length, document_frequency);
+                }
+            }
+        }
+
+        Ok(Some(top(scores, limit)))
+    }
+
+    /// Lit les statistiques du corpus d'un index
+    fn read_corpus(definition: &FullTextDefinition, tree: &dyn IndexTree) -> TreeResult<Corpus> {
+        match tree.get(&definition.prefix(CORPUS_TAG))? {
+            Some(data) => deserialize(&data).map_err(|e| abort(format!("Failed to deserialize corpus: {}", e))),
+            None => Ok(Corpus::default()),
+        }
+    }
+
+    /// Écrit les statistiques du corpus d'un index
+    fn write_corpus(definition: &FullTextDefinition, tree: &dyn IndexTree, corpus: Corpus) -> TreeResult<()> {
+        let key = definition.prefix(CORPUS_TAG);
+        if corpus.documents == 0 {
+            return tree.remove(&key);
+        }
+
+        let data = serialize(&corpus).map_err(|e| abort(format!("Failed to serialize corpus: {}", e)))?;
+        tree.insert(&key, data)
+    }
+
+    /// Indexe le texte d'un chemin, à la place de ses termes précédents
+    fn index_document(definition: &FullTextDefinition, tree: &dyn IndexTree, path: &Path, text: &str) -> TreeResult<()> {
+        Self::unindex_document(definition, tree, path)?;
+
+        let terms = tokenize(text);
+        if terms.is_empty() {
+            return Ok(());
+        }
+
+        let encoded = serialize(path).map_err(|e| abort(format!("Failed to serialize path: {}", e)))?;
+        let length = terms.len() as u32;
+        let frequencies = term_frequencies(&terms);
+
+        for (term, &frequency) in &frequencies {
+            tree.insert(&definition.posting_key(term, &encoded), encode_posting(frequency, length))?;
+        }
+
+        let document = Document {
+            terms: frequencies.into_iter().map(|(term, frequency)| (term.to_string(), frequency)).collect(),
+            length,
+        };
+        let data = serialize(&document).map_err(|e| abort(format!("Failed to serialize document: {}", e)))?;
+        tree.insert(&definition.document_key(&encoded), data)?;
+
+        let mut corpus = Self::read_corpus(definition, tree)?;
+        corpus.documents += 1;
+        corpus.total_length += length as u64;
+        Self::write_corpus(definition, tree, corpus)
+    }
+
+    /// Retire les termes indexés d'un chemin, s'il en a
+    fn unindex_document(definition: &FullTextDefinition, tree: &dyn IndexTree, path: &Path) -> TreeResult<()> {
+        let encoded = serialize(path).map_err(|e| abort(format!("Failed to serialize path: {}", e)))?;
+        let document_key = definition.document_key(&encoded);
+
+        let document: Document = match tree.get(&document_key)? {
+            Some(data) => deserialize(&data).map_err(|e| abort(format!("Failed to deserialize document: {}", e)))?,
+            None => return Ok(()),
+        };
+
+        for (term, _) in &document.terms {
+            tree.remove(&definition.posting_key(term, &encoded))?;
+        }
+        tree.remove(&document_key)?;
+
+        let mut corpus = Self::read_corpus(definition, tree)?;
+        corpus.documents = corpus.documents.saturating_sub(1);
+        corpus.total_length = corpus.total_length.saturating_sub(document.length as u64);
+        Self::write_corpus(definition, tree, corpus)
+    }
+}
+
+/// Encode la fréquence d'un terme et la longueur de son chemin
+fn encode_posting(frequency: u32, length: u32) -> Vec<u8> {
+    let mut data = frequency.to_be_bytes().to_vec();
+    data.extend_from_slice(&length.to_be_bytes());
+    data
+}
+
+/// Décode la fréquence d'un terme et la longueur de son chemin
+fn decode_posting(data: &[u8]) -> Result<(u32, u32)> {
+    if data.len() != 8 {
+        return Err(StoreError::DeserializationError("Invalid full-text index entry".to_string()));
+    }
+
+    let frequency = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    let length = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    Ok((frequency, length))
+}
+
+impl IndexImplementation for FullTextIndex {
+    fn add_path(&mut self, _path: &Path) -> Result<()> {
+        // Les termes ont besoin des valeurs
+        Ok(())
+    }
+
+    fn remove_path(&mut self, path: &Path) -> Result<()> {
+        let tree = self.get_index_tree()?;
+
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                Self::unindex_document(definition, &tree, path).map_err(into_store_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_by_prefix(&self, _prefix: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn find_by_pattern(&self, _pattern: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_index_tree()?])
+    }
+
+    fn indexes_values(&self) -> bool {
+        true
+    }
+
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        for definition in &self.definitions {
+            if !path.matches(&definition.pattern) {
+                continue;
+            }
+
+            // Seules les chaînes sont indexées ; une autre valeur remplace
+            // le texte du chemin
+            match value {
+                Value::String(text) => Self::index_document(definition, trees[0], path, text)?,
+                _ => Self::unindex_document(definition, trees[0], path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        // Les termes indexés du chemin sont relus : l'ancienne valeur
+        // n'est pas nécessaire
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                Self::unindex_document(definition, trees[0], path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        // Les déclarations sont conservées
+        self.get_index_tree()?.clear().map_err(|e| StoreError::Internal(format!("Failed to clear index tree: {}", e)))?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "FullTextIndex"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::index::{IndexDefinition, IndexKind};
+    use crate::core::index::testing::TestIndexes;
+
+    #[test]
+    fn test_tokenize_and_stem() {
+        assert_eq!(tokenize("The Databases, storing RUST trees!"), vec!["databas", "stor", "rust", "tree"]);
+        assert_eq!(tokenize("a database stored in a tree"), vec!["databas", "stor", "tree"]);
+        assert_eq!(tokenize("running queries"), tokenize("run query"));
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_by_bm25() {
+        let indexes = TestIndexes::new();
+        let path = |s: &str| Path::from_str(s).unwrap();
+        let pattern = path("posts.*.content");
+        let definition = IndexDefinition::new(pattern.clone(), IndexKind::FullText);
+        indexes.declare(&definition);
+
+        let posts = [
+            ("p1", "Rust is a systems language"),
+            ("p2", "A database written in Rust: the Rust database"),
+            ("p3", "Databases and indexes"),
+            ("p4", "Cooking with butter"),
+        ];
+        let mut documents = Vec::new();
+        for (id, text) in posts {
+            let content = format!("posts.{}.content", id);
+            indexes.write(&content, None, Some(Value::String(text.to_string())));
+            documents.push((path(&content), text.to_string()));
+        }
+        // Paths outside the pattern are not indexed
+        indexes.write("posts.p5.title", None, Some(Value::String("rust".to_string())));
+        assert_eq!(indexes.info(&definition).entries, 4);
+
+        let found = indexes.system.search(&pattern, "rust database", 20).unwrap().unwrap();
+        let paths: Vec<Path> = found.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(paths, vec![path("posts.p2.content"), path("posts.p3.content"), path("posts.p1.content")]);
+        assert!(found[0].1 > found[1].1 && found[1].1 > found[2].1);
+        // Scanning the texts gives the same ranking
+        assert_eq!(rank(&documents, "rust database", 20), found);
+
+        // A new value replaces the terms of the path
+        indexes.write("posts.p4.content", Some(Value::String("Cooking with butter".to_string())), Some(Value::String("Rust".to_string())));
+        let found = indexes.system.search(&pattern, "butter", 20).unwrap().unwrap();
+        assert!(found.is_empty());
+        let found = indexes.system.search(&pattern, "rust", 2).unwrap().unwrap();
+        assert_eq!(found[0].0, path("posts.p4.content"));
+        assert_eq!(found.len(), 2);
+
+        indexes.write("posts.p4.content", Some(Value::String("Rust".to_string())), None);
+        assert_eq!(indexes.info(&definition).entries, 3);
+        assert_eq!(indexes.system.search(&path("users.*.bio"), "rust", 20).unwrap(), None);
+    }
+}
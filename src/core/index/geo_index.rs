@@ -0,0 +1,625 @@
+// src/core/index/geo_index.rs
+//! Index géographique des points
+//!
+//! Un index géographique est déclaré sur un motif (`devices.*.location`) :
+//! chaque point qui y correspond est rangé sous son geohash, un entier de
+//! 64 bits qui entrelace les bits de sa longitude et de sa latitude. Des
+//! points proches partagent un long préfixe de geohash : une zone de
+//! recherche est couverte par quelques cellules, dont les entrées forment
+//! chacune un intervalle de clés contigu. Les points des cellules sont
+//! ensuite filtrés exactement.
+//!
+//! L'arbre de l'index contient deux sortes d'entrées, préfixées par
+//! l'identifiant de l'index :
+//! - `id | POINT_TAG | chemin` : le geohash du point d'un chemin, qui
+//!   permet de retirer son entrée quand sa valeur change ;
+//! - `id | CELL_TAG | geohash | chemin` : la latitude et la longitude du
+//!   point.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use bincode::{serialize, deserialize};
+use serde::{Serialize, Deserialize};
+use sled::Db;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use super::types::IndexImplementation;
+use super::tree::{IndexTree, TreeResult, abort, into_store_error};
+
+/// Préfixe des geohash des chemins
+const POINT_TAG: u8 = 0x00;
+/// Préfixe des entrées rangées par geohash
+const CELL_TAG: u8 = 0x01;
+
+/// Rayon moyen de la Terre, en mètres
+const EARTH_RADIUS: f64 = 6_371_008.8;
+/// Nombre maximal de cellules couvrant une zone de recherche
+const MAX_CELLS: u64 = 16;
+
+/// Distance entre deux points sur la sphère terrestre, en mètres
+/// (formule de haversine)
+pub fn distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let delta_phi = (lat2 - lat1).to_radians();
+    let delta_lambda = (lon2 - lon1).to_radians();
+
+    let a = (delta_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (delta_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// Zone d'une recherche géographique
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoArea {
+    /// Disque autour d'un point
+    Radius {
+        lat: f64,
+        lon: f64,
+        meters: f64,
+    },
+    /// Rectangle entre deux parallèles et deux méridiens ; il traverse
+    /// l'antiméridien quand `west` est plus grand que `east`
+    Box {
+        south: f64,
+        west: f64,
+        north: f64,
+        east: f64,
+    },
+}
+
+impl GeoArea {
+    /// Centre de la zone, d'où sont mesurées les distances des résultats
+    pub fn center(&self) -> (f64, f64) {
+        match *self {
+            GeoArea::Radius { lat, lon, .. } => (lat, lon),
+            GeoArea::Box { south, west, north, east } => {
+                let width = (east - west).rem_euclid(360.0);
+                let mut lon = west + width / 2.0;
+                if lon > 180.0 {
+                    lon -= 360.0;
+                }
+                ((south + north) / 2.0, lon)
+            },
+        }
+    }
+
+    /// Indique si un point est dans la zone
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match *self {
+            GeoArea::Radius { lat: center_lat, lon: center_lon, meters } => {
+                distance(center_lat, center_lon, lat, lon) <= meters
+            },
+            GeoArea::Box { south, west, north, east } => {
+                let within_lon = if west <= east {
+                    west <= lon && lon <= east
+                } else {
+                    lon >= west || lon <= east
+                };
+                south <= lat && lat <= north && within_lon
+            },
+        }
+    }
+
+    /// Distance d'un point au centre de la zone, en mètres
+    pub fn distance_from_center(&self, lat: f64, lon: f64) -> f64 {
+        let (center_lat, center_lon) = self.center();
+        distance(center_lat, center_lon, lat, lon)
+    }
+
+    /// Rectangles (sud, ouest, nord, est) couvrant la zone, sans traverser
+    /// l'antiméridien
+    fn bounds(&self) -> Vec<(f64, f64, f64, f64)> {
+        let (south, west, north, east) = match *self {
+            GeoArea::Radius { lat, lon, meters } => {
+                let delta_lat = (meters / EARTH_RADIUS).to_degrees();
+                let south = lat - delta_lat;
+                let north = lat + delta_lat;
+
+                // Près d'un pôle, le disque couvre toutes les longitudes
+                let delta_lon = (delta_lat / lat.to_radians().cos()).abs();
+                if south <= -90.0 || north >= 90.0 || delta_lon >= 180.0 {
+                    return vec![(south.max(-90.0), -180.0, north.min(90.0), 180.0)];
+                }
+
+                let wrap = |lon: f64| if lon < -180.0 { lon + 360.0 } else if lon > 180.0 { lon - 360.0 } else { lon };
+                (south, wrap(lon - delta_lon), north, wrap(lon + delta_lon))
+            },
+            GeoArea::Box { south, west, north, east } => (south, west, north, east),
+        };
+
+        if west <= east {
+            vec![(south, west, north, east)]
+        } else {
+            vec![(south, west, north, 180.0), (south, -180.0, north, east)]
+        }
+    }
+}
+
+/// Ramène une latitude sur 32 bits
+fn quantize_lat(lat: f64) -> u64 {
+    (((lat + 90.0) / 180.0 * 4_294_967_296.0) as u64).min(u32::MAX as u64)
+}
+
+/// Ramène une longitude sur 32 bits
+fn quantize_lon(lon: f64) -> u64 {
+    (((lon + 180.0) / 360.0 * 4_294_967_296.0) as u64).min(u32::MAX as u64)
+}
+
+/// Entrelace les bits d'une latitude et d'une longitude quantifiées, en
+/// commençant par la longitude
+fn interleave(lat: u64, lon: u64) -> u64 {
+    let mut hash = 0;
+    for bit in 0..32 {
+        hash |= ((lat >> bit) & 1) << (2 * bit);
+        hash |= ((lon >> bit) & 1) << (2 * bit + 1);
+    }
+    hash
+}
+
+/// Calcule le geohash d'un point
+pub fn geohash(lat: f64, lon: f64) -> u64 {
+    interleave(quantize_lat(lat), quantize_lon(lon))
+}
+
+/// Calcule les intervalles de geohash (bornes incluses) des cellules
+/// couvrant un rectangle
+///
+/// Les cellules sont les plus petites possibles tant qu'il n'en faut pas
+/// plus de `MAX_CELLS`.
+fn cover(south: f64, west: f64, north: f64, east: f64) -> Vec<(u64, u64)> {
+    let (south, north) = (quantize_lat(south.max(-90.0)), quantize_lat(north.min(90.0)));
+    let (west, east) = (quantize_lon(west), quantize_lon(east));
+
+    // Nombre de bits de chaque coordonnée retenus par les cellules
+    let cells = |bits: u32| {
+        let shift = 32 - bits;
+        ((north >> shift) - (south >> shift) + 1) * ((east >> shift) - (west >> shift) + 1)
+    };
+    let bits = (0..=32).rev().find(|&bits| cells(bits) <= MAX_CELLS).unwrap_or(0);
+    let shift = 32 - bits;
+    let span = u64::MAX.checked_shr(2 * bits).unwrap_or(0);
+
+    let mut ranges = Vec::new();
+    for lat in (south >> shift)..=(north >> shift) {
+        for lon in (west >> shift)..=(east >> shift) {
+            let start = interleave(lat << shift, lon << shift);
+            ranges.push((start, start | span));
+        }
+    }
+    ranges
+}
+
+/// Garde les points d'une zone, du plus proche au plus lointain de son
+/// centre, avec leur distance
+fn nearest(points: Vec<(Path, f64, f64)>, area: &GeoArea) -> Vec<(Path, f64)> {
+    let mut found: Vec<(Path, f64)> = points.into_iter()
+        .filter(|(_, lat, lon)| area.contains(*lat, *lon))
+        .map(|(path, lat, lon)| (path, area.distance_from_center(lat, lon)))
+        .collect();
+    found.sort_by(|(left_path, left), (right_path, right)| {
+        left.partial_cmp(right).unwrap_or(Ordering::Equal).then_with(|| left_path.cmp(right_path))
+    });
+    found
+}
+
+/// Trouve les points d'une zone sans index, parmi des points donnés
+///
+/// Donne les mêmes résultats qu'un index géographique sur ces points :
+/// sert aux recherches qu'aucun index prêt ne couvre.
+pub fn filter(points: &[(Path, f64, f64)], area: &GeoArea) -> Vec<(Path, f64)> {
+    nearest(points.to_vec(), area)
+}
+
+/// Déclaration d'un index géographique
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeoDefinition {
+    /// Identifiant de l'index, préfixe de ses clés
+    id: u32,
+    /// Motif des chemins indexés
+    pattern: Path,
+    /// Les données existantes sont indexées
+    ready: bool,
+}
+
+impl GeoDefinition {
+    /// Début des clés de l'index
+    fn prefix(&self, tag: u8) -> Vec<u8> {
+        let mut prefix = self.id.to_be_bytes().to_vec();
+        prefix.push(tag);
+        prefix
+    }
+
+    /// Clé du geohash d'un chemin
+    fn point_key(&self, path: &[u8]) -> Vec<u8> {
+        let mut key = self.prefix(POINT_TAG);
+        key.extend_from_slice(path);
+        key
+    }
+
+    /// Début des entrées d'un geohash
+    fn cell_prefix(&self, hash: u64) -> Vec<u8> {
+        let mut key = self.prefix(CELL_TAG);
+        key.extend_from_slice(&hash.to_be_bytes());
+        key
+    }
+
+    /// Clé de l'entrée d'un chemin sous son geohash
+    fn cell_key(&self, hash: u64, path: &[u8]) -> Vec<u8> {
+        let mut key = self.cell_prefix(hash);
+        key.extend_from_slice(path);
+        key
+    }
+}
+
+/// Index des points géographiques
+pub struct GeoIndex {
+    /// La base de données sled
+    db: Arc<Db>,
+    /// Nom de l'arbre pour stocker l'index
+    index_tree_name: String,
+    /// Nom de l'arbre pour stocker les déclarations
+    metadata_tree_name: String,
+    /// Cache mémoire des index déclarés
+    definitions: Vec<GeoDefinition>,
+}
+
+impl GeoIndex {
+    /// Crée un nouvel index géographique
+    pub fn new(db: Arc<Db>, base_name: &str) -> Result<Self> {
+        let mut index = GeoIndex {
+            db,
+            index_tree_name: format!("{}_entries", base_name),
+            metadata_tree_name: format!("{}_metadata", base_name),
+            definitions: Vec::new(),
+        };
+
+        // Charger les index déclarés depuis le stockage
+        for item in index.get_metadata_tree()?.iter() {
+            let (_, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
+            let definition = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize definition: {}", e)))?;
+            index.definitions.push(definition);
+        }
+
+        Ok(index)
+    }
+
+    /// Obtient l'arbre pour l'index
+    fn get_index_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.index_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open geo index tree: {}", e)))
+    }
+
+    /// Obtient l'arbre pour les déclarations
+    fn get_metadata_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.metadata_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open metadata tree: {}", e)))
+    }
+
+    /// Trouve la déclaration d'un index
+    fn definition(&self, pattern: &Path) -> Option<&GeoDefinition> {
+        self.definitions.iter().find(|definition| definition.pattern == *pattern)
+    }
+
+    /// Enregistre une déclaration
+    fn write_definition(&mut self, definition: GeoDefinition) -> Result<()> {
+        let tree = self.get_metadata_tree()?;
+        let data = serialize(&definition).map_err(|e| StoreError::Internal(format!("Failed to serialize definition: {}", e)))?;
+
+        tree.insert(definition.id.to_be_bytes(), data).map_err(|e| StoreError::Internal(format!("Failed to insert definition into metadata tree: {}", e)))?;
+        tree.flush().map_err(|e| StoreError::Internal(format!("Failed to flush metadata tree: {}", e)))?;
+
+        // Mettre à jour le cache
+        self.definitions.retain(|existing| existing.id != definition.id);
+        self.definitions.push(definition);
+
+        Ok(())
+    }
+
+    /// Déclare un index, en construction jusqu'à `mark_ready` : les
+    /// écritures sont indexées, mais l'index ne répond pas encore aux
+    /// recherches
+    pub fn begin_index(&mut self, pattern: &Path) -> Result<()> {
+        let id = self.definitions.iter().map(|definition| definition.id + 1).max().unwrap_or(0);
+        self.write_definition(GeoDefinition {
+            id,
+            pattern: pattern.clone(),
+            ready: false,
+        })
+    }
+
+    /// Marque un index comme prêt, une fois les données existantes indexées
+    pub fn mark_ready(&mut self, pattern: &Path) -> Result<()> {
+        match self.definition(pattern) {
+            Some(definition) => {
+                let definition = GeoDefinition { ready: true, ..definition.clone() };
+                self.write_definition(definition)
+            },
+            // L'index a pu être supprimé pendant la construction
+            None => Ok(()),
+        }
+    }
+
+    /// Supprime un index et ses entrées
+    pub fn remove_index(&mut self, pattern: &Path) -> Result<()> {
+        let id = match self.definition(pattern) {
+            Some(definition) => definition.id,
+            None => return Ok(()),
+        };
+
+        let metadata = self.get_metadata_tree()?;
+        metadata.remove(id.to_be_bytes()).map_err(|e| StoreError::Internal(format!("Failed to remove definition from metadata tree: {}", e)))?;
+        self.definitions.retain(|definition| definition.id != id);
+
+        let tree = self.get_index_tree()?;
+        for key in tree.scan_prefix(id.to_be_bytes()).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            tree.remove(key).map_err(|e| StoreError::Internal(format!("Failed to remove from index tree: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Liste les index déclarés : motif et état (prêt ou non)
+    pub fn indexes(&self) -> Vec<(Path, bool)> {
+        self.definitions.iter()
+            .map(|definition| (definition.pattern.clone(), definition.ready))
+            .collect()
+    }
+
+    /// Compte les points d'un index
+    pub fn count_points(&self, pattern: &Path) -> Result<usize> {
+        let definition = match self.definition(pattern) {
+            Some(definition) => definition,
+            None => return Ok(0),
+        };
+
+        Ok(self.get_index_tree()?.scan_prefix(definition.prefix(POINT_TAG)).count())
+    }
+
+    /// Trouve les points d'une zone parmi les chemins d'un motif, du plus
+    /// proche au plus lointain du centre de la zone, avec leur distance
+    /// en mètres
+    ///
+    /// Renvoie `None` si aucun index prêt ne couvre le motif.
+    pub fn find(&self, pattern: &Path, area: &GeoArea) -> Result<Option<Vec<(Path, f64)>>> {
+        let definition = self.definitions.iter()
+            .find(|definition| definition.ready && pattern.matches(&definition.pattern));
+        let definition = match definition {
+            Some(definition) => definition,
+            None => return Ok(None),
+        };
+
+        let tree = self.get_index_tree()?;
+        let cells = definition.prefix(CELL_TAG);
+        let header = cells.len();
+        let mut seen = HashSet::new();
+        let mut points = Vec::new();
+
+        for (south, west, north, east) in area.bounds() {
+            for (start, end) in cover(south, west, north, east) {
+                for item in tree.range(definition.cell_prefix(start)..) {
+                    let (key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+                    if !key.starts_with(&cells) {
+                        break;
+                    }
+
+                    let hash = u64::from_be_bytes(key[header..header + 8].try_into().unwrap());
+                    if hash > end {
+                        break;
+                    }
+
+                    let path: Path = deserialize(&key[header + 8..])
+                        .map_err(|e| StoreError::Internal(format!("Failed to deserialize path: {}", e)))?;
+                    if path.matches(pattern) && seen.insert(path.clone()) {
+                        let (lat, lon) = decode_point(&data)?;
+                        points.push((path, lat, lon));
+                    }
+                }
+            }
+        }
+
+        Ok(Some(nearest(points, area)))
+    }
+
+    /// Indexe le point d'un chemin, à la place de son point précédent
+    fn index_point(definition: &GeoDefinition, tree: &dyn IndexTree, path: &Path, lat: f64, lon: f64) -> TreeResult<()> {
+        Self::unindex_point(definition, tree, path)?;
+
+        let encoded = serialize(path).map_err(|e| abort(format!("Failed to serialize path: {}", e)))?;
+        let hash = geohash(lat, lon);
+        tree.insert(&definition.cell_key(hash, &encoded), encode_point(lat, lon))?;
+        tree.insert(&definition.point_key(&encoded), hash.to_be_bytes().to_vec())
+    }
+
+    /// Retire le point indexé d'un chemin, s'il en a un
+    fn unindex_point(definition: &GeoDefinition, tree: &dyn IndexTree, path: &Path) -> TreeResult<()> {
+        let encoded = serialize(path).map_err(|e| abort(format!("Failed to serialize path: {}", e)))?;
+        let point_key = definition.point_key(&encoded);
+
+        let hash = match tree.get(&point_key)? {
+            Some(data) => u64::from_be_bytes(data.as_ref().try_into()
+                .map_err(|_| abort("Invalid geo index entry".to_string()))?),
+            None => return Ok(()),
+        };
+
+        tree.remove(&definition.cell_key(hash, &encoded))?;
+        tree.remove(&point_key)
+    }
+}
+
+/// Encode la latitude et la longitude d'un point
+fn encode_point(lat: f64, lon: f64) -> Vec<u8> {
+    let mut data = lat.to_be_bytes().to_vec();
+    data.extend_from_slice(&lon.to_be_bytes());
+    data
+}
+
+/// Décode la latitude et la longitude d'un point
+fn decode_point(data: &[u8]) -> Result<(f64, f64)> {
+    if data.len() != 16 {
+        return Err(StoreError::DeserializationError("Invalid geo index entry".to_string()));
+    }
+
+    let lat = f64::from_be_bytes(data[..8].try_into().unwrap());
+    let lon = f64::from_be_bytes(data[8..].try_into().unwrap());
+    Ok((lat, lon))
+}
+
+impl IndexImplementation for GeoIndex {
+    fn add_path(&mut self, _path: &Path) -> Result<()> {
+        // Les points ont besoin des valeurs
+        Ok(())
+    }
+
+    fn remove_path(&mut self, path: &Path) -> Result<()> {
+        let tree = self.get_index_tree()?;
+
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                Self::unindex_point(definition, &tree, path).map_err(into_store_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_by_prefix(&self, _prefix: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn find_by_pattern(&self, _pattern: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_index_tree()?])
+    }
+
+    fn indexes_values(&self) -> bool {
+        true
+    }
+
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        for definition in &self.definitions {
+            if !path.matches(&definition.pattern) {
+                continue;
+            }
+
+            // Seuls les points sont indexés ; une autre valeur retire le
+            // point du chemin
+            match value {
+                Value::GeoPoint { lat, lon } => Self::index_point(definition, trees[0], path, *lat, *lon)?,
+                _ => Self::unindex_point(definition, trees[0], path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        // Le geohash du chemin est relu : l'ancienne valeur n'est pas
+        // nécessaire
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                Self::unindex_point(definition, trees[0], path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        // Les déclarations sont conservées
+        self.get_index_tree()?.clear().map_err(|e| StoreError::Internal(format!("Failed to clear index tree: {}", e)))?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "GeoIndex"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::index::{IndexDefinition, IndexKind};
+    use crate::core::index::testing::TestIndexes;
+
+    #[test]
+    fn test_distance_and_areas() {
+        // Paris to London
+        let meters = distance(48.8566, 2.3522, 51.5074, -0.1278);
+        assert!((meters - 343_500.0).abs() < 1_000.0);
+
+        let area = GeoArea::Box { south: -10.0, west: 170.0, north: 10.0, east: -170.0 };
+        assert!(area.contains(0.0, 179.5) && area.contains(0.0, -179.5));
+        assert!(!area.contains(0.0, 0.0));
+        assert_eq!(area.center(), (0.0, 180.0));
+        assert_eq!(area.bounds().len(), 2);
+
+        // Near a pole, a radius covers every longitude
+        let area = GeoArea::Radius { lat: 89.9, lon: 0.0, meters: 50_000.0 };
+        let bounds = area.bounds();
+        assert_eq!(bounds.len(), 1);
+        assert_eq!((bounds[0].1, bounds[0].2, bounds[0].3), (-180.0, 90.0, 180.0));
+        assert!(area.contains(89.9, 180.0));
+    }
+
+    #[tokio::test]
+    async fn test_find_within_areas() {
+        let indexes = TestIndexes::new();
+        let path = |s: &str| Path::from_str(s).unwrap();
+        let pattern = path("devices.*.location");
+        let definition = IndexDefinition::new(pattern.clone(), IndexKind::Geo);
+        indexes.declare(&definition);
+
+        let devices = [
+            ("paris", 48.8566, 2.3522),
+            ("versailles", 48.8049, 2.1204),
+            ("lyon", 45.7640, 4.8357),
+            ("london", 51.5074, -0.1278),
+            ("suva", -18.1416, 178.4419),
+            ("apia", -13.8333, -171.7500),
+        ];
+        let mut points = Vec::new();
+        for (id, lat, lon) in devices {
+            let location = format!("devices.{}.location", id);
+            indexes.write(&location, None, Some(Value::GeoPoint { lat, lon }));
+            points.push((path(&location), lat, lon));
+        }
+        // Other values are not indexed
+        indexes.write("devices.tv.location", None, Some(Value::String("Paris".to_string())));
+        assert_eq!(indexes.info(&definition).entries, 6);
+
+        let area = GeoArea::Radius { lat: 48.8566, lon: 2.3522, meters: 400_000.0 };
+        let found = indexes.system.find_in_area(&pattern, &area).unwrap().unwrap();
+        let paths: Vec<Path> = found.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(paths, vec![path("devices.paris.location"), path("devices.versailles.location"),
+            path("devices.london.location"), path("devices.lyon.location")]);
+        assert!(found[1].1 > 15_000.0 && found[1].1 < 20_000.0);
+        // Filtering the points gives the same results
+        assert_eq!(filter(&points, &area), found);
+
+        // A box across the antimeridian
+        let area = GeoArea::Box { south: -20.0, west: 175.0, north: -10.0, east: -170.0 };
+        let found = indexes.system.find_in_area(&pattern, &area).unwrap().unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(filter(&points, &area), found);
+
+        // A moved point leaves its previous cell
+        indexes.write("devices.paris.location", Some(Value::GeoPoint { lat: 48.8566, lon: 2.3522 }),
+            Some(Value::GeoPoint { lat: -15.0, lon: 179.0 }));
+        let area = GeoArea::Radius { lat: 48.8566, lon: 2.3522, meters: 20_000.0 };
+        let found = indexes.system.find_in_area(&pattern, &area).unwrap().unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, path("devices.versailles.location"));
+
+        indexes.write("devices.versailles.location", Some(Value::GeoPoint { lat: 48.8049, lon: 2.1204 }), None);
+        assert_eq!(indexes.info(&definition).entries, 5);
+        assert_eq!(indexes.system.find_in_area(&path("users.*.home"), &area).unwrap(), None);
+    }
+}
@@ -0,0 +1,298 @@
+// src/core/index/hnsw.rs
+//! Graphe HNSW (Hierarchical Navigable Small World) pour la recherche
+//! approximative des plus proches vecteurs
+//!
+//! Chaque vecteur est un nœud présent sur les couches 0 à son niveau ;
+//! les couches hautes, plus clairsemées, mènent rapidement vers la région
+//! du vecteur cherché, que la couche 0 explore ensuite. Le niveau d'un
+//! nœud est tiré d'un hachage de son chemin : le même ensemble de
+//! chemins, inséré dans le même ordre, donne le même graphe.
+//!
+//! Le graphe vit en mémoire. Un nœud supprimé est seulement marqué : il
+//! sert encore à la navigation mais n'apparaît plus dans les résultats,
+//! et le graphe est à reconstruire quand les suppressions s'accumulent.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::core::path::Path;
+use super::vector_index::VectorMetric;
+
+/// Nombre de voisins d'un nœud sur les couches hautes
+const M: usize = 16;
+/// Nombre de voisins d'un nœud sur la couche 0
+const M0: usize = 2 * M;
+/// Largeur de la recherche des voisins d'un nouveau nœud
+const EF_CONSTRUCTION: usize = 100;
+
+/// Nœud candidat, ordonné par distance au vecteur cherché
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Nœud du graphe
+struct Node {
+    path: Path,
+    vector: Vec<f32>,
+    /// Voisins sur chaque couche, de 0 au niveau du nœud
+    links: Vec<Vec<usize>>,
+    /// Le chemin a été supprimé ou a changé de vecteur
+    deleted: bool,
+}
+
+/// Graphe HNSW des vecteurs d'un index
+pub struct Hnsw {
+    metric: VectorMetric,
+    nodes: Vec<Node>,
+    /// Nœud courant de chaque chemin
+    ids: HashMap<Path, usize>,
+    /// Point d'entrée, sur la couche la plus haute
+    entry: Option<usize>,
+}
+
+impl Hnsw {
+    /// Crée un graphe vide
+    pub fn new(metric: VectorMetric) -> Self {
+        Hnsw {
+            metric,
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+        }
+    }
+
+    /// Nombre de vecteurs du graphe
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Indique si le graphe ne contient aucun vecteur
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Indique si les nœuds supprimés sont plus nombreux que les autres :
+    /// les recherches y perdent du temps, le graphe est à reconstruire
+    pub fn is_degraded(&self) -> bool {
+        self.nodes.len() - self.ids.len() > self.ids.len().max(M)
+    }
+
+    /// Tire le niveau d'un chemin, selon une loi géométrique de raison 1/M
+    fn level_of(path: &Path) -> usize {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        let uniform = ((hasher.finish() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        (-uniform.ln() / (M as f64).ln()) as usize
+    }
+
+    fn distance(&self, query: &[f32], node: usize) -> f32 {
+        self.metric.distance(query, &self.nodes[node].vector)
+    }
+
+    /// Ajoute ou remplace le vecteur d'un chemin
+    pub fn insert(&mut self, path: Path, vector: Vec<f32>) {
+        // Une transaction rejouée réécrit le même vecteur
+        if let Some(&id) = self.ids.get(&path) {
+            if self.nodes[id].vector == vector {
+                return;
+            }
+        }
+        self.remove(&path);
+
+        let level = Self::level_of(&path);
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            path: path.clone(),
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(path, id);
+
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => {
+                self.entry = Some(id);
+                return;
+            },
+        };
+
+        let query = self.nodes[id].vector.clone();
+        let top = self.nodes[entry].links.len() - 1;
+        let mut nearest = Candidate { distance: self.distance(&query, entry), node: entry };
+        for layer in (level + 1..=top).rev() {
+            nearest = self.greedy(&query, nearest, layer);
+        }
+
+        let mut entry_points = vec![nearest];
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &entry_points, EF_CONSTRUCTION, layer);
+
+            // Les nœuds supprimés guident la recherche, mais ne deviennent
+            // pas des voisins
+            let neighbours: Vec<usize> = candidates.iter()
+                .filter(|candidate| !self.nodes[candidate.node].deleted)
+                .take(M)
+                .map(|candidate| candidate.node)
+                .collect();
+            let capacity = if layer == 0 { M0 } else { M };
+            for &neighbour in &neighbours {
+                self.link(neighbour, id, layer, capacity);
+            }
+            self.nodes[id].links[layer] = neighbours;
+
+            entry_points = candidates;
+        }
+
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    /// Retire le vecteur d'un chemin des résultats
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(id) = self.ids.remove(path) {
+            self.nodes[id].deleted = true;
+        }
+    }
+
+    /// Ajoute un voisin à un nœud, en ne gardant que ses voisins les plus
+    /// proches au-delà de la capacité de la couche
+    fn link(&mut self, from: usize, to: usize, layer: usize, capacity: usize) {
+        self.nodes[from].links[layer].push(to);
+        if self.nodes[from].links[layer].len() <= capacity {
+            return;
+        }
+
+        let vector = &self.nodes[from].vector;
+        let mut scored: Vec<Candidate> = self.nodes[from].links[layer].iter()
+            .map(|&node| Candidate { distance: self.metric.distance(vector, &self.nodes[node].vector), node })
+            .collect();
+        scored.sort();
+        scored.truncate(capacity);
+        self.nodes[from].links[layer] = scored.into_iter().map(|candidate| candidate.node).collect();
+    }
+
+    /// Descend vers le nœud le plus proche d'une couche, de voisin en voisin
+    fn greedy(&self, query: &[f32], start: Candidate, layer: usize) -> Candidate {
+        let mut nearest = start;
+        loop {
+            let mut improved = false;
+            for &neighbour in &self.nodes[nearest.node].links[layer] {
+                let distance = self.distance(query, neighbour);
+                if distance < nearest.distance {
+                    nearest = Candidate { distance, node: neighbour };
+                    improved = true;
+                }
+            }
+            if !improved {
+                return nearest;
+            }
+        }
+    }
+
+    /// Cherche les `ef` nœuds les plus proches sur une couche, à partir de
+    /// points d'entrée, triés du plus proche au plus lointain
+    fn search_layer(&self, query: &[f32], entry_points: &[Candidate], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().map(|candidate| candidate.node).collect();
+        let mut candidates: BinaryHeap<Reverse<Candidate>> = entry_points.iter().copied().map(Reverse).collect();
+        let mut results: BinaryHeap<Candidate> = entry_points.iter().copied().collect();
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let furthest = results.peek().map_or(f32::INFINITY, |candidate| candidate.distance);
+            if current.distance > furthest && results.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.nodes[current.node].links[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+
+                let distance = self.distance(query, neighbour);
+                let furthest = results.peek().map_or(f32::INFINITY, |candidate| candidate.distance);
+                if results.len() < ef || distance < furthest {
+                    let candidate = Candidate { distance, node: neighbour };
+                    candidates.push(Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Cherche les `k` chemins dont les vecteurs sont les plus proches,
+    /// avec leur distance, en explorant `ef` candidats sur la couche 0
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(Path, f32)> {
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+
+        let top = self.nodes[entry].links.len() - 1;
+        let mut nearest = Candidate { distance: self.distance(query, entry), node: entry };
+        for layer in (1..=top).rev() {
+            nearest = self.greedy(query, nearest, layer);
+        }
+
+        self.search_layer(query, &[nearest], ef.max(k), 0)
+            .into_iter()
+            .filter(|candidate| !self.nodes[candidate.node].deleted)
+            .take(k)
+            .map(|candidate| (self.nodes[candidate.node].path.clone(), candidate.distance))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_finds_nearest_neighbours() {
+        let path = |i: usize| Path::from_str(&format!("points.p-{}.position", i)).unwrap();
+        let point = |i: usize| vec![(i % 40) as f32, (i / 40) as f32];
+
+        // A 40 x 40 grid of points
+        let mut graph = Hnsw::new(VectorMetric::Euclidean);
+        for i in 0..1600 {
+            graph.insert(path(i), point(i));
+        }
+        assert_eq!(graph.len(), 1600);
+
+        let found = graph.search(&[10.2, 20.3], 5, 50);
+        let nearest: Vec<Path> = found.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(nearest, [810, 850, 811, 851, 809].map(path));
+
+        // Removed and moved points leave the results
+        graph.remove(&path(810));
+        graph.insert(path(811), vec![100.0, 100.0]);
+        let found = graph.search(&[10.2, 20.3], 3, 50);
+        assert!(found.iter().all(|(found, _)| *found != path(810) && *found != path(811)));
+        assert_eq!(graph.search(&[99.0, 99.0], 1, 50)[0].0, path(811));
+        assert!(!graph.is_degraded());
+    }
+}
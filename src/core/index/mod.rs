@@ -4,16 +4,36 @@ pub mod worker;
 pub mod prefix_index;
 pub mod wildcard_index;
 pub mod value_index;
+pub mod state;
+pub mod tree;
+pub mod definition;
+pub mod composite_index;
+pub mod fulltext_index;
+pub mod hnsw;
+pub mod vector_index;
+pub mod geo_index;
+#[cfg(test)]
+mod testing;
 
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::core::path::Path;
 use crate::core::errors::{Result, StoreError};
-pub use types::{IndexImplementation, IndexOp, IndexStats};
+pub use types::{IndexImplementation, IndexOp, IndexStats, IndexConsistency, IndexReport, ValueRange};
+pub use definition::{IndexDefinition, IndexKind, IndexStatus, IndexInfo};
+pub use vector_index::VectorQuery;
+pub use geo_index::GeoArea;
 use value_index::ValueIndex;
+use composite_index::CompositeIndex;
+use fulltext_index::FullTextIndex;
+use vector_index::VectorIndex;
+use geo_index::GeoIndex;
 use worker::IndexWorker;
 use prefix_index::PrefixIndex;
 use wildcard_index::WildcardIndex;
+use state::IndexState;
+use tree::{IndexTree, MemoryTree, TreeResult, into_store_error};
 
 use crate::core::value::Value;
 
@@ -25,8 +45,22 @@ pub struct IndexSystem {
     wildcard_index: Arc<Mutex<WildcardIndex>>,
     /// Index par valeur
     value_index: Arc<Mutex<ValueIndex>>,
+    /// Index composites
+    composite_index: Arc<Mutex<CompositeIndex>>,
+    /// Index plein texte
+    fulltext_index: Arc<Mutex<FullTextIndex>>,
+    /// Index des vecteurs
+    vector_index: Arc<Mutex<VectorIndex>>,
+    /// Index géographiques
+    geo_index: Arc<Mutex<GeoIndex>>,
     /// Worker pour les opérations asynchrones,
     worker: IndexWorker,
+    /// Watermark et journal des écritures à indexer
+    state: Arc<IndexState>,
+    /// Arbres sled de chaque index, dans l'ordre de `implementations`
+    index_trees: Vec<Vec<sled::Tree>>,
+    /// Progression des index en construction (chemins indexés, total)
+    builds: Arc<Mutex<HashMap<IndexDefinition, (usize, usize)>>>,
 }
 
 impl IndexSystem {
@@ -36,12 +70,31 @@ impl IndexSystem {
         let prefix_index = Arc::new(Mutex::new(PrefixIndex::new(db.clone(), "prefix_index")?));
         let wildcard_index = Arc::new(Mutex::new(WildcardIndex::new(db.clone(), "wildcard_index")?));
         let value_index = Arc::new(Mutex::new(ValueIndex::new(db.clone(), "value_index")?));
+        let composite_index = Arc::new(Mutex::new(CompositeIndex::new(db.clone(), "composite_index")?));
+        let fulltext_index = Arc::new(Mutex::new(FullTextIndex::new(db.clone(), "fulltext_index")?));
+        let vector_index = Arc::new(Mutex::new(VectorIndex::new(db.clone(), "vector_index")?));
+        let geo_index = Arc::new(Mutex::new(GeoIndex::new(db.clone(), "geo_index")?));
+        let state = Arc::new(IndexState::open(&db)?);
+        let index_trees = vec![
+            prefix_index.lock().unwrap().trees()?,
+            wildcard_index.lock().unwrap().trees()?,
+            value_index.lock().unwrap().trees()?,
+            composite_index.lock().unwrap().trees()?,
+            fulltext_index.lock().unwrap().trees()?,
+            vector_index.lock().unwrap().trees()?,
+            geo_index.lock().unwrap().trees()?,
+        ];
         
         // Créer et configurer le worker
         let mut worker = IndexWorker::new();
+        worker.set_state(state.clone());
         worker.add_index(prefix_index.clone())?;
         worker.add_index(wildcard_index.clone())?;
         worker.add_index(value_index.clone())?;
+        worker.add_index(composite_index.clone())?;
+        worker.add_index(fulltext_index.clone())?;
+        worker.add_index(vector_index.clone())?;
+        worker.add_index(geo_index.clone())?;
         
         // Démarrer le worker une fois que tous les index sont ajoutés
         worker.start()?;
@@ -50,11 +103,167 @@ impl IndexSystem {
             prefix_index,
             wildcard_index,
             value_index,
+            composite_index,
+            fulltext_index,
+            vector_index,
+            geo_index,
             worker,
-            
+            state,
+            index_trees,
+            builds: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+    
+    /// Obtient tous les index, dans un ordre fixe
+    fn implementations(&self) -> [Arc<Mutex<dyn IndexImplementation>>; 7] {
+        [
+            self.prefix_index.clone(),
+            self.wildcard_index.clone(),
+            self.value_index.clone(),
+            self.composite_index.clone(),
+            self.fulltext_index.clone(),
+            self.vector_index.clone(),
+            self.geo_index.clone(),
+        ]
+    }
+    
+    /// Obtient les arbres sled de tous les index, à inclure dans une
+    /// transaction avec `IndexWriter::stage_write`
+    pub fn trees(&self) -> Vec<&sled::Tree> {
+        self.index_trees.iter().flatten().collect()
+    }
+    
+    /// Verrouille les index pour écrire dans une transaction sled
+    ///
+    /// À appeler avant la transaction : le worker ne modifie pas les
+    /// index tant que l'`IndexWriter` existe.
+    pub fn writer(&self) -> Result<IndexWriter<'_>> {
+        Ok(IndexWriter {
+            prefix_index: self.prefix_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock prefix index".to_string()))?,
+            wildcard_index: self.wildcard_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock wildcard index".to_string()))?,
+            value_index: self.value_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?,
+            composite_index: self.composite_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?,
+            fulltext_index: self.fulltext_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?,
+            vector_index: self.vector_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?,
+            geo_index: self.geo_index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?,
+            index_trees: &self.index_trees,
         })
     }
     
+    /// Vérifie que les index correspondent aux données, et les répare
+    ///
+    /// Le contenu attendu de chaque index est reconstruit en mémoire à
+    /// partir de tous les chemins des données, puis comparé aux arbres :
+    /// les entrées manquantes ou erronées sont réécrites, les entrées en
+    /// trop supprimées. En mode `Eventual`, les mises à jour encore en
+    /// attente dans le worker sont comptées comme des divergences.
+    ///
+    /// `endpoints` ouvre un nouveau parcours des données à chaque appel :
+    /// elles sont relues pour chaque index plutôt que chargées en mémoire.
+    pub fn verify<F, I>(&self, endpoints: F) -> Result<IndexReport>
+    where
+        F: Fn() -> I,
+        I: Iterator<Item = Result<(Path, Value)>>,
+    {
+        let mut report = IndexReport::default();
+        
+        for (index, index_trees) in self.implementations().iter().zip(&self.index_trees) {
+            // Le worker ne modifie pas l'index pendant la comparaison
+            let index = index.lock()
+                .map_err(|_| StoreError::Internal("Failed to lock index".to_string()))?;
+            
+            let expected: Vec<MemoryTree> = index_trees.iter().map(|_| MemoryTree::new()).collect();
+            let views: Vec<&dyn IndexTree> = expected.iter().map(|tree| tree as &dyn IndexTree).collect();
+            let mut checked_paths = 0;
+            for endpoint in endpoints() {
+                let (path, value) = endpoint?;
+                index.stage_add(&views, &path, &value).map_err(into_store_error)?;
+                checked_paths += 1;
+            }
+            report.checked_paths = checked_paths;
+            
+            for (tree, expected) in index_trees.iter().zip(expected) {
+                let mut expected = expected.into_entries();
+                let mut repairs = Vec::new();
+                
+                for item in tree.iter() {
+                    let (key, actual) = item
+                        .map_err(|e| StoreError::Internal(format!("Failed to iterate index: {}", e)))?;
+                    
+                    match expected.remove(&*key) {
+                        Some(entry) if *entry == *actual => {},
+                        Some(entry) => {
+                            report.missing_entries += 1;
+                            repairs.push((key.to_vec(), Some(entry)));
+                        },
+                        None => {
+                            report.stale_entries += 1;
+                            repairs.push((key.to_vec(), None));
+                        },
+                    }
+                }
+                
+                report.missing_entries += expected.len();
+                repairs.extend(expected.into_iter().map(|(key, entry)| (key, Some(entry))));
+                
+                for (key, entry) in repairs {
+                    match entry {
+                        Some(entry) => IndexTree::insert(tree, &key, entry),
+                        None => IndexTree::remove(tree, &key),
+                    }.map_err(into_store_error)?;
+                }
+                
+                tree.flush()
+                    .map_err(|e| StoreError::Internal(format!("Failed to flush index: {}", e)))?;
+            }
+            
+            log::debug!("IndexSystem: Verified {}", index.name());
+        }
+        
+        Ok(report)
+    }
+    
+    /// Obtient le watermark et le journal des index
+    pub fn state(&self) -> &IndexState {
+        &self.state
+    }
+    
+    /// Envoie une opération au worker sans attendre, dans l'ordre d'envoi
+    pub fn submit(&self, op: IndexOp) -> Result<()> {
+        self.worker.submit(op)
+    }
+    
+    /// Vide tous les index, avant une reconstruction complète
+    pub fn clear(&self) -> Result<()> {
+        self.prefix_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock prefix index".to_string()))?
+            .clear()?;
+        self.wildcard_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock wildcard index".to_string()))?
+            .clear()?;
+        self.value_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?
+            .clear()?;
+        self.fulltext_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?
+            .clear()?;
+        self.vector_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?
+            .clear()?;
+        self.geo_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?
+            .clear()?;
+        
+        Ok(())
+    }
+    
     /// Ajoute un chemin aux index de manière asynchrone
     pub async fn add_path(&self, path: Path) -> Result<()> {
         println!("\nIndexSystem: Adding path: {:?}", path);
@@ -75,16 +284,23 @@ impl IndexSystem {
         Ok(())
     }
     
-    /// Force le traitement de toutes les opérations en attente
+    /// Force le traitement de toutes les opérations en attente, et attend
+    /// qu'elles soient appliquées
     pub async fn flush(&self) -> Result<()> {
         println!("IndexSystem: Flushing");
         
         // Envoyer l'opération de flush au worker
         self.worker.submit_operation(IndexOp::Flush).await?;
+        self.worker.wait_processed().await?;
         
         Ok(())
     }
     
+    /// Indique si le worker a appliqué toutes les opérations envoyées
+    pub fn is_idle(&self) -> bool {
+        self.worker.is_idle()
+    }
+    
     /// Trouve tous les chemins correspondant à un préfixe
     pub fn find_by_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
         println!("IndexSystem: Finding by prefix: {:?}", prefix);
@@ -120,6 +336,214 @@ impl IndexSystem {
         Ok(wildcard_results)
     }
     
+    /// Déclare un index secondaire
+    ///
+    /// Les écritures sont indexées dès maintenant, mais l'index ne répond
+    /// aux requêtes qu'après `finish_build`, une fois les données
+    /// existantes indexées. Renvoie `false` si l'index existe déjà.
+    pub fn create_index(&self, definition: &IndexDefinition) -> Result<bool> {
+        if self.indexes()?.iter().any(|info| info.definition == *definition) {
+            return Ok(false);
+        }
+        
+        match &definition.kind {
+            IndexKind::Value => {
+                let mut index = self.value_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?;
+                index.begin_indexed_pattern(&definition.pattern)?;
+            },
+            IndexKind::Composite(fields) => {
+                let mut index = self.composite_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?;
+                index.begin_index(&definition.pattern, fields)?;
+            },
+            IndexKind::FullText => {
+                let mut index = self.fulltext_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?;
+                index.begin_index(&definition.pattern)?;
+            },
+            IndexKind::Vector { metric, approximate } => {
+                let mut index = self.vector_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?;
+                index.begin_index(&definition.pattern, *metric, *approximate)?;
+            },
+            IndexKind::Geo => {
+                let mut index = self.geo_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?;
+                index.begin_index(&definition.pattern)?;
+            },
+        }
+        
+        self.set_build_progress(definition, 0, 0);
+        Ok(true)
+    }
+    
+    /// Supprime un index secondaire et ses entrées
+    ///
+    /// Renvoie `false` si l'index n'existe pas.
+    pub fn drop_index(&self, definition: &IndexDefinition) -> Result<bool> {
+        if !self.indexes()?.iter().any(|info| info.definition == *definition) {
+            return Ok(false);
+        }
+        
+        match &definition.kind {
+            IndexKind::Value => {
+                let mut index = self.value_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?;
+                index.remove_indexed_pattern(&definition.pattern)?;
+            },
+            IndexKind::Composite(fields) => {
+                let mut index = self.composite_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?;
+                index.remove_index(&definition.pattern, fields)?;
+            },
+            IndexKind::FullText => {
+                let mut index = self.fulltext_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?;
+                index.remove_index(&definition.pattern)?;
+            },
+            IndexKind::Vector { .. } => {
+                let mut index = self.vector_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?;
+                index.remove_index(&definition.pattern)?;
+            },
+            IndexKind::Geo => {
+                let mut index = self.geo_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?;
+                index.remove_index(&definition.pattern)?;
+            },
+        }
+        
+        if let Ok(mut builds) = self.builds.lock() {
+            builds.remove(definition);
+        }
+        Ok(true)
+    }
+    
+    /// Liste les index secondaires, avec leur état et leur taille
+    pub fn indexes(&self) -> Result<Vec<IndexInfo>> {
+        let value_index = self.value_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?;
+        let composite_index = self.composite_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?;
+        let fulltext_index = self.fulltext_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?;
+        let vector_index = self.vector_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?;
+        let geo_index = self.geo_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?;
+        let builds = self.builds.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock index builds".to_string()))?;
+        
+        let status = |definition: &IndexDefinition, ready: bool| {
+            if ready {
+                IndexStatus::Ready
+            } else {
+                let (indexed, total) = builds.get(definition).copied().unwrap_or((0, 0));
+                IndexStatus::Building { indexed, total }
+            }
+        };
+        
+        let mut infos = Vec::new();
+        for (pattern, ready) in value_index.indexed_patterns() {
+            let definition = IndexDefinition::new(pattern, IndexKind::Value);
+            let entries = value_index.count_paths(&definition.pattern)?;
+            infos.push(IndexInfo { status: status(&definition, ready), definition, entries });
+        }
+        for (pattern, fields, ready) in composite_index.indexes() {
+            let entries = composite_index.count_entities(&pattern, &fields)?;
+            let definition = IndexDefinition::new(pattern, IndexKind::Composite(fields));
+            infos.push(IndexInfo { status: status(&definition, ready), definition, entries });
+        }
+        for (pattern, ready) in fulltext_index.indexes() {
+            let entries = fulltext_index.count_documents(&pattern)?;
+            let definition = IndexDefinition::new(pattern, IndexKind::FullText);
+            infos.push(IndexInfo { status: status(&definition, ready), definition, entries });
+        }
+        for (pattern, metric, approximate, ready) in vector_index.indexes() {
+            let entries = vector_index.count_vectors(&pattern)?;
+            let definition = IndexDefinition::new(pattern, IndexKind::Vector { metric, approximate });
+            infos.push(IndexInfo { status: status(&definition, ready), definition, entries });
+        }
+        for (pattern, ready) in geo_index.indexes() {
+            let entries = geo_index.count_points(&pattern)?;
+            let definition = IndexDefinition::new(pattern, IndexKind::Geo);
+            infos.push(IndexInfo { status: status(&definition, ready), definition, entries });
+        }
+        
+        infos.sort_by_key(|info| info.definition.to_string());
+        Ok(infos)
+    }
+    
+    /// Liste les index dont la construction n'est pas terminée (par
+    /// exemple interrompue par un arrêt)
+    pub fn pending_builds(&self) -> Result<Vec<IndexDefinition>> {
+        Ok(self.indexes()?
+            .into_iter()
+            .filter(|info| info.status != IndexStatus::Ready)
+            .map(|info| info.definition)
+            .collect())
+    }
+    
+    /// Obtient les arbres sled d'un type d'index, à inclure dans une
+    /// transaction avec `IndexWriter::stage_build`
+    pub fn build_trees(&self, kind: &IndexKind) -> Vec<&sled::Tree> {
+        match kind {
+            IndexKind::Value => self.index_trees[2].iter().collect(),
+            IndexKind::Composite(_) => self.index_trees[3].iter().collect(),
+            IndexKind::FullText => self.index_trees[4].iter().collect(),
+            IndexKind::Vector { .. } => self.index_trees[5].iter().collect(),
+            IndexKind::Geo => self.index_trees[6].iter().collect(),
+        }
+    }
+    
+    /// Enregistre la progression de la construction d'un index
+    pub fn set_build_progress(&self, definition: &IndexDefinition, indexed: usize, total: usize) {
+        if let Ok(mut builds) = self.builds.lock() {
+            builds.insert(definition.clone(), (indexed, total));
+        }
+    }
+    
+    /// Marque un index comme prêt, une fois les données existantes indexées
+    pub fn finish_build(&self, definition: &IndexDefinition) -> Result<()> {
+        match &definition.kind {
+            IndexKind::Value => {
+                let mut index = self.value_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?;
+                
+                // L'index a pu être supprimé pendant la construction
+                if index.indexed_patterns().iter().any(|(pattern, _)| *pattern == definition.pattern) {
+                    index.mark_pattern_ready(&definition.pattern)?;
+                }
+            },
+            IndexKind::Composite(fields) => {
+                let mut index = self.composite_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?;
+                index.mark_ready(&definition.pattern, fields)?;
+            },
+            IndexKind::FullText => {
+                let mut index = self.fulltext_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?;
+                index.mark_ready(&definition.pattern)?;
+            },
+            IndexKind::Vector { .. } => {
+                let mut index = self.vector_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?;
+                index.mark_ready(&definition.pattern)?;
+            },
+            IndexKind::Geo => {
+                let mut index = self.geo_index.lock()
+                    .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?;
+                index.mark_ready(&definition.pattern)?;
+            },
+        }
+        
+        if let Ok(mut builds) = self.builds.lock() {
+            builds.remove(definition);
+        }
+        Ok(())
+    }
+    
     /// Ajoute un pattern à indexer par valeur
     pub fn add_value_indexed_pattern(&self, pattern: &Path) -> Result<()> {
         if let Ok(mut index) = self.value_index.lock() {
@@ -149,6 +573,72 @@ impl IndexSystem {
         }
     }
 
+    /// Recherche par intervalle de valeurs, parmi les chemins d'un motif
+    ///
+    /// Renvoie `None` si l'index de valeurs ne couvre pas tous les chemins
+    /// du motif.
+    pub fn find_by_range(&self, pattern: &Path, range: &ValueRange) -> Result<Option<Vec<Path>>> {
+        let index = self.value_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock value index".to_string()))?;
+        
+        if !index.covers(pattern) {
+            return Ok(None);
+        }
+        
+        let paths = index.find_by_range(range)?
+            .into_iter()
+            .filter(|path| path.matches(pattern))
+            .collect();
+        Ok(Some(paths))
+    }
+
+    /// Trouve les entités d'un motif par un index composite : égalités
+    /// sur les premiers champs de l'index, et intervalle éventuel sur le
+    /// champ suivant
+    ///
+    /// Les entités sont triées par le champ de l'intervalle. Renvoie `None`
+    /// si aucun index composite prêt ne commence par ces champs.
+    pub fn find_by_fields(
+        &self,
+        pattern: &Path,
+        equals: &[(Path, Value)],
+        range: Option<(&Path, &ValueRange)>,
+    ) -> Result<Option<Vec<Path>>> {
+        let index = self.composite_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock composite index".to_string()))?;
+        index.find(pattern, equals, range)
+    }
+
+    /// Recherche plein texte parmi les chemins d'un motif, classés par
+    /// score BM25 décroissant
+    ///
+    /// Renvoie `None` si aucun index plein texte prêt ne couvre le motif.
+    pub fn search(&self, pattern: &Path, query: &str, limit: usize) -> Result<Option<Vec<(Path, f64)>>> {
+        let index = self.fulltext_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock full-text index".to_string()))?;
+        index.search(pattern, query, limit)
+    }
+
+    /// Recherche des vecteurs les plus proches parmi les chemins d'un
+    /// motif, classés par similarité décroissante
+    ///
+    /// Renvoie `None` si aucun index de vecteurs prêt ne couvre le motif.
+    pub fn search_vectors(&self, pattern: &Path, query: &VectorQuery) -> Result<Option<Vec<(Path, f64)>>> {
+        let index = self.vector_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock vector index".to_string()))?;
+        index.search(pattern, query)
+    }
+
+    /// Trouve les points d'une zone parmi les chemins d'un motif, du plus
+    /// proche au plus lointain de son centre, avec leur distance en mètres
+    ///
+    /// Renvoie `None` si aucun index géographique prêt ne couvre le motif.
+    pub fn find_in_area(&self, pattern: &Path, area: &GeoArea) -> Result<Option<Vec<(Path, f64)>>> {
+        let index = self.geo_index.lock()
+            .map_err(|_| StoreError::Internal("Failed to lock geo index".to_string()))?;
+        index.find(pattern, area)
+    }
+
     /// Recherche par condition
     pub fn find_by_condition(&self, operator: &str, value: &Value) -> Result<Vec<Path>> {
         if let Ok(index) = self.value_index.lock() {
@@ -186,7 +676,90 @@ impl Clone for IndexSystem {
             prefix_index: Arc::clone(&self.prefix_index),
             wildcard_index: Arc::clone(&self.wildcard_index),
             value_index: Arc::clone(&self.value_index),
+            composite_index: Arc::clone(&self.composite_index),
+            fulltext_index: Arc::clone(&self.fulltext_index),
+            vector_index: Arc::clone(&self.vector_index),
+            geo_index: Arc::clone(&self.geo_index),
             worker: self.worker.clone(),
+            state: Arc::clone(&self.state),
+            index_trees: self.index_trees.clone(),
+            builds: Arc::clone(&self.builds),
+        }
+    }
+}
+
+/// Index verrouillés, dont les écritures sont préparées dans une
+/// transaction sled
+///
+/// Une transaction sled bloque tous les accès à la base jusqu'à sa fin :
+/// les verrous des index sont donc pris avant elle, jamais pendant, sans
+/// quoi elle attendrait un thread qui attend lui-même la base.
+pub struct IndexWriter<'a> {
+    prefix_index: MutexGuard<'a, PrefixIndex>,
+    wildcard_index: MutexGuard<'a, WildcardIndex>,
+    value_index: MutexGuard<'a, ValueIndex>,
+    composite_index: MutexGuard<'a, CompositeIndex>,
+    fulltext_index: MutexGuard<'a, FullTextIndex>,
+    vector_index: MutexGuard<'a, VectorIndex>,
+    geo_index: MutexGuard<'a, GeoIndex>,
+    index_trees: &'a [Vec<sled::Tree>],
+}
+
+impl IndexWriter<'_> {
+    /// Applique une écriture à tous les index
+    ///
+    /// `trees` contient les arbres de `IndexSystem::trees()`, dans le même
+    /// ordre. `old` est la valeur remplacée, `new` la valeur écrite
+    /// (`None` pour une suppression).
+    pub fn stage_write(
+        &self,
+        trees: &[&dyn IndexTree],
+        path: &Path,
+        old: Option<&Value>,
+        new: Option<&Value>,
+    ) -> TreeResult<()> {
+        let indexes: [&dyn IndexImplementation; 7] = [
+            &*self.prefix_index,
+            &*self.wildcard_index,
+            &*self.value_index,
+            &*self.composite_index,
+            &*self.fulltext_index,
+            &*self.vector_index,
+            &*self.geo_index,
+        ];
+        
+        let mut offset = 0;
+        for (index, index_trees) in indexes.iter().zip(self.index_trees) {
+            let views = &trees[offset..offset + index_trees.len()];
+            offset += index_trees.len();
+            
+            if let Some(old) = old {
+                index.stage_remove(views, path, old)?;
+            }
+            if let Some(new) = new {
+                index.stage_add(views, path, new)?;
+            }
         }
+        
+        Ok(())
     }
-}
\ No newline at end of file
+    
+    /// Indexe un chemin existant pour la construction d'un index
+    ///
+    /// `trees` contient les arbres de `IndexSystem::build_trees`.
+    pub fn stage_build(
+        &self,
+        definition: &IndexDefinition,
+        trees: &[&dyn IndexTree],
+        path: &Path,
+        value: &Value,
+    ) -> TreeResult<()> {
+        match definition.kind {
+            IndexKind::Value => self.value_index.stage_add(trees, path, value),
+            IndexKind::Composite(_) => self.composite_index.stage_add(trees, path, value),
+            IndexKind::FullText => self.fulltext_index.stage_add(trees, path, value),
+            IndexKind::Vector { .. } => self.vector_index.stage_add(trees, path, value),
+            IndexKind::Geo => self.geo_index.stage_add(trees, path, value),
+        }
+    }
+}
@@ -2,10 +2,13 @@
 use std::sync::Arc;
 use sled::Db;
 use bincode::{serialize, deserialize};
+use sled::transaction::ConflictableTransactionError;
 
 use crate::core::path::Path;
+use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
 use super::types::IndexImplementation;
+use super::tree::{IndexTree, TreeResult, into_store_error};
 
 /// Index optimisé pour les recherches par préfixe
 pub struct PrefixIndex {
@@ -54,19 +57,8 @@ impl IndexImplementation for PrefixIndex {
         println!("PrefixIndex: Adding path: {:?}", path);
         let tree = self.get_tree()?;
         
-        // Créer la clé avec notre format textuel
-        let key = Self::create_index_key(path)?;
-        
-        // La valeur reste le chemin sérialisé
-        let value = serialize(path).map_err(|e| 
-            StoreError::SerializationError(e.to_string())
-        )?;
-        
-        println!("PrefixIndex: Inserting key: {}", String::from_utf8_lossy(&key));
-        
-        tree.insert(key, value).map_err(|e| 
-            StoreError::Internal(format!("Failed to insert into index: {}", e))
-        )?;
+        // La valeur ne compte pas pour cet index
+        self.stage_add(&[&tree], path, &Value::Null).map_err(into_store_error)?;
         
         // Assurer la persistance
         tree.flush().map_err(|e| 
@@ -79,10 +71,7 @@ impl IndexImplementation for PrefixIndex {
 
     fn remove_path(&mut self, path: &Path) -> Result<()> {
         let tree = self.get_tree()?;
-        let key = Self::create_index_key(path)?;
-        
-        tree.remove(key)
-        .map_err(|e| StoreError::Internal(format!("Failed to remove from index: {}", e)))?;
+        self.stage_remove(&[&tree], path, &Value::Null).map_err(into_store_error)?;
         
         // Assurons-nous que les modifications sont persistées
         tree.flush()
@@ -91,6 +80,28 @@ impl IndexImplementation for PrefixIndex {
         Ok(())
     }
     
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_tree()?])
+    }
+    
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        // Créer la clé avec notre format textuel
+        let key = Self::create_index_key(path).map_err(ConflictableTransactionError::Abort)?;
+        
+        // La valeur reste le chemin sérialisé
+        let value = serialize(path).map_err(|e| 
+            ConflictableTransactionError::Abort(StoreError::SerializationError(e.to_string()))
+        )?;
+        
+        println!("PrefixIndex: Inserting key: {}", String::from_utf8_lossy(&key));
+        trees[0].insert(&key, value)
+    }
+    
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        let key = Self::create_index_key(path).map_err(ConflictableTransactionError::Abort)?;
+        trees[0].remove(&key)
+    }
+    
     fn find_by_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
         println!("PrefixIndex: Finding by prefix: {:?}", prefix);
         let tree = self.get_tree()?;
@@ -99,17 +110,11 @@ impl IndexImplementation for PrefixIndex {
         let start_key = Self::create_index_key(prefix)?;
         println!("Start key: {}", String::from_utf8_lossy(&start_key));
         
-        // Pour la recherche par plage, on ajoute un séparateur à la fin
-        let mut end_key_bound = start_key.clone();
-        end_key_bound.push(b':');  // Ajouter le séparateur ':'
-        end_key_bound.push(0xFF);  // Ajouter un byte qui est après tous les caractères normaux
-        
-        println!("End key bound: {:?}", end_key_bound);
-        
         let mut results = Vec::new();
         
-        // Scan toutes les clés dans la plage
-        for item in tree.range(start_key.clone()..end_key_bound) {
+        // Les clés qui commencent par la clé du préfixe incluent aussi des
+        // voisins (users:u-10 pour users:u-1), d'où le filtre sur les chemins
+        for item in tree.scan_prefix(&start_key) {
             let (key, value) = item.map_err(|e| 
                 StoreError::Internal(format!("Failed to scan index: {}", e))
             )?;
@@ -117,17 +122,11 @@ impl IndexImplementation for PrefixIndex {
             println!("Found key in range: {}", String::from_utf8_lossy(&key));
             
             // Désérialiser la valeur pour obtenir le chemin
-            let path = deserialize(&value).map_err(|e| 
+            let path: Path = deserialize(&value).map_err(|e| 
                 StoreError::DeserializationError(e.to_string())
             )?;
             
-            results.push(path);
-        }
-        
-        // Vérifier aussi une correspondance exacte
-        if let Some(value) = tree.get(&start_key).map_err(|e| StoreError::Internal(format!("Failed to get from index: {}", e)))? {
-            let path = deserialize(&value).map_err(|e| StoreError::DeserializationError(e.to_string()))?;
-            if !results.contains(&path) {
+            if path.starts_with(prefix) {
                 results.push(path);
             }
         }
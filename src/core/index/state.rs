@@ -0,0 +1,168 @@
+// src/core/index/state.rs
+//! État durable des index persistants
+//!
+//! Chaque écriture du store ajoute une entrée au journal des index
+//! (numéro de séquence -> clé du chemin), dans la même transaction sled
+//! que les données. Le worker enregistre ensuite un « watermark », la
+//! dernière séquence appliquée aux index, et purge le journal jusqu'à
+//! elle. À l'ouverture, seules les entrées au-delà du watermark sont
+//! rejouées ; une reconstruction complète n'a lieu que si le format des
+//! index a changé ou si l'état est illisible.
+
+use sled::{Db, IVec};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::core::errors::{Result, StoreError};
+
+/// Version du format des arbres d'index
+///
+/// À incrémenter à chaque changement du contenu des index : les bases
+/// marquées avec une autre version sont réindexées à l'ouverture.
+/// Version 2 : ensembles de chemins triés. Version 3 : clés de valeurs
+/// ordonnées.
+pub const INDEX_FORMAT_VERSION: u32 = 3;
+
+/// Clé de la version du format dans l'arbre d'état
+const FORMAT_KEY: &[u8] = b"format";
+/// Clé du watermark dans l'arbre d'état
+const WATERMARK_KEY: &[u8] = b"watermark";
+
+/// Watermark et journal des index
+pub struct IndexState {
+    /// Version du format et watermark
+    state: sled::Tree,
+    /// Écritures pas encore appliquées aux index, par séquence
+    journal: sled::Tree,
+}
+
+impl IndexState {
+    /// Ouvre les arbres d'état des index
+    pub fn open(db: &Db) -> Result<Self> {
+        let state = db.open_tree("index_state")
+            .map_err(|e| StoreError::Internal(format!("Failed to open index state tree: {}", e)))?;
+        let journal = db.open_tree("index_journal")
+            .map_err(|e| StoreError::Internal(format!("Failed to open index journal tree: {}", e)))?;
+
+        Ok(IndexState { state, journal })
+    }
+
+    /// Obtient l'arbre du journal, écrit dans la transaction des données
+    pub fn journal(&self) -> &sled::Tree {
+        &self.journal
+    }
+
+    /// Ajoute une écriture au journal, dans une transaction de données
+    pub fn record(
+        journal: &TransactionalTree,
+        sequence: u64,
+        key: &[u8],
+    ) -> std::result::Result<(), ConflictableTransactionError<StoreError>> {
+        journal.insert(&sequence.to_be_bytes(), key)?;
+        Ok(())
+    }
+
+    /// Vérifie que les index ont été construits avec le format actuel
+    pub fn is_current(&self) -> Result<bool> {
+        let format = self.state.get(FORMAT_KEY)
+            .map_err(|e| StoreError::Internal(format!("Failed to read index state: {}", e)))?;
+
+        Ok(format.is_some_and(|bytes| *bytes == INDEX_FORMAT_VERSION.to_be_bytes()))
+    }
+
+    /// Lit la dernière séquence appliquée aux index
+    pub fn watermark(&self) -> Result<u64> {
+        let watermark = self.state.get(WATERMARK_KEY)
+            .map_err(|e| StoreError::Internal(format!("Failed to read index state: {}", e)))?;
+
+        match watermark {
+            Some(bytes) => decode_sequence(&bytes),
+            None => Ok(0),
+        }
+    }
+
+    /// Liste les écritures du journal postérieures au watermark, dans l'ordre
+    pub fn pending(&self) -> Result<Vec<(u64, IVec)>> {
+        let start = self.watermark()?.saturating_add(1);
+        let mut pending = Vec::new();
+
+        for item in self.journal.range(start.to_be_bytes()..) {
+            let (sequence, key) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read index journal: {}", e)))?;
+            pending.push((decode_sequence(&sequence)?, key));
+        }
+
+        Ok(pending)
+    }
+
+    /// Marque les index comme invalides avant une reconstruction, pour
+    /// qu'une reconstruction interrompue soit reprise à l'ouverture suivante
+    pub fn invalidate(&self) -> Result<()> {
+        self.state.remove(FORMAT_KEY)
+            .map_err(|e| StoreError::Internal(format!("Failed to write index state: {}", e)))?;
+        self.state.flush()
+            .map_err(|e| StoreError::Internal(format!("Failed to flush index state: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Enregistre que les écritures jusqu'à une séquence sont indexées
+    ///
+    /// Le watermark ne recule jamais, et les entrées du journal qu'il
+    /// couvre sont supprimées.
+    pub fn advance(&self, sequence: u64) -> Result<()> {
+        let watermark = self.watermark()?.max(sequence);
+
+        self.state.insert(WATERMARK_KEY, &watermark.to_be_bytes())
+            .map_err(|e| StoreError::Internal(format!("Failed to write index state: {}", e)))?;
+        self.state.insert(FORMAT_KEY, &INDEX_FORMAT_VERSION.to_be_bytes())
+            .map_err(|e| StoreError::Internal(format!("Failed to write index state: {}", e)))?;
+
+        for item in self.journal.range(..=watermark.to_be_bytes()) {
+            let (sequence, _) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read index journal: {}", e)))?;
+            self.journal.remove(sequence)
+                .map_err(|e| StoreError::Internal(format!("Failed to prune index journal: {}", e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Décode un numéro de séquence
+fn decode_sequence(bytes: &[u8]) -> Result<u64> {
+    let raw: [u8; 8] = bytes.try_into()
+        .map_err(|_| StoreError::DeserializationError("Invalid index sequence".to_string()))?;
+
+    Ok(u64::from_be_bytes(raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sled::Transactional;
+
+    #[test]
+    fn test_watermark_prunes_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let state = IndexState::open(&db).unwrap();
+        assert!(!state.is_current().unwrap());
+
+        for sequence in 1..=3u64 {
+            (state.journal(),).transaction(|(journal,)| {
+                IndexState::record(journal, sequence, b"key")
+            }).unwrap();
+        }
+
+        state.advance(2).unwrap();
+        assert!(state.is_current().unwrap());
+        assert_eq!(state.pending().unwrap().iter().map(|(s, _)| *s).collect::<Vec<_>>(), vec![3]);
+
+        // The watermark never goes back
+        state.advance(1).unwrap();
+        assert_eq!(state.watermark().unwrap(), 2);
+
+        state.invalidate().unwrap();
+        assert!(!state.is_current().unwrap());
+    }
+}
@@ -0,0 +1,62 @@
+//! Système d'indexation sur une base temporaire, partagé par les tests
+//! des index
+
+use std::sync::Arc;
+
+use tempfile::TempDir;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use super::{IndexDefinition, IndexInfo, IndexSystem};
+use super::tree::IndexTree;
+
+/// Système d'indexation ouvert sur une base sled temporaire
+///
+/// Le worker du système est lancé sur le runtime tokio courant : les
+/// tests qui l'utilisent sont des `#[tokio::test]`.
+pub struct TestIndexes {
+    /// Système d'indexation testé
+    pub system: IndexSystem,
+    /// Répertoire de la base, supprimé avec le système
+    _dir: TempDir,
+}
+
+impl TestIndexes {
+    /// Ouvre un système d'indexation sur une base vide
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(sled::open(dir.path()).unwrap());
+        let system = IndexSystem::new(db).unwrap();
+
+        TestIndexes { system, _dir: dir }
+    }
+
+    /// Déclare un index, prêt à répondre aux requêtes
+    pub fn declare(&self, definition: &IndexDefinition) {
+        assert!(self.system.create_index(definition).unwrap());
+        self.system.finish_build(definition).unwrap();
+    }
+
+    /// Applique une écriture à tous les index, comme une écriture
+    /// synchrone du store : `old` est la valeur remplacée, `new` la valeur
+    /// écrite (`None` pour une suppression)
+    pub fn write(&self, path: &str, old: Option<Value>, new: Option<Value>) {
+        let path: Path = path.parse().unwrap();
+        let trees: Vec<&dyn IndexTree> = self.system.trees()
+            .into_iter()
+            .map(|tree| tree as &dyn IndexTree)
+            .collect();
+
+        self.system.writer().unwrap()
+            .stage_write(&trees, &path, old.as_ref(), new.as_ref())
+            .unwrap();
+    }
+
+    /// Lit l'état d'un index déclaré
+    pub fn info(&self, definition: &IndexDefinition) -> IndexInfo {
+        self.system.indexes().unwrap()
+            .into_iter()
+            .find(|info| info.definition == *definition)
+            .unwrap()
+    }
+}
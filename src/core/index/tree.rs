@@ -0,0 +1,117 @@
+// src/core/index/tree.rs
+//! Arbres sur lesquels les index écrivent leurs entrées
+//!
+//! Un index décrit ses écritures une seule fois, sur `IndexTree` : elles
+//! s'appliquent aux arbres sled (worker asynchrone), aux arbres d'une
+//! transaction sled (mode synchrone, dans la transaction des données) ou
+//! à des arbres en mémoire (vérification des index).
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use sled::IVec;
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::core::errors::StoreError;
+
+/// Résultat d'une écriture d'index
+///
+/// Les conflits des transactions sled sont conservés, pour que sled
+/// puisse rejouer la transaction.
+pub type TreeResult<T> = std::result::Result<T, ConflictableTransactionError<StoreError>>;
+
+/// Arbre clé-valeur d'un index
+pub trait IndexTree {
+    /// Lit l'entrée d'une clé
+    fn get(&self, key: &[u8]) -> TreeResult<Option<IVec>>;
+
+    /// Écrit l'entrée d'une clé
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> TreeResult<()>;
+
+    /// Supprime l'entrée d'une clé
+    fn remove(&self, key: &[u8]) -> TreeResult<()>;
+}
+
+impl IndexTree for sled::Tree {
+    fn get(&self, key: &[u8]) -> TreeResult<Option<IVec>> {
+        sled::Tree::get(self, key)
+            .map_err(|e| abort(format!("Failed to read index: {}", e)))
+    }
+
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> TreeResult<()> {
+        sled::Tree::insert(self, key, value)
+            .map_err(|e| abort(format!("Failed to update index: {}", e)))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> TreeResult<()> {
+        sled::Tree::remove(self, key)
+            .map_err(|e| abort(format!("Failed to remove from index: {}", e)))?;
+        Ok(())
+    }
+}
+
+impl IndexTree for TransactionalTree {
+    fn get(&self, key: &[u8]) -> TreeResult<Option<IVec>> {
+        Ok(TransactionalTree::get(self, key)?)
+    }
+
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> TreeResult<()> {
+        TransactionalTree::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> TreeResult<()> {
+        TransactionalTree::remove(self, key)?;
+        Ok(())
+    }
+}
+
+/// Arbre en mémoire, où la vérification reconstruit le contenu attendu
+/// d'un index
+#[derive(Default)]
+pub struct MemoryTree {
+    entries: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryTree {
+    /// Crée un arbre vide
+    pub fn new() -> Self {
+        MemoryTree::default()
+    }
+
+    /// Obtient les entrées de l'arbre, triées par clé
+    pub fn into_entries(self) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        self.entries.into_inner()
+    }
+}
+
+impl IndexTree for MemoryTree {
+    fn get(&self, key: &[u8]) -> TreeResult<Option<IVec>> {
+        Ok(self.entries.borrow().get(key).map(|value| IVec::from(value.as_slice())))
+    }
+
+    fn insert(&self, key: &[u8], value: Vec<u8>) -> TreeResult<()> {
+        self.entries.borrow_mut().insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> TreeResult<()> {
+        self.entries.borrow_mut().remove(key);
+        Ok(())
+    }
+}
+
+/// Interrompt une écriture d'index avec une erreur
+pub fn abort(message: String) -> ConflictableTransactionError<StoreError> {
+    ConflictableTransactionError::Abort(StoreError::Internal(message))
+}
+
+/// Convertit l'erreur d'une écriture faite hors transaction
+pub fn into_store_error(error: ConflictableTransactionError<StoreError>) -> StoreError {
+    match error {
+        ConflictableTransactionError::Abort(e) => e,
+        ConflictableTransactionError::Storage(e) => StoreError::Internal(format!("Index storage error: {}", e)),
+        ConflictableTransactionError::Conflict => StoreError::Internal("Index transaction conflict".to_string()),
+    }
+}
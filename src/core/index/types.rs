@@ -1,8 +1,10 @@
 // src/core/index/types.rs
 use crate::{core::path::Path};
 use crate::core::value::Value;
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::ops::Bound;
 use crate::core::errors::Result;
+use super::tree::{IndexTree, TreeResult};
 
 /// Type d'opération d'indexation
 #[derive(Debug, Clone, PartialEq)]
@@ -13,6 +15,10 @@ pub enum IndexOp {
     Remove(Path),
     /// Ajouter un chemin avec sa valeur (pour l'index de valeurs)
     AddWithValue(Path, Value),
+    /// Retirer l'ancienne valeur d'un chemin de l'index de valeurs
+    RemoveValue(Path, Value),
+    /// Enregistrer que les écritures jusqu'à une séquence sont indexées
+    Watermark(u64),
     /// Forcer un flush des opérations en attente
     Flush,
     /// Arrêter le worker
@@ -32,6 +38,128 @@ pub struct IndexStats {
     pub pending_operations: usize,
 }
 
+/// Cohérence entre les données et les index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexConsistency {
+    /// Les index sont mis à jour en arrière-plan après chaque écriture :
+    /// une requête lancée juste après peut ne pas la voir
+    #[default]
+    Eventual,
+    /// Les index sont mis à jour dans la transaction sled des données :
+    /// une requête voit toujours les écritures qui la précèdent
+    Synchronous,
+}
+
+/// Résultat d'une vérification des index
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IndexReport {
+    /// Nombre de chemins des données vérifiés
+    pub checked_paths: usize,
+    /// Entrées d'index absentes ou erronées, réécrites
+    pub missing_entries: usize,
+    /// Entrées d'index sans chemin correspondant, supprimées
+    pub stale_entries: usize,
+}
+
+impl IndexReport {
+    /// Indique si les index correspondaient aux données
+    pub fn is_consistent(&self) -> bool {
+        self.missing_entries == 0 && self.stale_entries == 0
+    }
+}
+
+/// Intervalle de valeurs, pour les recherches par plage dans l'index
+/// de valeurs
+///
+/// Les nombres (entiers et flottants) se comparent entre eux, les chaînes
+/// et les booléens entre eux : une valeur d'un autre type n'appartient
+/// jamais à l'intervalle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueRange {
+    /// Borne inférieure
+    pub lower: Bound<Value>,
+    /// Borne supérieure
+    pub upper: Bound<Value>,
+}
+
+impl ValueRange {
+    /// Crée un intervalle non borné
+    pub fn new() -> Self {
+        ValueRange { lower: Bound::Unbounded, upper: Bound::Unbounded }
+    }
+    
+    /// Restreint l'intervalle aux valeurs au-dessus d'une borne
+    pub fn above(mut self, value: Value, inclusive: bool) -> Self {
+        let bound = if inclusive { Bound::Included(value) } else { Bound::Excluded(value) };
+        if Self::tighter(&bound, &self.lower, Ordering::Greater) {
+            self.lower = bound;
+        }
+        self
+    }
+    
+    /// Restreint l'intervalle aux valeurs au-dessous d'une borne
+    pub fn below(mut self, value: Value, inclusive: bool) -> Self {
+        let bound = if inclusive { Bound::Included(value) } else { Bound::Excluded(value) };
+        if Self::tighter(&bound, &self.upper, Ordering::Less) {
+            self.upper = bound;
+        }
+        self
+    }
+    
+    /// Indique si une nouvelle borne est plus restrictive que l'actuelle
+    /// (`direction` : `Greater` pour une borne inférieure)
+    fn tighter(bound: &Bound<Value>, current: &Bound<Value>, direction: Ordering) -> bool {
+        let (value, current_value) = match (bound, current) {
+            (_, Bound::Unbounded) => return true,
+            (Bound::Included(v) | Bound::Excluded(v), Bound::Included(c) | Bound::Excluded(c)) => (v, c),
+            (Bound::Unbounded, _) => return false,
+        };
+        
+        match compare_values(value, current_value) {
+            Some(Ordering::Equal) => matches!(bound, Bound::Excluded(_)),
+            Some(ordering) => ordering == direction,
+            // Des bornes de types différents : l'intervalle est vide,
+            // garder la nouvelle borne le rend vide aussi
+            None => true,
+        }
+    }
+    
+    /// Indique si une valeur appartient à l'intervalle
+    pub fn contains(&self, value: &Value) -> bool {
+        let above = match &self.lower {
+            Bound::Unbounded => true,
+            Bound::Included(lower) => matches!(compare_values(value, lower), Some(Ordering::Greater | Ordering::Equal)),
+            Bound::Excluded(lower) => matches!(compare_values(value, lower), Some(Ordering::Greater)),
+        };
+        let below = match &self.upper {
+            Bound::Unbounded => true,
+            Bound::Included(upper) => matches!(compare_values(value, upper), Some(Ordering::Less | Ordering::Equal)),
+            Bound::Excluded(upper) => matches!(compare_values(value, upper), Some(Ordering::Less)),
+        };
+        
+        above && below
+    }
+}
+
+impl Default for ValueRange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compare deux valeurs de types comparables
+pub fn compare_values(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+        (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+        (Value::Integer(l), Value::Float(r)) => (*l as f64).partial_cmp(r),
+        (Value::Float(l), Value::Integer(r)) => l.partial_cmp(&(*r as f64)),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        (Value::Boolean(l), Value::Boolean(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
+
 /// Trait pour les implémentations d'index
 pub trait IndexImplementation: Send + Sync {
     /// Ajouter un chemin à l'index (implémentation interne)
@@ -46,6 +174,22 @@ pub trait IndexImplementation: Send + Sync {
     /// Trouver tous les chemins qui correspondent à un motif (avec wildcards)
     fn find_by_pattern(&self, pattern: &Path) -> crate::core::errors::Result<Vec<Path>>;
     
+    /// Obtenir les arbres sled où l'index stocke ses entrées
+    fn trees(&self) -> Result<Vec<sled::Tree>>;
+    
+    /// Indique si les entrées de l'index dépendent des valeurs des chemins
+    /// (et pas seulement des chemins)
+    fn indexes_values(&self) -> bool {
+        false
+    }
+    
+    /// Écrire les entrées d'un chemin et de sa valeur, dans des arbres
+    /// donnés dans l'ordre de `trees`
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()>;
+    
+    /// Retirer les entrées d'un chemin, connaissant sa valeur
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()>;
+    
     /// Vider l'index
     fn clear(&mut self) -> crate::core::errors::Result<()>;
     
@@ -63,4 +207,7 @@ pub trait ValueIndexing: IndexImplementation {
     
     /// Trouve les chemins satisfaisant une condition
     fn find_by_condition(&self, operator: &str, value: &Value) -> Result<Vec<Path>>;
+    
+    /// Trouve les chemins dont la valeur appartient à un intervalle
+    fn find_by_range(&self, range: &ValueRange) -> Result<Vec<Path>>;
 }
\ No newline at end of file
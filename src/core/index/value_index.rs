@@ -2,12 +2,15 @@
 use std::sync::Arc;
 use sled::Db;
 use bincode::{serialize, deserialize};
-use std::collections::HashMap;
+use sled::transaction::ConflictableTransactionError;
+use std::collections::{BTreeSet, HashMap};
 
 use crate::core::path::Path;
 use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
-use super::types::IndexImplementation;
+use std::ops::Bound;
+use super::types::{IndexImplementation, ValueRange};
+use super::tree::{IndexTree, TreeResult, into_store_error};
 
 /// Index optimisé pour les recherches par valeur
 pub struct ValueIndex {
@@ -17,7 +20,8 @@ pub struct ValueIndex {
     index_tree_name: String,
     /// Nom de l'arbre pour stocker les métadonnées (patterns indexés)
     metadata_tree_name: String,
-    /// Cache mémoire des patterns indexés (pour les vérifications rapides)
+    /// Cache mémoire des patterns indexés (pour les vérifications rapides),
+    /// avec leur état : prêt, ou en construction
     indexed_patterns: HashMap<Path, bool>,
 }
 
@@ -48,9 +52,9 @@ impl ValueIndex {
         let tree = self.get_metadata_tree()?;
         
         for item in tree.iter() {
-            let (key, _) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
+            let (key, state) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
             let pattern: Path = deserialize(&key).map_err(|e| StoreError::Internal(format!("Failed to deserialize key: {}", e)))?;
-            self.indexed_patterns.insert(pattern, true);
+            self.indexed_patterns.insert(pattern, *state != [PATTERN_BUILDING]);
         }
         
         Ok(())
@@ -69,35 +73,36 @@ impl ValueIndex {
     }
     
     /// Crée une clé d'index à partir d'une valeur
-    fn create_value_key(value: &Value) -> Result<Vec<u8>> {
-        // (Même implémentation qu'avant)
+    ///
+    /// L'ordre des clés est celui des valeurs : null, booléens, nombres,
+    /// chaînes, puis références. Entiers et flottants partagent un même
+    /// code, suivi du nombre en flottant ordonné, puis d'un sous-code
+    /// (l'entier exact pour un entier, qui distingue 3 de 3.0).
+    pub(super) fn create_value_key(value: &Value) -> Result<Vec<u8>> {
         let mut key_bytes = Vec::new();
         
         match value {
             Value::Null => {
-                key_bytes.push(0x00); // Code pour null
+                key_bytes.push(NULL_TAG);
             },
             Value::Boolean(b) => {
-                key_bytes.push(0x01); // Code pour boolean
+                key_bytes.push(BOOLEAN_TAG);
                 key_bytes.push(if *b { 1 } else { 0 });
             },
             Value::Integer(i) => {
-                key_bytes.push(0x02); // Code pour integer
-                key_bytes.extend_from_slice(&i.to_be_bytes());
+                key_bytes.push(NUMBER_TAG);
+                key_bytes.extend_from_slice(&ordered_float(*i as f64));
+                key_bytes.push(INTEGER_SUBTAG);
+                // Bit de signe inversé : les négatifs avant les positifs
+                key_bytes.extend_from_slice(&((*i as u64) ^ SIGN_BIT).to_be_bytes());
             },
             Value::Float(f) => {
-                key_bytes.push(0x03); // Code pour float
-                let bits = f.to_bits().to_be_bytes();
-                if *f >= 0.0 {
-                    key_bytes.extend_from_slice(&bits);
-                } else {
-                    let mut inverted = bits;
-                    inverted[0] ^= 0x80;
-                    key_bytes.extend_from_slice(&inverted);
-                }
+                key_bytes.push(NUMBER_TAG);
+                key_bytes.extend_from_slice(&ordered_float(*f));
+                key_bytes.push(FLOAT_SUBTAG);
             },
             Value::String(s) => {
-                key_bytes.push(0x04); // Code pour string
+                key_bytes.push(STRING_TAG);
                 key_bytes.extend_from_slice(s.as_bytes());
             },
             Value::Binary(_, _) => {
@@ -105,8 +110,18 @@ impl ValueIndex {
                     "Binary values cannot be indexed".to_string()
                 ));
             },
+            Value::Vector(_) => {
+                return Err(StoreError::InvalidOperation(
+                    "Vector values cannot be indexed by value".to_string()
+                ));
+            },
+            Value::GeoPoint { .. } => {
+                return Err(StoreError::InvalidOperation(
+                    "Geographic points cannot be indexed by value".to_string()
+                ));
+            },
             Value::Reference(path) => {
-                key_bytes.push(0x05); // Code pour reference
+                key_bytes.push(REFERENCE_TAG);
                 let path_str = path.to_string();
                 key_bytes.extend_from_slice(path_str.as_bytes());
             },
@@ -115,20 +130,101 @@ impl ValueIndex {
         Ok(key_bytes)
     }
     
-    /// Ajoute un pattern à indexer
+    /// Retrouve la valeur d'une clé d'index
+    pub(super) fn decode_value_key(key: &[u8]) -> Result<Value> {
+        let invalid = || StoreError::DeserializationError("Invalid value index key".to_string());
+        let (tag, rest) = key.split_first().ok_or_else(invalid)?;
+        
+        match *tag {
+            NULL_TAG => Ok(Value::Null),
+            BOOLEAN_TAG => Ok(Value::Boolean(rest.first() == Some(&1))),
+            NUMBER_TAG => {
+                if rest.len() < 9 {
+                    return Err(invalid());
+                }
+                match rest[8] {
+                    INTEGER_SUBTAG => {
+                        let raw: [u8; 8] = rest[9..].try_into().map_err(|_| invalid())?;
+                        Ok(Value::Integer((u64::from_be_bytes(raw) ^ SIGN_BIT) as i64))
+                    },
+                    _ => {
+                        let raw: [u8; 8] = rest[..8].try_into().map_err(|_| invalid())?;
+                        Ok(Value::Float(unordered_float(raw)))
+                    },
+                }
+            },
+            STRING_TAG => String::from_utf8(rest.to_vec()).map(Value::String).map_err(|_| invalid()),
+            REFERENCE_TAG => {
+                let path = std::str::from_utf8(rest).map_err(|_| invalid())?;
+                Ok(Value::Reference(path.parse().map_err(|_| invalid())?))
+            },
+            _ => Err(invalid()),
+        }
+    }
+    
+    /// Calcule le début des clés d'une borne : pour un nombre, la partie
+    /// commune à un entier et au flottant égal
+    pub(super) fn bound_prefix(value: &Value) -> Result<Vec<u8>> {
+        let mut key = Self::create_value_key(value)?;
+        if key[0] == NUMBER_TAG {
+            key.truncate(9);
+        }
+        Ok(key)
+    }
+    
+    /// Ajoute un pattern à indexer, prêt immédiatement (sans données
+    /// existantes à indexer)
     pub fn add_indexed_pattern(&mut self, pattern: &Path) -> Result<()> {
+        self.write_pattern_state(pattern, true)
+    }
+    
+    /// Ajoute un pattern à indexer, en construction jusqu'à
+    /// `mark_pattern_ready` : les écritures sont indexées, mais l'index ne
+    /// répond pas encore aux requêtes
+    pub fn begin_indexed_pattern(&mut self, pattern: &Path) -> Result<()> {
+        self.write_pattern_state(pattern, false)
+    }
+    
+    /// Marque un pattern comme prêt, une fois les données existantes indexées
+    pub fn mark_pattern_ready(&mut self, pattern: &Path) -> Result<()> {
+        self.write_pattern_state(pattern, true)
+    }
+    
+    /// Enregistre l'état d'un pattern indexé
+    fn write_pattern_state(&mut self, pattern: &Path, ready: bool) -> Result<()> {
         let tree = self.get_metadata_tree()?;
         let pattern_key = serialize(&pattern).map_err(|e| StoreError::Internal(format!("Failed to serialize pattern: {}", e)))?;
+        let state = if ready { PATTERN_READY } else { PATTERN_BUILDING };
         
         // Stocker le pattern dans l'arbre de métadonnées
-        tree.insert(pattern_key, vec![1]).map_err(|e| StoreError::Internal(format!("Failed to insert pattern into metadata tree: {}", e)))?;
+        tree.insert(pattern_key, vec![state]).map_err(|e| StoreError::Internal(format!("Failed to insert pattern into metadata tree: {}", e)))?;
+        tree.flush().map_err(|e| StoreError::Internal(format!("Failed to flush metadata tree: {}", e)))?;
         
         // Mettre à jour le cache
-        self.indexed_patterns.insert(pattern.clone(), true);
+        self.indexed_patterns.insert(pattern.clone(), ready);
         
         Ok(())
     }
     
+    /// Liste les patterns indexés, avec leur état (prêt ou non)
+    pub fn indexed_patterns(&self) -> Vec<(Path, bool)> {
+        self.indexed_patterns.iter().map(|(pattern, ready)| (pattern.clone(), *ready)).collect()
+    }
+    
+    /// Compte les chemins indexés correspondant à un pattern
+    pub fn count_paths(&self, pattern: &Path) -> Result<usize> {
+        let tree = self.get_index_tree()?;
+        let mut count = 0;
+        
+        for item in tree.iter() {
+            let (_, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate index tree: {}", e)))?;
+            let paths: Vec<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
+            count += paths.iter().filter(|path| path.matches(pattern)).count();
+        }
+        
+        Ok(count)
+    }
+    
     /// Vérifie si un chemin correspond à un pattern indexé
     pub fn is_path_indexed(&self, path: &Path) -> Result<bool> {
         // Vérification rapide dans le cache
@@ -141,7 +237,8 @@ impl ValueIndex {
         Ok(false)
     }
     
-    /// Supprime un pattern indexé
+    /// Supprime un pattern indexé, avec les entrées des chemins qu'aucun
+    /// autre pattern n'indexe
     pub fn remove_indexed_pattern(&mut self, pattern: &Path) -> Result<()> {
         let tree = self.get_metadata_tree()?;
         let pattern_key = serialize(&pattern).map_err(|e| StoreError::Internal(format!("Failed to serialize pattern: {}", e)))?;
@@ -152,36 +249,61 @@ impl ValueIndex {
         // Mettre à jour le cache
         self.indexed_patterns.remove(pattern);
         
+        let index_tree = self.get_index_tree()?;
+        for item in index_tree.iter() {
+            let (value_key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate index tree: {}", e)))?;
+            let mut paths: BTreeSet<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
+            
+            let count = paths.len();
+            paths.retain(|path| !path.matches(pattern) || self.is_path_indexed(path).unwrap_or(false));
+            if paths.len() != count {
+                Self::write_paths(&index_tree, &value_key, &paths).map_err(into_store_error)?;
+            }
+        }
+        
         Ok(())
     }
     
     /// Ajoute un chemin avec sa valeur à l'index
     pub fn add_with_value(&mut self, path: &Path, value: &Value) -> Result<()> {
-        // Vérifier si ce chemin doit être indexé
-        if !self.is_path_indexed(path)? {
-            return Ok(());
-        }
-        
         let tree = self.get_index_tree()?;
-        let value_key = Self::create_value_key(value)?;
-        
-        // Récupérer les chemins existants pour cette valeur
-        let mut paths = if let Some(data) = tree.get(&value_key).map_err(|e| StoreError::Internal(format!("Failed to get value from index tree: {}", e)))? {
-            deserialize::<Vec<Path>>(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?
-        } else {
-            Vec::new()
-        };
-        
-        // Ajouter le nouveau chemin s'il n'existe pas déjà
-        if !paths.contains(path) {
-            paths.push(path.clone());
-            
-            // Mettre à jour l'index
-            let serialized = serialize(&paths).map_err(|e| StoreError::Internal(format!("Failed to serialize paths: {}", e)))?;
-            tree.insert(value_key, serialized).map_err(|e| StoreError::Internal(format!("Failed to insert into index tree: {}", e)))?;
+        self.stage_add(&[&tree], path, value).map_err(into_store_error)
+    }
+    
+    /// Retire un chemin de l'entrée de son ancienne valeur
+    pub fn remove_with_value(&mut self, path: &Path, value: &Value) -> Result<()> {
+        let tree = self.get_index_tree()?;
+        self.stage_remove(&[&tree], path, value).map_err(into_store_error)
+    }
+    
+    /// Calcule la clé d'une valeur indexable (les valeurs binaires, les
+    /// vecteurs et les points géographiques ne le sont pas)
+    pub fn indexable_key(value: &Value) -> Option<Vec<u8>> {
+        match value {
+            Value::Binary(_, _) | Value::Vector(_) | Value::GeoPoint { .. } => None,
+            _ => Self::create_value_key(value).ok(),
+        }
+    }
+    
+    /// Lit l'ensemble trié des chemins associé à une clé de valeur
+    fn read_paths(tree: &dyn IndexTree, value_key: &[u8]) -> TreeResult<BTreeSet<Path>> {
+        match tree.get(value_key)? {
+            Some(data) => deserialize(&data)
+                .map_err(|e| ConflictableTransactionError::Abort(StoreError::Internal(format!("Failed to deserialize data: {}", e)))),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+    
+    /// Écrit l'ensemble des chemins d'une clé de valeur (la clé disparaît
+    /// avec son dernier chemin)
+    fn write_paths(tree: &dyn IndexTree, value_key: &[u8], paths: &BTreeSet<Path>) -> TreeResult<()> {
+        if paths.is_empty() {
+            return tree.remove(value_key);
         }
         
-        Ok(())
+        let serialized = serialize(paths)
+            .map_err(|e| ConflictableTransactionError::Abort(StoreError::Internal(format!("Failed to serialize paths: {}", e))))?;
+        tree.insert(value_key, serialized)
     }
     
     /// Trouve tous les chemins ayant une valeur spécifique
@@ -199,29 +321,119 @@ impl ValueIndex {
     
     /// Trouve tous les chemins satisfaisant une condition de comparaison
     pub fn find_by_condition(&self, operator: &str, value: &Value) -> Result<Vec<Path>> {
-        // (Même implémentation qu'avant)
-        let tree = self.get_index_tree()?;
-        let value_key = Self::create_value_key(value)?;
-        let mut results = Vec::new();
-        
-        match operator {
-            "==" => {
-                // Égalité - recherche exacte
-                if let Some(data) = tree.get(&value_key).map_err(|e| StoreError::Internal(format!("Failed to get value from index tree: {}", e)))? {
-                    let paths: Vec<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
-                    results.extend(paths);
-                }
-            },
-            // (autres opérateurs...)
+        let range = match operator {
+            // Égalité - recherche exacte
+            "==" => return self.find_by_value(value),
+            ">" => ValueRange::new().above(value.clone(), false),
+            ">=" => ValueRange::new().above(value.clone(), true),
+            "<" => ValueRange::new().below(value.clone(), false),
+            "<=" => ValueRange::new().below(value.clone(), true),
             _ => {
                 return Err(StoreError::InvalidOperation(
                     format!("Unsupported operator: {}", operator)
                 ));
             }
+        };
+        
+        self.find_by_range(&range)
+    }
+    
+    /// Trouve tous les chemins dont la valeur appartient à un intervalle,
+    /// par un parcours ordonné de l'arbre d'index
+    pub fn find_by_range(&self, range: &ValueRange) -> Result<Vec<Path>> {
+        let bound_value = |bound: &Bound<Value>| match bound {
+            Bound::Included(value) | Bound::Excluded(value) => Some(value.clone()),
+            Bound::Unbounded => None,
+        };
+        let lower = bound_value(&range.lower);
+        let upper = bound_value(&range.upper);
+        
+        // Les bornes fixent le type des valeurs parcourues
+        let tag = match (&lower, &upper) {
+            (Some(value), _) | (None, Some(value)) => Self::create_value_key(value)?[0],
+            (None, None) => {
+                return Err(StoreError::InvalidOperation("A value range needs at least one bound".to_string()));
+            }
+        };
+        
+        let start = match &lower {
+            Some(value) => Self::bound_prefix(value)?,
+            None => vec![tag],
+        };
+        // Aucune clé valide ne contient l'octet 0xFF après le début de la
+        // borne : toutes les clés de la borne sont avant cette limite
+        let limit = match &upper {
+            Some(value) => {
+                let mut limit = Self::bound_prefix(value)?;
+                limit.push(0xFF);
+                limit
+            },
+            None => vec![tag + 1],
+        };
+        
+        let tree = self.get_index_tree()?;
+        let mut results = Vec::new();
+        
+        if start >= limit {
+            return Ok(results);
+        }
+        
+        // Les clés aux bornes sont décodées pour appliquer les bornes exactes
+        for item in tree.range(start..limit) {
+            let (value_key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            if !range.contains(&Self::decode_value_key(&value_key)?) {
+                continue;
+            }
+            
+            let paths: Vec<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
+            results.extend(paths);
         }
         
         Ok(results)
     }
+    
+    /// Vérifie que tous les chemins d'un motif sont indexés par valeur,
+    /// par un pattern prêt
+    pub fn covers(&self, pattern: &Path) -> bool {
+        self.indexed_patterns.iter().any(|(indexed, ready)| *ready && pattern.matches(indexed))
+    }
+}
+
+/// État d'un pattern dont les données existantes sont en cours d'indexation
+const PATTERN_BUILDING: u8 = 0;
+/// État d'un pattern prêt
+const PATTERN_READY: u8 = 1;
+
+/// Code des valeurs nulles dans les clés
+const NULL_TAG: u8 = 0x00;
+/// Code des booléens dans les clés
+const BOOLEAN_TAG: u8 = 0x01;
+/// Code des nombres (entiers et flottants) dans les clés
+const NUMBER_TAG: u8 = 0x02;
+/// Code des chaînes dans les clés
+const STRING_TAG: u8 = 0x03;
+/// Code des références dans les clés
+const REFERENCE_TAG: u8 = 0x04;
+/// Sous-code d'un nombre flottant
+const FLOAT_SUBTAG: u8 = 0x00;
+/// Sous-code d'un nombre entier
+const INTEGER_SUBTAG: u8 = 0x01;
+/// Bit de signe d'un nombre sur 64 bits
+const SIGN_BIT: u64 = 1 << 63;
+
+/// Encode un flottant en octets dont l'ordre est celui des nombres
+fn ordered_float(value: f64) -> [u8; 8] {
+    let bits = value.to_bits();
+    // Négatifs : tous les bits inversés ; positifs : bit de signe levé
+    let ordered = if bits & SIGN_BIT != 0 { !bits } else { bits | SIGN_BIT };
+    ordered.to_be_bytes()
+}
+
+/// Décode un flottant encodé par `ordered_float`
+fn unordered_float(bytes: [u8; 8]) -> f64 {
+    let ordered = u64::from_be_bytes(bytes);
+    let bits = if ordered & SIGN_BIT != 0 { ordered ^ SIGN_BIT } else { !ordered };
+    f64::from_bits(bits)
 }
 
 impl IndexImplementation for ValueIndex {
@@ -239,26 +451,59 @@ impl IndexImplementation for ValueIndex {
         
         for item in tree.iter() {
             let (value_key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate index tree: {}", e)))?;
-            let mut paths: Vec<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
+            let mut paths: BTreeSet<Path> = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize data: {}", e)))?;
             
-            // Vérifier si ce chemin est présent
-            if let Some(index) = paths.iter().position(|p| p == path) {
-                // Supprimer le chemin
-                paths.remove(index);
-                
-                // Mettre à jour ou supprimer l'entrée
-                if paths.is_empty() {
-                    tree.remove(value_key).map_err(|e| StoreError::Internal(format!("Failed to remove value from index tree: {}", e)))?;
-                } else {
-                    let serialized = serialize(&paths).map_err(|e| StoreError::Internal(format!("Failed to serialize paths: {}", e)))?;
-                    tree.insert(value_key, serialized).map_err(|e| StoreError::Internal(format!("Failed to insert into index tree: {}", e)))?;
-                }
+            // Supprimer le chemin s'il est présent, et mettre à jour ou supprimer l'entrée
+            if paths.remove(path) {
+                Self::write_paths(&tree, &value_key, &paths).map_err(into_store_error)?;
             }
         }
         
         Ok(())
     }
     
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        // Les patterns indexés sont une configuration, pas des entrées
+        Ok(vec![self.get_index_tree()?])
+    }
+    
+    fn indexes_values(&self) -> bool {
+        true
+    }
+    
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        // Vérifier si ce chemin doit être indexé
+        let indexed = self.is_path_indexed(path).map_err(ConflictableTransactionError::Abort)?;
+        let value_key = match Self::indexable_key(value) {
+            Some(value_key) if indexed => value_key,
+            _ => return Ok(()),
+        };
+        
+        // Ajouter le chemin aux chemins existants pour cette valeur
+        let mut paths = Self::read_paths(trees[0], &value_key)?;
+        if paths.insert(path.clone()) {
+            Self::write_paths(trees[0], &value_key, &paths)?;
+        }
+        
+        Ok(())
+    }
+    
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        // Un pattern a pu cesser d'être indexé depuis l'ajout du chemin :
+        // l'entrée est retirée dans tous les cas
+        let value_key = match Self::indexable_key(value) {
+            Some(value_key) => value_key,
+            None => return Ok(()),
+        };
+        
+        let mut paths = Self::read_paths(trees[0], &value_key)?;
+        if paths.remove(path) {
+            Self::write_paths(trees[0], &value_key, &paths)?;
+        }
+        
+        Ok(())
+    }
+    
     // (autres méthodes du trait...)
     
     fn find_by_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
@@ -280,4 +525,37 @@ impl IndexImplementation for ValueIndex {
     fn name(&self) -> &'static str {
         "ValueIndex"
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_keys_sort_as_values() {
+        let values = vec![
+            Value::Null,
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Integer(-5),
+            Value::Float(-1.5),
+            Value::Integer(0),
+            Value::Float(2.5),
+            Value::Float(3.0),
+            Value::Integer(3),
+            Value::Integer(10),
+            Value::Float(1e20),
+            Value::String("".to_string()),
+            Value::String("Ada".to_string()),
+            Value::String("Adam".to_string()),
+        ];
+
+        let keys: Vec<Vec<u8>> = values.iter().map(|v| ValueIndex::create_value_key(v).unwrap()).collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+
+        for (value, key) in values.iter().zip(&keys) {
+            assert_eq!(&ValueIndex::decode_value_key(key).unwrap(), value);
+        }
+    }
+}
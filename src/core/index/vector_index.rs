@@ -0,0 +1,569 @@
+// src/core/index/vector_index.rs
+//! Index des vecteurs, pour la recherche par similarité
+//!
+//! Un index de vecteurs est déclaré sur un motif (`docs.*.embedding`)
+//! avec une métrique (cosinus, distance euclidienne ou produit scalaire).
+//! L'arbre de l'index range le vecteur de chaque chemin du motif, sous la
+//! clé `id | chemin` ; une recherche exacte le parcourt entièrement.
+//!
+//! Un index approximatif y ajoute un graphe HNSW, construit en mémoire à
+//! la première recherche à partir de l'arbre, puis tenu à jour par les
+//! écritures. Les candidats du graphe sont relus dans l'arbre : seul
+//! celui-ci fait foi.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bincode::{serialize, deserialize};
+use serde::{Serialize, Deserialize};
+use sled::Db;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use super::hnsw::Hnsw;
+use super::types::IndexImplementation;
+use super::tree::{IndexTree, TreeResult, abort, into_store_error};
+
+/// Nombre minimal de candidats explorés dans le graphe
+const MIN_EF: usize = 64;
+
+/// Mesure de similarité entre deux vecteurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VectorMetric {
+    /// Cosinus de l'angle entre les vecteurs
+    Cosine,
+    /// Distance euclidienne (similarité `1 / (1 + distance)`)
+    Euclidean,
+    /// Produit scalaire
+    Dot,
+}
+
+impl VectorMetric {
+    /// Obtient le nom de la métrique
+    pub fn name(&self) -> &'static str {
+        match self {
+            VectorMetric::Cosine => "cosine",
+            VectorMetric::Euclidean => "l2",
+            VectorMetric::Dot => "dot",
+        }
+    }
+
+    /// Trouve une métrique par son nom
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cosine" => Some(VectorMetric::Cosine),
+            "l2" | "euclidean" => Some(VectorMetric::Euclidean),
+            "dot" => Some(VectorMetric::Dot),
+            _ => None,
+        }
+    }
+
+    /// Similarité de deux vecteurs, plus grande pour des vecteurs proches
+    ///
+    /// Renvoie `None` si les vecteurs n'ont pas la même dimension, ou si
+    /// l'un d'eux est nul pour le cosinus.
+    pub fn similarity(&self, a: &[f32], b: &[f32]) -> Option<f64> {
+        if a.len() != b.len() {
+            return None;
+        }
+
+        let dot = || a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum::<f64>();
+        let norm = |v: &[f32]| v.iter().map(|x| *x as f64 * *x as f64).sum::<f64>().sqrt();
+
+        match self {
+            VectorMetric::Cosine => {
+                let norms = norm(a) * norm(b);
+                if norms == 0.0 {
+                    None
+                } else {
+                    Some(dot() / norms)
+                }
+            },
+            VectorMetric::Euclidean => {
+                let distance = a.iter().zip(b)
+                    .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                Some(1.0 / (1.0 + distance))
+            },
+            VectorMetric::Dot => Some(dot()),
+        }
+    }
+
+    /// Distance de deux vecteurs pour le graphe HNSW, plus petite pour des
+    /// vecteurs proches (infinie si la similarité n'est pas définie)
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match (self, self.similarity(a, b)) {
+            (_, None) => f32::INFINITY,
+            (VectorMetric::Cosine, Some(similarity)) => (1.0 - similarity) as f32,
+            (VectorMetric::Euclidean, Some(similarity)) => (1.0 / similarity - 1.0) as f32,
+            (VectorMetric::Dot, Some(similarity)) => -similarity as f32,
+        }
+    }
+}
+
+/// Recherche des vecteurs les plus proches d'un vecteur
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorQuery {
+    /// Vecteur cherché
+    pub vector: Vec<f32>,
+    /// Métrique imposée (sinon, celle de l'index)
+    pub metric: Option<VectorMetric>,
+    /// Nombre maximal de résultats
+    pub limit: usize,
+    /// Similarité minimale des résultats
+    pub min_similarity: Option<f64>,
+}
+
+/// Déclaration d'un index de vecteurs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VectorDefinition {
+    /// Identifiant de l'index, préfixe de ses clés
+    id: u32,
+    /// Motif des chemins indexés
+    pattern: Path,
+    /// Métrique des recherches
+    metric: VectorMetric,
+    /// Les recherches passent par un graphe HNSW
+    approximate: bool,
+    /// Les données existantes sont indexées
+    ready: bool,
+}
+
+impl VectorDefinition {
+    /// Clé du vecteur d'un chemin
+    fn entry_key(&self, path: &Path) -> TreeResult<Vec<u8>> {
+        let mut key = self.id.to_be_bytes().to_vec();
+        key.extend(serialize(path).map_err(|e| abort(format!("Failed to serialize path: {}", e)))?);
+        Ok(key)
+    }
+}
+
+/// Garde les meilleurs scores au-dessus du minimum, du plus haut au plus
+/// bas
+fn top(scores: Vec<(Path, f64)>, query: &VectorQuery) -> Vec<(Path, f64)> {
+    let mut ranked: Vec<(Path, f64)> = scores.into_iter()
+        .filter(|(_, score)| query.min_similarity.is_none_or(|min| *score >= min))
+        .collect();
+    ranked.sort_by(|(left_path, left), (right_path, right)| {
+        right.partial_cmp(left).unwrap_or(Ordering::Equal).then_with(|| left_path.cmp(right_path))
+    });
+    ranked.truncate(query.limit);
+    ranked
+}
+
+/// Classe des vecteurs par similarité sans index
+///
+/// Donne les mêmes scores qu'une recherche exacte dans un index : sert
+/// aux recherches qu'aucun index prêt ne couvre. La métrique par défaut
+/// est le cosinus.
+pub fn rank(documents: &[(Path, Vec<f32>)], query: &VectorQuery) -> Vec<(Path, f64)> {
+    let metric = query.metric.unwrap_or(VectorMetric::Cosine);
+    let scores = documents.iter()
+        .filter_map(|(path, vector)| {
+            metric.similarity(&query.vector, vector).map(|score| (path.clone(), score))
+        })
+        .collect();
+    top(scores, query)
+}
+
+/// Index des vecteurs
+pub struct VectorIndex {
+    /// La base de données sled
+    db: Arc<Db>,
+    /// Nom de l'arbre pour stocker l'index
+    index_tree_name: String,
+    /// Nom de l'arbre pour stocker les déclarations
+    metadata_tree_name: String,
+    /// Cache mémoire des index déclarés
+    definitions: Vec<VectorDefinition>,
+    /// Graphes des index approximatifs, par identifiant, une fois
+    /// construits
+    ///
+    /// Ce verrou n'est pris que sous celui de l'index : il ne bloque
+    /// jamais une transaction sled.
+    graphs: Mutex<HashMap<u32, Hnsw>>,
+}
+
+impl VectorIndex {
+    /// Crée un nouvel index de vecteurs
+    pub fn new(db: Arc<Db>, base_name: &str) -> Result<Self> {
+        let mut index = VectorIndex {
+            db,
+            index_tree_name: format!("{}_entries", base_name),
+            metadata_tree_name: format!("{}_metadata", base_name),
+            definitions: Vec::new(),
+            graphs: Mutex::new(HashMap::new()),
+        };
+
+        // Charger les index déclarés depuis le stockage
+        for item in index.get_metadata_tree()?.iter() {
+            let (_, data) = item.map_err(|e| StoreError::Internal(format!("Failed to iterate metadata tree: {}", e)))?;
+            let definition = deserialize(&data).map_err(|e| StoreError::Internal(format!("Failed to deserialize definition: {}", e)))?;
+            index.definitions.push(definition);
+        }
+
+        Ok(index)
+    }
+
+    /// Obtient l'arbre pour l'index
+    fn get_index_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.index_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open vector index tree: {}", e)))
+    }
+
+    /// Obtient l'arbre pour les déclarations
+    fn get_metadata_tree(&self) -> Result<sled::Tree> {
+        self.db.open_tree(&self.metadata_tree_name)
+            .map_err(|e| StoreError::Internal(format!("Failed to open metadata tree: {}", e)))
+    }
+
+    /// Trouve la déclaration d'un index
+    fn definition(&self, pattern: &Path) -> Option<&VectorDefinition> {
+        self.definitions.iter().find(|definition| definition.pattern == *pattern)
+    }
+
+    /// Enregistre une déclaration
+    fn write_definition(&mut self, definition: VectorDefinition) -> Result<()> {
+        let tree = self.get_metadata_tree()?;
+        let data = serialize(&definition).map_err(|e| StoreError::Internal(format!("Failed to serialize definition: {}", e)))?;
+
+        tree.insert(definition.id.to_be_bytes(), data).map_err(|e| StoreError::Internal(format!("Failed to insert definition into metadata tree: {}", e)))?;
+        tree.flush().map_err(|e| StoreError::Internal(format!("Failed to flush metadata tree: {}", e)))?;
+
+        // Mettre à jour le cache
+        self.definitions.retain(|existing| existing.id != definition.id);
+        self.definitions.push(definition);
+
+        Ok(())
+    }
+
+    /// Déclare un index, en construction jusqu'à `mark_ready` : les
+    /// écritures sont indexées, mais l'index ne répond pas encore aux
+    /// recherches
+    pub fn begin_index(&mut self, pattern: &Path, metric: VectorMetric, approximate: bool) -> Result<()> {
+        let id = self.definitions.iter().map(|definition| definition.id + 1).max().unwrap_or(0);
+        self.write_definition(VectorDefinition {
+            id,
+            pattern: pattern.clone(),
+            metric,
+            approximate,
+            ready: false,
+        })
+    }
+
+    /// Marque un index comme prêt, une fois les données existantes indexées
+    pub fn mark_ready(&mut self, pattern: &Path) -> Result<()> {
+        match self.definition(pattern) {
+            Some(definition) => {
+                let definition = VectorDefinition { ready: true, ..definition.clone() };
+                self.write_definition(definition)
+            },
+            // L'index a pu être supprimé pendant la construction
+            None => Ok(()),
+        }
+    }
+
+    /// Supprime un index, ses entrées et son graphe
+    pub fn remove_index(&mut self, pattern: &Path) -> Result<()> {
+        let id = match self.definition(pattern) {
+            Some(definition) => definition.id,
+            None => return Ok(()),
+        };
+
+        let metadata = self.get_metadata_tree()?;
+        metadata.remove(id.to_be_bytes()).map_err(|e| StoreError::Internal(format!("Failed to remove definition from metadata tree: {}", e)))?;
+        self.definitions.retain(|definition| definition.id != id);
+        self.graphs.lock().unwrap().remove(&id);
+
+        let tree = self.get_index_tree()?;
+        for key in tree.scan_prefix(id.to_be_bytes()).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+            tree.remove(key).map_err(|e| StoreError::Internal(format!("Failed to remove from index tree: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Liste les index déclarés : motif, métrique, mode approximatif et
+    /// état (prêt ou non)
+    pub fn indexes(&self) -> Vec<(Path, VectorMetric, bool, bool)> {
+        self.definitions.iter()
+            .map(|definition| (definition.pattern.clone(), definition.metric, definition.approximate, definition.ready))
+            .collect()
+    }
+
+    /// Compte les vecteurs d'un index
+    pub fn count_vectors(&self, pattern: &Path) -> Result<usize> {
+        let definition = match self.definition(pattern) {
+            Some(definition) => definition,
+            None => return Ok(0),
+        };
+
+        Ok(self.get_index_tree()?.scan_prefix(definition.id.to_be_bytes()).count())
+    }
+
+    /// Recherche les chemins d'un motif dont les vecteurs sont les plus
+    /// proches d'un vecteur, classés par similarité décroissante
+    ///
+    /// Renvoie `None` si aucun index prêt ne couvre le motif avec la
+    /// métrique demandée.
+    pub fn search(&self, pattern: &Path, query: &VectorQuery) -> Result<Option<Vec<(Path, f64)>>> {
+        let definition = self.definitions.iter().find(|definition| {
+            definition.ready
+                && pattern.matches(&definition.pattern)
+                && query.metric.is_none_or(|metric| metric == definition.metric)
+        });
+        let definition = match definition {
+            Some(definition) => definition,
+            None => return Ok(None),
+        };
+
+        let tree = self.get_index_tree()?;
+
+        // Le graphe couvre tout le motif de l'index : pour un motif plus
+        // étroit, la plupart de ses candidats seraient écartés
+        let scores = if definition.approximate && *pattern == definition.pattern {
+            self.search_graph(definition, &tree, query)?
+        } else {
+            let mut scores = Vec::new();
+            for item in tree.scan_prefix(definition.id.to_be_bytes()) {
+                let (key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+                let path: Path = deserialize(&key[4..])
+                    .map_err(|e| StoreError::Internal(format!("Failed to deserialize path: {}", e)))?;
+                if !path.matches(pattern) {
+                    continue;
+                }
+
+                if let Some(score) = definition.metric.similarity(&query.vector, &decode_vector(&data)?) {
+                    scores.push((path, score));
+                }
+            }
+            scores
+        };
+
+        Ok(Some(top(scores, query)))
+    }
+
+    /// Cherche les candidats d'une recherche dans le graphe d'un index,
+    /// construit au besoin, et les note d'après leur vecteur dans l'arbre
+    fn search_graph(&self, definition: &VectorDefinition, tree: &sled::Tree, query: &VectorQuery) -> Result<Vec<(Path, f64)>> {
+        let mut graphs = self.graphs.lock().unwrap();
+
+        let stale = graphs.get(&definition.id).is_none_or(|graph| graph.is_degraded());
+        if stale {
+            let mut graph = Hnsw::new(definition.metric);
+            for item in tree.scan_prefix(definition.id.to_be_bytes()) {
+                let (key, data) = item.map_err(|e| StoreError::Internal(format!("Failed to scan index tree: {}", e)))?;
+                let path: Path = deserialize(&key[4..])
+                    .map_err(|e| StoreError::Internal(format!("Failed to deserialize path: {}", e)))?;
+                graph.insert(path, decode_vector(&data)?);
+            }
+            graphs.insert(definition.id, graph);
+        }
+
+        let ef = (query.limit * 4).max(MIN_EF);
+        let mut scores = Vec::new();
+        for (path, _) in graphs[&definition.id].search(&query.vector, ef, ef) {
+            // Une transaction annulée a pu laisser un nœud dans le graphe
+            let key = definition.entry_key(&path).map_err(into_store_error)?;
+            let data = match tree.get(key).map_err(|e| StoreError::Internal(format!("Failed to read index tree: {}", e)))? {
+                Some(data) => data,
+                None => continue,
+            };
+
+            if let Some(score) = definition.metric.similarity(&query.vector, &decode_vector(&data)?) {
+                scores.push((path, score));
+            }
+        }
+
+        Ok(scores)
+    }
+}
+
+/// Encode un vecteur : ses composantes en little-endian
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Décode un vecteur
+fn decode_vector(data: &[u8]) -> Result<Vec<f32>> {
+    if !data.len().is_multiple_of(4) {
+        return Err(StoreError::DeserializationError("Invalid vector index entry".to_string()));
+    }
+
+    Ok(data.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect())
+}
+
+impl IndexImplementation for VectorIndex {
+    fn add_path(&mut self, _path: &Path) -> Result<()> {
+        // Les vecteurs ont besoin des valeurs
+        Ok(())
+    }
+
+    fn remove_path(&mut self, path: &Path) -> Result<()> {
+        let tree = self.get_index_tree()?;
+        let mut graphs = self.graphs.lock().unwrap();
+
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                let key = definition.entry_key(path).map_err(into_store_error)?;
+                tree.remove(key).map_err(|e| StoreError::Internal(format!("Failed to remove from index tree: {}", e)))?;
+                if let Some(graph) = graphs.get_mut(&definition.id) {
+                    graph.remove(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_by_prefix(&self, _prefix: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn find_by_pattern(&self, _pattern: &Path) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
+
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_index_tree()?])
+    }
+
+    fn indexes_values(&self) -> bool {
+        true
+    }
+
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, value: &Value) -> TreeResult<()> {
+        let mut graphs = self.graphs.lock().unwrap();
+
+        for definition in &self.definitions {
+            if !path.matches(&definition.pattern) {
+                continue;
+            }
+
+            // Seuls les vecteurs sont indexés ; une autre valeur retire le
+            // vecteur du chemin
+            let key = definition.entry_key(path)?;
+            let graph = graphs.get_mut(&definition.id);
+            match value {
+                Value::Vector(vector) => {
+                    trees[0].insert(&key, encode_vector(vector))?;
+                    if let Some(graph) = graph {
+                        graph.insert(path.clone(), vector.clone());
+                    }
+                },
+                _ => {
+                    trees[0].remove(&key)?;
+                    if let Some(graph) = graph {
+                        graph.remove(path);
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        let mut graphs = self.graphs.lock().unwrap();
+
+        for definition in &self.definitions {
+            if path.matches(&definition.pattern) {
+                trees[0].remove(&definition.entry_key(path)?)?;
+                if let Some(graph) = graphs.get_mut(&definition.id) {
+                    graph.remove(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        // Les déclarations sont conservées
+        self.get_index_tree()?.clear().map_err(|e| StoreError::Internal(format!("Failed to clear index tree: {}", e)))?;
+        self.graphs.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "VectorIndex"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_metrics() {
+        let cosine = VectorMetric::Cosine.similarity(&[1.0, 0.0], &[1.0, 1.0]).unwrap();
+        assert!((cosine - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert_eq!(VectorMetric::Euclidean.similarity(&[0.0, 0.0], &[3.0, 4.0]), Some(1.0 / 6.0));
+        assert_eq!(VectorMetric::Dot.similarity(&[1.0, 2.0], &[3.0, 4.0]), Some(11.0));
+
+        // Undefined similarities
+        assert_eq!(VectorMetric::Cosine.similarity(&[0.0, 0.0], &[1.0, 1.0]), None);
+        assert_eq!(VectorMetric::Dot.similarity(&[1.0], &[1.0, 2.0]), None);
+        assert_eq!(VectorMetric::from_name("euclidean"), Some(VectorMetric::Euclidean));
+    }
+
+    #[test]
+    fn test_exact_and_approximate_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(sled::open(dir.path()).unwrap());
+        let mut index = VectorIndex::new(db, "vector_index").unwrap();
+
+        let path = |s: &str| Path::from_str(s).unwrap();
+        let exact = path("docs.*.embedding");
+        let approximate = path("images.*.embedding");
+        index.begin_index(&exact, VectorMetric::Cosine, false).unwrap();
+        index.begin_index(&approximate, VectorMetric::Cosine, true).unwrap();
+        index.mark_ready(&exact).unwrap();
+        index.mark_ready(&approximate).unwrap();
+
+        let tree = index.get_index_tree().unwrap();
+        let mut documents = Vec::new();
+        for i in 0..200 {
+            let angle = i as f32 / 200.0 * std::f32::consts::FRAC_PI_2;
+            let vector = vec![angle.cos(), angle.sin()];
+            for collection in ["docs", "images"] {
+                let embedding = path(&format!("{}.d{}.embedding", collection, i));
+                index.stage_add(&[&tree], &embedding, &Value::Vector(vector.clone())).unwrap();
+            }
+            documents.push((path(&format!("docs.d{}.embedding", i)), vector));
+        }
+        // Other values are not indexed
+        index.stage_add(&[&tree], &path("docs.text.embedding"), &Value::String("x".to_string())).unwrap();
+        assert_eq!(index.count_vectors(&exact).unwrap(), 200);
+
+        let query = VectorQuery { vector: vec![1.0, 0.0], metric: None, limit: 3, min_similarity: None };
+        let found = index.search(&exact, &query).unwrap().unwrap();
+        let paths: Vec<Path> = found.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(paths, vec![path("docs.d0.embedding"), path("docs.d1.embedding"), path("docs.d2.embedding")]);
+        // Scanning the vectors gives the same results
+        assert_eq!(rank(&documents, &query), found);
+
+        // The graph finds the same neighbours, and follows the writes
+        let found = index.search(&approximate, &query).unwrap().unwrap();
+        let paths: Vec<Path> = found.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(paths, vec![path("images.d0.embedding"), path("images.d1.embedding"), path("images.d2.embedding")]);
+        index.stage_remove(&[&tree], &path("images.d0.embedding"), &Value::Null).unwrap();
+        index.stage_add(&[&tree], &path("images.d199.embedding"), &Value::Vector(vec![1.0, 0.0])).unwrap();
+        let found = index.search(&approximate, &query).unwrap().unwrap();
+        assert_eq!(found[0].0, path("images.d199.embedding"));
+        assert!(found.iter().all(|(found, _)| *found != path("images.d0.embedding")));
+
+        // Minimum similarity and metric
+        let query = VectorQuery { vector: vec![0.0, 1.0], metric: None, limit: 10, min_similarity: Some(0.9999) };
+        assert!(index.search(&exact, &query).unwrap().unwrap().len() < 10);
+        let query = VectorQuery { metric: Some(VectorMetric::Dot), ..query };
+        assert_eq!(index.search(&exact, &query).unwrap(), None);
+    }
+}
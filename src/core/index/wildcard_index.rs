@@ -1,12 +1,15 @@
 // src/core/index/wildcard_index.rs
-use std::collections::{HashSet, BTreeMap};
+use std::collections::{HashSet, BTreeSet};
 use std::sync::Arc;
 use sled::Db;
+use sled::transaction::ConflictableTransactionError;
 use bincode::{serialize, deserialize};
 
 use crate::core::path::{Path, PathSegment};
+use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
 use super::types::IndexImplementation;
+use super::tree::{IndexTree, TreeResult, into_store_error};
 
 /// Index optimisé pour les recherches avec wildcards
 pub struct WildcardIndex {
@@ -72,79 +75,87 @@ impl WildcardIndex {
         Ok(key.as_bytes().to_vec())
     }
     
-    /// Indexe un chemin pour les requêtes avec wildcards à un niveau
-    fn index_for_single_wildcards(&self, path: &Path) -> Result<()> {
-        let tree = self.get_single_tree()?;
+    /// Ajoute un chemin à l'ensemble associé à une clé
+    ///
+    /// Les ensembles sont triés, pour qu'un même ensemble ait toujours la
+    /// même représentation (et puisse être comparé lors d'une vérification).
+    fn insert_into_set(tree: &dyn IndexTree, key: &[u8], path: &Path) -> TreeResult<()> {
+        let mut paths = Self::read_set(tree, key)?;
+        paths.insert(path.clone());
+        
+        let serialized = serialize(&paths)
+            .map_err(|e| ConflictableTransactionError::Abort(StoreError::SerializationError(e.to_string())))?;
+        tree.insert(key, serialized)
+    }
+    
+    /// Retire un chemin de l'ensemble associé à une clé, sans toucher
+    /// aux autres chemins (la clé disparaît avec son dernier chemin)
+    fn remove_from_set(tree: &dyn IndexTree, key: &[u8], path: &Path) -> TreeResult<()> {
+        let mut paths = Self::read_set(tree, key)?;
+        if !paths.remove(path) {
+            return Ok(());
+        }
+        
+        if paths.is_empty() {
+            tree.remove(key)
+        } else {
+            let serialized = serialize(&paths)
+                .map_err(|e| ConflictableTransactionError::Abort(StoreError::SerializationError(e.to_string())))?;
+            tree.insert(key, serialized)
+        }
+    }
+    
+    /// Lit l'ensemble des chemins associé à une clé
+    fn read_set(tree: &dyn IndexTree, key: &[u8]) -> TreeResult<BTreeSet<Path>> {
+        match tree.get(key)? {
+            Some(data) => deserialize(&data)
+                .map_err(|e| ConflictableTransactionError::Abort(StoreError::DeserializationError(e.to_string()))),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+    
+    /// Calcule les clés d'un chemin pour les wildcards à un niveau
+    fn single_wildcard_keys(path: &Path) -> Result<Vec<Vec<u8>>> {
         let segments = path.segments();
+        let mut keys = Vec::with_capacity(segments.len());
         
         // Générer tous les motifs possibles avec un wildcard
         for wildcard_pos in 0..segments.len() {
             // Créer une copie du chemin avec une position en wildcard
-            let mut pattern_segments = Vec::with_capacity(segments.len());
-            
-            for (i, segment) in segments.iter().enumerate() {
+            let pattern_segments = segments.iter()
+            .enumerate()
+            .map(|(i, s)| {
                 if i == wildcard_pos {
-                    pattern_segments.push(PathSegment::new("*")); // Wildcard ici
+                    PathSegment::new("*") // Wildcard ici
                 } else {
-                    pattern_segments.push(segment.clone()); // Segment normal
+                    s.clone() // Segment normal
                 }
-            }
+            })
+            .collect::<Vec<_>>();
             
-            // Créer le chemin avec un wildcard
             let pattern_path = Path::from_segments(pattern_segments);
             println!("Creating pattern for indexing: {:?}", pattern_path);
             
-            // Créer la clé du motif
-            let pattern_key = Self::create_structural_pattern(&pattern_path)?;
-            
-            // Créer/mettre à jour le HashSet des chemins pour ce motif
-            let mut paths = if let Some(data) = tree.get(&pattern_key).map_err(|e| StoreError::Internal(format!("Failed to get pattern key: {}", e)))? {
-                let existing: HashSet<Path> = deserialize(&data).map_err(|e| StoreError::DeserializationError(e.to_string()))?;
-                existing
-            } else {
-                HashSet::new()
-            };
-            
-            // Ajouter le chemin actuel à l'ensemble
-            paths.insert(path.clone());
-            
-            // Stocker l'ensemble mis à jour
-            let serialized = serialize(&paths).map_err(|e| StoreError::SerializationError(e.to_string()))?;
-            println!("Storing path {:?} under pattern key: {}", 
-            path, String::from_utf8_lossy(&pattern_key));
-            tree.insert(pattern_key, serialized).map_err(|e| StoreError::Internal(format!("Failed to insert into single tree: {}", e)))?;
+            keys.push(Self::create_structural_pattern(&pattern_path)?);
         }
         
-        Ok(())
+        Ok(keys)
     }
     
-    
-    /// Indexe un chemin pour les requêtes avec wildcards multi-niveaux
-    fn index_for_multi_wildcards(&self, path: &Path) -> Result<()> {
-        let tree = self.get_multi_tree()?;
+    /// Calcule les clés d'un chemin pour les wildcards multi-niveaux (un
+    /// suffixe du chemin par clé)
+    fn multi_wildcard_keys(path: &Path) -> Result<Vec<Vec<u8>>> {
         let segments = path.segments()
         .iter()
         .map(|s| s.as_str())
         .collect::<Vec<_>>();
         
-        // Pour chaque suffixe du chemin
+        let mut keys = Vec::with_capacity(segments.len());
         for start_pos in 0..segments.len() {
-            let suffix = &segments[start_pos..];
-            let suffix_key = Self::create_suffix_key(suffix)?;
-            
-            // Stocker le chemin dans l'entrée du suffixe
-            let serialized_path = serialize(path).map_err(|e| 
-                StoreError::SerializationError(e.to_string())
-            )?;
-            
-            println!("Storing suffix: {:?} -> path: {:?}", 
-            String::from_utf8_lossy(&suffix_key), path);
-            
-            tree.insert(suffix_key, serialized_path)
-            .map_err(|e| StoreError::Internal(format!("Failed to update index: {}", e)))?;
+            keys.push(Self::create_suffix_key(&segments[start_pos..])?);
         }
         
-        Ok(())
+        Ok(keys)
     }
     
     /// Pour déboguer: lister toutes les clés dans l'arbre des wildcards à un niveau
@@ -157,12 +168,12 @@ impl WildcardIndex {
                 StoreError::Internal(format!("Failed to iterate tree: {}", e))
             )?;
             
-            let path: Path = deserialize(&value).map_err(|e|
+            let paths: HashSet<Path> = deserialize(&value).map_err(|e|
                 StoreError::DeserializationError(e.to_string())
             )?;
             
-            println!("Key: {} => Path: {:?}", 
-            String::from_utf8_lossy(&key), path);
+            println!("Key: {} => Paths: {:?}", 
+            String::from_utf8_lossy(&key), paths);
         }
         
         println!("=== END DUMP ===");
@@ -175,14 +186,15 @@ impl IndexImplementation for WildcardIndex {
         println!("WildcardIndex: Adding path: {:?}", path);
         
         // Indexer pour les deux types de wildcards
-        self.index_for_single_wildcards(path)?;
-        self.index_for_multi_wildcards(path)?;
+        let single_tree = self.get_single_tree()?;
+        let multi_tree = self.get_multi_tree()?;
+        self.stage_add(&[&single_tree, &multi_tree], path, &Value::Null).map_err(into_store_error)?;
         
         // Assurer la persistance
-        self.get_single_tree()?.flush().map_err(|e| 
+        single_tree.flush().map_err(|e| 
             StoreError::Internal(format!("Failed to flush single tree: {}", e))
         )?;
-        self.get_multi_tree()?.flush().map_err(|e| 
+        multi_tree.flush().map_err(|e| 
             StoreError::Internal(format!("Failed to flush multi tree: {}", e))
         )?;
         
@@ -191,46 +203,9 @@ impl IndexImplementation for WildcardIndex {
     
     fn remove_path(&mut self, path: &Path) -> Result<()> {
         // Supprimer pour les deux types de wildcards
-        
-        // Pour les wildcards à un niveau
         let single_tree = self.get_single_tree()?;
-        let segments = path.segments();
-        
-        for wildcard_pos in 0..segments.len() {
-            let pattern_segments = segments.iter()
-            .enumerate()
-            .map(|(i, s)| {
-                if i == wildcard_pos {
-                    PathSegment::new("*")
-                } else {
-                    s.clone()
-                }
-            })
-            .collect::<Vec<_>>();
-            
-            let pattern_path = Path::from_segments(pattern_segments);
-            let pattern_key = Self::create_structural_pattern(&pattern_path)?;
-            
-            single_tree.remove(pattern_key).map_err(|e| 
-                StoreError::Internal(format!("Failed to remove from single tree: {}", e))
-            )?;
-        }
-        
-        // Pour les wildcards multi-niveaux
         let multi_tree = self.get_multi_tree()?;
-        let segments_str = path.segments()
-        .iter()
-        .map(|s| s.as_str())
-        .collect::<Vec<_>>();
-        
-        for start_pos in 0..segments_str.len() {
-            let suffix = &segments_str[start_pos..];
-            let suffix_key = Self::create_suffix_key(suffix)?;
-            
-            multi_tree.remove(suffix_key).map_err(|e| 
-                StoreError::Internal(format!("Failed to remove from multi tree: {}", e))
-            )?;
-        }
+        self.stage_remove(&[&single_tree, &multi_tree], path, &Value::Null).map_err(into_store_error)?;
         
         // Assurer la persistance
         single_tree.flush().map_err(|e| 
@@ -243,6 +218,33 @@ impl IndexImplementation for WildcardIndex {
         Ok(())
     }
     
+    fn trees(&self) -> Result<Vec<sled::Tree>> {
+        Ok(vec![self.get_single_tree()?, self.get_multi_tree()?])
+    }
+    
+    fn stage_add(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        // Plusieurs chemins peuvent partager un motif ou un suffixe
+        for key in Self::single_wildcard_keys(path).map_err(ConflictableTransactionError::Abort)? {
+            Self::insert_into_set(trees[0], &key, path)?;
+        }
+        for key in Self::multi_wildcard_keys(path).map_err(ConflictableTransactionError::Abort)? {
+            Self::insert_into_set(trees[1], &key, path)?;
+        }
+        
+        Ok(())
+    }
+    
+    fn stage_remove(&self, trees: &[&dyn IndexTree], path: &Path, _value: &Value) -> TreeResult<()> {
+        for key in Self::single_wildcard_keys(path).map_err(ConflictableTransactionError::Abort)? {
+            Self::remove_from_set(trees[0], &key, path)?;
+        }
+        for key in Self::multi_wildcard_keys(path).map_err(ConflictableTransactionError::Abort)? {
+            Self::remove_from_set(trees[1], &key, path)?;
+        }
+        
+        Ok(())
+    }
+    
     fn find_by_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
         // Pour la recherche par préfixe, on utilise l'approche la plus simple
         // On parcourt tous les chemins dans l'index des wildcards à un niveau
@@ -255,12 +257,11 @@ impl IndexImplementation for WildcardIndex {
             let (_, value_bytes) = item
             .map_err(|e| StoreError::Internal(format!("Failed to iterate index: {}", e)))?;
             
-            let path: Path = deserialize(&value_bytes)
+            // Chaque motif est associé à l'ensemble des chemins qu'il couvre
+            let paths: HashSet<Path> = deserialize(&value_bytes)
             .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
             
-            if path.starts_with(prefix) {
-                results.insert(path);
-            }
+            results.extend(paths.into_iter().filter(|path| path.starts_with(prefix)));
         }
         
         println!("WildcardIndex: Found {} paths", results.len());
@@ -293,17 +294,19 @@ impl IndexImplementation for WildcardIndex {
             // Chercher tous les motifs qui pourraient correspondre si le format de clé n'est pas exact
             println!("Scanning all keys for potential matches");
             for item in tree.iter() {
-                let (key, value_bytes) = item.map_err(|e| 
+                let (_, value_bytes) = item.map_err(|e| 
                     StoreError::Internal(format!("Failed to iterate index: {}", e))
                 )?;
                 
-                let path: Path = deserialize(&value_bytes)
+                let paths: HashSet<Path> = deserialize(&value_bytes)
                 .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
                 
-                // Vérifier si le chemin correspond au motif
-                if path.matches(pattern) {
-                    println!("Found matching path via scan: {:?}", path);
-                    results.insert(path);
+                // Vérifier si les chemins correspondent au motif
+                for path in paths {
+                    if path.matches(pattern) {
+                        println!("Found matching path via scan: {:?}", path);
+                        results.insert(path);
+                    }
                 }
             }
         }
@@ -333,12 +336,7 @@ impl IndexImplementation for WildcardIndex {
                 
                 let tree = self.get_multi_tree()?;
                 
-                if let Some(value_bytes) = tree.get(&suffix_key).map_err(|e| 
-                    StoreError::Internal(format!("Failed to get from multi tree: {}", e))
-                )? {
-                    let path: Path = deserialize(&value_bytes)
-                    .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
-                    
+                for path in Self::read_set(&tree, &suffix_key).map_err(into_store_error)? {
                     // Vérifier que le chemin correspond au motif complet
                     if path.matches(pattern) {
                         println!("Found match with suffix: {:?}", path);
@@ -352,12 +350,14 @@ impl IndexImplementation for WildcardIndex {
                         StoreError::Internal(format!("Failed to scan multi tree: {}", e))
                     )?;
                     
-                    let path: Path = deserialize(&value_bytes)
+                    let paths: HashSet<Path> = deserialize(&value_bytes)
                     .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
                     
-                    if path.matches(pattern) {
-                        println!("Found match with suffix prefix: {:?}", path);
-                        results.insert(path);
+                    for path in paths {
+                        if path.matches(pattern) {
+                            println!("Found match with suffix prefix: {:?}", path);
+                            results.insert(path);
+                        }
                     }
                 }
             } else {
@@ -368,12 +368,14 @@ impl IndexImplementation for WildcardIndex {
                         StoreError::Internal(format!("Failed to iterate index: {}", e))
                     )?;
                     
-                    let path: Path = deserialize(&value_bytes)
+                    let paths: HashSet<Path> = deserialize(&value_bytes)
                     .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
                     
-                    if path.matches(pattern) {
-                        println!("Found match via full scan: {:?}", path);
-                        results.insert(path);
+                    for path in paths {
+                        if path.matches(pattern) {
+                            println!("Found match via full scan: {:?}", path);
+                            results.insert(path);
+                        }
                     }
                 }
             }
@@ -1,23 +1,27 @@
-use std::any::Any;
 // src/core/index/worker.rs (modifié)
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc::{self, Sender, Receiver};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
 
 use crate::core::index::value_index;
 use crate::core::path::Path;
+use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
 use super::types::{IndexOp, IndexStats, IndexImplementation};
-use super::value_index::ValueIndex;
+use super::state::IndexState;
+use super::tree::{IndexTree, into_store_error};
 
 // Trait pour effacer le type générique de l'index
 trait AnyIndex: Send + Sync {
     fn add_path(&mut self, path: &Path) -> Result<()>;
     fn remove_path(&mut self, path: &Path) -> Result<()>;
+    // Index dont les entrées dépendent des valeurs (sinon, seul le
+    // chemin compte)
+    fn add_with_value(&mut self, path: &Path, value: &Value) -> Result<()>;
+    fn remove_value(&mut self, path: &Path, value: &Value) -> Result<()>;
     fn name(&self) -> &str;
-    // Ajout de ces méthodes pour le downcasting
-    fn as_any(&self) -> &dyn Any;
-    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 // Implémentation de AnyIndex qui enveloppe un IndexImplementation
@@ -37,41 +41,74 @@ impl<T: IndexImplementation + 'static> AnyIndex for IndexWrapper<T> {
         index.remove_path(path)
     }
     
-    fn name(&self) -> &str {
-        &self.name
+    fn add_with_value(&mut self, path: &Path, value: &Value) -> Result<()> {
+        let mut index = self.index.lock().unwrap();
+        if !index.indexes_values() {
+            return index.add_path(path);
+        }
+        
+        let trees = index.trees()?;
+        let views: Vec<&dyn IndexTree> = trees.iter().map(|tree| tree as &dyn IndexTree).collect();
+        index.stage_add(&views, path, value).map_err(into_store_error)
     }
-
-    fn as_any(&self) -> &dyn Any {
-        &self.index
+    
+    fn remove_value(&mut self, path: &Path, value: &Value) -> Result<()> {
+        let index = self.index.lock().unwrap();
+        if !index.indexes_values() {
+            return Ok(());
+        }
+        
+        let trees = index.trees()?;
+        let views: Vec<&dyn IndexTree> = trees.iter().map(|tree| tree as &dyn IndexTree).collect();
+        index.stage_remove(&views, path, value).map_err(into_store_error)
     }
     
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        &mut self.index
+    fn name(&self) -> &str {
+        &self.name
     }
 }
 
 /// Gestionnaire des opérations d'indexation asynchrones
 pub struct IndexWorker {
     /// Émetteur pour les opérations d'indexation
-    tx: Option<Sender<IndexOp>>,
+    tx: Option<UnboundedSender<IndexOp>>,
     /// Handle de la tâche worker
     worker_handle: Option<JoinHandle<()>>,
     /// Statistiques d'indexation
     stats: Arc<Mutex<IndexStats>>,
     /// Liste des index (utilisée uniquement pour le démarrage)
     indexes: Vec<Box<dyn AnyIndex>>,
+    /// État durable des index (watermark), s'il y en a un
+    state: Option<Arc<IndexState>>,
+    /// Nombre d'opérations envoyées au worker
+    submitted: Arc<AtomicU64>,
+    /// Nombre d'opérations traitées par le worker
+    processed: watch::Receiver<u64>,
+    /// Émetteur du nombre d'opérations traitées (cédé au worker au démarrage)
+    processed_tx: Option<watch::Sender<u64>>,
 }
 
 impl IndexWorker {
     /// Crée un nouveau worker d'indexation
     pub fn new() -> Self {
+        let (processed_tx, processed) = watch::channel(0);
+        
         IndexWorker {
             tx: None,
             worker_handle: None,
             stats: Arc::new(Mutex::new(IndexStats::default())),
             indexes: Vec::new(),
+            state: None,
+            submitted: Arc::new(AtomicU64::new(0)),
+            processed,
+            processed_tx: Some(processed_tx),
         }
     }
+    
+    /// Définit l'état durable mis à jour par les opérations `Watermark`
+    pub fn set_state(&mut self, state: Arc<IndexState>) {
+        self.state = Some(state);
+    }
 
     pub fn add_index<T: IndexImplementation + 'static>(&mut self, index_impl: Arc<Mutex<T>>) -> Result<()> {
         // Obtenir le nom de l'index
@@ -103,16 +140,22 @@ impl IndexWorker {
         
         println!("Worker: Starting worker task with {} indexes", self.indexes.len());
         
-        // Création du canal pour la communication
-        let (tx, rx) = mpsc::channel(1000);
+        // Création du canal pour la communication (non borné, pour que
+        // les écritures synchrones du store puissent y envoyer leurs
+        // opérations dans l'ordre)
+        let (tx, rx) = mpsc::unbounded_channel();
         let stats = Arc::clone(&self.stats);
+        let state = self.state.clone();
+        let processed = self.processed_tx.take().ok_or_else(||
+            StoreError::Internal("Index worker already started".to_string())
+        )?;
         
         // Conversion en liste de Box<dyn AnyIndex>
         let indexes = std::mem::take(&mut self.indexes);
         
         // Démarre la tâche de traitement
         let handle = tokio::spawn(async move {
-            Self::process_operations(rx, indexes, stats).await;
+            Self::process_operations(rx, indexes, stats, state, processed).await;
         });
         
         self.tx = Some(tx);
@@ -124,9 +167,11 @@ impl IndexWorker {
 
     /// Traite les opérations en arrière-plan
     async fn process_operations(
-        mut rx: Receiver<IndexOp>,
+        mut rx: UnboundedReceiver<IndexOp>,
         mut indexes: Vec<Box<dyn AnyIndex>>,
         stats: Arc<Mutex<IndexStats>>,
+        state: Option<Arc<IndexState>>,
+        processed: watch::Sender<u64>,
     ) {
         
         println!("Worker: Started processing operations with {} indexes", indexes.len());
@@ -178,6 +223,15 @@ impl IndexWorker {
                         stats.pending_operations = stats.pending_operations.saturating_sub(1);
                     }
                 },
+                IndexOp::Watermark(sequence) => {
+                    // Les opérations sont traitées dans l'ordre : toutes
+                    // celles qui précèdent sont appliquées
+                    if let Some(state) = &state {
+                        if let Err(e) = state.advance(sequence) {
+                            eprintln!("Error saving index watermark: {:?}", e);
+                        }
+                    }
+                },
                 IndexOp::Flush => {
                     // Juste un signal pour traiter toutes les opérations en attente
                     println!("Worker: Flushing operations");
@@ -186,22 +240,23 @@ impl IndexWorker {
                     println!("Worker: Shutting down");
                     break; // Sortir de la boucle pour arrêter
                 },
+                IndexOp::RemoveValue(path, value) => {
+                    // Seuls les index de valeurs dépendent de l'ancienne valeur
+                    for index in &mut indexes {
+                        if let Err(e) = index.remove_value(&path, &value) {
+                            eprintln!("Error removing value from {}: {:?}", index.name(), e);
+                        }
+                    }
+                },
                 IndexOp::AddWithValue(path, value) => {
-                    // Chercher l'index de valeurs dans la liste des index
+                    // Les index de valeurs ont besoin de la valeur, les
+                    // autres index seulement du chemin
                     let mut success = false;
                     for index in &mut indexes {
-                        if index.name() == "ValueIndex" {
-                            // Downcast depuis la référence
-                            if let Some(value_index) = index.as_any_mut().downcast_mut::<ValueIndex>() {
-                                match value_index.add_with_value(&path, &value) {
-                                    Ok(()) => {
-                                        success = true;
-                                        break;
-                                    },
-                                    Err(e) => {
-                                        eprintln!("Error adding path with value: {:?}", e);
-                                    }
-                                }
+                        match index.add_with_value(&path, &value) {
+                            Ok(()) => success = true,
+                            Err(e) => {
+                                eprintln!("Error adding path with value to {}: {:?}", index.name(), e);
                             }
                         }
                     }
@@ -214,6 +269,9 @@ impl IndexWorker {
                     }
                 },
             }
+            
+            // Signaler l'opération traitée à ceux qui attendent le worker
+            processed.send_modify(|count| *count += 1);
         }
     }
     
@@ -221,24 +279,50 @@ impl IndexWorker {
     
     /// Envoie une opération d'indexation au worker
     pub async fn submit_operation(&self, op: IndexOp) -> Result<()> {
+        self.submit(op)
+    }
+    
+    /// Envoie une opération d'indexation au worker sans attendre
+    ///
+    /// Les opérations sont appliquées dans l'ordre d'envoi.
+    pub fn submit(&self, op: IndexOp) -> Result<()> {
         let tx = self.tx.as_ref().ok_or_else(|| 
             StoreError::Internal("Index worker not started".to_string())
         )?;
         
         // Incrémenter le compteur d'opérations en attente
-        if matches!(op, IndexOp::Add(_) | IndexOp::Remove(_)) {
+        if matches!(op, IndexOp::Add(_) | IndexOp::Remove(_) | IndexOp::AddWithValue(..)) {
             let mut stats = self.stats.lock().unwrap();
             stats.pending_operations += 1;
         }
         
         // Envoyer l'opération au worker
-        tx.send(op).await.map_err(|_| 
-            StoreError::Internal("Failed to send operation to index worker".to_string())
+        self.submitted.fetch_add(1, Ordering::SeqCst);
+        if tx.send(op).is_err() {
+            self.submitted.fetch_sub(1, Ordering::SeqCst);
+            return Err(StoreError::Internal("Failed to send operation to index worker".to_string()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Attend que le worker ait traité toutes les opérations déjà envoyées
+    pub async fn wait_processed(&self) -> Result<()> {
+        let target = self.submitted.load(Ordering::SeqCst);
+        let mut processed = self.processed.clone();
+        
+        processed.wait_for(|count| *count >= target).await.map_err(|_|
+            StoreError::Internal("Index worker stopped".to_string())
         )?;
         
         Ok(())
     }
     
+    /// Indique si toutes les opérations envoyées ont été traitées
+    pub fn is_idle(&self) -> bool {
+        *self.processed.borrow() >= self.submitted.load(Ordering::SeqCst)
+    }
+    
     /// Obtient les statistiques actuelles
     pub fn get_stats(&self) -> IndexStats {
         let stats = self.stats.lock().unwrap();
@@ -248,7 +332,7 @@ impl IndexWorker {
     /// Arrête le worker
     pub async fn shutdown(&self) -> Result<()> {
         if let Some(tx) = &self.tx {
-            let _ = tx.send(IndexOp::Shutdown).await;
+            let _ = tx.send(IndexOp::Shutdown);
         }
         
         Ok(())
@@ -262,6 +346,10 @@ impl Clone for IndexWorker {
             worker_handle: None,
             stats: Arc::clone(&self.stats),
             indexes: Vec::new(), // Les index ne sont pas clonés, ils ne sont utilisés que lors du démarrage
+            state: self.state.clone(),
+            submitted: Arc::clone(&self.submitted),
+            processed: self.processed.clone(),
+            processed_tx: None,
         }
     }
 }
\ No newline at end of file
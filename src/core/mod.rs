@@ -4,6 +4,11 @@ pub mod store;
 pub mod errors;
 pub mod entity;
 pub mod index;
+pub mod transaction;
+pub mod snapshot;
+pub mod version;
+pub mod changes;
+pub mod patch;
 
 
 pub use path::Path;
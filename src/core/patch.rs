@@ -0,0 +1,351 @@
+//! JSON Patch (RFC 6902) for entities
+//!
+//! A patch is a list of operations addressing the fields of an entity
+//! with JSON Pointers (RFC 6901), relative to the path the entity is
+//! stored under. Each operation is translated into endpoint writes staged
+//! on a transaction, so a failing operation (including a failed `test`)
+//! leaves the store untouched.
+//!
+//! Empty objects and arrays have no endpoint: removing the last item of
+//! an array removes the array itself.
+
+use serde::{Serialize, Deserialize};
+
+use super::path::{Path, PathSegment};
+use super::entity::{Entity, reconstruct_entity};
+use super::errors::{Result, StoreError};
+use super::store::{ReadStore, Store};
+use super::transaction::Transaction;
+
+/// An operation of a JSON Patch document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Add a field, insert an array item, or replace the whole entity
+    Add { path: String, value: serde_json::Value },
+    /// Remove a field or an array item
+    Remove { path: String },
+    /// Replace an existing value
+    Replace { path: String, value: serde_json::Value },
+    /// Remove a value and add it at another location
+    Move { from: String, path: String },
+    /// Add a copy of a value at another location
+    Copy { from: String, path: String },
+    /// Check that a location holds the given value
+    Test { path: String, value: serde_json::Value },
+}
+
+/// Parse a JSON Patch document (an array of operations)
+pub fn parse_patch(json: serde_json::Value) -> Result<Vec<PatchOperation>> {
+    serde_json::from_value(json)
+        .map_err(|e| StoreError::InvalidOperation(format!("Invalid JSON Patch: {}", e)))
+}
+
+/// A location in the entity, resolved from a JSON Pointer
+enum Location {
+    /// The entity itself
+    Root(Path),
+    /// A field of an object
+    Field(Path),
+    /// An item of an array (`index` may be the length when appending)
+    Item { array: Path, index: usize, len: usize },
+}
+
+impl Location {
+    /// Path of the value at this location
+    fn path(&self) -> Path {
+        match self {
+            Location::Root(path) | Location::Field(path) => path.clone(),
+            Location::Item { array, index, .. } => item_path(array, *index),
+        }
+    }
+}
+
+impl<S: Store + ?Sized> Transaction<'_, S> {
+    /// Stage a JSON Patch on the entity under a prefix
+    ///
+    /// The operations are applied in order, each one seeing the writes of
+    /// the previous ones. An error, such as a failed `test`, is returned
+    /// as soon as an operation fails.
+    pub fn apply_patch(&mut self, prefix: &Path, patch: &[PatchOperation]) -> Result<()> {
+        for operation in patch {
+            self.apply_patch_operation(prefix, operation)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_patch_operation(&mut self, prefix: &Path, operation: &PatchOperation) -> Result<()> {
+        match operation {
+            PatchOperation::Add { path, value } => {
+                let location = self.resolve_pointer(prefix, path, true)?;
+                self.add_at(location, Entity::from_json(value.clone())?)
+            },
+            PatchOperation::Remove { path } => {
+                let location = self.resolve_pointer(prefix, path, false)?;
+                self.remove_at(location, path)
+            },
+            PatchOperation::Replace { path, value } => {
+                let location = self.resolve_pointer(prefix, path, false)?;
+                self.value_at(&location, path)?;
+                self.replace_entity(&location.path(), &Entity::from_json(value.clone())?)
+            },
+            PatchOperation::Move { from, path } => {
+                if from == path {
+                    return Ok(());
+                }
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err(StoreError::InvalidOperation(
+                        format!("Cannot move {} into one of its children", from)
+                    ));
+                }
+
+                let source = self.resolve_pointer(prefix, from, false)?;
+                let value = self.value_at(&source, from)?;
+                self.remove_at(source, from)?;
+
+                let target = self.resolve_pointer(prefix, path, true)?;
+                self.add_at(target, value)
+            },
+            PatchOperation::Copy { from, path } => {
+                let source = self.resolve_pointer(prefix, from, false)?;
+                let value = self.value_at(&source, from)?;
+
+                let target = self.resolve_pointer(prefix, path, true)?;
+                self.add_at(target, value)
+            },
+            PatchOperation::Test { path, value } => {
+                let location = self.resolve_pointer(prefix, path, false)?;
+                let actual = self.value_at(&location, path)?;
+
+                if !entities_equal(&actual, &Entity::from_json(value.clone())?) {
+                    return Err(StoreError::InvalidOperation(
+                        format!("JSON Patch test failed at {}", path)
+                    ));
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Resolve a JSON Pointer relative to the entity prefix
+    ///
+    /// Whether a token names a field or an array index depends on the
+    /// value it is applied to. `-` designates the end of an array, and is
+    /// only accepted when adding.
+    fn resolve_pointer(&self, prefix: &Path, pointer: &str, adding: bool) -> Result<Location> {
+        if pointer.is_empty() {
+            return Ok(Location::Root(prefix.clone()));
+        }
+
+        let tokens = pointer.strip_prefix('/')
+            .ok_or_else(|| invalid_pointer(pointer))?
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect::<Vec<_>>();
+
+        let mut parent = prefix.clone();
+
+        for (i, token) in tokens.iter().enumerate() {
+            let is_last = i == tokens.len() - 1;
+
+            match self.array_len(&parent)? {
+                Some(len) => {
+                    let index = if token == "-" && adding && is_last {
+                        len
+                    } else {
+                        parse_index(token).ok_or_else(|| invalid_pointer(pointer))?
+                    };
+
+                    // Adding may insert at the end, every other access
+                    // must target an existing item
+                    let in_bounds = if adding && is_last { index <= len } else { index < len };
+                    if !in_bounds {
+                        return Err(StoreError::InvalidOperation(
+                            format!("Array index out of bounds in {}", pointer)
+                        ));
+                    }
+
+                    if is_last {
+                        return Ok(Location::Item { array: parent, index, len });
+                    }
+                    parent = item_path(&parent, index);
+                },
+                None => {
+                    let segment = PathSegment::new(token.as_str());
+                    if token.is_empty() || token.contains('.') || segment.is_wildcard() || segment.is_array_index() {
+                        return Err(invalid_pointer(pointer));
+                    }
+
+                    parent.push(token.clone());
+                    if is_last {
+                        return Ok(Location::Field(parent));
+                    }
+                },
+            }
+        }
+
+        unreachable!("a pointer has at least one token")
+    }
+
+    /// Length of the array stored at a path, if the path holds an array
+    fn array_len(&self, path: &Path) -> Result<Option<usize>> {
+        let len = self.list_prefix(path)?
+            .iter()
+            .filter_map(|child| child.segment(path.len()).and_then(PathSegment::as_index))
+            .max()
+            .map(|last| last + 1);
+
+        Ok(len)
+    }
+
+    /// Read the value at a location, failing if there is none
+    fn value_at(&self, location: &Location, pointer: &str) -> Result<Entity> {
+        reconstruct_entity(self, &location.path())
+            .map_err(|_| StoreError::InvalidOperation(format!("No value at {}", pointer)))
+    }
+
+    /// Add a value at a location
+    fn add_at(&mut self, location: Location, value: Entity) -> Result<()> {
+        match location {
+            Location::Root(path) => self.replace_entity(&path, &value),
+            Location::Field(path) => {
+                // The parent object must already exist
+                let parent = Path::from_segments(path.segments()[..path.len() - 1].to_vec());
+                if self.list_prefix(&parent)?.is_empty() {
+                    return Err(StoreError::NotFound(parent));
+                }
+
+                self.replace_entity(&path, &value)
+            },
+            Location::Item { array, index, len } => {
+                if index == len {
+                    return self.replace_entity(&item_path(&array, index), &value);
+                }
+
+                // Inserting shifts the following items
+                let mut items = self.array_items(&array)?;
+                items.insert(index, value);
+                self.replace_entity(&array, &Entity::Array(items))
+            },
+        }
+    }
+
+    /// Remove the value at a location
+    fn remove_at(&mut self, location: Location, pointer: &str) -> Result<()> {
+        self.value_at(&location, pointer)?;
+
+        match location {
+            Location::Root(path) | Location::Field(path) => {
+                for child in self.list_prefix(&path)? {
+                    self.delete(&child)?;
+                }
+                Ok(())
+            },
+            Location::Item { array, index, .. } => {
+                // Removing shifts the following items
+                let mut items = self.array_items(&array)?;
+                items.remove(index);
+                self.replace_entity(&array, &Entity::Array(items))
+            },
+        }
+    }
+
+    /// Read the items of the array stored at a path
+    fn array_items(&self, array: &Path) -> Result<Vec<Entity>> {
+        match reconstruct_entity(self, array)? {
+            Entity::Array(items) => Ok(items),
+            _ => Err(StoreError::InvalidOperation(format!("{} is not an array", array))),
+        }
+    }
+}
+
+/// Path of an array item
+fn item_path(array: &Path, index: usize) -> Path {
+    let mut path = array.clone();
+    path.push(format!("[{}]", index));
+    path
+}
+
+/// Parse an array index token (no sign or leading zero allowed)
+fn parse_index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) || !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    token.parse().ok()
+}
+
+/// Error for a pointer that cannot address the entity
+fn invalid_pointer(pointer: &str) -> StoreError {
+    StoreError::InvalidOperation(format!("Invalid JSON Pointer: {}", pointer))
+}
+
+/// Compare two entities as JSON values (numbers compare by value)
+fn entities_equal(a: &Entity, b: &Entity) -> bool {
+    match (a, b) {
+        (Entity::Integer(i), Entity::Float(f)) | (Entity::Float(f), Entity::Integer(i)) => *i as f64 == *f,
+        (Entity::Object(a), Entity::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(key, value)| b.get(key).is_some_and(|other| entities_equal(value, other)))
+        },
+        (Entity::Array(a), Entity::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| entities_equal(a, b))
+        },
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::storage::MemoryStore;
+
+    #[test]
+    fn test_apply_patch() {
+        let mut store = MemoryStore::new();
+        let prefix = Path::from_str("users.u-1").unwrap();
+
+        let mut tx = Transaction::new(&mut store);
+        tx.set_entity(&prefix, &Entity::from_json(serde_json::json!({
+            "name": "alice",
+            "tags": ["a", "c"],
+            "address": { "city": "Paris" }
+        })).unwrap()).unwrap();
+        tx.commit().unwrap();
+
+        let patch = parse_patch(serde_json::json!([
+            { "op": "test", "path": "/name", "value": "alice" },
+            { "op": "add", "path": "/tags/1", "value": "b" },
+            { "op": "add", "path": "/tags/-", "value": "d" },
+            { "op": "move", "from": "/address/city", "path": "/city" },
+            { "op": "copy", "from": "/name", "path": "/login" },
+            { "op": "remove", "path": "/tags/0" },
+            { "op": "replace", "path": "/name", "value": "bob" }
+        ])).unwrap();
+
+        let mut tx = Transaction::new(&mut store);
+        tx.apply_patch(&prefix, &patch).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(reconstruct_entity(&store, &prefix).unwrap().to_json(), serde_json::json!({
+            "name": "bob",
+            "login": "alice",
+            "tags": ["b", "c", "d"],
+            "city": "Paris"
+        }));
+
+        // A failed test discards the whole patch
+        let patch = parse_patch(serde_json::json!([
+            { "op": "remove", "path": "/login" },
+            { "op": "test", "path": "/name", "value": "alice" }
+        ])).unwrap();
+
+        let mut tx = Transaction::new(&mut store);
+        assert!(tx.apply_patch(&prefix, &patch).is_err());
+        drop(tx);
+
+        assert!(store.exists(&Path::from_str("users.u-1.login").unwrap()).unwrap());
+    }
+}
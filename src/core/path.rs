@@ -3,6 +3,7 @@
 //! This module defines the Path structure, which represents
 //! a hierarchical path to a specific data endpoint in the database.
 
+use std::cmp::Ordering;
 use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
@@ -30,8 +31,39 @@ pub enum SegmentType {
     ArrayIndex(usize),
 }
 
+impl SegmentType {
+    /// Rank of the segment type in the path order
+    fn rank(&self) -> u8 {
+        match self {
+            SegmentType::ArrayIndex(_) => 0,
+            SegmentType::Named(_) => 1,
+            SegmentType::SingleWildcard => 2,
+            SegmentType::MultiWildcard => 3,
+        }
+    }
+}
+
+/// Order segments as their keys sort in a persistent store: array indices
+/// before names, each by index or by name. The variants keep their
+/// declaration order, which their serialized form depends on.
+impl Ord for SegmentType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SegmentType::Named(a), SegmentType::Named(b)) => a.cmp(b),
+            (SegmentType::ArrayIndex(a), SegmentType::ArrayIndex(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SegmentType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// A segment in a path
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct PathSegment(SegmentType);
 
 impl PathSegment {
@@ -125,7 +157,7 @@ impl PathSegment {
 }
 
 /// A path in the database (e.g., "users.u-123456.profile.bio")
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Path {
     segments: Vec<PathSegment>,
 }
@@ -196,6 +228,14 @@ impl Path {
         // Check if the pattern has a multi-wildcard
         for (i, segment) in pattern.segments().iter().enumerate() {
             if segment.is_multi_wildcard() {
+                // The segments before the multi-wildcard must match one by one
+                if self.len() < i || !pattern.segments()[..i].iter()
+                    .zip(self.segments.iter())
+                    .all(|(pattern_segment, segment)| pattern_segment.matches(segment))
+                {
+                    return false;
+                }
+                
                 // A multi-wildcard can match zero or more segments
 
                 // If it's the last segment, the multi-wildcard matches everything
@@ -335,6 +375,12 @@ mod tests {
         assert!(path2.matches(&pattern));
         assert!(path3.matches(&pattern));
         assert!(!path4.matches(&pattern));
+        
+        // Segments before a trailing multi-wildcard must still match
+        let trailing: Path = "users.**".parse().unwrap();
+        let other: Path = "orders.o-1.total".parse().unwrap();
+        assert!(path2.matches(&trailing));
+        assert!(!other.matches(&trailing));
     }
     
     #[test]
@@ -0,0 +1,111 @@
+//! Point-in-time snapshots
+//!
+//! A snapshot is a read-only view of a store frozen at the moment it
+//! was taken. Writes made to the store afterwards are not visible
+//! through it, so reads spanning many endpoints (entity reconstruction,
+//! wildcard queries) always see a consistent state.
+
+use chrono::{DateTime, Utc};
+
+use super::path::Path;
+use super::value::Value;
+use super::entity::{Entity, reconstruct_entity};
+use super::errors::Result;
+use super::store::ReadStore;
+use super::version::Version;
+
+/// A read-only view of a store at a given sequence number
+pub struct Snapshot {
+    /// Store-specific view implementing the reads
+    view: Box<dyn ReadStore>,
+    /// Sequence number of the last write visible in the snapshot
+    sequence: u64,
+}
+
+impl Snapshot {
+    /// Wrap a store-specific view taken at the given sequence number
+    pub fn new(view: Box<dyn ReadStore>, sequence: u64) -> Self {
+        Snapshot { view, sequence }
+    }
+
+    /// Get the sequence number the snapshot was taken at
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Reconstruct an entity as it was when the snapshot was taken
+    pub fn get_entity(&self, prefix: &Path) -> Result<Entity> {
+        reconstruct_entity(self, prefix)
+    }
+}
+
+impl ReadStore for Snapshot {
+    fn get(&self, path: &Path) -> Result<Value> {
+        self.view.get(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        self.view.exists(path)
+    }
+
+    fn list_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
+        self.view.list_prefix(prefix)
+    }
+
+    fn get_prefix(&self, prefix: &Path) -> Result<Vec<(Path, Value)>> {
+        self.view.get_prefix(prefix)
+    }
+
+    fn query(&self, pattern: &Path) -> Result<Vec<(Path, Value)>> {
+        self.view.query(pattern)
+    }
+
+    fn count(&self) -> Result<usize> {
+        self.view.count()
+    }
+
+    fn count_prefix(&self, prefix: &Path) -> Result<usize> {
+        self.view.count_prefix(prefix)
+    }
+
+    fn history(&self, path: &Path, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<Version>> {
+        self.view.history(path, from, to)
+    }
+
+    fn get_as_of(&self, path: &Path, as_of: DateTime<Utc>) -> Result<Value> {
+        self.view.get_as_of(path, as_of)
+    }
+
+    fn get_prefix_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        self.view.get_prefix_as_of(prefix, as_of)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::store::Store;
+    use crate::storage::MemoryStore;
+
+    fn path(s: &str) -> Path {
+        Path::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_ignores_later_writes() {
+        let mut store = MemoryStore::new();
+        store.set(path("users.u-1.name"), Value::from("alice")).unwrap();
+        store.set(path("users.u-1.email"), Value::from("alice@example.com")).unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        store.set(path("users.u-1.name"), Value::from("bob")).unwrap();
+        store.delete(&path("users.u-1.email")).unwrap();
+        store.set(path("users.u-2.name"), Value::from("carol")).unwrap();
+
+        assert_eq!(snapshot.get(&path("users.u-1.name")).unwrap(), Value::from("alice"));
+        assert!(snapshot.exists(&path("users.u-1.email")).unwrap());
+        assert_eq!(snapshot.count_prefix(&path("users")).unwrap(), 2);
+        assert!(snapshot.sequence() < store.snapshot().unwrap().sequence());
+    }
+}
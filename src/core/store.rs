@@ -1,42 +1,309 @@
 use std::any::Any;
 
+use chrono::{DateTime, Utc};
+
 use crate::core::path::Path;
 use crate::core::value::Value;
-use crate::core::errors::Result;
+use crate::core::errors::{Result, StoreError};
+use crate::core::snapshot::Snapshot;
+use crate::core::version::{self, Version, RetentionPolicy};
+use crate::core::changes::ChangeFeed;
+use crate::core::index::{IndexConsistency, ValueRange, VectorQuery, GeoArea, IndexDefinition, IndexInfo};
+use crate::core::transaction::Transaction;
 
-/// Trait defining the core operations of a store
-pub trait Store: Send + Sync {
-    /// Set a value at the given path
-    fn set(&mut self, path: Path, value: Value) -> Result<()>;
-    
+/// A single write operation, used to apply several writes atomically
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOp {
+    /// Set a value at a path
+    Set(Path, Value),
+    /// Delete the value at a path
+    Delete(Path),
+}
+
+impl WriteOp {
+    /// Get the path targeted by this operation
+    pub fn path(&self) -> &Path {
+        match self {
+            WriteOp::Set(path, _) => path,
+            WriteOp::Delete(path) => path,
+        }
+    }
+}
+
+/// Endpoints read one at a time from a store, in path order
+pub type ScannedEndpoints<'a> = Box<dyn Iterator<Item = Result<(Path, Value)>> + 'a>;
+
+/// Apply a batch of deletes, skipping the store when there is nothing
+/// to delete
+fn delete_all<S: Store + ?Sized>(store: &mut S, ops: Vec<WriteOp>) -> Result<usize> {
+    let count = ops.len();
+    if count > 0 {
+        store.apply_batch(ops)?;
+    }
+
+    Ok(count)
+}
+
+/// Trait defining the read operations of a store
+pub trait ReadStore: Send + Sync {
     /// Get a value at the given path
     fn get(&self, path: &Path) -> Result<Value>;
-    
-    /// Delete a value at the given path
-    fn delete(&mut self, path: &Path) -> Result<()>;
-    
+
     /// Check if a path exists in the store
     fn exists(&self, path: &Path) -> Result<bool>;
-    
+
     /// List all paths that start with the given prefix
     fn list_prefix(&self, prefix: &Path) -> Result<Vec<Path>>;
-    
+
     /// Get all values under a prefix (for entity reconstruction)
     fn get_prefix(&self, prefix: &Path) -> Result<Vec<(Path, Value)>>;
-    
+
     /// Query paths that match a pattern (which may contain wildcards)
     fn query(&self, pattern: &Path) -> Result<Vec<(Path, Value)>>;
-    
+
+    /// Iterate the values whose path matches a pattern, in path order
+    ///
+    /// Stores with ordered keys read the matches as they go; the others
+    /// collect and sort them.
+    fn scan_matching(&self, pattern: &Path) -> Result<ScannedEndpoints<'_>> {
+        let mut pairs = self.query(pattern)?;
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Box::new(pairs.into_iter().map(Ok)))
+    }
+
     /// Count the number of paths in the store
     fn count(&self) -> Result<usize>;
-    
+
     /// Count the number of paths under a prefix
     fn count_prefix(&self, prefix: &Path) -> Result<usize>;
+
+    /// Find the paths matching a pattern whose value lies in a range,
+    /// through a value index
+    ///
+    /// Returns `None` when no value index covers the pattern; callers then
+    /// scan the values matching the pattern.
+    fn find_by_value_range(&self, _pattern: &Path, _range: &ValueRange) -> Result<Option<Vec<Path>>> {
+        Ok(None)
+    }
+
+    /// Find the entities matching a pattern whose fields equal the given
+    /// values, and optionally whose next field lies in a range, through a
+    /// composite index
+    ///
+    /// The fields are relative to the entities. The entities are ordered
+    /// by the field of the range. Returns `None` when no composite index
+    /// starts with these fields.
+    fn find_by_fields(
+        &self,
+        _pattern: &Path,
+        _equals: &[(Path, Value)],
+        _range: Option<(&Path, &ValueRange)>,
+    ) -> Result<Option<Vec<Path>>> {
+        Ok(None)
+    }
+
+    /// Search the strings at the paths matching a pattern for the terms
+    /// of a text, through a full-text index
+    ///
+    /// Returns at most `limit` paths with their BM25 score, best first, or
+    /// `None` when no full-text index covers the pattern.
+    fn search_text(&self, _pattern: &Path, _query: &str, _limit: usize) -> Result<Option<Vec<(Path, f64)>>> {
+        Ok(None)
+    }
+
+    /// Find the vectors at the paths matching a pattern that are the
+    /// most similar to a query vector, through a vector index
+    ///
+    /// Returns the paths with their similarity, most similar first, or
+    /// `None` when no vector index covers the pattern.
+    fn search_vectors(&self, _pattern: &Path, _query: &VectorQuery) -> Result<Option<Vec<(Path, f64)>>> {
+        Ok(None)
+    }
+
+    /// Find the geographic points at the paths matching a pattern that
+    /// lie within an area, through a geo index
+    ///
+    /// Returns the paths with their distance in meters from the center of
+    /// the area, closest first, or `None` when no geo index covers the
+    /// pattern.
+    fn find_in_area(&self, _pattern: &Path, _area: &GeoArea) -> Result<Option<Vec<(Path, f64)>>> {
+        Ok(None)
+    }
+
+    /// List the recorded versions of a path, oldest first, optionally
+    /// restricted to the ones written within `from` and `to` (inclusive)
+    fn history(
+        &self,
+        _path: &Path,
+        _from: Option<DateTime<Utc>>,
+        _to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Version>> {
+        Err(version::versioning_disabled())
+    }
+
+    /// Get the value a path had at the given time
+    fn get_as_of(&self, path: &Path, as_of: DateTime<Utc>) -> Result<Value> {
+        let versions = self.history(path, None, Some(as_of))?;
+
+        version::value_as_of(&versions, as_of)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(path.clone()))
+    }
+
+    /// Get all values a prefix had at the given time
+    fn get_prefix_as_of(&self, _prefix: &Path, _as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        Err(version::versioning_disabled())
+    }
+}
+
+/// Trait defining the core operations of a store
+pub trait Store: ReadStore {
+    /// Set a value at the given path
+    fn set(&mut self, path: Path, value: Value) -> Result<()>;
+
+    /// Delete a value at the given path
+    fn delete(&mut self, path: &Path) -> Result<()>;
+
+    /// Apply a batch of writes atomically: either every operation
+    /// is applied, or none of them is
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()>;
+
+    /// Delete every value under a prefix, the prefix itself included
+    ///
+    /// The deletes are applied as a single batch. Returns the number of
+    /// removed endpoints.
+    fn delete_prefix(&mut self, prefix: &Path) -> Result<usize> {
+        if prefix.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete values at empty prefix".to_string()));
+        }
+
+        let ops = self.get_prefix(prefix)?
+            .into_iter()
+            .map(|(path, _)| WriteOp::Delete(path))
+            .collect();
+
+        delete_all(self, ops)
+    }
+
+    /// Delete every value whose path matches a pattern (which may contain
+    /// wildcards)
+    ///
+    /// The deletes are applied as a single batch. Returns the number of
+    /// removed endpoints.
+    fn delete_matching(&mut self, pattern: &Path) -> Result<usize> {
+        if pattern.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete values at empty pattern".to_string()));
+        }
+
+        let ops = self.query(pattern)?
+            .into_iter()
+            .map(|(path, _)| WriteOp::Delete(path))
+            .collect();
+
+        delete_all(self, ops)
+    }
+
+    /// Copy every value under a prefix to another, empty, prefix
+    ///
+    /// Returns the number of copied endpoints.
+    fn copy_prefix(&mut self, from: &Path, to: &Path) -> Result<usize> {
+        let mut tx = Transaction::new(self);
+        let count = tx.copy_prefix(from, to)?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Move every value under a prefix to another, empty, prefix
+    ///
+    /// With `rewrite_references`, the references pointing into the moved
+    /// subtree are updated too. The writes are applied atomically.
+    /// Returns the number of moved endpoints.
+    fn move_prefix(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        let mut tx = Transaction::new(self);
+        let count = tx.move_prefix(from, to, rewrite_references)?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Move every subtree matching a pattern to the matching path of
+    /// another pattern, e.g. `users.*.mail` to `users.*.email`
+    ///
+    /// The writes are applied atomically. Returns the number of moved
+    /// endpoints.
+    fn rename_matching(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        let mut tx = Transaction::new(self);
+        let count = tx.rename_matching(from, to, rewrite_references)?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Take a read-only, point-in-time snapshot of the store
+    fn snapshot(&self) -> Result<Snapshot>;
+
+    /// Enable versioning with the given retention policy, or disable it
+    /// with `None`
+    ///
+    /// Versions already recorded are kept when versioning is disabled.
+    fn set_versioning(&mut self, _policy: Option<RetentionPolicy>) -> Result<()> {
+        Err(StoreError::InvalidOperation("This store does not support versioning".to_string()))
+    }
+    
+    /// Get the feed publishing the writes applied to the store
+    fn change_feed(&self) -> &ChangeFeed;
+    
+    /// Choose whether indexes are updated with the data or in the
+    /// background
+    ///
+    /// Stores without background indexes are always consistent, and
+    /// accept either mode.
+    fn set_index_consistency(&mut self, _consistency: IndexConsistency) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Declare a secondary index on the paths matching a pattern
+    ///
+    /// The existing values are indexed in the background, and the index
+    /// answers queries once they all are. Returns `false` if the index
+    /// already exists.
+    fn create_index(&mut self, _definition: IndexDefinition) -> Result<bool> {
+        Err(StoreError::InvalidOperation("This store does not support secondary indexes".to_string()))
+    }
+    
+    /// Drop a secondary index and its entries
+    ///
+    /// Returns `false` if the index does not exist.
+    fn drop_index(&mut self, _definition: &IndexDefinition) -> Result<bool> {
+        Err(StoreError::InvalidOperation("This store does not support secondary indexes".to_string()))
+    }
+    
+    /// List the secondary indexes, with their build status and size
+    fn indexes(&self) -> Result<Vec<IndexInfo>> {
+        Ok(Vec::new())
+    }
+    
+    /// Require the values at the paths matching a pattern to be distinct
+    ///
+    /// The existing values are checked first; writes breaking the
+    /// constraint then fail with `StoreError::ConstraintViolation`.
+    /// Returns `false` if the constraint already exists.
+    fn add_unique_constraint(&mut self, _pattern: &Path) -> Result<bool> {
+        Err(StoreError::InvalidOperation("This store does not support constraints".to_string()))
+    }
+    
+    /// Drop a unique constraint
+    ///
+    /// Returns `false` if the constraint does not exist.
+    fn drop_unique_constraint(&mut self, _pattern: &Path) -> Result<bool> {
+        Err(StoreError::InvalidOperation("This store does not support constraints".to_string()))
+    }
+    
+    /// List the patterns under a unique constraint
+    fn unique_constraints(&self) -> Result<Vec<Path>> {
+        Ok(Vec::new())
+    }
     
     /// Flush changes (for persistent stores)
     fn flush(&self) -> Result<()>;
 
     fn as_any(&self) -> &dyn Any;
 }
-
-
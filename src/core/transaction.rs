@@ -0,0 +1,696 @@
+//! Transactions for Hyperion
+//!
+//! This module provides a transaction type that stages writes on top of
+//! a store and applies them all at once on commit. Reads made through
+//! the transaction see its own staged writes.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use super::path::Path;
+use super::value::Value;
+use super::entity::Entity;
+use super::errors::{Result, StoreError};
+use super::store::{ReadStore, ScannedEndpoints, Store, WriteOp};
+use super::version::Version;
+use super::index::{ValueRange, VectorQuery, GeoArea};
+
+/// A set of writes staged against a store
+///
+/// Nothing reaches the underlying store until `commit` is called.
+/// Dropping the transaction (or calling `rollback`) discards the
+/// staged writes.
+pub struct Transaction<'a, S: Store + ?Sized> {
+    /// The store the transaction is applied to
+    store: &'a mut S,
+    /// Staged writes (`None` marks a deletion), ordered by path so that
+    /// a commit applies them in the same order on every run
+    writes: BTreeMap<Path, Option<Value>>,
+}
+
+impl<'a, S: Store + ?Sized> Transaction<'a, S> {
+    /// Start a new transaction on the given store
+    pub fn new(store: &'a mut S) -> Self {
+        Transaction {
+            store,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Stage a value at the given path
+    pub fn set(&mut self, path: Path, value: Value) -> Result<()> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot set value at empty path".to_string()));
+        }
+
+        self.writes.insert(path, Some(value));
+        Ok(())
+    }
+
+    /// Stage the deletion of the value at the given path
+    pub fn delete(&mut self, path: &Path) -> Result<()> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete value at empty path".to_string()));
+        }
+
+        if !self.exists(path)? {
+            return Err(StoreError::NotFound(path.clone()));
+        }
+
+        self.writes.insert(path.clone(), None);
+        Ok(())
+    }
+
+    /// Stage the deletion of every value under a prefix, the prefix
+    /// itself included
+    ///
+    /// Returns the number of endpoints that will be removed.
+    pub fn delete_prefix(&mut self, prefix: &Path) -> Result<usize> {
+        if prefix.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete values at empty prefix".to_string()));
+        }
+
+        let paths = self.list_prefix(prefix)?;
+        Ok(self.stage_deletes(paths))
+    }
+
+    /// Stage the deletion of every value whose path matches a pattern
+    ///
+    /// Returns the number of endpoints that will be removed.
+    pub fn delete_matching(&mut self, pattern: &Path) -> Result<usize> {
+        if pattern.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete values at empty pattern".to_string()));
+        }
+
+        let paths = self.query(pattern)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        Ok(self.stage_deletes(paths))
+    }
+
+    /// Stage a copy of every value under a prefix to another prefix
+    ///
+    /// The target prefix must not hold any value. Returns the number of
+    /// copied endpoints.
+    pub fn copy_prefix(&mut self, from: &Path, to: &Path) -> Result<usize> {
+        let endpoints = self.relocated_endpoints(from, to)?;
+        let count = endpoints.len();
+
+        for (path, value) in endpoints {
+            self.set(rebase(&path, from, to), value)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Stage the move of every value under a prefix to another prefix
+    ///
+    /// The target prefix must not hold any value. When
+    /// `rewrite_references` is set, the references pointing into the
+    /// moved subtree are updated, which reads every endpoint of the store.
+    /// Returns the number of moved endpoints.
+    pub fn move_prefix(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        let count = self.relocate(from, to)?;
+
+        if rewrite_references {
+            self.rewrite_references(&[(from.clone(), to.clone())])?;
+        }
+
+        Ok(count)
+    }
+
+    /// Stage the move of every subtree matching a pattern, e.g.
+    /// `users.*.mail` to `users.*.email`
+    ///
+    /// Both patterns must have the same number of single-level
+    /// wildcards: the segments matched by the wildcards of `from` fill
+    /// the wildcards of `to`, in order. References are rewritten as with
+    /// `move_prefix`. Returns the number of moved endpoints.
+    pub fn rename_matching(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        if from.is_empty() || to.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot rename values at empty pattern".to_string()));
+        }
+        if from.segments().iter().chain(to.segments()).any(|s| s.is_multi_wildcard()) {
+            return Err(StoreError::InvalidOperation("Rename patterns only support single-level wildcards".to_string()));
+        }
+        let wildcards = |pattern: &Path| pattern.segments().iter().filter(|s| s.is_single_wildcard()).count();
+        if wildcards(from) != wildcards(to) {
+            return Err(StoreError::InvalidOperation(
+                format!("{} and {} must have the same number of wildcards", from, to)
+            ));
+        }
+
+        // The matching paths and everything below them
+        let mut below = from.clone();
+        below.push("**");
+        let roots: HashSet<Path> = self.query(&below)?
+            .into_iter()
+            .map(|(path, _)| Path::from_segments(path.segments()[..from.len()].to_vec()))
+            .collect();
+
+        let moves: Vec<(Path, Path)> = roots.into_iter()
+            .map(|root| {
+                let target = fill_wildcards(to, from, &root);
+                (root, target)
+            })
+            .filter(|(root, target)| root != target)
+            .collect();
+
+        let mut count = 0;
+        for (root, target) in &moves {
+            count += self.relocate(root, target)?;
+        }
+
+        if rewrite_references {
+            self.rewrite_references(&moves)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Check whether the transaction has staged any write
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Stage the endpoints of an entity under a prefix
+    ///
+    /// Endpoints under the prefix that the entity does not mention are
+    /// kept. Values that conflict with the new structure are removed: a
+    /// path cannot hold a value and have fields at the same time.
+    pub fn set_entity(&mut self, prefix: &Path, entity: &Entity) -> Result<()> {
+        for (path, value) in entity.to_endpoints(prefix) {
+            for len in prefix.len().max(1)..path.len() {
+                let ancestor = Path::from_segments(path.segments()[..len].to_vec());
+                if self.exists(&ancestor)? {
+                    self.delete(&ancestor)?;
+                }
+            }
+
+            for child in self.list_prefix(&path)? {
+                if child != path {
+                    self.delete(&child)?;
+                }
+            }
+
+            self.set(path, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stage the endpoints of an entity under a prefix, removing the
+    /// endpoints under the prefix that the entity does not contain
+    pub fn replace_entity(&mut self, prefix: &Path, entity: &Entity) -> Result<()> {
+        let endpoints = entity.to_endpoints(prefix);
+        let kept: HashSet<&Path> = endpoints.iter().map(|(path, _)| path).collect();
+
+        for path in self.list_prefix(prefix)? {
+            if !kept.contains(&path) {
+                self.delete(&path)?;
+            }
+        }
+
+        for (path, value) in endpoints {
+            self.set(path, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stage a JSON Merge Patch (RFC 7396) on the entity under a prefix
+    ///
+    /// Fields of an object patch are merged recursively, `null` fields
+    /// remove the corresponding subtree, and any other value (including
+    /// arrays) replaces the target.
+    pub fn merge_entity(&mut self, prefix: &Path, patch: &Entity) -> Result<()> {
+        let fields = match patch {
+            Entity::Object(fields) => fields,
+            _ => return self.replace_entity(prefix, patch),
+        };
+
+        // The target becomes an object: drop a value or array items
+        // stored in its place
+        for path in self.list_prefix(prefix)? {
+            let is_item = path.segment(prefix.len()).is_some_and(|segment| segment.is_array_index());
+            if path == *prefix || is_item {
+                self.delete(&path)?;
+            }
+        }
+
+        for (name, value) in fields {
+            let mut path = prefix.clone();
+            path.push(name.clone());
+
+            match value {
+                Entity::Null => {
+                    for child in self.list_prefix(&path)? {
+                        self.delete(&child)?;
+                    }
+                },
+                _ => self.merge_entity(&path, value)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply all staged writes to the store atomically
+    pub fn commit(self) -> Result<()> {
+        let mut ops = Vec::with_capacity(self.writes.len());
+
+        for (path, staged) in self.writes {
+            match staged {
+                Some(value) => ops.push(WriteOp::Set(path, value)),
+                None => {
+                    // A path created and deleted within the transaction
+                    // never reached the store, so there is nothing to delete
+                    if self.store.exists(&path)? {
+                        ops.push(WriteOp::Delete(path));
+                    }
+                }
+            }
+        }
+
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        self.store.apply_batch(ops)
+    }
+
+    /// Discard all staged writes
+    pub fn rollback(self) {
+        // Staged writes only live in the transaction, dropping it is enough
+    }
+
+    /// Stage the move of the values under a prefix
+    fn relocate(&mut self, from: &Path, to: &Path) -> Result<usize> {
+        let endpoints = self.relocated_endpoints(from, to)?;
+        let count = endpoints.len();
+
+        for (path, value) in endpoints {
+            self.writes.insert(rebase(&path, from, to), Some(value));
+            self.writes.insert(path, None);
+        }
+
+        Ok(count)
+    }
+
+    /// Read the endpoints to move or copy from one prefix to another
+    fn relocated_endpoints(&self, from: &Path, to: &Path) -> Result<Vec<(Path, Value)>> {
+        if from.is_empty() || to.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot move values from or to an empty prefix".to_string()));
+        }
+        if from.has_wildcards() || to.has_wildcards() {
+            return Err(StoreError::InvalidOperation("Cannot move values with a wildcard prefix".to_string()));
+        }
+        if from.starts_with(to) || to.starts_with(from) {
+            return Err(StoreError::InvalidOperation(format!("Cannot move {} to {}: the prefixes overlap", from, to)));
+        }
+
+        let endpoints = self.get_prefix(from)?;
+        if endpoints.is_empty() {
+            return Err(StoreError::NotFound(from.clone()));
+        }
+        if !self.list_prefix(to)?.is_empty() {
+            return Err(StoreError::InvalidOperation(format!("Cannot move {} to {}: the target is not empty", from, to)));
+        }
+
+        Ok(endpoints)
+    }
+
+    /// Point the references into moved prefixes to their new location
+    fn rewrite_references(&mut self, moves: &[(Path, Path)]) -> Result<()> {
+        for (path, value) in self.get_prefix(&Path::new())? {
+            if let Value::Reference(target) = value {
+                if let Some((from, to)) = moves.iter().find(|(from, _)| target.starts_with(from)) {
+                    self.set(path, Value::Reference(rebase(&target, from, to)))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stage the deletion of paths known to exist
+    fn stage_deletes(&mut self, paths: Vec<Path>) -> usize {
+        let count = paths.len();
+        for path in paths {
+            self.writes.insert(path, None);
+        }
+
+        count
+    }
+
+    /// Overlay the staged writes on top of endpoints read from the store
+    fn overlay<F>(&self, base: Vec<(Path, Value)>, filter: F) -> Vec<(Path, Value)>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut seen = HashSet::new();
+        let mut results = Vec::with_capacity(base.len());
+
+        for (path, value) in base {
+            match self.writes.get(&path) {
+                Some(Some(staged)) => results.push((path.clone(), staged.clone())),
+                Some(None) => {},
+                None => results.push((path.clone(), value)),
+            }
+            seen.insert(path);
+        }
+
+        for (path, staged) in &self.writes {
+            if let Some(value) = staged {
+                if !seen.contains(path) && filter(path) {
+                    results.push((path.clone(), value.clone()));
+                }
+            }
+        }
+
+        results
+    }
+}
+
+/// Move a path from one prefix to another
+fn rebase(path: &Path, from: &Path, to: &Path) -> Path {
+    let mut segments = to.segments().to_vec();
+    segments.extend_from_slice(&path.segments()[from.len()..]);
+    Path::from_segments(segments)
+}
+
+/// Fill the wildcards of `to` with the segments of `path` matched by the
+/// wildcards of `from`
+fn fill_wildcards(to: &Path, from: &Path, path: &Path) -> Path {
+    let mut captured = from.segments().iter()
+        .zip(path.segments())
+        .filter(|(pattern, _)| pattern.is_single_wildcard())
+        .map(|(_, segment)| segment.clone());
+
+    let segments = to.segments().iter()
+        .map(|segment| {
+            if segment.is_single_wildcard() {
+                captured.next().unwrap_or_else(|| segment.clone())
+            } else {
+                segment.clone()
+            }
+        })
+        .collect();
+
+    Path::from_segments(segments)
+}
+
+impl<S: Store + ?Sized> ReadStore for Transaction<'_, S> {
+    fn get(&self, path: &Path) -> Result<Value> {
+        match self.writes.get(path) {
+            Some(Some(value)) => Ok(value.clone()),
+            Some(None) => Err(StoreError::NotFound(path.clone())),
+            None => self.store.get(path),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        match self.writes.get(path) {
+            Some(staged) => Ok(staged.is_some()),
+            None => self.store.exists(path),
+        }
+    }
+
+    fn list_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
+        let paths = self.get_prefix(prefix)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+
+        Ok(paths)
+    }
+
+    fn get_prefix(&self, prefix: &Path) -> Result<Vec<(Path, Value)>> {
+        let base = self.store.get_prefix(prefix)?;
+        Ok(self.overlay(base, |path| path.starts_with(prefix)))
+    }
+
+    fn query(&self, pattern: &Path) -> Result<Vec<(Path, Value)>> {
+        let base = self.store.query(pattern)?;
+        Ok(self.overlay(base, |path| path.matches(pattern)))
+    }
+
+    fn scan_matching(&self, pattern: &Path) -> Result<ScannedEndpoints<'_>> {
+        if self.writes.is_empty() {
+            return self.store.scan_matching(pattern);
+        }
+
+        // The staged writes are merged into the values of the store
+        let base = self.store.scan_matching(pattern)?.collect::<Result<Vec<_>>>()?;
+        let mut pairs = self.overlay(base, |path| path.matches(pattern));
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Box::new(pairs.into_iter().map(Ok)))
+    }
+
+    fn count(&self) -> Result<usize> {
+        let mut count = self.store.count()?;
+
+        for (path, staged) in &self.writes {
+            let in_store = self.store.exists(path)?;
+            match staged {
+                Some(_) if !in_store => count += 1,
+                None if in_store => count -= 1,
+                _ => {},
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn count_prefix(&self, prefix: &Path) -> Result<usize> {
+        Ok(self.list_prefix(prefix)?.len())
+    }
+
+    // The indexes of the store do not know the staged writes: once a
+    // write is staged, callers scan the values instead
+
+    fn find_by_value_range(&self, pattern: &Path, range: &ValueRange) -> Result<Option<Vec<Path>>> {
+        match self.writes.is_empty() {
+            true => self.store.find_by_value_range(pattern, range),
+            false => Ok(None),
+        }
+    }
+
+    fn find_by_fields(
+        &self,
+        pattern: &Path,
+        equals: &[(Path, Value)],
+        range: Option<(&Path, &ValueRange)>,
+    ) -> Result<Option<Vec<Path>>> {
+        match self.writes.is_empty() {
+            true => self.store.find_by_fields(pattern, equals, range),
+            false => Ok(None),
+        }
+    }
+
+    fn search_text(&self, pattern: &Path, query: &str, limit: usize) -> Result<Option<Vec<(Path, f64)>>> {
+        match self.writes.is_empty() {
+            true => self.store.search_text(pattern, query, limit),
+            false => Ok(None),
+        }
+    }
+
+    fn search_vectors(&self, pattern: &Path, query: &VectorQuery) -> Result<Option<Vec<(Path, f64)>>> {
+        match self.writes.is_empty() {
+            true => self.store.search_vectors(pattern, query),
+            false => Ok(None),
+        }
+    }
+
+    fn find_in_area(&self, pattern: &Path, area: &GeoArea) -> Result<Option<Vec<(Path, f64)>>> {
+        match self.writes.is_empty() {
+            true => self.store.find_in_area(pattern, area),
+            false => Ok(None),
+        }
+    }
+
+    // Staged writes only get a version once committed, so the history
+    // is the one of the store
+
+    fn history(&self, path: &Path, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<Version>> {
+        self.store.history(path, from, to)
+    }
+
+    fn get_as_of(&self, path: &Path, as_of: DateTime<Utc>) -> Result<Value> {
+        self.store.get_as_of(path, as_of)
+    }
+
+    fn get_prefix_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        self.store.get_prefix_as_of(prefix, as_of)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::storage::MemoryStore;
+
+    fn path(s: &str) -> Path {
+        Path::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_commit_applies_all_writes() {
+        let mut store = MemoryStore::new();
+        store.set(path("users.u-1.name"), Value::from("alice")).unwrap();
+
+        let mut tx = Transaction::new(&mut store);
+        tx.set(path("users.u-1.email"), Value::from("alice@example.com")).unwrap();
+        tx.set(path("emails_by_user.alice"), Value::from("u-1")).unwrap();
+        tx.delete(&path("users.u-1.name")).unwrap();
+
+        // Reads through the transaction see the staged writes
+        assert_eq!(tx.count_prefix(&path("users.u-1")).unwrap(), 1);
+        assert!(tx.get(&path("users.u-1.name")).is_err());
+        tx.commit().unwrap();
+
+        assert_eq!(store.count().unwrap(), 2);
+        assert!(!store.exists(&path("users.u-1.name")).unwrap());
+        assert_eq!(store.get(&path("emails_by_user.alice")).unwrap(), Value::from("u-1"));
+    }
+
+    #[test]
+    fn test_commit_applies_writes_in_path_order() {
+        let mut store = MemoryStore::new();
+        store.set(path("users.u-2.name"), Value::from("bob")).unwrap();
+        let mut changes = store.change_feed().subscribe(path("users.**"));
+
+        let mut tx = Transaction::new(&mut store);
+        tx.set(path("users.u-3.name"), Value::from("carol")).unwrap();
+        tx.delete(&path("users.u-2.name")).unwrap();
+        tx.set(path("users.u-1.name"), Value::from("alice")).unwrap();
+        tx.commit().unwrap();
+
+        let mut paths = Vec::new();
+        while let Some(event) = changes.try_recv() {
+            paths.push(event.path.to_string());
+        }
+        assert_eq!(paths, ["users.u-1.name", "users.u-2.name", "users.u-3.name"]);
+    }
+
+    #[test]
+    fn test_rollback_discards_writes() {
+        let mut store = MemoryStore::new();
+
+        let mut tx = Transaction::new(&mut store);
+        tx.set(path("users.u-1.email"), Value::from("alice@example.com")).unwrap();
+        assert!(tx.delete(&path("users.u-2.email")).is_err());
+        tx.rollback();
+
+        assert_eq!(store.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_failed_batch_leaves_store_untouched() {
+        let mut store = MemoryStore::new();
+        store.set(path("a"), Value::from(1)).unwrap();
+
+        let result = store.apply_batch(vec![
+            WriteOp::Set(path("b"), Value::from(2)),
+            WriteOp::Delete(path("missing")),
+        ]);
+
+        assert!(result.is_err());
+        assert!(!store.exists(&path("b")).unwrap());
+    }
+
+    #[test]
+    fn test_prefix_and_pattern_deletes() {
+        let mut store = MemoryStore::new();
+        store.set(path("users.u-1.name"), Value::from("alice")).unwrap();
+        store.set(path("users.u-1.address.city"), Value::from("Paris")).unwrap();
+        store.set(path("users.u-10.name"), Value::from("bob")).unwrap();
+        store.set(path("sessions.s-1.token"), Value::from("t1")).unwrap();
+        store.set(path("sessions.s-1.user"), Value::from("u-1")).unwrap();
+        store.set(path("sessions.s-2.token"), Value::from("t2")).unwrap();
+
+        // Staged deletes see the writes of the transaction
+        let mut tx = Transaction::new(&mut store);
+        tx.set(path("sessions.s-3.token"), Value::from("t3")).unwrap();
+        assert_eq!(tx.delete_matching(&path("sessions.*.token")).unwrap(), 3);
+        assert_eq!(tx.delete_matching(&path("sessions.*.token")).unwrap(), 0);
+        tx.commit().unwrap();
+
+        assert_eq!(store.count_prefix(&path("sessions")).unwrap(), 1);
+
+        // A prefix is not a string prefix: u-10 is kept
+        assert_eq!(store.delete_prefix(&path("users.u-1")).unwrap(), 2);
+        assert_eq!(store.delete_prefix(&path("users.u-1")).unwrap(), 0);
+        assert!(store.exists(&path("users.u-10.name")).unwrap());
+
+        assert!(store.delete_prefix(&Path::new()).is_err());
+    }
+
+    #[test]
+    fn test_move_copy_and_rename() {
+        let mut store = MemoryStore::new();
+        store.set(path("users.u-1.name"), Value::from("alice")).unwrap();
+        store.set(path("users.u-1.mail"), Value::from("alice@example.com")).unwrap();
+        store.set(path("users.u-2.mail.work"), Value::from("bob@example.com")).unwrap();
+        store.set(path("orders.o-1.user"), Value::Reference(path("users.u-1"))).unwrap();
+
+        assert_eq!(store.copy_prefix(&path("users.u-1"), &path("backup.u-1")).unwrap(), 2);
+        assert!(store.copy_prefix(&path("users.u-1"), &path("backup.u-1")).is_err());
+        assert!(store.move_prefix(&path("users"), &path("users.u-3"), false).is_err());
+
+        assert_eq!(store.move_prefix(&path("users.u-1"), &path("archived_users.u-1"), true).unwrap(), 2);
+        assert_eq!(store.count_prefix(&path("users.u-1")).unwrap(), 0);
+        assert_eq!(store.get(&path("archived_users.u-1.name")).unwrap(), Value::from("alice"));
+        assert_eq!(store.get(&path("orders.o-1.user")).unwrap(), Value::Reference(path("archived_users.u-1")));
+
+        // Whole subtrees are renamed, and a failing rename writes nothing
+        assert_eq!(store.rename_matching(&path("*.*.mail"), &path("*.*.email"), false).unwrap(), 3);
+        assert_eq!(store.get(&path("users.u-2.email.work")).unwrap(), Value::from("bob@example.com"));
+        assert_eq!(store.get(&path("backup.u-1.email")).unwrap(), Value::from("alice@example.com"));
+
+        store.set(path("users.u-2.contact"), Value::from("x")).unwrap();
+        assert!(store.rename_matching(&path("*.*.email"), &path("*.*.contact"), false).is_err());
+        assert!(store.exists(&path("backup.u-1.email")).unwrap());
+
+        assert!(store.rename_matching(&path("users.*.email"), &path("users.email"), false).is_err());
+    }
+
+    #[test]
+    fn test_entity_writes() {
+        let mut store = MemoryStore::new();
+        let entity = |json| Entity::from_json(json).unwrap();
+
+        let mut tx = Transaction::new(&mut store);
+        tx.set_entity(&path("users.u-1"), &entity(serde_json::json!({
+            "name": "alice",
+            "tags": ["a", "b", "c"],
+            "profile": { "age": 30, "city": "Paris" }
+        }))).unwrap();
+        tx.commit().unwrap();
+
+        // Merge: null removes, objects merge, arrays are replaced
+        let mut tx = Transaction::new(&mut store);
+        tx.merge_entity(&path("users.u-1"), &entity(serde_json::json!({
+            "tags": ["d"],
+            "profile": { "city": null, "country": "FR" }
+        }))).unwrap();
+        tx.commit().unwrap();
+
+        let merged = crate::core::entity::reconstruct_entity(&store, &path("users.u-1")).unwrap();
+        assert_eq!(merged.to_json(), serde_json::json!({
+            "name": "alice",
+            "tags": ["d"],
+            "profile": { "age": 30, "country": "FR" }
+        }));
+
+        // Replace: endpoints missing from the entity are removed
+        let mut tx = Transaction::new(&mut store);
+        tx.replace_entity(&path("users.u-1"), &entity(serde_json::json!({ "name": "bob" }))).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(store.list_prefix(&path("users.u-1")).unwrap(), vec![path("users.u-1.name")]);
+    }
+}
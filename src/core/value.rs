@@ -24,6 +24,13 @@ pub enum Value {
     Binary(Vec<u8>, Option<String>),
     /// Reference to another path
     Reference(Path),
+    /// Embedding vector, for similarity search
+    Vector(Vec<f32>),
+    /// Geographic point, in degrees (WGS 84)
+    GeoPoint {
+        lat: f64,
+        lon: f64,
+    },
 }
 
 impl Value {
@@ -67,6 +74,16 @@ impl Value {
         matches!(self, Value::Reference(_))
     }
     
+    /// Check if the value is a vector
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Value::Vector(_))
+    }
+    
+    /// Check if the value is a geographic point
+    pub fn is_geo_point(&self) -> bool {
+        matches!(self, Value::GeoPoint { .. })
+    }
+    
     /// Get a string representation of the value's type
     pub fn type_name(&self) -> &'static str {
         match self {
@@ -77,6 +94,8 @@ impl Value {
             Value::String(_) => "string",
             Value::Binary(_, _) => "binary",
             Value::Reference(_) => "reference",
+            Value::Vector(_) => "vector",
+            Value::GeoPoint { .. } => "geopoint",
         }
     }
 }
@@ -98,6 +117,8 @@ impl fmt::Display for Value {
                 }
             },
             Value::Reference(path) => write!(f, "@{}", path),
+            Value::Vector(vector) => write!(f, "[vector: {} dimensions]", vector.len()),
+            Value::GeoPoint { lat, lon } => write!(f, "geopoint({}, {})", lat, lon),
         }
     }
 }
@@ -139,6 +160,12 @@ impl From<&str> for Value {
     }
 }
 
+impl From<Vec<f32>> for Value {
+    fn from(vector: Vec<f32>) -> Self {
+        Value::Vector(vector)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,6 +180,8 @@ mod tests {
         let string = Value::String("Hello".to_string());
         let binary = Value::Binary(vec![1, 2, 3], Some("image/jpeg".to_string()));
         let reference = Value::Reference(Path::from_str("users.u-123456").unwrap());
+        let vector = Value::Vector(vec![0.5, -1.0]);
+        let point = Value::GeoPoint { lat: 48.8566, lon: 2.3522 };
         
         assert!(null.is_null());
         assert!(boolean.is_boolean());
@@ -163,6 +192,9 @@ mod tests {
         assert!(string.is_string());
         assert!(binary.is_binary());
         assert!(reference.is_reference());
+        assert!(vector.is_vector());
+        assert!(point.is_geo_point());
+        assert_eq!(point.to_string(), "geopoint(48.8566, 2.3522)");
     }
     
     #[test]
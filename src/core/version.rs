@@ -0,0 +1,156 @@
+//! Versioned endpoints
+//!
+//! When versioning is enabled on a store, every write also records the
+//! new value of the endpoint with the time it was written. The recorded
+//! versions let callers list the changes of an endpoint over time and
+//! read the database as it was at a given moment.
+//!
+//! History only starts once versioning is enabled: values written before
+//! that (or while it was disabled) have no recorded version.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use super::value::Value;
+use super::errors::{Result, StoreError};
+
+/// A recorded value of an endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    /// When the value was written
+    pub timestamp: DateTime<Utc>,
+    /// The value written (`None` if the endpoint was deleted)
+    pub value: Option<Value>,
+}
+
+/// How long the versions of an endpoint are kept
+///
+/// The latest version of an endpoint is always kept, so that reading
+/// as of a recent time still finds the value it had then.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetentionPolicy {
+    /// Keep every version
+    KeepAll,
+    /// Keep at most this many versions per endpoint
+    MaxVersions(usize),
+    /// Drop versions that were replaced longer ago than this
+    MaxAge(Duration),
+}
+
+impl RetentionPolicy {
+    /// Check whether a version must be kept
+    ///
+    /// `rank` is the position of the version starting from the newest
+    /// one (0), `replaced_at` the time the next version was written.
+    pub fn keeps(&self, rank: usize, replaced_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        if rank == 0 {
+            return true;
+        }
+
+        match self {
+            RetentionPolicy::KeepAll => true,
+            RetentionPolicy::MaxVersions(max) => rank < *max,
+            RetentionPolicy::MaxAge(age) => replaced_at.is_none_or(|at| now - at <= *age),
+        }
+    }
+
+    /// Drop the versions this policy does not keep, oldest first
+    pub fn prune(&self, versions: &mut Vec<Version>, now: DateTime<Utc>) {
+        let len = versions.len();
+        let keep: Vec<bool> = (0..len)
+            .map(|i| {
+                let replaced_at = versions.get(i + 1).map(|next| next.timestamp);
+                self.keeps(len - 1 - i, replaced_at, now)
+            })
+            .collect();
+
+        let mut keep = keep.into_iter();
+        versions.retain(|_| keep.next().unwrap_or(true));
+    }
+}
+
+/// Keep only the versions written within the given bounds (inclusive)
+pub fn in_range(versions: Vec<Version>, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<Version> {
+    versions.into_iter()
+        .filter(|v| from.is_none_or(|from| v.timestamp >= from))
+        .filter(|v| to.is_none_or(|to| v.timestamp <= to))
+        .collect()
+}
+
+/// Find the value an endpoint had at the given time, in versions sorted
+/// from oldest to newest
+///
+/// Returns `None` if the endpoint did not exist at that time.
+pub fn value_as_of(versions: &[Version], as_of: DateTime<Utc>) -> Option<&Value> {
+    versions.iter()
+        .rev()
+        .find(|v| v.timestamp <= as_of)
+        .and_then(|v| v.value.as_ref())
+}
+
+/// Parse a timestamp given as RFC 3339 or as a plain date (midnight UTC)
+pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| datetime.and_utc())
+        .ok_or_else(|| StoreError::InvalidOperation(format!("Invalid timestamp: {}", s)))
+}
+
+/// Error returned by stores that do not record versions
+pub fn versioning_disabled() -> StoreError {
+    StoreError::InvalidOperation("Versioning is not enabled for this store".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(timestamp: &str, value: Option<i64>) -> Version {
+        Version {
+            timestamp: parse_timestamp(timestamp).unwrap(),
+            value: value.map(Value::from),
+        }
+    }
+
+    #[test]
+    fn test_value_as_of() {
+        let versions = vec![
+            version("2024-01-01", Some(1)),
+            version("2024-02-01", None),
+            version("2024-03-01", Some(3)),
+        ];
+
+        assert_eq!(value_as_of(&versions, parse_timestamp("2023-12-31").unwrap()), None);
+        assert_eq!(value_as_of(&versions, parse_timestamp("2024-01-15").unwrap()), Some(&Value::from(1)));
+        assert_eq!(value_as_of(&versions, parse_timestamp("2024-02-15").unwrap()), None);
+        assert_eq!(value_as_of(&versions, parse_timestamp("2024-03-01T00:00:00Z").unwrap()), Some(&Value::from(3)));
+    }
+
+    #[test]
+    fn test_retention_policies() {
+        let now = parse_timestamp("2024-04-01").unwrap();
+        let all = vec![
+            version("2024-01-01", Some(1)),
+            version("2024-02-01", Some(2)),
+            version("2024-03-01", Some(3)),
+        ];
+
+        let mut versions = all.clone();
+        RetentionPolicy::MaxVersions(2).prune(&mut versions, now);
+        assert_eq!(versions, all[1..].to_vec());
+
+        // The second version was replaced a month ago, the first two months ago
+        let mut versions = all.clone();
+        RetentionPolicy::MaxAge(Duration::days(40)).prune(&mut versions, now);
+        assert_eq!(versions, all[1..].to_vec());
+
+        // The latest version is kept whatever its age
+        let mut versions = all.clone();
+        RetentionPolicy::MaxAge(Duration::days(1)).prune(&mut versions, now);
+        assert_eq!(versions, all[2..].to_vec());
+    }
+}
@@ -10,9 +10,10 @@ pub mod ql;
 pub mod server;
 
 use std::path::{Path as StdPath, PathBuf};
+use chrono::{DateTime, Utc};
 use tokio::runtime::Runtime;
 use core::store::Store;
-use core::entity::reconstruct_entity;
+use core::entity::{reconstruct_entity, entity_from_endpoints};
 use storage::{MemoryStore, PersistentStore};
 
 /// Main API for Hyperion database
@@ -54,6 +55,67 @@ impl Hyperion {
         self.store.delete(path)
     }
     
+    /// Delete every value under a prefix, the prefix itself included
+    ///
+    /// Returns the number of removed endpoints.
+    pub fn delete_prefix(&mut self, prefix: &Path) -> Result<usize> {
+        self.store.delete_prefix(prefix)
+    }
+    
+    /// Delete every value whose path matches a pattern
+    ///
+    /// Returns the number of removed endpoints.
+    pub fn delete_matching(&mut self, pattern: &Path) -> Result<usize> {
+        self.store.delete_matching(pattern)
+    }
+    
+    /// Copy every value under a prefix to another, empty, prefix
+    ///
+    /// Returns the number of copied endpoints.
+    pub fn copy_prefix(&mut self, from: &Path, to: &Path) -> Result<usize> {
+        self.store.copy_prefix(from, to)
+    }
+    
+    /// Move every value under a prefix to another, empty, prefix
+    ///
+    /// With `rewrite_references`, the references pointing into the moved
+    /// subtree are updated too. Returns the number of moved endpoints.
+    pub fn move_prefix(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        self.store.move_prefix(from, to, rewrite_references)
+    }
+    
+    /// Rename the subtrees matching a pattern, e.g. `users.*.mail` to
+    /// `users.*.email`
+    ///
+    /// Returns the number of moved endpoints.
+    pub fn rename_matching(&mut self, from: &Path, to: &Path, rewrite_references: bool) -> Result<usize> {
+        self.store.rename_matching(from, to, rewrite_references)
+    }
+    
+    /// Run several writes as a single atomic transaction
+    ///
+    /// The closure receives a transaction on which writes are staged.
+    /// If it returns `Ok`, all staged writes are committed at once;
+    /// if it returns an error, they are rolled back and the error is
+    /// returned.
+    pub fn transaction<F, R>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Transaction<'_, dyn Store>) -> Result<R>,
+    {
+        let mut tx = Transaction::new(&mut *self.store);
+        
+        match f(&mut tx) {
+            Ok(result) => {
+                tx.commit()?;
+                Ok(result)
+            },
+            Err(e) => {
+                tx.rollback();
+                Err(e)
+            }
+        }
+    }
+    
     /// Check if a path exists
     pub fn exists(&self, path: &Path) -> Result<bool> {
         self.store.exists(path)
@@ -74,6 +136,90 @@ impl Hyperion {
         reconstruct_entity(&*self.store, prefix)
     }
     
+    /// Write an entity under a prefix, one endpoint per field
+    ///
+    /// Endpoints under the prefix that the entity does not mention are
+    /// kept. The writes are applied atomically.
+    pub fn set_entity(&mut self, prefix: &Path, entity: &Entity) -> Result<()> {
+        self.transaction(|tx| tx.set_entity(prefix, entity))
+    }
+    
+    /// Replace the entity under a prefix
+    ///
+    /// Unlike `set_entity`, endpoints under the prefix that the entity
+    /// does not contain are deleted. The writes are applied atomically.
+    pub fn replace_entity(&mut self, prefix: &Path, entity: &Entity) -> Result<()> {
+        self.transaction(|tx| tx.replace_entity(prefix, entity))
+    }
+    
+    /// Apply a JSON Merge Patch (RFC 7396) to the entity under a prefix
+    ///
+    /// `null` fields of the patch delete the corresponding subtrees. The
+    /// writes are applied atomically.
+    pub fn merge_entity(&mut self, prefix: &Path, patch: &Entity) -> Result<()> {
+        self.transaction(|tx| tx.merge_entity(prefix, patch))
+    }
+    
+    /// Apply a JSON Patch (RFC 6902) to the entity under a prefix
+    ///
+    /// The operations address the fields of the entity with JSON
+    /// Pointers. The patch is applied atomically: if any operation fails,
+    /// including a `test`, nothing is written.
+    pub fn patch_entity(&mut self, prefix: &Path, patch: &[PatchOperation]) -> Result<()> {
+        self.transaction(|tx| tx.apply_patch(prefix, patch))
+    }
+    
+    /// Take a read-only, point-in-time snapshot of the database
+    ///
+    /// The snapshot does not borrow the database: it can be read after
+    /// the database (or the lock around it) has been released, and never
+    /// sees writes made after it was taken.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.store.snapshot()
+    }
+    
+    /// Enable versioning with the given retention policy, or disable it
+    /// with `None`
+    pub fn set_versioning(&mut self, policy: Option<RetentionPolicy>) -> Result<()> {
+        self.store.set_versioning(policy)
+    }
+    
+    /// List the recorded versions of a path, oldest first
+    pub fn history(
+        &self,
+        path: &Path,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Version>> {
+        self.store.history(path, from, to)
+    }
+    
+    /// Get the value a path had at the given time
+    pub fn get_as_of(&self, path: &Path, as_of: DateTime<Utc>) -> Result<Value> {
+        self.store.get_as_of(path, as_of)
+    }
+    
+    /// Reconstruct an entity as it was at the given time
+    pub fn get_entity_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Entity> {
+        entity_from_endpoints(self.store.get_prefix_as_of(prefix, as_of)?, prefix)
+    }
+    
+    /// Subscribe to the writes of the paths matching a pattern
+    ///
+    /// The pattern may contain wildcards; a path without wildcards also
+    /// receives the writes of all the endpoints below it.
+    pub fn subscribe(&self, pattern: Path) -> Subscription {
+        self.store.change_feed().subscribe(pattern)
+    }
+    
+    /// Get a handle on the feed publishing the writes of the database
+    ///
+    /// The handle stays usable without borrowing the database, e.g. to
+    /// subscribe while the database is behind a lock.
+    pub fn change_feed(&self) -> ChangeFeed {
+        self.store.change_feed().clone()
+    }
+    
     /// Flush changes to disk (no-op for in-memory store)
     pub fn flush(&self) -> Result<()> {
         self.store.flush()
@@ -99,6 +245,55 @@ impl Hyperion {
         }
     }
     
+    /// Choose whether indexes are updated with the data (every query
+    /// sees the writes before it) or in the background
+    pub fn set_index_consistency(&mut self, consistency: IndexConsistency) -> Result<()> {
+        self.store.set_index_consistency(consistency)
+    }
+    
+    /// Check that the indexes match the data and repair any divergence
+    /// (only available for persistent store)
+    pub fn verify_indexes(&self) -> Result<Option<IndexReport>> {
+        if let Some(persistent) = self.store.as_any().downcast_ref::<PersistentStore>() {
+            Ok(Some(persistent.verify_indexes()?))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    /// Declare a secondary index on the paths matching a pattern
+    ///
+    /// Existing values are indexed in the background; `indexes` reports
+    /// the progress. Returns `false` if the index already exists.
+    pub fn create_index(&mut self, pattern: &Path, kind: IndexKind) -> Result<bool> {
+        self.store.create_index(IndexDefinition::new(pattern.clone(), kind))
+    }
+    
+    /// Drop a secondary index
+    pub fn drop_index(&mut self, pattern: &Path, kind: IndexKind) -> Result<bool> {
+        self.store.drop_index(&IndexDefinition::new(pattern.clone(), kind))
+    }
+    
+    /// List the secondary indexes with their build status and size
+    pub fn indexes(&self) -> Result<Vec<IndexInfo>> {
+        self.store.indexes()
+    }
+    
+    /// Require the values at the paths matching a pattern to be distinct
+    pub fn add_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        self.store.add_unique_constraint(pattern)
+    }
+    
+    /// Drop a unique constraint
+    pub fn drop_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        self.store.drop_unique_constraint(pattern)
+    }
+    
+    /// List the patterns under a unique constraint
+    pub fn unique_constraints(&self) -> Result<Vec<Path>> {
+        self.store.unique_constraints()
+    }
+    
     // Ajouter à src/lib.rs
     /// Crée une instance Hyperion à partir d'un store existant
     pub fn from_store(store: Box<dyn Store>) -> Self {
@@ -118,6 +313,16 @@ pub use core::path::Path;
 pub use core::value::Value;
 pub use core::entity::Entity;
 pub use core::errors::{Result, StoreError};
-pub use core::index::IndexStats;
+pub use core::transaction::Transaction;
+pub use core::snapshot::Snapshot;
+pub use core::version::{Version, RetentionPolicy};
+pub use core::changes::{ChangeEvent, ChangeOperation, ChangeFeed, Subscription};
+pub use core::patch::PatchOperation;
+pub use core::index::{
+    IndexStats, IndexConsistency, IndexReport,
+    IndexDefinition, IndexKind, IndexInfo, IndexStatus, VectorQuery,
+    GeoArea,
+};
+pub use core::index::vector_index::VectorMetric;
 
 
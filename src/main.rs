@@ -1,10 +1,5 @@
-mod core;
-mod storage;
-
 use hyperion::core::path::Path;
 use hyperion::core::value::Value;
-use hyperion::core::entity::reconstruct_entity;
-use hyperion::storage::PersistentStore;
 use std::str::FromStr;
 
 use hyperion::Hyperion;
@@ -2,33 +2,106 @@
 //!
 //! This module defines the structures that represent the parsed query.
 
-use crate::core::path::Path;
+use crate::core::path::{Path, PathSegment};
 use crate::core::value::Value;
+use crate::core::index::IndexKind;
 
 /// A complete query
 #[derive(Debug, Clone)]
 pub struct Query {
+    /// Schema declarations
+    pub declarations: Vec<Declaration>,
     /// Operations to perform
     pub operations: Vec<Operation>,
     /// Expression to return
     pub return_expr: Option<Expression>,
+    /// Subscription to register (instead of a return expression)
+    pub subscription: Option<SubscribeStatement>,
+}
+
+/// Schema declarations
+#[derive(Debug, Clone)]
+pub enum Declaration {
+    /// Declare a value index on the paths matching a pattern
+    /// (`@index users.*.email`), a composite index on fields of the
+    /// entities matching it (`@index orders.* (customer, status)`), a
+    /// full-text index on their strings (`@index posts.*.content fulltext`),
+    /// a vector index (`@index docs.*.embedding vector(cosine, hnsw)`) or a
+    /// geo index (`@index devices.*.location geo`)
+    Index {
+        /// The indexed pattern
+        pattern: Path,
+        /// The type of the index
+        kind: IndexKind,
+    },
+    /// Require the values at the paths matching a pattern to be distinct
+    /// (`@constraint users.*.email unique`)
+    Unique {
+        /// The constrained pattern
+        pattern: Path,
+    },
+}
+
+/// A subscription to the writes of the paths matching a pattern
+#[derive(Debug, Clone)]
+pub struct SubscribeStatement {
+    /// The pattern (which may contain wildcards)
+    pub pattern: Path,
+    /// Conditions on the change events
+    pub where_clause: Option<WhereExpression>,
 }
 
 /// Types of operations
 #[derive(Debug, Clone)]
 pub enum Operation {
+    /// Bind the value of an expression to a variable, for the rest of
+    /// the block (`let user = users.u-123456;`)
+    Let {
+        /// The name of the variable
+        name: String,
+        /// The value to bind
+        expression: Expression,
+    },
     /// Assign a value to a path
     Assignment {
-        /// The path to assign to
-        path: Path,
+        /// The path to assign to, which may have computed segments
+        /// (`users[id].username`)
+        path: Vec<PathPart>,
         /// The value to assign
         expression: Expression,
     },
-    /// Delete a path
+    /// Delete a path, or every path matching a pattern
     Delete {
-        /// The path to delete
+        /// The path or pattern to delete (`users.u-1.**` deletes the
+        /// whole entity)
         path: Path,
     },
+    /// Move the values under a prefix to another prefix
+    Move {
+        /// The prefix to move
+        from: Path,
+        /// The new prefix
+        to: Path,
+        /// Whether references into the moved subtree are updated
+        rewrite_references: bool,
+    },
+    /// Copy the values under a prefix to another prefix
+    Copy {
+        /// The prefix to copy
+        from: Path,
+        /// The prefix of the copy
+        to: Path,
+    },
+    /// Move the subtrees matching a pattern, e.g. `users.*.mail` to
+    /// `users.*.email`
+    Rename {
+        /// The pattern to rename
+        from: Path,
+        /// The new pattern, with the same wildcards
+        to: Path,
+        /// Whether references into the moved subtrees are updated
+        rewrite_references: bool,
+    },
 }
 
 /// Comparison operators for conditions
@@ -46,15 +119,62 @@ pub enum ComparisonOperator {
     GreaterThan,
     /// Greater than or equal (>=)
     GreaterThanOrEqual,
+    /// Item of an array, or substring of a string (in); `not in` is the
+    /// negation of a membership condition
+    In,
+    /// Array containing an item, or string containing a substring
+    /// (contains)
+    Contains,
+    /// String starting with a prefix (starts_with)
+    StartsWith,
 }
 
-/// Logical operators for combining conditions
+impl ComparisonOperator {
+    /// Get the operator to use when both sides are swapped, if there is one
+    pub fn reversed(&self) -> Option<Self> {
+        match self {
+            ComparisonOperator::Equal => Some(ComparisonOperator::Equal),
+            ComparisonOperator::NotEqual => Some(ComparisonOperator::NotEqual),
+            ComparisonOperator::LessThan => Some(ComparisonOperator::GreaterThan),
+            ComparisonOperator::LessThanOrEqual => Some(ComparisonOperator::GreaterThanOrEqual),
+            ComparisonOperator::GreaterThan => Some(ComparisonOperator::LessThan),
+            ComparisonOperator::GreaterThanOrEqual => Some(ComparisonOperator::LessThanOrEqual),
+            ComparisonOperator::In => Some(ComparisonOperator::Contains),
+            ComparisonOperator::Contains => Some(ComparisonOperator::In),
+            ComparisonOperator::StartsWith => None,
+        }
+    }
+}
+
+/// Operators computing a value from two others
 #[derive(Debug, Clone, PartialEq)]
-pub enum LogicalOperator {
-    /// AND (&&)
-    And,
-    /// OR (||)
-    Or,
+pub enum BinaryOperator {
+    /// Addition of numbers, or concatenation of strings (+)
+    Add,
+    /// Subtraction (-)
+    Subtract,
+    /// Multiplication (*)
+    Multiply,
+    /// Division, truncated between integers (/)
+    Divide,
+    /// Remainder of the division (%)
+    Remainder,
+    /// The left side, unless it is missing or null (??)
+    Coalesce,
+}
+
+impl BinaryOperator {
+    /// Get the symbol of the operator
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::Coalesce => "??",
+        }
+    }
 }
 
 /// A condition in a where clause
@@ -68,13 +188,43 @@ pub struct Condition {
     pub right: Box<Expression>,
 }
 
-/// A where clause with conditions
+/// A boolean expression in a where clause
+///
+/// `||` binds less tightly than `&&`, which binds less tightly than `!`;
+/// parentheses group sub-expressions.
+#[derive(Debug, Clone)]
+pub enum WhereExpression {
+    /// A comparison
+    Condition(Condition),
+    /// A value tested on its own, which holds when it is `true`
+    /// (e.g. `their.active`)
+    Truth(Box<Expression>),
+    /// Both sides hold (&&)
+    And(Box<WhereExpression>, Box<WhereExpression>),
+    /// Either side holds (||)
+    Or(Box<WhereExpression>, Box<WhereExpression>),
+    /// The expression does not hold (!)
+    Not(Box<WhereExpression>),
+}
+
+/// A segment of a path written in a query
 #[derive(Debug, Clone)]
-pub struct WhereClause {
-    /// First condition
-    pub first_condition: Condition,
-    /// Additional conditions with logical operators
-    pub additional_conditions: Vec<(LogicalOperator, Condition)>,
+pub enum PathPart {
+    /// A segment written as is
+    Segment(PathSegment),
+    /// A segment computed when the query runs, e.g. `id` in `users[id]`:
+    /// a string is a field name, an integer an array index
+    Dynamic(Expression),
+}
+
+/// A field of a projection
+#[derive(Debug, Clone)]
+pub enum Projection {
+    /// A field of the entity, relative to it (e.g. `profile.full_name`)
+    Field(Path),
+    /// An object built from fields of the same entity
+    /// (e.g. `contact: { email, phone }`)
+    Object(Vec<(String, Projection)>),
 }
 
 /// Types of expressions
@@ -82,8 +232,13 @@ pub struct WhereClause {
 pub enum Expression {
     /// A literal value
     Literal(Value),
-    /// A path reference
+    /// An array of expressions (`["admin", "owner"]`)
+    Array(Vec<Expression>),
+    /// A path reference, into the value of a variable when its first
+    /// segment is bound by `let`
     Path(Path),
+    /// A path reference with computed segments (`users[id].username`)
+    DynamicPath(Vec<PathPart>),
     /// A 'their' path reference
     TheirPath(Vec<String>),
     /// A function call
@@ -92,12 +247,40 @@ pub enum Expression {
         name: String,
         /// The arguments to the function
         arguments: Vec<Expression>,
+        /// The named arguments to the function (`name: value`)
+        named_arguments: Vec<(String, Expression)>,
+    },
+    /// An operation on the values of two expressions
+    Binary {
+        /// The left side
+        left: Box<Expression>,
+        /// The operator
+        operator: BinaryOperator,
+        /// The right side
+        right: Box<Expression>,
     },
+    /// The opposite of a number (`-x`)
+    Negate(Box<Expression>),
     /// A filtered expression (with where clause)
     Filtered {
         /// The base expression
         base: Box<Expression>,
         /// The where clause
-        where_clause: WhereClause,
+        where_clause: WhereExpression,
+    },
+    /// An expression shaped by a projection
+    Projected {
+        /// The base expression
+        base: Box<Expression>,
+        /// The fields of the result, with their names
+        projection: Vec<(String, Projection)>,
+    },
+    /// An aggregation computed for each group of entities
+    /// (`count(orders.*) group by their.country`)
+    Grouped {
+        /// The aggregation, filtered or not
+        base: Box<Expression>,
+        /// The fields grouping the entities, relative to them
+        group_by: Vec<Vec<String>>,
     },
 }
\ No newline at end of file
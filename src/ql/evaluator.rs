@@ -1,55 +1,183 @@
 //! Expression evaluator for HyperionQL
 //!
 //! This module provides functionality to evaluate expressions in the context
-//! of a database store.
+//! of a database store. Expressions evaluate to entities: a path that has
+//! no value of its own evaluates to the object reconstructed from the
+//! endpoints below it.
 
 use std::str::FromStr;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-use crate::core::entity::{Entity, reconstruct_entity};
+use bincode::serialize;
+use chrono::{DateTime, Utc};
+
+use crate::core::entity::{Entity, reconstruct_entity, entity_from_endpoints};
 use crate::core::errors::{Result, StoreError};
-use crate::core::store::Store;
+use crate::core::store::ReadStore;
 use crate::core::value::Value;
-use crate::core::path::Path;
-use crate::ql::ast::{Expression, ComparisonOperator, LogicalOperator, Condition, WhereClause};
+use crate::core::path::{Path, PathSegment};
+use crate::core::version::parse_timestamp;
+use crate::core::changes::{ChangeEvent, ChangeFilter};
+use crate::core::index::{ValueRange, VectorQuery, GeoArea};
+use crate::core::index::geo_index;
+use crate::core::index::fulltext_index;
+use crate::core::index::vector_index::{self, VectorMetric};
+use crate::ql::ast::{Expression, ComparisonOperator, BinaryOperator, Condition, WhereExpression, Projection, PathPart};
+
+/// Number of paths returned by `search()` and `vector_search()` without
+/// a `limit` argument
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// Path of an entity matching a where clause, with its endpoints when they
+/// were read to evaluate the clause
+type MatchingEntity = (Path, Option<Vec<(Path, Value)>>);
+
+/// Field of the entities of a where clause (`their.profile.age`), with the
+/// conditions comparing it to a value
+type FieldConditions = (Vec<String>, Vec<(ComparisonOperator, Value)>);
+
+/// Entities found by a composite index, with the fields whose conditions
+/// they satisfy
+type CompositeMatch = (Vec<Path>, Vec<Vec<String>>);
 
 /// Context for expression evaluation
 pub struct EvaluationContext {
     // No longer stores a reference to the store
+    /// Values bound by `let` in the block, by name
+    variables: HashMap<String, Entity>,
 }
 
 impl EvaluationContext {
     /// Create a new evaluation context
     pub fn new() -> Self {
-        EvaluationContext {}
+        EvaluationContext {
+            variables: HashMap::new(),
+        }
+    }
+    
+    /// Bind a value to a variable, replacing any previous value
+    pub fn bind(&mut self, name: &str, value: Entity) {
+        self.variables.insert(name.to_string(), value);
+    }
+    
+    /// Check whether a path starts with a variable
+    pub fn is_variable(&self, path: &Path) -> bool {
+        path.segment(0).is_some_and(|segment| {
+            !segment.is_array_index() && self.variables.contains_key(&segment.as_str())
+        })
+    }
+    
+    /// Read the field of a variable a path designates, if it starts with one
+    fn variable_field(&self, path: &Path) -> Option<Result<Entity>> {
+        let value = self.variables.get(&path.segment(0)?.as_str())?;
+        Some(
+            Self::field_of(value, &path.segments()[1..])
+                .cloned()
+                .ok_or_else(|| StoreError::NotFound(path.clone()))
+        )
+    }
+    
+    /// Compute the segments of a path written with computed segments
+    ///
+    /// A string gives a named segment and a non-negative integer an array
+    /// index.
+    pub fn resolve_path<S: ReadStore + ?Sized>(&self, store: &S, parts: &[PathPart]) -> Result<Path> {
+        let mut segments = Vec::with_capacity(parts.len());
+        for part in parts {
+            let segment = match part {
+                PathPart::Segment(segment) => segment.clone(),
+                PathPart::Dynamic(expr) => match self.evaluate(store, expr)? {
+                    Entity::String(name) => {
+                        let segment = PathSegment::new(name.as_str());
+                        if name.is_empty() || name.contains('.') || segment.is_wildcard() {
+                            return Err(StoreError::InvalidOperation(
+                                format!("Invalid path segment: {:?}", name)
+                            ));
+                        }
+                        segment
+                    },
+                    Entity::Integer(index) if index >= 0 => PathSegment::new(format!("[{}]", index)),
+                    entity => return Err(StoreError::InvalidOperation(
+                        format!("Path segments must be strings or non-negative integers, found {}", entity.type_name())
+                    )),
+                },
+            };
+            segments.push(segment);
+        }
+        
+        Ok(Path::from_segments(segments))
     }
     
     /// Evaluate an expression in this context
-    pub fn evaluate<S: Store + ?Sized>(&self, store: &S, expr: &Expression) -> Result<Value> {
+    pub fn evaluate<S: ReadStore + ?Sized>(&self, store: &S, expr: &Expression) -> Result<Entity> {
         match expr {
-            Expression::Literal(val) => Ok(val.clone()),
-            Expression::Path(path) => Self::evaluate_path(store, path),
+            Expression::Literal(val) => Ok(Entity::from(val.clone())),
+            Expression::Array(items) => items.iter()
+                .map(|item| self.evaluate(store, item))
+                .collect::<Result<Vec<_>>>()
+                .map(Entity::Array),
+            Expression::Binary { left, operator: BinaryOperator::Coalesce, right } => {
+                // Un chemin absent vaut null
+                match self.evaluate(store, left) {
+                    Ok(Entity::Null) | Err(StoreError::NotFound(_)) => self.evaluate(store, right),
+                    result => result,
+                }
+            },
+            Expression::Binary { left, operator, right } => {
+                let left = self.evaluate(store, left)?;
+                let right = self.evaluate(store, right)?;
+                Self::apply_operator(&left, operator, &right)
+            },
+            Expression::Negate(operand) => Self::negate(&self.evaluate(store, operand)?),
+            Expression::Path(path) => match self.variable_field(path) {
+                Some(entity) => entity,
+                None => Self::evaluate_path(store, path),
+            },
+            Expression::DynamicPath(parts) => {
+                let path = self.resolve_path(store, parts)?;
+                self.evaluate(store, &Expression::Path(path))
+            },
             Expression::TheirPath(_) => Err(StoreError::InvalidOperation(
                 "'their' can only be used in a 'where' clause".to_string()
             )),
-            Expression::FunctionCall { name, arguments } => {
-                self.evaluate_function_call(store, name, arguments)
+            Expression::FunctionCall { name, arguments, named_arguments } => {
+                self.evaluate_function_call(store, name, arguments, named_arguments)
+            },
+            Expression::Filtered { base, where_clause } => match &**base {
+                Expression::FunctionCall { name, arguments, named_arguments } if AggregateFunction::from_name(name).is_some() => {
+                    self.evaluate_aggregate(store, name, arguments, named_arguments, Some(where_clause), &[])
+                },
+                _ => self.evaluate_filtered_expression(store, base, where_clause, None),
+            },
+            Expression::Projected { base, projection } => {
+                self.evaluate_projection(store, base, projection)
+            },
+            Expression::Grouped { base, group_by } => {
+                let (call, where_clause) = match &**base {
+                    Expression::Filtered { base, where_clause } => (&**base, Some(where_clause)),
+                    base => (base, None),
+                };
+                match call {
+                    Expression::FunctionCall { name, arguments, named_arguments } if AggregateFunction::from_name(name).is_some() => {
+                        self.evaluate_aggregate(store, name, arguments, named_arguments, where_clause, group_by)
+                    },
+                    _ => Err(StoreError::InvalidOperation(
+                        "'group by' only applies to aggregation functions (count, sum, avg, min, max, distinct)".to_string()
+                    )),
+                }
             },
-            Expression::Filtered { base, where_clause } => {
-                self.evaluate_filtered_expression(store, base, where_clause)
-            }
         }
     }
     
     /// Evaluate a path expression by fetching its value from the store
-    fn evaluate_path<S: Store + ?Sized>(store: &S, path: &Path) -> Result<Value> {
+    fn evaluate_path<S: ReadStore + ?Sized>(store: &S, path: &Path) -> Result<Entity> {
         // Try to get a direct value first
         match store.get(path) {
-            Ok(value) => Ok(value),
+            Ok(value) => Ok(Entity::from(value)),
             Err(StoreError::NotFound(_)) => {
                 // If direct value doesn't exist, try to reconstruct an entity
                 match reconstruct_entity(store, path) {
-                    Ok(entity) => entity_to_value(&entity),
+                    Ok(entity) => Ok(entity),
                     Err(_) => Err(StoreError::NotFound(path.clone()))
                 }
             },
@@ -57,62 +185,171 @@ impl EvaluationContext {
         }
     }
     
-    /// Evaluate an entity expression by reconstructing the entity
-    fn evaluate_entity<S: Store + ?Sized>(&self, store: &S, path: &Path) -> Result<Value> {
-        let entity = reconstruct_entity(store, path)?;
-        entity_to_value(&entity)
-    }
-    
-    fn evaluate_filtered_expression<S: Store + ?Sized>(
+    fn evaluate_filtered_expression<S: ReadStore + ?Sized>(
         &self, 
         store: &S,
         base: &Expression, 
-        where_clause: &WhereClause
-    ) -> Result<Value> {
-        // Currently we only support filtering on path expressions
+        where_clause: &WhereExpression,
+        projection: Option<&[(String, Projection)]>,
+    ) -> Result<Entity> {
+        // Currently we only support filtering on stored paths
         let base_path = match base {
-            Expression::Path(path) => path,
+            Expression::Path(path) => path.clone(),
+            Expression::DynamicPath(parts) => self.resolve_path(store, parts)?,
             _ => return Err(StoreError::InvalidOperation(
                 "Filtering is currently only supported on path expressions".to_string()
             )),
         };
+        if self.is_variable(&base_path) {
+            return Err(StoreError::InvalidOperation(
+                "Filtering is currently only supported on stored paths, not variables".to_string()
+            ));
+        }
+        
+        let mut result_entities = Vec::new();
+        for (entity_path, endpoints) in self.matching_entities(store, &base_path, where_clause)? {
+            let entity = match (endpoints, projection) {
+                (Some(endpoints), projection) => {
+                    let entity = entity_from_endpoints(endpoints, &entity_path)?;
+                    Some(match projection {
+                        Some(projection) => Self::project_entity(&entity, projection)?,
+                        None => entity,
+                    })
+                },
+                // With no condition left to check, only the projected fields
+                // are read
+                (None, Some(projection)) => self.project_path(store, &entity_path, projection)?,
+                (None, None) => {
+                    let endpoints = store.get_prefix(&entity_path)?;
+                    match endpoints.is_empty() {
+                        // Skip entities that can't be reconstructed
+                        true => None,
+                        false => Some(entity_from_endpoints(endpoints, &entity_path)?),
+                    }
+                },
+            };
+            
+            if let Some(entity) = entity {
+                result_entities.push(entity);
+            }
+        }
+        
+        Ok(Entity::Array(result_entities))
+    }
+    
+    /// Find the entities below a base path for which a where expression
+    /// holds
+    ///
+    /// Each entity comes with its endpoints when they had to be read to
+    /// evaluate the expression, and without them when indexes answered it.
+    fn matching_entities<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        base_path: &Path,
+        where_clause: &WhereExpression,
+    ) -> Result<Vec<MatchingEntity>> {
+        // The conditions of the top-level && that compare a 'their' path
+        // with a literal narrow the candidates down through the indexes;
+        // the rest of the expression is evaluated on each candidate
+        let mut conjuncts = Vec::new();
+        Self::collect_conjuncts(where_clause, &mut conjuncts);
         
-        // Process the where clause to extract conditions on 'their' paths
-        let their_conditions = self.extract_their_conditions(where_clause)?;
+        let mut their_conditions = Vec::new();
+        let mut remaining = Vec::new();
+        for conjunct in conjuncts {
+            match conjunct {
+                WhereExpression::Condition(condition) => match self.their_condition(condition) {
+                    Some(their_condition) => their_conditions.push(their_condition),
+                    None => remaining.push(conjunct),
+                },
+                _ => remaining.push(conjunct),
+            }
+        }
         
         if their_conditions.is_empty() {
-            return Err(StoreError::InvalidOperation(
-                "Where clause must contain conditions on 'their' paths".to_string()
-            ));
+            // Sans condition à indexer, chaque entité du chemin de base est
+            // candidate
+            let mut candidates: BTreeMap<String, Vec<(Path, Value)>> = BTreeMap::new();
+            for (path, value) in store.get_prefix(base_path)? {
+                if let Some(entity_id) = Self::entity_id(base_path, &path) {
+                    candidates.entry(entity_id).or_default().push((path, value));
+                }
+            }
+            
+            let mut matching = Vec::new();
+            for (entity_id, endpoints) in candidates {
+                let entity_path = Path::from_str(&format!("{}.{}", base_path, entity_id))?;
+                if self.satisfies(store, &entity_path, &endpoints, &remaining)? {
+                    matching.push((entity_path, Some(endpoints)));
+                }
+            }
+            
+            return Ok(matching);
+        }
+        
+        // Conditions on the same field are answered together, so that
+        // `their.age > 30 && their.age < 40` is a single range
+        let mut grouped: Vec<FieldConditions> = Vec::new();
+        for (their_path, operator, value) in their_conditions {
+            match grouped.iter_mut().find(|(path, _)| *path == their_path) {
+                Some((_, conditions)) => conditions.push((operator, value)),
+                None => grouped.push((their_path, vec![(operator, value)])),
+            }
         }
         
-        // Find matching entity IDs for each condition
-        let mut all_matching_ids = HashSet::new();
+        // Find matching entity IDs for each field, kept sorted so that the
+        // entities come out in the same order on every run
+        let mut all_matching_ids = BTreeSet::new();
         let mut is_first_condition = true;
         
-        for (their_path, operator, value) in &their_conditions {
+        // A composite index may answer the equalities, and a range on the
+        // next field, at once
+        if let Some((entities, answered)) = Self::find_by_fields(store, base_path, &grouped)? {
+            all_matching_ids = entities.iter()
+                .filter_map(|entity| Self::entity_id(base_path, entity))
+                .collect();
+            is_first_condition = false;
+            grouped.retain(|(their_path, _)| !answered.contains(their_path));
+        }
+        
+        for (their_path, conditions) in &grouped {
             // Construct wildcard path for searching
             // e.g., users.*.active for "their.active"
             let search_path_str = format!("{}.*.{}", base_path, their_path.join("."));
             let search_path = Path::from_str(&search_path_str)?;
             
-            // Find all paths matching this pattern
-            let matching_paths = store.query(&search_path)?;
+            // Use a value index when one covers the field, otherwise filter
+            // every path matching the pattern by its value
+            let indexed_paths = match Self::value_range(conditions) {
+                Some(range) => store.find_by_value_range(&search_path, &range)?,
+                None => None,
+            };
             
-            // Filter paths by the condition value
-            let mut matching_ids_for_condition = HashSet::new();
+            let mut matching_ids_for_condition = BTreeSet::new();
             
-            for (path, actual_value) in matching_paths {
-                // Check if the value matches our condition
-                if self.compare_values(&actual_value, operator, value)? {
-                    // Extract the entity ID from the path
-                    // e.g., "u-123456" from "users.u-123456.active"
-                    let path_segments = path.segments();
-                    if path_segments.len() >= 2 {
-                        let entity_id = path_segments[1].as_str();
-                        matching_ids_for_condition.insert(entity_id.to_string());
+            match indexed_paths {
+                Some(paths) => {
+                    for path in paths {
+                        if let Some(entity_id) = Self::entity_id(base_path, &path) {
+                            matching_ids_for_condition.insert(entity_id);
+                        }
                     }
-                }
+                },
+                None => {
+                    for (path, actual_value) in store.query(&search_path)? {
+                        // Check if the value matches every condition; a value
+                        // that cannot be compared with the operand does not
+                        let holds = conditions.iter().all(|(operator, value)| {
+                            self.compare_values(&actual_value, operator, value).unwrap_or(false)
+                        });
+                        
+                        if holds {
+                            if let Some(entity_id) = Self::entity_id(base_path, &path) {
+                                matching_ids_for_condition.insert(entity_id);
+                            }
+                        }
+                    }
+                },
             }
             
             // Combine with previous conditions using appropriate logical operation
@@ -129,76 +366,455 @@ impl EvaluationContext {
             }
         }
         
-        // Reconstruct matching entities
-        let mut result_entities = Vec::new();
-        
+        let mut matching = Vec::new();
         for entity_id in all_matching_ids {
             let entity_path_str = format!("{}.{}", base_path, entity_id);
             let entity_path = Path::from_str(&entity_path_str)?;
             
-            match reconstruct_entity(store, &entity_path) {
+            // With no condition left to check, the endpoints of the entity
+            // are not read
+            if remaining.is_empty() {
+                matching.push((entity_path, None));
+                continue;
+            }
+            
+            let endpoints = store.get_prefix(&entity_path)?;
+            if self.satisfies(store, &entity_path, &endpoints, &remaining)? {
+                matching.push((entity_path, Some(endpoints)));
+            }
+        }
+        
+        Ok(matching)
+    }
+    
+    /// Shape the result of an expression with a projection
+    ///
+    /// Entities at a path, filtered or not, are read field by field; other
+    /// results are shaped once evaluated, item by item for arrays.
+    fn evaluate_projection<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        base: &Expression,
+        projection: &[(String, Projection)],
+    ) -> Result<Entity> {
+        match base {
+            Expression::Path(path) if !self.is_variable(path) => {
+                self.project_path(store, path, projection)?
+                    .ok_or_else(|| StoreError::NotFound(path.clone()))
+            },
+            Expression::DynamicPath(parts) => {
+                let path = self.resolve_path(store, parts)?;
+                self.evaluate_projection(store, &Expression::Path(path), projection)
+            },
+            Expression::Filtered { base, where_clause } => {
+                self.evaluate_filtered_expression(store, base, where_clause, Some(projection))
+            },
+            _ => match self.evaluate(store, base)? {
+                Entity::Array(items) => items.iter()
+                    .map(|item| Self::project_entity(item, projection))
+                    .collect::<Result<Vec<_>>>()
+                    .map(Entity::Array),
+                entity => Self::project_entity(&entity, projection),
+            },
+        }
+    }
+    
+    /// Read the projected fields of the entity at a path, without
+    /// reconstructing the rest of it
+    ///
+    /// Returns `None` when there is no entity at the path.
+    fn project_path<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        entity_path: &Path,
+        projection: &[(String, Projection)],
+    ) -> Result<Option<Entity>> {
+        let mut found = false;
+        let shaped = Self::project(projection, &mut |field| {
+            let field_path = Path::from_segments(
+                entity_path.segments().iter().chain(field.segments()).cloned().collect()
+            );
+            match Self::evaluate_path(store, &field_path) {
                 Ok(entity) => {
-                    let entity_value = entity_to_value(&entity)?;
-                    result_entities.push(entity_value);
+                    found = true;
+                    Ok(entity)
                 },
-                Err(_) => continue, // Skip entities that can't be reconstructed
+                Err(StoreError::NotFound(_)) => Ok(Entity::Null),
+                Err(e) => Err(e),
             }
+        })?;
+        
+        // Aucun champ trouvé : l'entité elle-même existe-t-elle ?
+        if !found && store.count_prefix(entity_path)? == 0 {
+            return Ok(None);
         }
         
-        // Return as a JSON array
-        let json_array = format!("[{}]", result_entities
-            .iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(", "));
+        Ok(Some(shaped))
+    }
+    
+    /// Shape an entity already reconstructed with a projection
+    fn project_entity(entity: &Entity, projection: &[(String, Projection)]) -> Result<Entity> {
+        if !matches!(entity, Entity::Object(_)) {
+            return Err(StoreError::InvalidOperation(
+                format!("Cannot project a {} value, only objects", entity.type_name())
+            ));
+        }
         
-        Ok(Value::String(json_array))
+        Self::project(projection, &mut |field| {
+            Ok(Self::field_of(entity, field.segments()).cloned().unwrap_or(Entity::Null))
+        })
+    }
+    
+    /// Find the field of an entity below some segments
+    fn field_of<'a>(entity: &'a Entity, segments: &[PathSegment]) -> Option<&'a Entity> {
+        segments.iter().try_fold(entity, |current, segment| {
+            match (current, segment.as_index()) {
+                (Entity::Object(fields), None) => fields.get(&segment.as_str()),
+                (Entity::Array(items), Some(index)) => items.get(index),
+                _ => None,
+            }
+        })
     }
     
-    fn extract_their_conditions(&self, where_clause: &WhereClause) 
-    -> Result<Vec<(Vec<String>, ComparisonOperator, Value)>> {
+    /// Build the object described by a projection, reading each field with
+    /// `field`; missing fields are null
+    fn project(
+        projection: &[(String, Projection)],
+        field: &mut dyn FnMut(&Path) -> Result<Entity>,
+    ) -> Result<Entity> {
+        let mut object = HashMap::with_capacity(projection.len());
+        for (name, item) in projection {
+            let entity = match item {
+                Projection::Field(path) => field(path)?,
+                Projection::Object(fields) => Self::project(fields, field)?,
+            };
+            object.insert(name.clone(), entity);
+        }
         
-        let mut their_conditions = Vec::new();
+        Ok(Entity::Object(object))
+    }
+    
+    /// Flatten the operands of the top-level `&&` of a where expression
+    fn collect_conjuncts<'a>(expression: &'a WhereExpression, conjuncts: &mut Vec<&'a WhereExpression>) {
+        match expression {
+            WhereExpression::And(left, right) => {
+                Self::collect_conjuncts(left, conjuncts);
+                Self::collect_conjuncts(right, conjuncts);
+            },
+            _ => conjuncts.push(expression),
+        }
+    }
+    
+    /// Check that every remaining conjunct of the where expression holds
+    /// for a candidate entity, given its endpoints
+    fn satisfies<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        entity_path: &Path,
+        endpoints: &[(Path, Value)],
+        conjuncts: &[&WhereExpression],
+    ) -> Result<bool> {
+        if endpoints.is_empty() {
+            // Skip entities that can't be reconstructed
+            return Ok(false);
+        }
         
-        // Process the first condition
-        self.extract_condition_if_their(&where_clause.first_condition, &mut their_conditions)?;
+        for conjunct in conjuncts {
+            if !self.holds(store, conjunct, entity_path, endpoints)? {
+                return Ok(false);
+            }
+        }
+        
+        Ok(true)
+    }
+    
+    /// Evaluate a where expression on an entity, given its endpoints
+    fn holds<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        expression: &WhereExpression,
+        entity_path: &Path,
+        endpoints: &[(Path, Value)],
+    ) -> Result<bool> {
+        match expression {
+            WhereExpression::Condition(condition) => {
+                let left = self.operand_entity(store, &condition.left, entity_path, endpoints)?;
+                let right = self.operand_entity(store, &condition.right, entity_path, endpoints)?;
+                
+                // Des valeurs absentes ou incomparables ne satisfont pas la
+                // condition
+                Ok(match (left, right) {
+                    (Some(left), Some(right)) => self.compare_entities(&left, &condition.operator, &right),
+                    _ => false,
+                })
+            },
+            WhereExpression::Truth(operand) => {
+                Ok(self.operand_entity(store, operand, entity_path, endpoints)? == Some(Entity::Boolean(true)))
+            },
+            WhereExpression::And(left, right) => {
+                Ok(self.holds(store, left, entity_path, endpoints)? && self.holds(store, right, entity_path, endpoints)?)
+            },
+            WhereExpression::Or(left, right) => {
+                Ok(self.holds(store, left, entity_path, endpoints)? || self.holds(store, right, entity_path, endpoints)?)
+            },
+            WhereExpression::Not(inner) => Ok(!self.holds(store, inner, entity_path, endpoints)?),
+        }
+    }
+    
+    /// Evaluate an operand of a where expression for an entity
+    ///
+    /// 'their' paths are read from the endpoints of the entity; other
+    /// paths and function calls are evaluated against the store. Missing
+    /// values, and operations on values of the wrong types, give `None`.
+    fn operand_entity<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        operand: &Expression,
+        entity_path: &Path,
+        endpoints: &[(Path, Value)],
+    ) -> Result<Option<Entity>> {
+        match operand {
+            Expression::TheirPath(segments) => {
+                let path = match segments.is_empty() {
+                    true => entity_path.clone(),
+                    false => Path::from_str(&format!("{}.{}", entity_path, segments.join(".")))?,
+                };
+                let below: Vec<(Path, Value)> = endpoints.iter()
+                    .filter(|(endpoint, _)| endpoint.starts_with(&path))
+                    .cloned()
+                    .collect();
+                Ok(entity_from_endpoints(below, &path).ok())
+            },
+            Expression::Literal(value) => Ok(Some(Entity::from(value.clone()))),
+            Expression::Array(items) => {
+                let mut array = Vec::with_capacity(items.len());
+                for item in items {
+                    array.push(self.operand_entity(store, item, entity_path, endpoints)?.unwrap_or(Entity::Null));
+                }
+                Ok(Some(Entity::Array(array)))
+            },
+            Expression::Binary { left, operator: BinaryOperator::Coalesce, right } => {
+                match self.operand_entity(store, left, entity_path, endpoints)? {
+                    None | Some(Entity::Null) => self.operand_entity(store, right, entity_path, endpoints),
+                    left => Ok(left),
+                }
+            },
+            Expression::Binary { left, operator, right } => {
+                let left = self.operand_entity(store, left, entity_path, endpoints)?;
+                let right = self.operand_entity(store, right, entity_path, endpoints)?;
+                Ok(match (left, right) {
+                    (Some(left), Some(right)) => Self::apply_operator(&left, operator, &right).ok(),
+                    _ => None,
+                })
+            },
+            Expression::Negate(operand) => {
+                Ok(self.operand_entity(store, operand, entity_path, endpoints)?
+                    .and_then(|entity| Self::negate(&entity).ok()))
+            },
+            _ => match self.evaluate(store, operand) {
+                Ok(entity) => Ok(Some(entity)),
+                Err(StoreError::NotFound(_)) => Ok(None),
+                Err(e) => Err(e),
+            },
+        }
+    }
+    
+    /// Compare two entities: membership tests look into arrays, other
+    /// comparisons apply to single values (objects and arrays can only be
+    /// equal or not)
+    fn compare_entities(&self, left: &Entity, operator: &ComparisonOperator, right: &Entity) -> bool {
+        match (operator, left, right) {
+            (ComparisonOperator::In, item, Entity::Array(items))
+            | (ComparisonOperator::Contains, Entity::Array(items), item) => items.contains(item),
+            _ => match (left.clone().into_value(), right.clone().into_value()) {
+                (Some(left), Some(right)) => self.compare_values(&left, operator, &right).unwrap_or(false),
+                _ => match operator {
+                    ComparisonOperator::Equal => left == right,
+                    ComparisonOperator::NotEqual => left != right,
+                    _ => false,
+                },
+            },
+        }
+    }
+    
+    /// Find the entities matching the conditions on several fields through
+    /// a composite index
+    ///
+    /// Returns the entities and the fields whose conditions they satisfy,
+    /// or `None` when no composite index starts with the fields compared
+    /// for equality.
+    fn find_by_fields<S: ReadStore + ?Sized>(
+        store: &S,
+        base_path: &Path,
+        grouped: &[FieldConditions],
+    ) -> Result<Option<CompositeMatch>> {
+        let field = |their_path: &[String]| Path::from_segments(their_path.iter().map(PathSegment::new).collect());
+        
+        let mut equals = Vec::new();
+        let mut ranges = Vec::new();
+        for (their_path, conditions) in grouped {
+            match conditions.as_slice() {
+                [(ComparisonOperator::Equal, value)] => equals.push((their_path, value.clone())),
+                _ => {
+                    if let Some(range) = Self::value_range(conditions) {
+                        ranges.push((their_path, range));
+                    }
+                },
+            }
+        }
         
-        // Process additional conditions
-        for (_, condition) in &where_clause.additional_conditions {
-            self.extract_condition_if_their(condition, &mut their_conditions)?;
+        if equals.is_empty() {
+            return Ok(None);
+        }
+        
+        let pattern = Path::from_str(&format!("{}.*", base_path))?;
+        let equal_fields: Vec<(Path, Value)> = equals.iter()
+            .map(|(their_path, value)| (field(their_path), value.clone()))
+            .collect();
+        let mut answered: Vec<Vec<String>> = equals.iter().map(|(their_path, _)| (*their_path).clone()).collect();
+        
+        // Prefer an index that also answers a range
+        for (their_path, range) in &ranges {
+            if let Some(entities) = store.find_by_fields(&pattern, &equal_fields, Some((&field(their_path), range)))? {
+                answered.push((*their_path).clone());
+                return Ok(Some((entities, answered)));
+            }
         }
         
-        Ok(their_conditions)
+        Ok(store.find_by_fields(&pattern, &equal_fields, None)?.map(|entities| (entities, answered)))
     }
     
-    fn extract_condition_if_their(&self, condition: &Condition, 
-        result: &mut Vec<(Vec<String>, ComparisonOperator, Value)>) -> Result<()> {
-            
-        println!("Extracting condition: {:?}", condition);
-        // Check if left side is a TheirPath and right side is a literal
-        if let (Expression::TheirPath(path), Expression::Literal(value)) = (&*condition.left, &*condition.right) {
-            result.push((path.clone(), condition.operator.clone(), value.clone()));
-            return Ok(());
+    /// Turn the conditions on a field into a value range, if they are all
+    /// ordering comparisons
+    fn value_range(conditions: &[(ComparisonOperator, Value)]) -> Option<ValueRange> {
+        let mut range = ValueRange::new();
+        for (operator, value) in conditions {
+            range = match operator {
+                ComparisonOperator::GreaterThan => range.above(value.clone(), false),
+                ComparisonOperator::GreaterThanOrEqual => range.above(value.clone(), true),
+                ComparisonOperator::LessThan => range.below(value.clone(), false),
+                ComparisonOperator::LessThanOrEqual => range.below(value.clone(), true),
+                ComparisonOperator::Equal | ComparisonOperator::NotEqual
+                | ComparisonOperator::In | ComparisonOperator::Contains
+                | ComparisonOperator::StartsWith => return None,
+            };
         }
         
-        // Check if right side is a TheirPath and left side is a literal (reversed condition)
-        if let (Expression::Literal(value), Expression::TheirPath(path)) = (&*condition.left, &*condition.right) {
+        Some(range)
+    }
+    
+    /// Extract the entity ID of a path below the filtered base path
+    /// e.g., "u-123456" from "users.u-123456.active"
+    fn entity_id(base_path: &Path, path: &Path) -> Option<String> {
+        path.segments().get(base_path.len()).map(|segment| segment.as_str())
+    }
+    
+    /// Read a condition comparing a 'their' path with a literal (or a
+    /// variable) as the field, the operator and the value, the field on
+    /// the left
+    ///
+    /// Membership conditions are left out: the field may be an array,
+    /// stored as several endpoints.
+    fn their_condition(&self, condition: &Condition) -> Option<(Vec<String>, ComparisonOperator, Value)> {
+        let (path, operator, value) = match (&*condition.left, &*condition.right) {
+            (Expression::TheirPath(path), right) if !path.is_empty() => {
+                (path, condition.operator.clone(), self.constant_value(right)?)
+            },
             // Reverse the operator for correct comparison
-            let reversed_operator = match condition.operator {
-                ComparisonOperator::Equal => ComparisonOperator::Equal,
-                ComparisonOperator::NotEqual => ComparisonOperator::NotEqual,
-                ComparisonOperator::LessThan => ComparisonOperator::GreaterThan,
-                ComparisonOperator::LessThanOrEqual => ComparisonOperator::GreaterThanOrEqual,
-                ComparisonOperator::GreaterThan => ComparisonOperator::LessThan,
-                ComparisonOperator::GreaterThanOrEqual => ComparisonOperator::LessThanOrEqual,
-            };
-            
-            result.push((path.clone(), reversed_operator, value.clone()));
-            return Ok(());
+            (left, Expression::TheirPath(path)) if !path.is_empty() => {
+                (path, condition.operator.reversed()?, self.constant_value(left)?)
+            },
+            _ => return None,
+        };
+        
+        match operator {
+            ComparisonOperator::In | ComparisonOperator::Contains => None,
+            operator => Some((path.clone(), operator, value)),
+        }
+    }
+    
+    /// Get the value of a literal, or of the field of a variable holding a
+    /// single value
+    fn constant_value(&self, expr: &Expression) -> Option<Value> {
+        match expr {
+            Expression::Literal(value) => Some(value.clone()),
+            Expression::Path(path) => self.variable_field(path)?.ok()?.into_value(),
+            _ => None,
+        }
+    }
+    
+    /// Get the entity of a constant expression: a literal, an array of
+    /// literals, or the field of a variable
+    fn constant_entity(&self, expr: &Expression) -> Option<Entity> {
+        match expr {
+            Expression::Array(items) => items.iter()
+                .map(|item| self.constant_entity(item))
+                .collect::<Option<Vec<_>>>()
+                .map(Entity::Array),
+            Expression::Path(path) => self.variable_field(path)?.ok(),
+            _ => self.constant_value(expr).map(Entity::from),
+        }
+    }
+    
+    /// Compile the where clause of a subscription into an event filter
+    ///
+    /// Conditions compare a field of the event (`new_value`, `old_value`,
+    /// `path` or `operation`) with a literal; a field on its own holds
+    /// when it is `true`.
+    pub fn event_filter(&self, where_clause: &WhereExpression) -> Result<ChangeFilter> {
+        Ok(match where_clause {
+            WhereExpression::Condition(condition) => {
+                let (field, operator, value) = self.event_condition(condition)?;
+                Box::new(move |event| {
+                    // Des valeurs incomparables ne satisfont pas la condition
+                    EvaluationContext::new().compare_entities(&Entity::from(field.value_of(event)), &operator, &value)
+                })
+            },
+            WhereExpression::Truth(operand) => match &**operand {
+                Expression::Path(path) => {
+                    let field = EventField::from_path(path)?;
+                    Box::new(move |event| field.value_of(event) == Value::Boolean(true))
+                },
+                _ => return Err(StoreError::InvalidOperation(
+                    "Subscription conditions must compare an event field with a literal value".to_string()
+                )),
+            },
+            WhereExpression::And(left, right) => {
+                let (left, right) = (self.event_filter(left)?, self.event_filter(right)?);
+                Box::new(move |event| left(event) && right(event))
+            },
+            WhereExpression::Or(left, right) => {
+                let (left, right) = (self.event_filter(left)?, self.event_filter(right)?);
+                Box::new(move |event| left(event) || right(event))
+            },
+            WhereExpression::Not(inner) => {
+                let inner = self.event_filter(inner)?;
+                Box::new(move |event| !inner(event))
+            },
+        })
+    }
+    
+    fn event_condition(&self, condition: &Condition) -> Result<(EventField, ComparisonOperator, Entity)> {
+        // Un chemin qui ne commence pas par une variable désigne un champ de
+        // l'événement
+        let event_field = |expr: &Expression| match expr {
+            Expression::Path(path) if !self.is_variable(path) => Some(EventField::from_path(path)),
+            _ => None,
+        };
+        
+        if let (Some(field), Some(value)) = (event_field(&condition.left), self.constant_entity(&condition.right)) {
+            return Ok((field?, condition.operator.clone(), value));
+        }
+        if let (Some(value), Some(field), Some(operator)) = (
+            self.constant_entity(&condition.left),
+            event_field(&condition.right),
+            condition.operator.reversed(),
+        ) {
+            return Ok((field?, operator, value));
         }
         
         Err(StoreError::InvalidOperation(
-            "Where conditions must compare 'their' paths with literal values".to_string()
+            "Subscription conditions must compare an event field with a literal value".to_string()
         ))
     }
     
@@ -213,6 +829,7 @@ impl EvaluationContext {
                     (Value::Integer(l), Value::Float(r)) => Ok((*l as f64) < *r),
                     (Value::Float(l), Value::Integer(r)) => Ok(*l < (*r as f64)),
                     (Value::String(l), Value::String(r)) => Ok(l < r),
+                    (Value::Boolean(l), Value::Boolean(r)) => Ok(l < r),
                     _ => Err(StoreError::InvalidOperation(
                         format!("Cannot compare {:?} and {:?} with <", left, right)
                     )),
@@ -225,6 +842,7 @@ impl EvaluationContext {
                     (Value::Integer(l), Value::Float(r)) => Ok((*l as f64) <= *r),
                     (Value::Float(l), Value::Integer(r)) => Ok(*l <= (*r as f64)),
                     (Value::String(l), Value::String(r)) => Ok(l <= r),
+                    (Value::Boolean(l), Value::Boolean(r)) => Ok(l <= r),
                     _ => Err(StoreError::InvalidOperation(
                         format!("Cannot compare {:?} and {:?} with <=", left, right)
                     )),
@@ -237,6 +855,7 @@ impl EvaluationContext {
                     (Value::Integer(l), Value::Float(r)) => Ok((*l as f64) > *r),
                     (Value::Float(l), Value::Integer(r)) => Ok(*l > (*r as f64)),
                     (Value::String(l), Value::String(r)) => Ok(l > r),
+                    (Value::Boolean(l), Value::Boolean(r)) => Ok(l > r),
                     _ => Err(StoreError::InvalidOperation(
                         format!("Cannot compare {:?} and {:?} with >", left, right)
                     )),
@@ -249,16 +868,150 @@ impl EvaluationContext {
                     (Value::Integer(l), Value::Float(r)) => Ok((*l as f64) >= *r),
                     (Value::Float(l), Value::Integer(r)) => Ok(*l >= (*r as f64)),
                     (Value::String(l), Value::String(r)) => Ok(l >= r),
+                    (Value::Boolean(l), Value::Boolean(r)) => Ok(l >= r),
                     _ => Err(StoreError::InvalidOperation(
                         format!("Cannot compare {:?} and {:?} with >=", left, right)
                     )),
                 }
             },
+            ComparisonOperator::In => self.compare_values(right, &ComparisonOperator::Contains, left),
+            ComparisonOperator::Contains => {
+                match (left, right) {
+                    (Value::String(l), Value::String(r)) => Ok(l.contains(r.as_str())),
+                    (Value::Vector(items), Value::Integer(r)) => Ok(items.contains(&(*r as f32))),
+                    (Value::Vector(items), Value::Float(r)) => Ok(items.contains(&(*r as f32))),
+                    _ => Err(StoreError::InvalidOperation(
+                        format!("Cannot compare {:?} and {:?} with contains", left, right)
+                    )),
+                }
+            },
+            ComparisonOperator::StartsWith => {
+                match (left, right) {
+                    (Value::String(l), Value::String(r)) => Ok(l.starts_with(r.as_str())),
+                    _ => Err(StoreError::InvalidOperation(
+                        format!("Cannot compare {:?} and {:?} with starts_with", left, right)
+                    )),
+                }
+            },
+        }
+    }
+    
+    /// Apply an arithmetic operator to two entities
+    ///
+    /// As in comparisons, integers and floats mix into floats; two integers
+    /// give an integer, the division being truncated, and an integer
+    /// overflow is an error. `+` also concatenates strings, with the text
+    /// of a number or a boolean on the other side (`"v" + 2` is `"v2"`).
+    fn apply_operator(left: &Entity, operator: &BinaryOperator, right: &Entity) -> Result<Entity> {
+        let division_by_zero = || StoreError::InvalidOperation(
+            format!("Division by zero with {}", operator.symbol())
+        );
+        let cannot_apply = || StoreError::InvalidOperation(
+            format!("Cannot apply {} to {} and {}", operator.symbol(), left.type_name(), right.type_name())
+        );
+        
+        match (left, right) {
+            _ if *operator == BinaryOperator::Coalesce => {
+                Ok(if *left == Entity::Null { right.clone() } else { left.clone() })
+            },
+            (Entity::String(_), _) | (_, Entity::String(_)) if *operator == BinaryOperator::Add => {
+                match (Self::text(left), Self::text(right)) {
+                    (Some(l), Some(r)) => Ok(Entity::String(l + &r)),
+                    _ => Err(cannot_apply()),
+                }
+            },
+            (Entity::Integer(l), Entity::Integer(r)) => {
+                let result = match operator {
+                    BinaryOperator::Divide | BinaryOperator::Remainder if *r == 0 => return Err(division_by_zero()),
+                    BinaryOperator::Add => l.checked_add(*r),
+                    BinaryOperator::Subtract => l.checked_sub(*r),
+                    BinaryOperator::Multiply => l.checked_mul(*r),
+                    BinaryOperator::Divide => l.checked_div(*r),
+                    BinaryOperator::Remainder => l.checked_rem(*r),
+                    BinaryOperator::Coalesce => Some(*l),
+                };
+                result.map(Entity::Integer).ok_or_else(|| StoreError::InvalidOperation(
+                    format!("Integer overflow in {} {} {}", l, operator.symbol(), r)
+                ))
+            },
+            _ => {
+                let number = |entity: &Entity| match entity {
+                    Entity::Integer(i) => Some(*i as f64),
+                    Entity::Float(f) => Some(*f),
+                    _ => None,
+                };
+                
+                match (number(left), number(right)) {
+                    (Some(_), Some(r)) if r == 0.0 && matches!(operator, BinaryOperator::Divide | BinaryOperator::Remainder) => {
+                        Err(division_by_zero())
+                    },
+                    (Some(l), Some(r)) => Ok(Entity::Float(match operator {
+                        BinaryOperator::Add => l + r,
+                        BinaryOperator::Subtract => l - r,
+                        BinaryOperator::Multiply => l * r,
+                        BinaryOperator::Divide => l / r,
+                        BinaryOperator::Remainder => l % r,
+                        BinaryOperator::Coalesce => l,
+                    })),
+                    _ => Err(cannot_apply()),
+                }
+            },
+        }
+    }
+    
+    /// Get the text a string concatenates with an entity, if any
+    fn text(entity: &Entity) -> Option<String> {
+        match entity {
+            Entity::String(s) => Some(s.clone()),
+            Entity::Integer(i) => Some(i.to_string()),
+            Entity::Float(f) => Some(f.to_string()),
+            Entity::Boolean(b) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+    
+    /// Compute the opposite of a number
+    fn negate(entity: &Entity) -> Result<Entity> {
+        match entity {
+            Entity::Integer(i) => i.checked_neg().map(Entity::Integer).ok_or_else(|| StoreError::InvalidOperation(
+                format!("Integer overflow in -{}", i)
+            )),
+            Entity::Float(f) => Ok(Entity::Float(-f)),
+            _ => Err(StoreError::InvalidOperation(
+                format!("Cannot negate a {} value", entity.type_name())
+            )),
         }
     }
     
     /// Evaluate a function call
-    fn evaluate_function_call<S: Store + ?Sized>(&self, store: &S, name: &str, arguments: &[Expression]) -> Result<Value> {
+    fn evaluate_function_call<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        name: &str,
+        arguments: &[Expression],
+        named_arguments: &[(String, Expression)],
+    ) -> Result<Entity> {
+        // Les fonctions temporelles et la recherche prennent un chemin, qui
+        // ne doit pas être évalué
+        match name {
+            "history" => return self.function_history(store, arguments, named_arguments),
+            "snapshot" => return self.function_snapshot(store, arguments, named_arguments),
+            "search" => return self.function_search(store, arguments, named_arguments),
+            "vector_search" => return self.function_vector_search(store, arguments, named_arguments),
+            "within_radius" => return self.function_within_radius(store, arguments, named_arguments),
+            "within_box" => return self.function_within_box(store, arguments, named_arguments),
+            _ if AggregateFunction::from_name(name).is_some() => {
+                return self.evaluate_aggregate(store, name, arguments, named_arguments, None, &[]);
+            },
+            _ => {}
+        }
+        
+        if !named_arguments.is_empty() {
+            return Err(StoreError::InvalidOperation(
+                format!("{}() function does not accept named arguments", name)
+            ));
+        }
+        
         // Évaluer les arguments
         let mut evaluated_args = Vec::with_capacity(arguments.len());
         for arg in arguments {
@@ -268,9 +1021,9 @@ impl EvaluationContext {
         
         // Exécuter la fonction en fonction de son nom
         match name {
-            "count" => self.function_count(store, &evaluated_args),
             "now" => self.function_now(),
             "uuid" => self.function_uuid(),
+            "geopoint" => self.function_geopoint(&evaluated_args),
             // Ajouter d'autres fonctions ici...
             _ => Err(StoreError::InvalidOperation(
                 format!("Unknown function: {}", name)
@@ -278,9 +1031,220 @@ impl EvaluationContext {
         }
     }
     
+    /// Evaluate an aggregation function (`count`, `sum`, `avg`, `min`,
+    /// `max` or `distinct`), optionally filtered and grouped
+    ///
+    /// Over a path pattern, the values of the matching endpoints are
+    /// streamed into the aggregate, without reconstructing entities; the
+    /// entities filtered and grouped are those at the first wildcard of the
+    /// pattern, and a path without wildcard stands for its items. A where
+    /// clause is evaluated on one entity at a time, as its endpoints are
+    /// scanned. Other arguments are aggregated over the items of the array
+    /// they evaluate to.
+    fn evaluate_aggregate<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        name: &str,
+        arguments: &[Expression],
+        named_arguments: &[(String, Expression)],
+        where_clause: Option<&WhereExpression>,
+        group_by: &[Vec<String>],
+    ) -> Result<Entity> {
+        let function = AggregateFunction::from_name(name)
+            .ok_or_else(|| StoreError::InvalidOperation(format!("Unknown function: {}", name)))?;
+        self.check_named_arguments(name, named_arguments, &[])?;
+        
+        let argument = match arguments {
+            [argument] => argument,
+            _ => return Err(StoreError::InvalidOperation(
+                format!("{}() function requires exactly one argument", name)
+            )),
+        };
+        
+        let pattern = match argument {
+            Expression::Path(path) if !self.is_variable(path) => path.clone(),
+            Expression::DynamicPath(parts) => self.resolve_path(store, parts)?,
+            _ => {
+                if where_clause.is_some() || !group_by.is_empty() {
+                    return Err(StoreError::InvalidOperation(
+                        format!("{}() function can only filter and group the entities of a path pattern", name)
+                    ));
+                }
+                
+                return match (function, self.evaluate(store, argument)?) {
+                    (_, Entity::Array(items)) => {
+                        let mut aggregate = Aggregate::new(function);
+                        for item in &items {
+                            aggregate.add(item)?;
+                        }
+                        aggregate.result()
+                    },
+                    // An array of numbers is a vector
+                    (_, Entity::Vector(items)) => {
+                        let mut aggregate = Aggregate::new(function);
+                        for item in items {
+                            aggregate.add(&Entity::Float(item as f64))?;
+                        }
+                        aggregate.result()
+                    },
+                    // Number of endpoints under a path written in a string
+                    (AggregateFunction::Count, path @ Entity::String(_)) => self.function_count(store, &[path]),
+                    (_, entity) => Err(StoreError::InvalidOperation(
+                        format!("{}() function requires a path pattern or an array, not a {} value", name, entity.type_name())
+                    )),
+                };
+            },
+        };
+        
+        // The entities are at the level of the first wildcard; a path
+        // without wildcard stands for its items
+        let (level, pattern) = match pattern.segments().iter().position(|segment| segment.is_wildcard()) {
+            Some(level) => (level, pattern),
+            None => (pattern.len(), Path::from_str(&format!("{}.*", pattern))?),
+        };
+        let base = Path::from_segments(pattern.segments()[..level].to_vec());
+        
+        if (where_clause.is_some() || !group_by.is_empty()) && !pattern.segments()[level].is_single_wildcard() {
+            return Err(StoreError::InvalidOperation(
+                format!("{}() function requires a single wildcard (*) at the level of the filtered or grouped entities", name)
+            ));
+        }
+        
+        // Value of each grouping field, by entity
+        let mut keys = Vec::with_capacity(group_by.len());
+        for field in group_by {
+            if field.is_empty() {
+                return Err(StoreError::InvalidOperation(
+                    "'group by' requires fields of the entities, e.g. their.country".to_string()
+                ));
+            }
+            if field.join(".") == name {
+                return Err(StoreError::InvalidOperation(
+                    format!("Cannot group by a field named after the aggregation: {}", name)
+                ));
+            }
+            
+            let key_pattern = Path::from_str(&format!("{}.*.{}", base, field.join(".")))?;
+            let mut values: HashMap<String, Value> = HashMap::new();
+            for item in store.scan_matching(&key_pattern)? {
+                let (path, value) = item?;
+                if let Some(entity_id) = Self::entity_id(&base, &path) {
+                    values.insert(entity_id, value);
+                }
+            }
+            keys.push(values);
+        }
+        
+        // count counts the paths of the pattern, whether their value is an
+        // object or not: the endpoints are read under the pattern, and those
+        // of the same path only count once
+        let counting_paths = function == AggregateFunction::Count
+            && !pattern.segments().iter().any(|segment| segment.is_multi_wildcard());
+        let scanned = match counting_paths {
+            true => Path::from_str(&format!("{}.**", pattern))?,
+            false => pattern.clone(),
+        };
+        
+        let mut counted = HashSet::new();
+        let mut groups: Vec<(Vec<Value>, Aggregate)> = Vec::new();
+        let mut positions: HashMap<Vec<u8>, usize> = HashMap::new();
+        if group_by.is_empty() {
+            groups.push((Vec::new(), Aggregate::new(function)));
+            positions.insert(Vec::new(), 0);
+        }
+        
+        // The endpoints are folded into their group as the store scans
+        // them in path order, so that the groups and the distinct values
+        // come out in that order too
+        let mut fold = |path: &Path, value: Value| -> Result<()> {
+            let entity_id = match Self::entity_id(&base, path) {
+                Some(entity_id) => entity_id,
+                None => return Ok(()),
+            };
+            if counting_paths && !counted.insert(Path::from_segments(path.segments()[..pattern.len()].to_vec())) {
+                return Ok(());
+            }
+            
+            let position = match group_by.is_empty() {
+                true => 0,
+                false => {
+                    let key: Vec<Value> = keys.iter()
+                        .map(|values| values.get(&entity_id).cloned().unwrap_or(Value::Null))
+                        .collect();
+                    let encoded = serialize(&key).map_err(|e| StoreError::SerializationError(e.to_string()))?;
+                    *positions.entry(encoded).or_insert_with(|| {
+                        groups.push((key, Aggregate::new(function)));
+                        groups.len() - 1
+                    })
+                },
+            };
+            groups[position].1.add(&Entity::from(value))
+        };
+        
+        match where_clause {
+            None => {
+                for item in store.scan_matching(&scanned)? {
+                    let (path, value) = item?;
+                    fold(&path, value)?;
+                }
+            },
+            Some(where_clause) => {
+                // The endpoints of an entity come one after the other: they
+                // are buffered until the next entity, the where clause is
+                // evaluated on them, and those under the pattern are folded
+                // if it holds
+                let mut items = store.scan_matching(&Path::from_str(&format!("{}.*.**", base))?)?;
+                let mut current: Option<Path> = None;
+                let mut endpoints = Vec::new();
+                loop {
+                    let item = items.next().transpose()?;
+                    let next = item.as_ref()
+                        .map(|(path, _)| Path::from_segments(path.segments()[..=level].to_vec()));
+                    
+                    if next != current {
+                        if let Some(entity_path) = &current {
+                            if self.satisfies(store, entity_path, &endpoints, &[where_clause])? {
+                                for (path, value) in endpoints.drain(..) {
+                                    if path.matches(&scanned) {
+                                        fold(&path, value)?;
+                                    }
+                                }
+                            }
+                            endpoints.clear();
+                        }
+                        current = next;
+                    }
+                    
+                    match item {
+                        Some(endpoint) => endpoints.push(endpoint),
+                        None => break,
+                    }
+                }
+            },
+        }
+        
+        if group_by.is_empty() {
+            let (_, aggregate) = groups.pop().unwrap();
+            return aggregate.result();
+        }
+        
+        // One object per group, in the order of its first entity
+        let mut results = Vec::with_capacity(groups.len());
+        for (key, aggregate) in groups {
+            let mut result: HashMap<String, Entity> = group_by.iter()
+                .map(|field| field.join("."))
+                .zip(key.into_iter().map(Entity::from))
+                .collect();
+            result.insert(name.to_string(), aggregate.result()?);
+            results.push(Entity::Object(result));
+        }
+        
+        Ok(Entity::Array(results))
+    }
+    
     // Implémentations de fonctions intégrées
     
-    fn function_count<S: Store + ?Sized>(&self, store: &S, args: &[Value]) -> Result<Value> {
+    fn function_count<S: ReadStore + ?Sized>(&self, store: &S, args: &[Entity]) -> Result<Entity> {
         if args.len() != 1 {
             return Err(StoreError::InvalidOperation(
                 "count() function requires exactly one argument".to_string()
@@ -288,13 +1252,13 @@ impl EvaluationContext {
         }
         
         match &args[0] {
-            Value::String(path_str) => {
+            Entity::String(path_str) => {
                 // Utiliser FromStr correctement
                 let path = Path::from_str(path_str)?;
                 
                 // Compter les éléments sous ce chemin
                 let count = store.count_prefix(&path)?;
-                Ok(Value::Integer(count as i64))
+                Ok(Entity::Integer(count as i64))
             },
             _ => Err(StoreError::InvalidOperation(
                 "count() function requires a path string argument".to_string()
@@ -302,36 +1266,568 @@ impl EvaluationContext {
         }
     }
     
-    fn function_now(&self) -> Result<Value> {
+    fn function_history<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        let path = self.path_argument(store, "history", args)?;
+        let from = self.timestamp_argument(store, "history", named_args, "from")?;
+        let to = self.timestamp_argument(store, "history", named_args, "to")?;
+        self.check_named_arguments("history", named_args, &["from", "to"])?;
+        
+        let versions = store.history(&path, from, to)?;
+        
+        // Retourner les versions sous forme de tableau d'objets
+        let versions = versions.into_iter()
+            .map(|v| {
+                let mut version = HashMap::new();
+                version.insert("timestamp".to_string(), Entity::String(v.timestamp.to_rfc3339()));
+                version.insert("value".to_string(), v.value.map_or(Entity::Null, Entity::from));
+                Entity::Object(version)
+            })
+            .collect();
+        
+        Ok(Entity::Array(versions))
+    }
+    
+    fn function_snapshot<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        let path = self.path_argument(store, "snapshot", args)?;
+        let as_of = self.timestamp_argument(store, "snapshot", named_args, "as_of")?
+            .ok_or_else(|| StoreError::InvalidOperation(
+                "snapshot() function requires an as_of argument".to_string()
+            ))?;
+        self.check_named_arguments("snapshot", named_args, &["as_of"])?;
+        
+        // Comme pour un chemin, essayer d'abord une valeur directe
+        match store.get_as_of(&path, as_of) {
+            Ok(value) => Ok(Entity::from(value)),
+            Err(StoreError::NotFound(_)) => {
+                let endpoints = store.get_prefix_as_of(&path, as_of)?;
+                entity_from_endpoints(endpoints, &path)
+            },
+            Err(e) => Err(e),
+        }
+    }
+    
+    fn function_search<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        let (pattern, text) = match args {
+            [Expression::Path(pattern), text] => (pattern.clone(), text),
+            [Expression::Literal(Value::String(pattern)), text] => (Path::from_str(pattern)?, text),
+            _ => return Err(StoreError::InvalidOperation(
+                "search() function requires a path pattern and a text".to_string()
+            )),
+        };
+        let text = match self.evaluate(store, text)? {
+            Entity::String(text) => text,
+            _ => return Err(StoreError::InvalidOperation(
+                "search() function requires a text string as its second argument".to_string()
+            )),
+        };
+        let limit = match named_args.iter().find(|(name, _)| name == "limit") {
+            Some((_, expr)) => match self.evaluate(store, expr)? {
+                Entity::Integer(limit) if limit >= 0 => limit as usize,
+                _ => return Err(StoreError::InvalidOperation(
+                    "search() function requires a non-negative integer for limit".to_string()
+                )),
+            },
+            None => DEFAULT_SEARCH_LIMIT,
+        };
+        self.check_named_arguments("search", named_args, &["limit"])?;
+        
+        // Sans index plein texte prêt, les chaînes du motif sont classées
+        // une à une, avec les mêmes scores
+        let ranked = match store.search_text(&pattern, &text, limit)? {
+            Some(ranked) => ranked,
+            None => {
+                let documents: Vec<(Path, String)> = store.query(&pattern)?
+                    .into_iter()
+                    .filter_map(|(path, value)| match value {
+                        Value::String(text) => Some((path, text)),
+                        _ => None,
+                    })
+                    .collect();
+                fulltext_index::rank(&documents, &text, limit)
+            },
+        };
+        
+        Ok(ranked_paths(ranked))
+    }
+    
+    fn function_vector_search<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        let (pattern, vector, options) = match args {
+            [Expression::Path(pattern), vector, options @ ..] if options.len() <= 2 => (pattern.clone(), vector, options),
+            [Expression::Literal(Value::String(pattern)), vector, options @ ..] if options.len() <= 2 => {
+                (Path::from_str(pattern)?, vector, options)
+            },
+            _ => return Err(StoreError::InvalidOperation(
+                "vector_search() function requires a path pattern and a vector".to_string()
+            )),
+        };
+        
+        // Un vecteur, ou un tableau de nombres (un vecteur stocké comme tel)
+        let vector = match self.evaluate(store, vector)? {
+            Entity::Vector(vector) => vector,
+            Entity::Array(items) => items.into_iter()
+                .map(|item| match item {
+                    Entity::Integer(i) => Ok(i as f32),
+                    Entity::Float(f) => Ok(f as f32),
+                    _ => Err(StoreError::InvalidOperation(
+                        "vector_search() function requires a vector of numbers".to_string()
+                    )),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => return Err(StoreError::InvalidOperation(
+                "vector_search() function requires a vector as its second argument".to_string()
+            )),
+        };
+        
+        // limit et min_similarity, en position ou nommés
+        let named = |name: &str| named_args.iter().find(|(arg_name, _)| arg_name == name).map(|(_, expr)| expr);
+        let limit = match options.first().or_else(|| named("limit")) {
+            Some(expr) => match self.evaluate(store, expr)? {
+                Entity::Integer(limit) if limit >= 0 => limit as usize,
+                _ => return Err(StoreError::InvalidOperation(
+                    "vector_search() function requires a non-negative integer for limit".to_string()
+                )),
+            },
+            None => DEFAULT_SEARCH_LIMIT,
+        };
+        let min_similarity = match options.get(1).or_else(|| named("min_similarity")) {
+            Some(expr) => match self.evaluate(store, expr)? {
+                Entity::Integer(min) => Some(min as f64),
+                Entity::Float(min) => Some(min),
+                _ => return Err(StoreError::InvalidOperation(
+                    "vector_search() function requires a number for min_similarity".to_string()
+                )),
+            },
+            None => None,
+        };
+        let metric = match named("metric") {
+            Some(expr) => match self.evaluate(store, expr)? {
+                Entity::String(name) => Some(VectorMetric::from_name(&name).ok_or_else(|| StoreError::InvalidOperation(
+                    format!("Unknown vector metric: {} (expected cosine, l2 or dot)", name)
+                ))?),
+                _ => return Err(StoreError::InvalidOperation(
+                    "vector_search() function requires a string for metric".to_string()
+                )),
+            },
+            None => None,
+        };
+        self.check_named_arguments("vector_search", named_args, &["limit", "min_similarity", "metric"])?;
+        
+        let query = VectorQuery { vector, metric, limit, min_similarity };
+        
+        // Sans index de vecteurs prêt, les vecteurs du motif sont comparés
+        // un à un
+        let ranked = match store.search_vectors(&pattern, &query)? {
+            Some(ranked) => ranked,
+            None => {
+                let documents: Vec<(Path, Vec<f32>)> = store.query(&pattern)?
+                    .into_iter()
+                    .filter_map(|(path, value)| match value {
+                        Value::Vector(vector) => Some((path, vector)),
+                        _ => None,
+                    })
+                    .collect();
+                vector_index::rank(&documents, &query)
+            },
+        };
+        
+        Ok(ranked_paths(ranked))
+    }
+    
+    fn function_within_radius<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        self.check_named_arguments("within_radius", named_args, &[])?;
+        let (pattern, point, meters) = match args {
+            [pattern, point, meters] => (self.pattern_argument("within_radius", pattern)?, point, meters),
+            _ => return Err(StoreError::InvalidOperation(
+                "within_radius() function requires a path pattern, a point and a distance in meters".to_string()
+            )),
+        };
+        
+        let (lat, lon) = self.point_argument(store, "within_radius", point)?;
+        let meters = match self.evaluate(store, meters)? {
+            Entity::Integer(meters) if meters >= 0 => meters as f64,
+            Entity::Float(meters) if meters >= 0.0 => meters,
+            _ => return Err(StoreError::InvalidOperation(
+                "within_radius() function requires a non-negative distance in meters".to_string()
+            )),
+        };
+        
+        self.find_in_area(store, &pattern, &GeoArea::Radius { lat, lon, meters })
+    }
+    
+    fn function_within_box<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        args: &[Expression],
+        named_args: &[(String, Expression)],
+    ) -> Result<Entity> {
+        self.check_named_arguments("within_box", named_args, &[])?;
+        let (pattern, south_west, north_east) = match args {
+            [pattern, south_west, north_east] => (self.pattern_argument("within_box", pattern)?, south_west, north_east),
+            _ => return Err(StoreError::InvalidOperation(
+                "within_box() function requires a path pattern, a south-west point and a north-east point".to_string()
+            )),
+        };
+        
+        let (south, west) = self.point_argument(store, "within_box", south_west)?;
+        let (north, east) = self.point_argument(store, "within_box", north_east)?;
+        if south > north {
+            return Err(StoreError::InvalidOperation(
+                "within_box() function requires a south-west point south of the north-east point".to_string()
+            ));
+        }
+        
+        // Un point ouest plus à l'est que le point est : la boîte traverse
+        // l'antiméridien
+        self.find_in_area(store, &pattern, &GeoArea::Box { south, west, north, east })
+    }
+    
+    /// Trouver les points d'une zone, par un index géographique ou en
+    /// parcourant les points du motif
+    fn find_in_area<S: ReadStore + ?Sized>(&self, store: &S, pattern: &Path, area: &GeoArea) -> Result<Entity> {
+        let found = match store.find_in_area(pattern, area)? {
+            Some(found) => found,
+            None => {
+                let points: Vec<(Path, f64, f64)> = store.query(pattern)?
+                    .into_iter()
+                    .filter_map(|(path, value)| match value {
+                        Value::GeoPoint { lat, lon } => Some((path, lat, lon)),
+                        _ => None,
+                    })
+                    .collect();
+                geo_index::filter(&points, area)
+            },
+        };
+        
+        // Retourner les chemins du plus proche au plus lointain, avec leur
+        // distance en mètres
+        let results = found.into_iter()
+            .map(|(path, distance)| {
+                let mut result = HashMap::new();
+                result.insert("path".to_string(), Entity::String(path.to_string()));
+                result.insert("distance".to_string(), Entity::Float(distance));
+                Entity::Object(result)
+            })
+            .collect();
+        
+        Ok(Entity::Array(results))
+    }
+    
+    /// Extraire un motif de chemin, écrit tel quel ou dans une chaîne
+    fn pattern_argument(&self, function: &str, expr: &Expression) -> Result<Path> {
+        match expr {
+            Expression::Path(pattern) => Ok(pattern.clone()),
+            Expression::Literal(Value::String(pattern)) => Ok(Path::from_str(pattern)?),
+            _ => Err(StoreError::InvalidOperation(
+                format!("{}() function requires a path pattern as its first argument", function)
+            )),
+        }
+    }
+    
+    /// Évaluer un point géographique : une valeur `geopoint`, ou un objet
+    /// avec des champs `lat` et `lon`
+    fn point_argument<S: ReadStore + ?Sized>(&self, store: &S, function: &str, expr: &Expression) -> Result<(f64, f64)> {
+        let coordinate = |entity: Option<&Entity>| match entity {
+            Some(Entity::Integer(i)) => Some(*i as f64),
+            Some(Entity::Float(f)) => Some(*f),
+            _ => None,
+        };
+        
+        let point = match self.evaluate(store, expr)? {
+            Entity::GeoPoint { lat, lon } => Some((lat, lon)),
+            Entity::Object(fields) => coordinate(fields.get("lat")).zip(coordinate(fields.get("lon"))),
+            _ => None,
+        };
+        
+        point
+            .filter(|(lat, lon)| (-90.0..=90.0).contains(lat) && (-180.0..=180.0).contains(lon))
+            .ok_or_else(|| StoreError::InvalidOperation(
+                format!("{}() function requires geographic points", function)
+            ))
+    }
+    
+    /// Extraire le chemin passé en unique argument positionnel
+    fn path_argument<S: ReadStore + ?Sized>(&self, store: &S, function: &str, args: &[Expression]) -> Result<Path> {
+        match args {
+            [Expression::Path(path)] => Ok(path.clone()),
+            [Expression::DynamicPath(parts)] => self.resolve_path(store, parts),
+            [Expression::Literal(Value::String(path_str))] => Ok(Path::from_str(path_str)?),
+            _ => Err(StoreError::InvalidOperation(
+                format!("{}() function requires a path as its first argument", function)
+            )),
+        }
+    }
+    
+    /// Évaluer un argument nommé contenant une date
+    fn timestamp_argument<S: ReadStore + ?Sized>(
+        &self,
+        store: &S,
+        function: &str,
+        named_args: &[(String, Expression)],
+        name: &str,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let expr = match named_args.iter().find(|(arg_name, _)| arg_name == name) {
+            Some((_, expr)) => expr,
+            None => return Ok(None),
+        };
+        
+        match self.evaluate(store, expr)? {
+            Entity::String(s) => Ok(Some(parse_timestamp(&s)?)),
+            _ => Err(StoreError::InvalidOperation(
+                format!("{}() function requires a date string for {}", function, name)
+            )),
+        }
+    }
+    
+    /// Rejeter les arguments nommés inconnus
+    fn check_named_arguments(&self, function: &str, named_args: &[(String, Expression)], allowed: &[&str]) -> Result<()> {
+        for (name, _) in named_args {
+            if !allowed.contains(&name.as_str()) {
+                return Err(StoreError::InvalidOperation(
+                    format!("Unknown argument for {}(): {}", function, name)
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn function_now(&self) -> Result<Entity> {
         // Retourne la date et l'heure actuelles au format ISO 8601
         let now = chrono::Utc::now();
         let now_str = now.to_rfc3339();
-        Ok(Value::String(now_str))
+        Ok(Entity::String(now_str))
+    }
+    
+    fn function_geopoint(&self, args: &[Entity]) -> Result<Entity> {
+        let coordinate = |entity: &Entity| match entity {
+            Entity::Integer(i) => Some(*i as f64),
+            Entity::Float(f) => Some(*f),
+            _ => None,
+        };
+        
+        match args {
+            [lat, lon] => match (coordinate(lat), coordinate(lon)) {
+                (Some(lat), Some(lon)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => {
+                    Ok(Entity::GeoPoint { lat, lon })
+                },
+                _ => Err(StoreError::InvalidOperation(
+                    "geopoint() function requires a latitude within [-90, 90] and a longitude within [-180, 180]".to_string()
+                )),
+            },
+            _ => Err(StoreError::InvalidOperation(
+                "geopoint() function requires a latitude and a longitude".to_string()
+            )),
+        }
     }
     
-    fn function_uuid(&self) -> Result<Value> {
+    fn function_uuid(&self) -> Result<Entity> {
         // Générer un UUID v4
         let uuid = uuid::Uuid::new_v4();
-        Ok(Value::String(uuid.to_string()))
+        Ok(Entity::String(uuid.to_string()))
+    }
+}
+
+/// Build the result of a ranked search: an array of `{path, score}`
+/// objects, best first
+fn ranked_paths(ranked: Vec<(Path, f64)>) -> Entity {
+    let results = ranked.into_iter()
+        .map(|(path, score)| {
+            let mut result = HashMap::new();
+            result.insert("path".to_string(), Entity::String(path.to_string()));
+            result.insert("score".to_string(), Entity::Float(score));
+            Entity::Object(result)
+        })
+        .collect();
+    
+    Entity::Array(results)
+}
+
+/// An aggregation function over the values matching a pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Distinct,
+}
+
+impl AggregateFunction {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "avg" => Some(AggregateFunction::Avg),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "distinct" => Some(AggregateFunction::Distinct),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Distinct => "distinct",
+        }
+    }
+}
+
+/// Running state of an aggregation, fed one value at a time
+///
+/// Null values only count for `count`.
+struct Aggregate {
+    function: AggregateFunction,
+    /// Number of values added
+    count: i64,
+    /// Sum for `avg`, as a float
+    total: f64,
+    /// Sum, minimum or maximum so far
+    current: Option<Entity>,
+    /// Distinct values in order of first appearance, with their encodings
+    distinct: Vec<Entity>,
+    seen: HashSet<Vec<u8>>,
+}
+
+impl Aggregate {
+    fn new(function: AggregateFunction) -> Self {
+        Aggregate {
+            function,
+            count: 0,
+            total: 0.0,
+            current: None,
+            distinct: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+    
+    fn add(&mut self, value: &Entity) -> Result<()> {
+        if *value == Entity::Null && self.function != AggregateFunction::Count {
+            return Ok(());
+        }
+        self.count += 1;
+        
+        let name = self.function.name();
+        let number = match value {
+            Entity::Integer(i) => Some(*i as f64),
+            Entity::Float(f) => Some(*f),
+            _ => None,
+        };
+        
+        match self.function {
+            AggregateFunction::Count => {},
+            AggregateFunction::Sum | AggregateFunction::Avg if number.is_none() => {
+                return Err(StoreError::InvalidOperation(
+                    format!("{}() function requires numbers, not a {} value", name, value.type_name())
+                ));
+            },
+            AggregateFunction::Sum => {
+                self.current = Some(match &self.current {
+                    Some(sum) => EvaluationContext::apply_operator(sum, &BinaryOperator::Add, value)?,
+                    None => value.clone(),
+                });
+            },
+            AggregateFunction::Avg => self.total += number.unwrap_or_default(),
+            AggregateFunction::Min | AggregateFunction::Max => {
+                let operator = match self.function {
+                    AggregateFunction::Min => ComparisonOperator::LessThan,
+                    _ => ComparisonOperator::GreaterThan,
+                };
+                let scalar = |entity: &Entity| entity.clone().into_value().ok_or_else(|| StoreError::InvalidOperation(
+                    format!("{}() function requires single values, not a {} value", name, entity.type_name())
+                ));
+                
+                let replaces = match &self.current {
+                    Some(current) => EvaluationContext::new().compare_values(&scalar(value)?, &operator, &scalar(current)?)?,
+                    None => {
+                        scalar(value)?;
+                        true
+                    },
+                };
+                if replaces {
+                    self.current = Some(value.clone());
+                }
+            },
+            AggregateFunction::Distinct => {
+                let encoded = serialize(value).map_err(|e| StoreError::SerializationError(e.to_string()))?;
+                if self.seen.insert(encoded) {
+                    self.distinct.push(value.clone());
+                }
+            },
+        }
+        
+        Ok(())
     }
+    
+    fn result(self) -> Result<Entity> {
+        Ok(match self.function {
+            AggregateFunction::Count => Entity::Integer(self.count),
+            AggregateFunction::Sum => self.current.unwrap_or(Entity::Integer(0)),
+            AggregateFunction::Avg if self.count == 0 => Entity::Null,
+            AggregateFunction::Avg => Entity::Float(self.total / self.count as f64),
+            AggregateFunction::Min | AggregateFunction::Max => self.current.unwrap_or(Entity::Null),
+            AggregateFunction::Distinct => Entity::Array(self.distinct),
+        })
+    }
+}
+
+/// A field of a change event that subscription conditions can test
+enum EventField {
+    NewValue,
+    OldValue,
+    Path,
+    Operation,
 }
 
-// Helper function to convert Entity to Value
-fn entity_to_value(entity: &Entity) -> Result<Value> {
-    match entity {
-        Entity::Null => Ok(Value::Null),
-        Entity::Boolean(b) => Ok(Value::Boolean(*b)),
-        Entity::Integer(i) => Ok(Value::Integer(*i)),
-        Entity::Float(f) => Ok(Value::Float(*f)),
-        Entity::String(s) => Ok(Value::String(s.clone())),
-        Entity::Binary(data, mime) => Ok(Value::Binary(data.clone(), mime.clone())),
-        Entity::Reference(path) => Ok(Value::Reference(path.clone())),
-        Entity::Object(_) | Entity::Array(_) => {
-            // Pour les objets et tableaux, nous devons les sérialiser en JSON
-            // puis les convertir en chaîne de caractères
-            let json = serde_json::to_string(entity)
-                .map_err(|e| StoreError::SerializationError(e.to_string()))?;
-            Ok(Value::String(json))
-        }
-    }
-}
\ No newline at end of file
+impl EventField {
+    fn from_path(path: &Path) -> Result<Self> {
+        match path.to_string().as_str() {
+            "new_value" => Ok(EventField::NewValue),
+            "old_value" => Ok(EventField::OldValue),
+            "path" => Ok(EventField::Path),
+            "operation" => Ok(EventField::Operation),
+            other => Err(StoreError::InvalidOperation(
+                format!("Unknown event field in subscription condition: {}", other)
+            )),
+        }
+    }
+    
+    fn value_of(&self, event: &ChangeEvent) -> Value {
+        match self {
+            EventField::NewValue => event.new_value.clone().unwrap_or(Value::Null),
+            EventField::OldValue => event.old_value.clone().unwrap_or(Value::Null),
+            EventField::Path => Value::String(event.path.to_string()),
+            EventField::Operation => Value::String(event.operation.as_str().to_string()),
+        }
+    }
+}
@@ -1,52 +1,283 @@
 //! Query executor for HyperionQL
 
-use crate::core::errors::Result;
+use crate::core::errors::{Result, StoreError};
 use crate::core::store::Store;
-use crate::core::value::Value;
-use crate::ql::ast::{Query, Operation};
+use crate::core::changes::{ChangeFilter, Subscription};
+use crate::core::entity::Entity;
+use crate::core::transaction::Transaction;
+use crate::core::index::IndexDefinition;
+use crate::ql::ast::{Query, Declaration, Operation, SubscribeStatement};
 use crate::ql::evaluator::EvaluationContext;
 
 /// Execute a parsed query against the store
-pub fn execute_query<S: Store + ?Sized>(store: &mut S, query: &Query) -> Result<Value> {
-    // Create context (no store reference)
-    let context = EvaluationContext::new();
+///
+/// The whole block runs as a single transaction: if any operation or
+/// the return expression fails, none of the writes are applied, and the
+/// indexes and constraints declared by the block are dropped again.
+pub fn execute_query<S: Store + ?Sized>(store: &mut S, query: &Query) -> Result<Entity> {
+    if query.subscription.is_some() {
+        return Err(StoreError::InvalidOperation(
+            "Subscriptions return a stream of changes and cannot be executed as a query".to_string()
+        ));
+    }
+    
+    // Declarations are applied before the writes, so that a constraint
+    // also checks the writes of the block
+    let declared = apply_declarations(store, query)?;
+    let result = run_block(store, query);
+    if result.is_err() {
+        undo_declarations(store, &declared);
+    }
+    
+    result
+}
+
+/// Run the operations and the return expression of a block in a single
+/// transaction
+fn run_block<S: Store + ?Sized>(store: &mut S, query: &Query) -> Result<Entity> {
+    // Create context (no store reference), which holds the variables of
+    // the block
+    let mut context = EvaluationContext::new();
+    
+    // Stage every write of the block in a transaction
+    let mut tx = Transaction::new(store);
     
-    // Execute all operations in order
+    // Execute all operations in order (an early return drops the
+    // transaction, which discards the staged writes)
     for operation in &query.operations {
-        execute_operation(store, &context, operation)?;
+        execute_operation(&mut tx, &mut context, operation)?;
     }
     
-    // Evaluate and return the return expression, or true if no return
-    match &query.return_expr {
+    // Evaluate the return expression, or true if no return
+    let result = match &query.return_expr {
         Some(expr) => {
-            // Pass store explicitly to evaluate
-            context.evaluate(store, expr)
+            // Reads go through the transaction to see the staged writes
+            context.evaluate(&tx, expr)?
         },
-        None => Ok(Value::Boolean(true)),
+        None => Entity::Boolean(true),
+    };
+    
+    // Apply all writes at once
+    tx.commit()?;
+    
+    Ok(result)
+}
+
+/// Register the subscription of a parsed query
+///
+/// The operations of the block, if any, are applied first (in a single
+/// transaction), so the subscription only receives the writes made
+/// after the query.
+pub fn subscribe<S: Store + ?Sized>(store: &mut S, query: &Query) -> Result<Subscription> {
+    let statement = query.subscription.as_ref()
+        .ok_or_else(|| StoreError::InvalidOperation("Query does not contain a subscribe statement".to_string()))?;
+    
+    let declared = apply_declarations(store, query)?;
+    let filter = match run_subscription_block(store, query, statement) {
+        Ok(filter) => filter,
+        Err(e) => {
+            undo_declarations(store, &declared);
+            return Err(e);
+        },
+    };
+    
+    let feed = store.change_feed();
+    let subscription = match filter {
+        Some(filter) => feed.subscribe_filtered(statement.pattern.clone(), filter),
+        None => feed.subscribe(statement.pattern.clone()),
+    };
+    
+    Ok(subscription)
+}
+
+/// Run the operations of a subscription block in a single transaction,
+/// and compile the filter of its subscribe statement
+fn run_subscription_block<S: Store + ?Sized>(
+    store: &mut S,
+    query: &Query,
+    statement: &SubscribeStatement,
+) -> Result<Option<ChangeFilter>> {
+    let mut context = EvaluationContext::new();
+    let mut tx = Transaction::new(store);
+    for operation in &query.operations {
+        execute_operation(&mut tx, &mut context, operation)?;
+    }
+    
+    // Compile the filter, which may use the variables of the block, before
+    // writing anything
+    let filter = match &statement.where_clause {
+        Some(where_clause) => Some(context.event_filter(where_clause)?),
+        None => None,
+    };
+    tx.commit()?;
+    
+    Ok(filter)
+}
+
+/// Apply the schema declarations of a query
+///
+/// Declarations are idempotent: declaring an existing index or constraint
+/// again is a no-op, so a block can declare the schema it relies on.
+/// Returns the declarations that created an index or a constraint, so
+/// that they can be undone if the block fails; if a declaration fails,
+/// the previous ones are undone before returning the error.
+fn apply_declarations<'q, S: Store + ?Sized>(store: &mut S, query: &'q Query) -> Result<Vec<&'q Declaration>> {
+    let mut declared = Vec::new();
+    
+    for declaration in &query.declarations {
+        let created = match declaration {
+            Declaration::Index { pattern, kind } => {
+                store.create_index(IndexDefinition::new(pattern.clone(), kind.clone()))
+            },
+            Declaration::Unique { pattern } => {
+                store.add_unique_constraint(pattern)
+            },
+        };
+        
+        match created {
+            Ok(true) => declared.push(declaration),
+            Ok(false) => {},
+            Err(e) => {
+                undo_declarations(store, &declared);
+                return Err(e);
+            },
+        }
+    }
+    
+    Ok(declared)
+}
+
+/// Drop the indexes and constraints created by the declarations of a
+/// failed block
+///
+/// The error of the block is the one reported, so a failure to drop is
+/// only logged.
+fn undo_declarations<S: Store + ?Sized>(store: &mut S, declared: &[&Declaration]) {
+    for declaration in declared.iter().rev() {
+        let dropped = match declaration {
+            Declaration::Index { pattern, kind } => {
+                store.drop_index(&IndexDefinition::new(pattern.clone(), kind.clone()))
+            },
+            Declaration::Unique { pattern } => {
+                store.drop_unique_constraint(pattern)
+            },
+        };
+        
+        if let Err(e) = dropped {
+            log::warn!("Failed to undo a declaration of a failed block: {}", e);
+        }
     }
 }
 
 /// Execute a single operation
 fn execute_operation<S: Store + ?Sized>(
-    store: &mut S,
-    context: &EvaluationContext,
+    tx: &mut Transaction<'_, S>,
+    context: &mut EvaluationContext,
     operation: &Operation
 ) -> Result<()> {
     match operation {
+        Operation::Let { name, expression } => {
+            let entity = context.evaluate(&*tx, expression)?;
+            context.bind(name, entity);
+            
+            Ok(())
+        },
         Operation::Assignment { path, expression } => {
+            // Compute the segments of the path, e.g. users[id].username
+            let path = &context.resolve_path(&*tx, path)?;
+            if context.is_variable(path) {
+                return Err(StoreError::InvalidOperation(
+                    format!("Cannot assign to {}: variables hold values, not paths", path)
+                ));
+            }
+            
             // Evaluate the expression
-            let value = context.evaluate(store, expression)?;
+            let entity = context.evaluate(&*tx, expression)?;
             
-            // Store the value at the specified path
-            store.set(path.clone(), value)?;
+            // Store the value at the specified path, or one endpoint per
+            // field for objects and arrays
+            tx.set_entity(path, &entity)?;
             
             Ok(())
         },
         Operation::Delete { path } => {
-            // Delete the value at the specified path
-            store.delete(path)?;
+            // Delete the value at the specified path, or every value
+            // matching a pattern
+            if path.has_wildcards() {
+                tx.delete_matching(path)?;
+            } else {
+                tx.delete(path)?;
+            }
+            
+            Ok(())
+        },
+        Operation::Move { from, to, rewrite_references } => {
+            tx.move_prefix(from, to, *rewrite_references)?;
+            
+            Ok(())
+        },
+        Operation::Copy { from, to } => {
+            tx.copy_prefix(from, to)?;
+            
+            Ok(())
+        },
+        Operation::Rename { from, to, rewrite_references } => {
+            tx.rename_matching(from, to, *rewrite_references)?;
             
             Ok(())
         },
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::core::store::{ReadStore, Store};
+    use crate::core::entity::Entity;
+    use crate::core::errors::StoreError;
+    use crate::storage::memory::MemoryStore;
+    use crate::storage::persistent::PersistentStore;
+    use crate::ql;
+
+    /// Patterns of the indexes declared on a store
+    fn indexed_patterns(store: &PersistentStore) -> Vec<String> {
+        store.indexes().unwrap()
+            .into_iter()
+            .map(|info| info.definition.pattern.to_string())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_index_declaration_is_undone_with_a_failed_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+
+        let result = ql::execute_query(&mut store, "{ @index users.*.age users.u1.age = 36; return true }").unwrap();
+        assert_eq!(result, Entity::Boolean(true));
+        assert_eq!(indexed_patterns(&store), vec!["users.*.age".to_string()]);
+
+        // Deleting a missing path fails the block after the declaration
+        let result = ql::execute_query(&mut store, "{ @index users.*.name users.u1.name = \"Ada\"; delete users.u2.name; }");
+        assert!(result.is_err());
+        assert_eq!(indexed_patterns(&store), vec!["users.*.age".to_string()]);
+        assert_eq!(store.count().unwrap(), 1);
+
+        // An index that existed before the failed block is kept
+        let result = ql::execute_query(&mut store, "{ @index users.*.age delete users.u2.age; }");
+        assert!(result.is_err());
+        assert_eq!(indexed_patterns(&store), vec!["users.*.age".to_string()]);
+    }
+
+    #[test]
+    fn test_constraint_declaration_checks_the_block() {
+        let mut store = MemoryStore::new();
+
+        let result = ql::execute_query(&mut store, "{ @constraint users.*.email unique users.a.email = \"a@x\"; users.b.email = \"a@x\"; }");
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+        assert!(store.unique_constraints().unwrap().is_empty());
+        assert_eq!(store.count().unwrap(), 0);
+
+        ql::execute_query(&mut store, "{ @constraint users.*.email unique users.a.email = \"a@x\"; }").unwrap();
+        let result = ql::execute_query(&mut store, "{ users.b.email = \"a@x\"; }");
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+        assert_eq!(store.unique_constraints().unwrap().len(), 1);
+    }
+}
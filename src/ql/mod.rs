@@ -4,16 +4,30 @@ pub mod ast;
 pub mod parser;
 pub mod evaluator;
 pub mod executor;
+#[cfg(test)]
+mod tests;
 
 use crate::core::errors::Result;
 use crate::core::store::Store;
-use crate::core::value::Value;
+use crate::core::entity::Entity;
+use crate::core::changes::Subscription;
 
 /// Execute a query string on the given store
-pub fn execute_query<S: Store + ?Sized>(store: &mut S, query_str: &str) -> Result<Value> {
+pub fn execute_query<S: Store + ?Sized>(store: &mut S, query_str: &str) -> Result<Entity> {
     // Parse the query
     let query = parser::parse_query(query_str)?;
     
     // Execute the query
     executor::execute_query(store, &query)
+}
+
+/// Register the subscription described by a query string on the given store
+///
+/// The query must end with a `subscribe(pattern) where ...` statement.
+pub fn subscribe<S: Store + ?Sized>(store: &mut S, query_str: &str) -> Result<Subscription> {
+    // Parse the query
+    let query = parser::parse_query(query_str)?;
+    
+    // Register the subscription
+    executor::subscribe(store, &query)
 }
\ No newline at end of file
@@ -7,9 +7,11 @@ use pest_derive::Parser;
 use pest::iterators::{Pair, Pairs};
 
 use crate::core::errors::{Result, StoreError};
-use crate::core::path::Path;
+use crate::core::path::{Path, PathSegment};
 use crate::core::value::Value;
-use crate::ql::ast::{Query, Operation, Expression, ComparisonOperator, LogicalOperator, Condition, WhereClause};
+use crate::core::index::IndexKind;
+use crate::core::index::vector_index::VectorMetric;
+use crate::ql::ast::{Query, Declaration, Operation, Expression, ComparisonOperator, BinaryOperator, Condition, WhereExpression, Projection, PathPart, SubscribeStatement};
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -32,12 +34,17 @@ fn parse_query_ast(pairs: Pairs<Rule>) -> Result<Query> {
         .next()
         .ok_or_else(|| StoreError::InvalidOperation("Empty query".to_string()))?;
     
+    let mut declarations = Vec::new();
     let mut operations = Vec::new();
     let mut return_expr = None;
+    let mut subscription = None;
     
     // Itérer sur les parties de la requête
     for pair in query_pair.into_inner() {
         match pair.as_rule() {
+            Rule::declaration => {
+                declarations.push(parse_declaration(pair)?);
+            },
             Rule::operation => {
                 let operation = parse_operation(pair)?;
                 operations.push(operation);
@@ -47,18 +54,93 @@ fn parse_query_ast(pairs: Pairs<Rule>) -> Result<Query> {
                 let expr = parse_expression(expr_pair)?;
                 return_expr = Some(expr);
             },
+            Rule::subscribe_stmt => {
+                subscription = Some(parse_subscribe_statement(pair)?);
+            },
             _ => {}
         }
     }
     
     // Vérifier qu'il y a au moins des opérations si pas de return
-    if operations.is_empty() && return_expr.is_none() {
+    if declarations.is_empty() && operations.is_empty() && return_expr.is_none() && subscription.is_none() {
         return Err(StoreError::InvalidOperation("Query must contain at least one operation or a return statement".to_string()));
     }
     
     Ok(Query {
+        declarations,
         operations,
         return_expr,
+        subscription,
+    })
+}
+
+fn parse_declaration(pair: Pair<Rule>) -> Result<Declaration> {
+    let inner = pair.into_inner().next().unwrap();
+    
+    match inner.as_rule() {
+        Rule::index_decl => {
+            let mut inner_pairs = inner.into_inner();
+            let pattern = parse_path(inner_pairs.next().unwrap())?;
+            let kind = match inner_pairs.next() {
+                Some(kind_pair) if kind_pair.as_rule() == Rule::fulltext => IndexKind::FullText,
+                Some(kind_pair) if kind_pair.as_rule() == Rule::vector_kind => parse_vector_kind(kind_pair)?,
+                Some(kind_pair) if kind_pair.as_rule() == Rule::geo => IndexKind::Geo,
+                Some(fields_pair) => {
+                    let fields = fields_pair.into_inner().map(parse_path).collect::<Result<Vec<_>>>()?;
+                    IndexKind::Composite(fields)
+                },
+                None => IndexKind::Value,
+            };
+            
+            Ok(Declaration::Index { pattern, kind })
+        },
+        Rule::constraint_decl => {
+            let pattern = parse_path(inner.into_inner().next().unwrap())?;
+            Ok(Declaration::Unique { pattern })
+        },
+        _ => Err(StoreError::InvalidOperation(format!("Unsupported declaration: {:?}", inner.as_rule()))),
+    }
+}
+
+fn parse_vector_kind(pair: Pair<Rule>) -> Result<IndexKind> {
+    let mut metric = VectorMetric::Cosine;
+    let mut approximate = false;
+    
+    if let Some(options) = pair.into_inner().next() {
+        let mut options = options.into_inner();
+        
+        let name = options.next().unwrap().as_str();
+        metric = VectorMetric::from_name(name).ok_or_else(|| StoreError::InvalidOperation(
+            format!("Unknown vector metric: {} (expected cosine, l2 or dot)", name)
+        ))?;
+        
+        // Mode de recherche : exacte, ou approximative par un graphe HNSW
+        approximate = match options.next().map(|mode| mode.as_str()) {
+            None | Some("exact") => false,
+            Some("hnsw") => true,
+            Some(mode) => return Err(StoreError::InvalidOperation(
+                format!("Unknown vector search mode: {} (expected exact or hnsw)", mode)
+            )),
+        };
+    }
+    
+    Ok(IndexKind::Vector { metric, approximate })
+}
+
+fn parse_subscribe_statement(pair: Pair<Rule>) -> Result<SubscribeStatement> {
+    let mut inner_pairs = pair.into_inner();
+    
+    let pattern_pair = inner_pairs.next().unwrap();
+    let pattern = parse_path(pattern_pair)?;
+    
+    let where_clause = match inner_pairs.next() {
+        Some(where_clause_pair) => Some(parse_where_clause(where_clause_pair)?),
+        None => None,
+    };
+    
+    Ok(SubscribeStatement {
+        pattern,
+        where_clause,
     })
 }
 
@@ -66,12 +148,30 @@ fn parse_operation(pair: Pair<Rule>) -> Result<Operation> {
     let inner = pair.into_inner().next().unwrap();
     
     match inner.as_rule() {
+        Rule::let_binding => {
+            let mut inner_pairs = inner.into_inner();
+            // Le mot-clé let
+            inner_pairs.next();
+            let name = inner_pairs.next().unwrap().as_str().to_string();
+            let expr_pair = inner_pairs.next().unwrap();
+            
+            if name == "their" {
+                return Err(StoreError::InvalidOperation(
+                    "'their' is a keyword and cannot name a variable".to_string()
+                ));
+            }
+            
+            Ok(Operation::Let {
+                name,
+                expression: parse_expression(expr_pair)?,
+            })
+        },
         Rule::assignment => {
             let mut inner_pairs = inner.into_inner();
             let path_pair = inner_pairs.next().unwrap();
             let expr_pair = inner_pairs.next().unwrap();
             
-            let path = parse_path(path_pair)?;
+            let path = parse_path_parts(path_pair)?;
             let expr = parse_expression(expr_pair)?;
             
             Ok(Operation::Assignment {
@@ -85,6 +185,27 @@ fn parse_operation(pair: Pair<Rule>) -> Result<Operation> {
             
             Ok(Operation::Delete { path })
         },
+        Rule::move_op | Rule::rename_op => {
+            let rule = inner.as_rule();
+            let mut inner_pairs = inner.into_inner();
+            let from = parse_path(inner_pairs.next().unwrap())?;
+            let to = parse_path(inner_pairs.next().unwrap())?;
+            // Le suffixe "with references" est optionnel
+            let rewrite_references = inner_pairs.next().is_some();
+            
+            if rule == Rule::move_op {
+                Ok(Operation::Move { from, to, rewrite_references })
+            } else {
+                Ok(Operation::Rename { from, to, rewrite_references })
+            }
+        },
+        Rule::copy_op => {
+            let mut inner_pairs = inner.into_inner();
+            let from = parse_path(inner_pairs.next().unwrap())?;
+            let to = parse_path(inner_pairs.next().unwrap())?;
+            
+            Ok(Operation::Copy { from, to })
+        },
         _ => Err(StoreError::InvalidOperation(
             format!("Unexpected operation type: {:?}", inner.as_rule())
         )),
@@ -99,22 +220,77 @@ fn parse_expression(pair: Pair<Rule>) -> Result<Expression> {
     let primary_expr_pair = inner_pairs.next()
         .ok_or_else(|| StoreError::InvalidOperation("Missing primary expression".to_string()))?;
     
-    let primary_expr = parse_primary_expression(primary_expr_pair)?;
+    let mut expression = parse_value_expression(primary_expr_pair)?;
     
-    // Vérifier s'il y a une clause where
-    if let Some(where_clause_pair) = inner_pairs.next() {
-        if where_clause_pair.as_rule() == Rule::where_clause {
-            let where_clause = parse_where_clause(where_clause_pair)?;
-            
-            return Ok(Expression::Filtered {
-                base: Box::new(primary_expr),
-                where_clause,
-            });
+    for pair in inner_pairs {
+        match pair.as_rule() {
+            // Clause where
+            Rule::where_clause => {
+                let where_clause = parse_where_clause(pair)?;
+                expression = Expression::Filtered {
+                    base: Box::new(expression),
+                    where_clause,
+                };
+            },
+            // Projection, appliquée après le filtre
+            Rule::projection => {
+                expression = Expression::Projected {
+                    base: Box::new(expression),
+                    projection: parse_projection(pair)?,
+                };
+            },
+            // Regroupement d'une agrégation, filtrée ou non
+            Rule::group_by => {
+                let group_by = pair.into_inner()
+                    .map(|their_pair| their_pair.into_inner().map(|segment| segment.as_str().to_string()).collect())
+                    .collect();
+                expression = Expression::Grouped {
+                    base: Box::new(expression),
+                    group_by,
+                };
+            },
+            _ => return Err(StoreError::InvalidOperation(
+                format!("Unexpected expression part: {:?}", pair.as_rule())
+            )),
         }
     }
     
-    // Pas de clause where, retourner l'expression primaire
-    Ok(primary_expr)
+    Ok(expression)
+}
+
+// Parse les champs d'une projection avec leur nom : un champ sans nom
+// garde celui de son dernier segment
+fn parse_projection(pair: Pair<Rule>) -> Result<Vec<(String, Projection)>> {
+    let mut fields = Vec::new();
+    
+    for field_pair in pair.into_inner() {
+        let mut inner_pairs = field_pair.into_inner();
+        let first = inner_pairs.next().unwrap();
+        
+        let field = match inner_pairs.next() {
+            Some(value_pair) => {
+                let name = first.as_str().to_string();
+                match value_pair.as_rule() {
+                    Rule::projection => (name, Projection::Object(parse_projection(value_pair)?)),
+                    _ => (name, Projection::Field(parse_path(value_pair)?)),
+                }
+            },
+            None => {
+                let path = parse_path(first)?;
+                let name = path.segments().last().map(|segment| segment.as_str()).unwrap_or_default();
+                (name, Projection::Field(path))
+            },
+        };
+        
+        if fields.iter().any(|(name, _)| *name == field.0) {
+            return Err(StoreError::InvalidOperation(
+                format!("Duplicate field in projection: {}", field.0)
+            ));
+        }
+        fields.push(field);
+    }
+    
+    Ok(fields)
 }
 
 // Nouvelle fonction pour parser une expression primaire
@@ -123,9 +299,27 @@ fn parse_primary_expression(pair: Pair<Rule>) -> Result<Expression> {
     
     match inner.as_rule() {
         Rule::literal => parse_literal(inner),
-        Rule::path => {
-            let path = parse_path(inner)?;
-            Ok(Expression::Path(path))
+        Rule::array_literal => {
+            let items = inner.into_inner()
+                .map(parse_value_expression)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Expression::Array(items))
+        },
+        Rule::dynamic_path => {
+            let parts = parse_path_parts(inner)?;
+            
+            // Sans segment calculé, le chemin est connu dès l'analyse
+            if parts.iter().all(|part| matches!(part, PathPart::Segment(_))) {
+                let segments = parts.into_iter()
+                    .filter_map(|part| match part {
+                        PathPart::Segment(segment) => Some(segment),
+                        PathPart::Dynamic(_) => None,
+                    })
+                    .collect();
+                Ok(Expression::Path(Path::from_segments(segments)))
+            } else {
+                Ok(Expression::DynamicPath(parts))
+            }
         },
         Rule::their_path => {
             let mut segments = Vec::new();
@@ -147,12 +341,24 @@ fn parse_primary_expression(pair: Pair<Rule>) -> Result<Expression> {
             let name = name_pair.as_str().to_string();
             
             let mut arguments = Vec::new();
+            let mut named_arguments = Vec::new();
             for arg_pair in inner_pairs {
-                let arg = parse_primary_expression(arg_pair)?;
-                arguments.push(arg);
+                if arg_pair.as_rule() == Rule::named_argument {
+                    let mut named_pairs = arg_pair.into_inner();
+                    let arg_name = named_pairs.next().unwrap().as_str().to_string();
+                    let arg = parse_value_expression(named_pairs.next().unwrap())?;
+                    named_arguments.push((arg_name, arg));
+                } else if arg_pair.as_rule() == Rule::pattern_argument {
+                    // Un motif n'est pas évalué : la fonction le reçoit comme chemin
+                    arguments.push(Expression::Path(parse_path(arg_pair)?));
+                } else {
+                    // sum([1, 2, 3]) additionne des entiers, pas un vecteur
+                    let arg = parse_collection(arg_pair)?;
+                    arguments.push(arg);
+                }
             }
             
-            Ok(Expression::FunctionCall { name, arguments })
+            Ok(Expression::FunctionCall { name, arguments, named_arguments })
         },
         _ => Err(StoreError::InvalidOperation(
             format!("Unexpected primary expression type: {:?}", inner.as_rule())
@@ -160,61 +366,180 @@ fn parse_primary_expression(pair: Pair<Rule>) -> Result<Expression> {
     }
 }
 
-fn parse_where_clause(pair: Pair<Rule>) -> Result<WhereClause> {
+fn parse_where_clause(pair: Pair<Rule>) -> Result<WhereExpression> {
     let where_expr_pair = pair.into_inner().next().unwrap();
     
-    let mut conditions_pairs = where_expr_pair.into_inner();
-    
-    // La première condition est obligatoire
-    let first_condition_pair = conditions_pairs.next()
-        .ok_or_else(|| StoreError::InvalidOperation("Missing condition in where clause".to_string()))?;
-    
-    let first_condition = parse_condition(first_condition_pair)?;
-    
-    // Collecter les conditions supplémentaires avec leurs opérateurs logiques
-    let mut additional_conditions = Vec::new();
-    
-    while let Some(op_pair) = conditions_pairs.next() {
-        if op_pair.as_rule() != Rule::logical_op {
-            return Err(StoreError::InvalidOperation(
-                format!("Expected logical operator, found {:?}", op_pair.as_rule())
-            ));
-        }
-        
-        let operator = parse_logical_operator(op_pair)?;
-        
-        let cond_pair = conditions_pairs.next()
-            .ok_or_else(|| StoreError::InvalidOperation("Missing condition after logical operator".to_string()))?;
-        
-        let condition = parse_condition(cond_pair)?;
-        
-        additional_conditions.push((operator, condition));
+    parse_where_expression(where_expr_pair)
+}
+
+// Construit l'arbre d'une expression booléenne : la grammaire place déjà
+// les || au-dessus des &&, eux-mêmes au-dessus des !
+fn parse_where_expression(pair: Pair<Rule>) -> Result<WhereExpression> {
+    match pair.as_rule() {
+        Rule::where_expression | Rule::where_and => {
+            let is_or = pair.as_rule() == Rule::where_expression;
+            let mut operands = pair.into_inner().map(parse_where_expression);
+            
+            // Les opérandes s'associent de gauche à droite
+            let first = operands.next()
+                .ok_or_else(|| StoreError::InvalidOperation("Missing condition in where clause".to_string()))??;
+            operands.try_fold(first, |left, right| {
+                let (left, right) = (Box::new(left), Box::new(right?));
+                Ok(if is_or { WhereExpression::Or(left, right) } else { WhereExpression::And(left, right) })
+            })
+        },
+        Rule::where_factor => {
+            let mut negations = 0;
+            let mut term = None;
+            for inner in pair.into_inner() {
+                match inner.as_rule() {
+                    Rule::not_op => negations += 1,
+                    _ => term = Some(inner),
+                }
+            }
+            
+            let term = term
+                .ok_or_else(|| StoreError::InvalidOperation("Missing condition after '!'".to_string()))?;
+            let mut expression = parse_where_expression(term)?;
+            for _ in 0..negations {
+                expression = WhereExpression::Not(Box::new(expression));
+            }
+            Ok(expression)
+        },
+        Rule::where_term => {
+            let inner = pair.into_inner().next().unwrap();
+            match inner.as_rule() {
+                Rule::where_expression => parse_where_expression(inner),
+                Rule::condition => parse_condition(inner),
+                // Une valeur seule est vraie quand elle vaut true
+                _ => Ok(WhereExpression::Truth(Box::new(parse_value_expression(inner)?))),
+            }
+        },
+        _ => Err(StoreError::InvalidOperation(
+            format!("Unexpected where expression type: {:?}", pair.as_rule())
+        )),
     }
-    
-    Ok(WhereClause {
-        first_condition,
-        additional_conditions,
-    })
 }
 
 
-fn parse_condition(pair: Pair<Rule>) -> Result<Condition> {
+fn parse_condition(pair: Pair<Rule>) -> Result<WhereExpression> {
     let mut inner_pairs = pair.into_inner();
     
     let left_pair = inner_pairs.next().unwrap();
-    let left = Box::new(parse_primary_expression(left_pair)?);
     
+    // `a not in b` est la négation de `a in b`
     let op_pair = inner_pairs.next().unwrap();
-    let operator = parse_comparison_operator(op_pair)?;
+    let negated = op_pair.clone().into_inner().next()
+        .is_some_and(|inner| inner.as_rule() == Rule::not_in_op);
+    let operator = if negated { ComparisonOperator::In } else { parse_comparison_operator(op_pair)? };
     
+    // La liste d'un test d'appartenance est un tableau, même quand elle ne
+    // contient que des nombres
     let right_pair = inner_pairs.next().unwrap();
-    let right = Box::new(parse_primary_expression(right_pair)?);
+    let left = Box::new(match operator {
+        ComparisonOperator::Contains => parse_collection(left_pair)?,
+        _ => parse_value_expression(left_pair)?,
+    });
+    let right = Box::new(match operator {
+        ComparisonOperator::In => parse_collection(right_pair)?,
+        _ => parse_value_expression(right_pair)?,
+    });
     
-    Ok(Condition {
+    let condition = WhereExpression::Condition(Condition {
         left,
         operator,
         right,
-    })
+    });
+    
+    Ok(if negated { WhereExpression::Not(Box::new(condition)) } else { condition })
+}
+
+// Lit une liste de nombres écrite telle quelle comme un tableau : la
+// grammaire essaie les vecteurs avant les tableaux, mais les nombres d'un
+// vecteur perdent leur type et leur précision (36 in [18, 36], sum([1, 2]))
+fn parse_collection(pair: Pair<Rule>) -> Result<Expression> {
+    let mut inner = pair.clone();
+    while inner.as_rule() != Rule::vector_literal {
+        let mut children = inner.clone().into_inner();
+        match (children.next(), children.next()) {
+            (Some(child), None) => inner = child,
+            _ => return parse_value_expression(pair),
+        }
+    }
+    
+    let items = inner.into_inner()
+        .map(|number| parse_number(number).map(Expression::Literal))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Expression::Array(items))
+}
+
+// Construit l'arbre d'une expression de valeur : la grammaire place déjà
+// les ?? au-dessus des + et -, eux-mêmes au-dessus des * / et %
+fn parse_value_expression(pair: Pair<Rule>) -> Result<Expression> {
+    match pair.as_rule() {
+        Rule::value_expression | Rule::additive | Rule::multiplicative => {
+            let mut inner_pairs = pair.into_inner();
+            let mut expression = parse_value_expression(inner_pairs.next().unwrap())?;
+            
+            // Les opérandes s'associent de gauche à droite
+            while let Some(op_pair) = inner_pairs.next() {
+                let operator = match op_pair.as_str() {
+                    "??" => BinaryOperator::Coalesce,
+                    "+" => BinaryOperator::Add,
+                    "-" => BinaryOperator::Subtract,
+                    "*" => BinaryOperator::Multiply,
+                    "/" => BinaryOperator::Divide,
+                    "%" => BinaryOperator::Remainder,
+                    other => return Err(StoreError::InvalidOperation(
+                        format!("Unknown operator: {}", other)
+                    )),
+                };
+                let right = parse_value_expression(inner_pairs.next().unwrap())?;
+                
+                expression = Expression::Binary {
+                    left: Box::new(expression),
+                    operator,
+                    right: Box::new(right),
+                };
+            }
+            
+            Ok(expression)
+        },
+        Rule::unary => {
+            let mut negations = 0;
+            let mut operand = None;
+            for inner in pair.into_inner() {
+                match inner.as_rule() {
+                    Rule::negate => negations += 1,
+                    _ => operand = Some(inner),
+                }
+            }
+            
+            // -9223372036854775808 ne tient sur 64 bits qu'avec son signe
+            let operand = operand.unwrap();
+            let mut expression = match format!("-{}", operand.as_str()).parse::<i64>() {
+                Ok(i64::MIN) if negations > 0 => {
+                    negations -= 1;
+                    Expression::Literal(Value::Integer(i64::MIN))
+                },
+                _ => parse_value_expression(operand)?,
+            };
+            for _ in 0..negations {
+                expression = match expression {
+                    // Un nombre écrit tel quel est négatif dès l'analyse
+                    Expression::Literal(Value::Integer(i)) if i != i64::MIN => Expression::Literal(Value::Integer(-i)),
+                    Expression::Literal(Value::Float(f)) => Expression::Literal(Value::Float(-f)),
+                    expression => Expression::Negate(Box::new(expression)),
+                };
+            }
+            
+            Ok(expression)
+        },
+        Rule::primary_expression => parse_primary_expression(pair),
+        _ => Err(StoreError::InvalidOperation(
+            format!("Unexpected value expression type: {:?}", pair.as_rule())
+        )),
+    }
 }
 
 // Nouvelle fonction pour parser un opérateur de comparaison
@@ -226,21 +551,29 @@ fn parse_comparison_operator(pair: Pair<Rule>) -> Result<ComparisonOperator> {
         "<=" => Ok(ComparisonOperator::LessThanOrEqual),
         ">" => Ok(ComparisonOperator::GreaterThan),
         ">=" => Ok(ComparisonOperator::GreaterThanOrEqual),
+        "in" => Ok(ComparisonOperator::In),
+        "contains" => Ok(ComparisonOperator::Contains),
+        "starts_with" => Ok(ComparisonOperator::StartsWith),
         _ => Err(StoreError::InvalidOperation(
             format!("Unknown comparison operator: {}", pair.as_str())
         )),
     }
 }
 
-// Nouvelle fonction pour parser un opérateur logique
-fn parse_logical_operator(pair: Pair<Rule>) -> Result<LogicalOperator> {
-    match pair.as_str() {
-        "&&" => Ok(LogicalOperator::And),
-        "||" => Ok(LogicalOperator::Or),
-        _ => Err(StoreError::InvalidOperation(
-            format!("Unknown logical operator: {}", pair.as_str())
-        )),
+// Un nombre écrit sans point est un entier, s'il tient sur 64 bits :
+// 7.0 / 2 vaut 3.5, mais 7 / 2 vaut 3
+fn parse_number(pair: Pair<Rule>) -> Result<Value> {
+    if !pair.as_str().contains('.') {
+        if let Ok(i) = pair.as_str().parse::<i64>() {
+            return Ok(Value::Integer(i));
+        }
     }
+    
+    let n = pair.as_str().parse::<f64>()
+        .map_err(|_| StoreError::InvalidOperation(
+            format!("Invalid number: {}", pair.as_str())
+        ))?;
+    Ok(Value::Float(n))
 }
 
 fn parse_literal(pair: Pair<Rule>) -> Result<Expression> {
@@ -253,19 +586,7 @@ fn parse_literal(pair: Pair<Rule>) -> Result<Expression> {
             let s = &s[1..s.len()-1]; // Enlever les guillemets
             Ok(Expression::Literal(Value::String(s.to_string())))
         },
-        Rule::number => {
-            let n = inner.as_str().parse::<f64>()
-                .map_err(|_| StoreError::InvalidOperation(
-                    format!("Invalid number: {}", inner.as_str())
-                ))?;
-                
-            // Déterminer si c'est un entier ou un flottant
-            if n.fract() == 0.0 && n >= i64::MIN as f64 && n <= i64::MAX as f64 {
-                Ok(Expression::Literal(Value::Integer(n as i64)))
-            } else {
-                Ok(Expression::Literal(Value::Float(n)))
-            }
-        },
+        Rule::number => parse_number(inner).map(Expression::Literal),
         Rule::boolean => {
             let b = inner.as_str() == "true";
             Ok(Expression::Literal(Value::Boolean(b)))
@@ -273,12 +594,38 @@ fn parse_literal(pair: Pair<Rule>) -> Result<Expression> {
         Rule::null => {
             Ok(Expression::Literal(Value::Null))
         },
+        Rule::vector_literal => {
+            let vector = inner.into_inner()
+                .map(|number| number.as_str().parse::<f32>().map_err(|_| StoreError::InvalidOperation(
+                    format!("Invalid number: {}", number.as_str())
+                )))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Expression::Literal(Value::Vector(vector)))
+        },
         _ => Err(StoreError::InvalidOperation(
             format!("Unexpected literal type: {:?}", inner.as_rule())
         )),
     }
 }
 
+// Parse un chemin dont des segments peuvent être calculés
+fn parse_path_parts(pair: Pair<Rule>) -> Result<Vec<PathPart>> {
+    let mut parts = Vec::new();
+    
+    for part_pair in pair.into_inner() {
+        match part_pair.as_rule() {
+            Rule::dynamic_segment => {
+                let expr_pair = part_pair.into_inner().next().unwrap();
+                parts.push(PathPart::Dynamic(parse_value_expression(expr_pair)?));
+            },
+            // L'identifiant de tête et les segments écrits tels quels
+            _ => parts.push(PathPart::Segment(PathSegment::new(part_pair.as_str().trim()))),
+        }
+    }
+    
+    Ok(parts)
+}
+
 fn parse_path(pair: Pair<Rule>) -> Result<Path> {
     let path_str = pair.as_str().trim();
     Path::from_str(path_str).map_err(|e| StoreError::InvalidOperation(format!("Path error: {}", e)))
@@ -0,0 +1,329 @@
+//! Tests of HyperionQL, parsing and running whole queries on a memory store
+
+use serde_json::{json, Value as Json};
+
+use crate::core::errors::StoreError;
+use crate::storage::memory::MemoryStore;
+use crate::ql;
+
+/// Store holding a few users and items
+fn sample_store() -> MemoryStore {
+    let mut store = MemoryStore::new();
+    ql::execute_query(&mut store, r#"{
+        users.a.name = "Ada"; users.a.active = true; users.a.admin = false; users.a.age = 36;
+        users.b.name = "Bob"; users.b.active = false; users.b.admin = true; users.b.age = 17;
+        users.c.name = "Cy"; users.c.active = true; users.c.admin = false; users.c.age = 15;
+        items.i.price = 30; items.i.discount = 25;
+        items.j.price = 30; items.j.discount = 5;
+    }"#).unwrap();
+    store
+}
+
+/// Run a query, returning its result as JSON
+fn run(store: &mut MemoryStore, query: &str) -> Json {
+    ql::execute_query(store, query).unwrap().to_json()
+}
+
+/// Names of the users returned by a query, sorted
+fn names(store: &mut MemoryStore, query: &str) -> Vec<String> {
+    let mut names: Vec<String> = match run(store, query) {
+        Json::Array(users) => users.iter().map(|user| user["name"].as_str().unwrap().to_string()).collect(),
+        other => panic!("Expected an array, got {}", other),
+    };
+    names.sort();
+    names
+}
+
+#[test]
+fn test_and_binds_tighter_than_or() {
+    let mut store = sample_store();
+
+    // Read as admin || (active && age > 18)
+    assert_eq!(names(&mut store, "{ return users where their.admin || their.active && their.age > 18 }"), ["Ada", "Bob"]);
+    assert_eq!(names(&mut store, "{ return users where their.active && their.age > 18 || their.admin }"), ["Ada", "Bob"]);
+}
+
+#[test]
+fn test_negations() {
+    let mut store = sample_store();
+
+    assert_eq!(names(&mut store, "{ return users where !their.active }"), ["Bob"]);
+    assert_eq!(names(&mut store, "{ return users where !!their.active }"), ["Ada", "Cy"]);
+    assert_eq!(names(&mut store, "{ return users where !their.active || !their.admin }"), ["Ada", "Bob", "Cy"]);
+}
+
+#[test]
+fn test_parenthesised_groups() {
+    let mut store = sample_store();
+
+    assert_eq!(names(&mut store, "{ return users where (their.admin || their.active) && their.age > 16 }"), ["Ada", "Bob"]);
+    assert_eq!(names(&mut store, "{ return users where !(their.admin || their.age > 30) }"), ["Cy"]);
+}
+
+#[test]
+fn test_bare_truth_test() {
+    let mut store = sample_store();
+
+    assert_eq!(names(&mut store, "{ return users where their.active }"), ["Ada", "Cy"]);
+}
+
+#[test]
+fn test_condition_starting_with_a_parenthesis() {
+    let mut store = sample_store();
+
+    let found = run(&mut store, "{ return items where (their.price - their.discount) > 10 }");
+    assert_eq!(found, json!([{ "price": 30, "discount": 5 }]));
+}
+
+#[test]
+fn test_projection_of_a_single_entity() {
+    let mut store = sample_store();
+    ql::execute_query(&mut store, r#"{
+        users.a.profile.full_name = "Ada Lovelace"; users.a.profile.email = "ada@example.com";
+    }"#).unwrap();
+
+    assert_eq!(run(&mut store, "{ return users.a.{ name, age } }"), json!({ "name": "Ada", "age": 36 }));
+
+    // Renamed fields and nested objects
+    let found = run(&mut store, "{ return users.a.{ display_name: profile.full_name, contact: { profile.email, age } } }");
+    assert_eq!(found, json!({
+        "display_name": "Ada Lovelace",
+        "contact": { "email": "ada@example.com", "age": 36 },
+    }));
+}
+
+#[test]
+fn test_projection_of_a_collection() {
+    let mut store = sample_store();
+
+    let found = run(&mut store, "{ return users where their.active { name, years: age } }");
+    assert_eq!(found, json!([{ "name": "Ada", "years": 36 }, { "name": "Cy", "years": 15 }]));
+}
+
+#[test]
+fn test_projection_of_a_missing_field() {
+    let mut store = sample_store();
+
+    let found = run(&mut store, "{ return users.b.{ name, email, info: { phone } } }");
+    assert_eq!(found, json!({ "name": "Bob", "email": null, "info": { "phone": null } }));
+}
+
+#[test]
+fn test_variables_hold_entities() {
+    let mut store = sample_store();
+
+    assert_eq!(run(&mut store, "{ let user = users.a; return user.name }"), json!("Ada"));
+    // The value is read when the variable is bound
+    assert_eq!(run(&mut store, "{ let user = users.a; users.a.name = \"Eve\"; return user.name }"), json!("Ada"));
+}
+
+#[test]
+fn test_computed_path_segments() {
+    let mut store = sample_store();
+
+    // A string names a field
+    assert_eq!(run(&mut store, "{ let id = \"b\"; return users[id].name }"), json!("Bob"));
+    ql::execute_query(&mut store, "{ let id = \"b\"; users[id].username = \"bob\"; }").unwrap();
+    assert_eq!(run(&mut store, "{ return users.b.username }"), json!("bob"));
+
+    // An integer is an array index
+    ql::execute_query(&mut store, "{ posts[0].title = \"First\"; posts[1].title = \"Second\"; }").unwrap();
+    assert_eq!(run(&mut store, "{ let i = 1; return posts[i].title }"), json!("Second"));
+    ql::execute_query(&mut store, "{ let i = 0; posts[i].title = \"Changed\"; }").unwrap();
+    assert_eq!(run(&mut store, "{ return posts[0].title }"), json!("Changed"));
+}
+
+#[test]
+fn test_let_prefix_is_not_a_keyword() {
+    let mut store = sample_store();
+
+    assert_eq!(run(&mut store, "{ letter = 1; return letter }"), json!(1));
+    assert_eq!(run(&mut store, "{ let_count = 2; return let_count }"), json!(2));
+}
+
+#[test]
+fn test_variables_cannot_be_assigned() {
+    let mut store = sample_store();
+
+    let result = ql::execute_query(&mut store, "{ let user = users.a; user = 3; }");
+    assert!(matches!(result, Err(StoreError::InvalidOperation(message))
+        if message == "Cannot assign to user: variables hold values, not paths"));
+}
+
+#[test]
+fn test_arithmetic() {
+    let mut store = sample_store();
+
+    assert_eq!(run(&mut store, "{ return 2 + 3 * 4 - -1 }"), json!(15));
+    assert_eq!(run(&mut store, "{ return (2 + 3) * 4 }"), json!(20));
+    assert_eq!(run(&mut store, "{ return 7 / 2 }"), json!(3));
+    assert_eq!(run(&mut store, "{ return 7.0 / 2 }"), json!(3.5));
+    assert_eq!(run(&mut store, "{ return 7 % 3 }"), json!(1));
+    assert_eq!(run(&mut store, "{ return users.a.age - users.b.age }"), json!(19));
+    assert_eq!(run(&mut store, "{ return -9223372036854775808 }"), json!(i64::MIN));
+
+    for (query, error) in [
+        ("{ return 1 / 0 }", "Division by zero with /"),
+        ("{ return 9223372036854775807 + 1 }", "Integer overflow in 9223372036854775807 + 1"),
+        ("{ return -9223372036854775808 / -1 }", "Integer overflow in -9223372036854775808 / -1"),
+        ("{ return -9223372036854775808 % -1 }", "Integer overflow in -9223372036854775808 % -1"),
+        ("{ return -(-9223372036854775808) }", "Integer overflow in --9223372036854775808"),
+    ] {
+        let result = ql::execute_query(&mut store, query);
+        assert!(matches!(&result, Err(StoreError::InvalidOperation(message)) if message == error), "{}: {:?}", query, result);
+    }
+}
+
+#[test]
+fn test_string_concatenation() {
+    let mut store = sample_store();
+
+    assert_eq!(run(&mut store, "{ return users.a.name + \" \" + users.b.name }"), json!("Ada Bob"));
+    assert_eq!(run(&mut store, "{ return \"a\" + 1 }"), json!("a1"));
+    assert_eq!(run(&mut store, "{ return 1.5 + \"a\" + true }"), json!("1.5atrue"));
+    // Other operators do not apply to strings
+    assert!(ql::execute_query(&mut store, "{ return \"a\" * 2 }").is_err());
+}
+
+#[test]
+fn test_coalesce() {
+    let mut store = sample_store();
+
+    assert_eq!(run(&mut store, "{ return users.z.name ?? \"none\" }"), json!("none"));
+    assert_eq!(run(&mut store, "{ return users.a.name ?? \"none\" }"), json!("Ada"));
+    assert_eq!(run(&mut store, "{ return null ?? users.z.name ?? 1 + 1 }"), json!(2));
+}
+
+#[test]
+fn test_membership_and_string_operators() {
+    let mut store = sample_store();
+
+    assert_eq!(names(&mut store, "{ return users where their.age in [15, 36] }"), ["Ada", "Cy"]);
+    assert_eq!(names(&mut store, "{ return users where their.age not in [15, 36] }"), ["Bob"]);
+    assert_eq!(names(&mut store, "{ return users where their.name in [\"Ada\", \"Cy\"] }"), ["Ada", "Cy"]);
+    assert_eq!(names(&mut store, "{ return users where their.name contains \"d\" }"), ["Ada"]);
+    assert_eq!(names(&mut store, "{ return users where their.name starts_with \"B\" }"), ["Bob"]);
+
+    // A list of numbers is an array, whose numbers keep their type
+    assert!(names(&mut store, "{ return users where their.age in [36.0] }").is_empty());
+    ql::execute_query(&mut store, "{ users.a.age = 16777217; }").unwrap();
+    assert!(names(&mut store, "{ return users where their.age in [16777216] }").is_empty());
+}
+
+#[test]
+fn test_fields_of_another_type_do_not_match() {
+    let mut store = sample_store();
+    ql::execute_query(&mut store, r#"{ users.d.name = 42; users.d.age = "old"; }"#).unwrap();
+
+    let found = run(&mut store, "{ return users where their.age > 18 }");
+    assert_eq!(found, json!([{ "name": "Ada", "active": true, "admin": false, "age": 36 }]));
+    assert_eq!(names(&mut store, "{ return users where their.name starts_with \"C\" }"), ["Cy"]);
+    assert_eq!(names(&mut store, "{ return users where their.age < 18 && their.name contains \"y\" }"), ["Cy"]);
+}
+
+#[test]
+fn test_membership_in_subscription_filters() {
+    let mut store = sample_store();
+
+    let mut subscription = ql::subscribe(&mut store, "{ subscribe(users.*.name) where new_value in [\"Eve\", \"Zoe\"] }").unwrap();
+    ql::execute_query(&mut store, "{ users.a.name = \"Ann\"; users.b.name = \"Zoe\"; }").unwrap();
+    assert_eq!(subscription.try_recv().map(|event| event.path.to_string()), Some("users.b.name".to_string()));
+    assert!(subscription.try_recv().is_none());
+
+    let mut subscription = ql::subscribe(&mut store, "{ subscribe(users.*.age) where new_value in [16, 18] }").unwrap();
+    ql::execute_query(&mut store, "{ users.a.age = 18; users.b.age = 16.0; }").unwrap();
+    assert_eq!(subscription.try_recv().map(|event| event.path.to_string()), Some("users.a.age".to_string()));
+    assert!(subscription.try_recv().is_none());
+}
+
+/// Store holding a few orders
+fn orders_store() -> MemoryStore {
+    let mut store = MemoryStore::new();
+    ql::execute_query(&mut store, r#"{
+        orders.o1.total = 30; orders.o1.status = "paid"; orders.o1.country = "FR"; orders.o1.channel = "web";
+        orders.o2.total = 12.5; orders.o2.status = "paid"; orders.o2.country = "DE"; orders.o2.channel = "shop";
+        orders.o3.total = 20; orders.o3.status = "open"; orders.o3.country = "FR"; orders.o3.channel = "web";
+        orders.o4.total = 8; orders.o4.status = "paid"; orders.o4.country = "FR"; orders.o4.channel = "shop";
+    }"#).unwrap();
+    store
+}
+
+#[test]
+fn test_aggregations() {
+    let mut store = orders_store();
+
+    assert_eq!(run(&mut store, "{ return count(orders.*) }"), json!(4));
+    assert_eq!(run(&mut store, "{ return count(orders) }"), json!(4));
+    assert_eq!(run(&mut store, "{ return sum(orders.*.total) }"), json!(70.5));
+    assert_eq!(run(&mut store, "{ return avg(orders.*.total) }"), json!(17.625));
+    assert_eq!(run(&mut store, "{ return min(orders.*.total) }"), json!(8));
+    assert_eq!(run(&mut store, "{ return max(orders.*.total) }"), json!(30));
+    assert_eq!(run(&mut store, "{ return distinct(orders.*.country) }"), json!(["FR", "DE"]));
+    assert_eq!(run(&mut store, "{ return count(orders.*) where their.channel == \"web\" }"), json!(2));
+    assert_eq!(run(&mut store, "{ return sum([1, 2, 3]) }"), json!(6));
+    assert_eq!(run(&mut store, "{ return sum([1, 2.5]) }"), json!(3.5));
+    assert_eq!(run(&mut store, "{ return max([3, 9007199254740993]) }"), json!(9007199254740993i64));
+}
+
+#[test]
+fn test_aggregations_of_integers_and_floats() {
+    let mut store = orders_store();
+
+    // Integers only sum into an integer, a float makes the sum a float
+    assert_eq!(run(&mut store, "{ return sum(orders.*.total) where their.country == \"FR\" }"), json!(58));
+    assert_eq!(run(&mut store, "{ return sum(orders.*.total) where their.status == \"paid\" }"), json!(50.5));
+    assert_eq!(run(&mut store, "{ return avg(orders.*.total) where their.country == \"FR\" }"), json!(58.0 / 3.0));
+}
+
+#[test]
+fn test_aggregations_of_nothing() {
+    let mut store = orders_store();
+
+    assert_eq!(run(&mut store, "{ return count(carts.*) }"), json!(0));
+    assert_eq!(run(&mut store, "{ return sum(carts.*.total) }"), json!(0));
+    assert_eq!(run(&mut store, "{ return avg(carts.*.total) }"), json!(null));
+    assert_eq!(run(&mut store, "{ return min(orders.*.total) where their.status == \"void\" }"), json!(null));
+    assert_eq!(run(&mut store, "{ return max(carts.*.total) }"), json!(null));
+    assert_eq!(run(&mut store, "{ return distinct(carts.*.total) }"), json!([]));
+}
+
+#[test]
+fn test_aggregations_grouped_by_fields() {
+    let mut store = orders_store();
+
+    let found = run(&mut store, "{ return count(orders.*) group by their.country }");
+    assert_eq!(found, json!([{ "country": "FR", "count": 3 }, { "country": "DE", "count": 1 }]));
+
+    let found = run(&mut store, "{ return sum(orders.*.total) group by their.country, their.channel }");
+    assert_eq!(found, json!([
+        { "country": "FR", "channel": "web", "sum": 50 },
+        { "country": "DE", "channel": "shop", "sum": 12.5 },
+        { "country": "FR", "channel": "shop", "sum": 8 },
+    ]));
+
+    let found = run(&mut store, "{ return sum(orders.*.total) where their.status == \"paid\" group by their.country }");
+    assert_eq!(found, json!([{ "country": "FR", "sum": 38 }, { "country": "DE", "sum": 12.5 }]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_aggregations_scan_a_persistent_store_in_path_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = crate::storage::persistent::PersistentStore::open_async(dir.path()).await.unwrap();
+    ql::execute_query(&mut store, r#"{
+        orders.o2.total = 12.5; orders.o2.country = "DE";
+        orders.o1.total = 30; orders.o1.country = "FR";
+        orders.o3.total = 8; orders.o3.country = "FR";
+        carts.c1.total = 100;
+    }"#).unwrap();
+
+    let found = ql::execute_query(&mut store, "{ return sum(orders.*.total) group by their.country }").unwrap().to_json();
+    assert_eq!(found, json!([{ "country": "FR", "sum": 38 }, { "country": "DE", "sum": 12.5 }]));
+    let found = ql::execute_query(&mut store, "{ return distinct(orders.*.country) }").unwrap().to_json();
+    assert_eq!(found, json!(["FR", "DE"]));
+
+    // The where clause is evaluated on each entity as it is scanned
+    let found = ql::execute_query(&mut store, "{ return count(orders.*) where their.total > 10 && their.country == \"FR\" }").unwrap().to_json();
+    assert_eq!(found, json!(1));
+    let found = ql::execute_query(&mut store, "{ return sum(orders.*.total) where their.total < 20 || their.country == \"DE\" }").unwrap().to_json();
+    assert_eq!(found, json!(20.5));
+}
@@ -15,6 +15,8 @@ pub struct ServerConfig {
     pub port: u16,
     /// Adresse d'écoute
     pub host: String,
+    /// Nombre d'événements conservés pour la reprise de /api/watch
+    pub replay_capacity: usize,
 }
 
 impl Default for ServerConfig {
@@ -22,6 +24,7 @@ impl Default for ServerConfig {
         ServerConfig {
             port: 3000,
             host: "127.0.0.1".to_string(),
+            replay_capacity: 1024,
         }
     }
 }
@@ -37,6 +40,10 @@ pub struct HyperionServer {
 impl HyperionServer {
     /// Crée un nouveau serveur avec l'instance Hyperion fournie
     pub fn new(hyperion: Hyperion, config: ServerConfig) -> Self {
+        // Conserver les derniers changements pour que les clients de
+        // /api/watch puissent reprendre après une reconnexion
+        hyperion.change_feed().enable_replay(config.replay_capacity);
+        
         HyperionServer {
             hyperion: Arc::new(Mutex::new(hyperion)),
             config,
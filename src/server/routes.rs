@@ -4,11 +4,20 @@
 use serde_json::Error;
 use warp::{Filter, Rejection, Reply};
 use warp::filters::body::json;
+use warp::sse::Event;
+use warp::ws::{Message, WebSocket, Ws};
+use futures_util::{stream, SinkExt, StreamExt};
 use serde::{Serialize, Deserialize};
+use std::convert::Infallible;
 use std::sync::{Arc, Mutex};
 use crate::Hyperion;
 use crate::core::path::Path;
 use crate::core::value::Value;
+use crate::core::entity::Entity;
+use crate::core::errors::StoreError;
+use crate::core::patch::parse_patch;
+use crate::core::changes::{ChangeEvent, Subscription};
+use crate::core::index::{IndexInfo, IndexKind, IndexStatus};
 use std::str::FromStr;
 
 /// Requête pour définir une valeur
@@ -20,6 +29,15 @@ struct SetRequest {
     value: serde_json::Value,
 }
 
+/// Requête pour appliquer un JSON Patch à une entité
+#[derive(Debug, Deserialize)]
+struct PatchRequest {
+    /// Chemin de l'entité
+    path: String,
+    /// Opérations JSON Patch (RFC 6902)
+    patch: serde_json::Value,
+}
+
 /// Requête pour récupérer une valeur
 #[derive(Debug, Deserialize)]
 struct GetRequest {
@@ -34,6 +52,63 @@ struct QueryRequest {
     query: String,
 }
 
+/// Requête pour surveiller les changements
+#[derive(Debug, Deserialize)]
+struct WatchRequest {
+    /// Motif des chemins à surveiller (peut contenir des wildcards)
+    pattern: String,
+    /// Reprendre après ce numéro de séquence
+    since: Option<u64>,
+}
+
+/// Description d'un index secondaire
+#[derive(Debug, Serialize)]
+struct IndexResponse {
+    /// Motif des chemins indexés
+    pattern: String,
+    /// Type de l'index
+    kind: &'static str,
+    /// Champs d'un index composite
+    fields: Vec<String>,
+    /// Métrique d'un index de vecteurs
+    metric: Option<&'static str>,
+    /// Recherches approximatives (graphe HNSW) d'un index de vecteurs
+    approximate: bool,
+    /// État de construction ("building" ou "ready")
+    status: &'static str,
+    /// Nombre de chemins déjà indexés par la construction
+    indexed: Option<usize>,
+    /// Nombre de chemins à indexer par la construction
+    total: Option<usize>,
+    /// Nombre de chemins présents dans l'index
+    entries: usize,
+}
+
+impl From<IndexInfo> for IndexResponse {
+    fn from(info: IndexInfo) -> Self {
+        let (status, indexed, total) = match info.status {
+            IndexStatus::Building { indexed, total } => ("building", Some(indexed), Some(total)),
+            IndexStatus::Ready => ("ready", None, None),
+        };
+        let (metric, approximate) = match info.definition.kind {
+            IndexKind::Vector { metric, approximate } => (Some(metric.name()), approximate),
+            _ => (None, false),
+        };
+        
+        IndexResponse {
+            pattern: info.definition.pattern.to_string(),
+            kind: info.definition.kind.name(),
+            fields: info.definition.kind.fields().iter().map(|field| field.to_string()).collect(),
+            metric,
+            approximate,
+            status,
+            indexed,
+            total,
+            entries: info.entries,
+        }
+    }
+}
+
 /// Réponse générique pour l'API
 #[derive(Debug, Serialize)]
 struct ApiResponse<T> {
@@ -61,7 +136,28 @@ pub fn api_routes(
         .and(warp::post())
         .and(json::<SetRequest>())
         .and(with_hyperion(hyperion.clone()))
-        .and_then(handle_set);
+        .and_then(|req, hyperion| handle_entity_write(req, hyperion, EntityWrite::Set));
+    
+    // Route POST /api/replace
+    let replace_route = warp::path!("api" / "replace")
+        .and(warp::post())
+        .and(json::<SetRequest>())
+        .and(with_hyperion(hyperion.clone()))
+        .and_then(|req, hyperion| handle_entity_write(req, hyperion, EntityWrite::Replace));
+    
+    // Route POST /api/merge
+    let merge_route = warp::path!("api" / "merge")
+        .and(warp::post())
+        .and(json::<SetRequest>())
+        .and(with_hyperion(hyperion.clone()))
+        .and_then(|req, hyperion| handle_entity_write(req, hyperion, EntityWrite::Merge));
+    
+    // Route POST /api/patch
+    let patch_route = warp::path!("api" / "patch")
+        .and(warp::post())
+        .and(json::<PatchRequest>())
+        .and(with_hyperion(hyperion.clone()))
+        .and_then(handle_patch);
     
     // Route POST /api/query
     let query_route = warp::path!("api" / "query")
@@ -74,11 +170,35 @@ pub fn api_routes(
     let list_route = warp::path!("api" / "list")
         .and(warp::get())
         .and(warp::query::<GetRequest>())
-        .and(with_hyperion(hyperion))
+        .and(with_hyperion(hyperion.clone()))
         .and_then(handle_list);
     
+    // Route GET /api/indexes
+    let indexes_route = warp::path!("api" / "indexes")
+        .and(warp::get())
+        .and(with_hyperion(hyperion.clone()))
+        .and_then(handle_indexes);
+    
+    // Route GET /api/watch?pattern=...&since=... en WebSocket
+    let watch_ws_route = warp::path!("api" / "watch")
+        .and(warp::get())
+        .and(warp::ws())
+        .and(warp::query::<WatchRequest>())
+        .and(with_hyperion(hyperion.clone()))
+        .map(handle_watch_ws);
+    
+    // Route GET /api/watch?pattern=...&since=... en Server-Sent Events
+    let watch_sse_route = warp::path!("api" / "watch")
+        .and(warp::get())
+        .and(warp::query::<WatchRequest>())
+        .and(warp::header::optional::<u64>("last-event-id"))
+        .and(with_hyperion(hyperion))
+        .and_then(handle_watch_sse);
+    
     // Combiner toutes les routes
-    get_route.or(set_route).or(query_route).or(list_route)
+    get_route.or(set_route).or(replace_route).or(merge_route).or(patch_route)
+        .or(query_route).or(list_route).or(indexes_route)
+        .or(watch_ws_route).or(watch_sse_route)
 }
 
 /// Fonction utilitaire pour partager l'instance Hyperion avec les gestionnaires
@@ -106,11 +226,19 @@ async fn handle_get(
     
     let response = {
         let db = hyperion.lock().unwrap();
-        match db.get(&path) {
-            Ok(value) => ApiResponse {
+        
+        // Sans valeur propre, le chemin désigne l'entité formée par ses sous-chemins
+        let entity = match db.get(&path) {
+            Ok(value) => Ok(Entity::from(value)),
+            Err(StoreError::NotFound(_)) => db.get_entity(&path),
+            Err(e) => Err(e),
+        };
+        
+        match entity {
+            Ok(entity) => ApiResponse {
                 success: true,
                 error: None,
-                data: Some(value_to_json(&value)),
+                data: Some(entity.to_json()),
             },
             Err(e) => ApiResponse {
                 success: false,
@@ -123,10 +251,22 @@ async fn handle_get(
     Ok(warp::reply::json(&response))
 }
 
-/// Gestionnaire pour POST /api/set
-async fn handle_set(
+/// Manière d'écrire une entité
+#[derive(Debug, Clone, Copy)]
+enum EntityWrite {
+    /// Écrire les champs donnés (POST /api/set)
+    Set,
+    /// Remplacer l'entité entière (POST /api/replace)
+    Replace,
+    /// Appliquer un JSON Merge Patch (POST /api/merge)
+    Merge,
+}
+
+/// Gestionnaire pour POST /api/set, /api/replace et /api/merge
+async fn handle_entity_write(
     req: SetRequest,
-    hyperion: Arc<Mutex<Hyperion>>
+    hyperion: Arc<Mutex<Hyperion>>,
+    mode: EntityWrite
 ) -> Result<impl Reply, Rejection> {
     let path = match Path::from_str(&req.path) {
         Ok(p) => p,
@@ -139,8 +279,8 @@ async fn handle_set(
         }
     };
     
-    let value = match json_to_value(req.value) {
-        Ok(v) => v,
+    let entity = match Entity::from_json(req.value) {
+        Ok(e) => e,
         Err(e) => {
             return Ok(warp::reply::json(&ApiResponse {
                 success: false,
@@ -152,7 +292,64 @@ async fn handle_set(
     
     let response = {
         let mut db = hyperion.lock().unwrap();
-        match db.set(path, value) {
+        
+        // Les objets et tableaux sont décomposés en un endpoint par champ,
+        // écrits dans une seule transaction
+        let result = match mode {
+            EntityWrite::Set => db.set_entity(&path, &entity),
+            EntityWrite::Replace => db.replace_entity(&path, &entity),
+            EntityWrite::Merge => db.merge_entity(&path, &entity),
+        };
+        
+        match result {
+            Ok(_) => ApiResponse {
+                success: true,
+                error: None,
+                data: None::<()>,
+            },
+            Err(e) => ApiResponse {
+                success: false,
+                error: Some(format!("Error: {}", e)),
+                data: None::<()>,
+            },
+        }
+    };
+    
+    Ok(warp::reply::json(&response))
+}
+
+/// Gestionnaire pour POST /api/patch
+async fn handle_patch(
+    req: PatchRequest,
+    hyperion: Arc<Mutex<Hyperion>>
+) -> Result<impl Reply, Rejection> {
+    let path = match Path::from_str(&req.path) {
+        Ok(p) => p,
+        Err(e) => {
+            return Ok(warp::reply::json(&ApiResponse {
+                success: false,
+                error: Some(format!("Invalid path: {}", e)),
+                data: None::<()>,
+            }));
+        }
+    };
+    
+    let patch = match parse_patch(req.patch) {
+        Ok(p) => p,
+        Err(e) => {
+            return Ok(warp::reply::json(&ApiResponse {
+                success: false,
+                error: Some(format!("Error: {}", e)),
+                data: None::<()>,
+            }));
+        }
+    };
+    
+    let response = {
+        let mut db = hyperion.lock().unwrap();
+        
+        // Le patch est appliqué en entier ou pas du tout
+        match db.patch_entity(&path, &patch) {
             Ok(_) => ApiResponse {
                 success: true,
                 error: None,
@@ -182,10 +379,10 @@ async fn handle_query(
         
         // Utiliser le store avec execute_query
         match crate::ql::execute_query(store, &req.query) {
-            Ok(value) => ApiResponse {
+            Ok(entity) => ApiResponse {
                 success: true,
                 error: None,
-                data: Some(value_to_json(&value)),
+                data: Some(entity.to_json()),
             },
             Err(e) => ApiResponse {
                 success: false,
@@ -236,92 +433,150 @@ async fn handle_list(
     Ok(warp::reply::json(&response))
 }
 
-/// Convertit une Value Hyperion en serde_json::Value
-fn value_to_json(value: &Value) -> serde_json::Value {
-    match value {
-        Value::Null => serde_json::Value::Null,
-        Value::Boolean(b) => serde_json::Value::Bool(*b),
-        Value::Integer(i) => serde_json::Value::Number((*i).into()),
-        Value::Float(f) => {
-            if let Some(n) = serde_json::Number::from_f64(*f) {
-                serde_json::Value::Number(n)
-            } else {
-                // Fallback pour les nombres non représentables en JSON
-                serde_json::Value::String(f.to_string())
-            }
-        },
-        Value::String(s) => serde_json::Value::String(s.clone()),
-        Value::Binary(data, mime) => {
-            // Encoder en base64
-            let encoded = base64::encode(data);
-            let mut obj = serde_json::Map::new();
-            obj.insert("type".to_string(), serde_json::Value::String("binary".to_string()));
-            obj.insert("data".to_string(), serde_json::Value::String(encoded));
-            if let Some(m) = mime {
-                obj.insert("mime".to_string(), serde_json::Value::String(m.clone()));
-            }
-            serde_json::Value::Object(obj)
-        },
-        Value::Reference(path) => {
-            let mut obj = serde_json::Map::new();
-            obj.insert("type".to_string(), serde_json::Value::String("reference".to_string()));
-            obj.insert("path".to_string(), serde_json::Value::String(path.to_string()));
-            serde_json::Value::Object(obj)
-        },
+/// Gestionnaire pour GET /api/indexes
+async fn handle_indexes(
+    hyperion: Arc<Mutex<Hyperion>>
+) -> Result<impl Reply, Rejection> {
+    let response = {
+        let db = hyperion.lock().unwrap();
+        match db.indexes() {
+            Ok(indexes) => ApiResponse {
+                success: true,
+                error: None,
+                data: Some(indexes.into_iter().map(IndexResponse::from).collect::<Vec<_>>()),
+            },
+            Err(e) => ApiResponse {
+                success: false,
+                error: Some(format!("Error: {}", e)),
+                data: None,
+            },
+        }
+    };
+    
+    Ok(warp::reply::json(&response))
+}
+
+/// S'abonne aux changements sans garder le verrou sur l'instance Hyperion
+fn subscribe_to_changes(
+    hyperion: &Arc<Mutex<Hyperion>>,
+    pattern: Path,
+    since: Option<u64>
+) -> Subscription {
+    let feed = hyperion.lock().unwrap().change_feed();
+    
+    match since {
+        Some(since) => feed.subscribe_since(pattern, None, since),
+        None => feed.subscribe(pattern),
     }
 }
-fn json_to_value(json: serde_json::Value) -> Result<Value, String> {
-    match json {
-        serde_json::Value::Null => Ok(Value::Null),
-        serde_json::Value::Bool(b) => Ok(Value::Boolean(b)),
-        serde_json::Value::Number(n) => {
-            if n.is_i64() {
-                Ok(Value::Integer(n.as_i64().unwrap()))
-            } else {
-                Ok(Value::Float(n.as_f64().unwrap_or(0.0)))
-            }
-        },
-        serde_json::Value::String(s) => Ok(Value::String(s)),
-        serde_json::Value::Array(_) => {
-            // Pour les tableaux, nous serialisons tout le JSON en chaîne
-            let json_str = serde_json::to_string(&json)
-                .map_err(|e| format!("Failed to serialize JSON array: {}", e))?;
-            Ok(Value::String(json_str))
-        },
-        serde_json::Value::Object(ref obj) => { // Utilisez `ref` ici pour emprunter obj au lieu de le déplacer
-            // Vérifier s'il s'agit d'un type spécial comme binary ou reference
-            if let Some(serde_json::Value::String(t)) = obj.get("type") {
-                match t.as_str() {
-                    "binary" => {
-                        let data = obj.get("data")
-                            .and_then(|v| v.as_str())
-                            .ok_or("Missing data for binary type")?;
-                        let mime = obj.get("mime").and_then(|v| v.as_str()).map(String::from);
-                        let decoded = base64::decode(data)
-                            .map_err(|e| format!("Invalid base64 data: {}", e))?;
-                        Ok(Value::Binary(decoded, mime))
-                    },
-                    "reference" => {
-                        let path_str = obj.get("path")
-                            .and_then(|v| v.as_str())
-                            .ok_or("Missing path for reference type")?;
-                        let path = Path::from_str(path_str)
-                            .map_err(|e| format!("Invalid path: {}", e))?;
-                        Ok(Value::Reference(path))
-                    },
-                    _ => {
-                        // Type inconnu, sérialiser en JSON
-                        let json_str = serde_json::to_string(&json)
-                            .map_err(|e| format!("Failed to serialize JSON object: {}", e))?;
-                        Ok(Value::String(json_str))
-                    }
+
+/// Gestionnaire pour GET /api/watch en Server-Sent Events
+async fn handle_watch_sse(
+    req: WatchRequest,
+    last_event_id: Option<u64>,
+    hyperion: Arc<Mutex<Hyperion>>
+) -> Result<warp::reply::Response, Rejection> {
+    let pattern = match Path::from_str(&req.pattern) {
+        Ok(p) => p,
+        Err(e) => {
+            return Ok(warp::reply::json(&ApiResponse {
+                success: false,
+                error: Some(format!("Invalid pattern: {}", e)),
+                data: None::<()>,
+            }).into_response());
+        }
+    };
+    
+    // Le navigateur renvoie Last-Event-ID lorsqu'il se reconnecte
+    let subscription = subscribe_to_changes(&hyperion, pattern, last_event_id.or(req.since));
+    
+    // Prévenir le client s'il doit recharger son état
+    let reset = if subscription.missed_events() {
+        Some(Ok(Event::default().event("reset").data("Some changes could not be replayed")))
+    } else {
+        None
+    };
+    
+    let changes = subscription.map(|event| {
+        Ok::<_, Infallible>(Event::default()
+            .id(event.sequence.to_string())
+            .event("change")
+            .data(change_to_json(&event).to_string()))
+    });
+    
+    let events = stream::iter(reset).chain(changes);
+    
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response())
+}
+
+/// Gestionnaire pour GET /api/watch en WebSocket
+fn handle_watch_ws(
+    ws: Ws,
+    req: WatchRequest,
+    hyperion: Arc<Mutex<Hyperion>>
+) -> impl Reply {
+    ws.on_upgrade(move |socket| watch_socket(socket, req, hyperion))
+}
+
+/// Envoie les changements sur le WebSocket jusqu'à sa fermeture
+async fn watch_socket(mut socket: WebSocket, req: WatchRequest, hyperion: Arc<Mutex<Hyperion>>) {
+    let pattern = match Path::from_str(&req.pattern) {
+        Ok(p) => p,
+        Err(e) => {
+            let error = serde_json::json!({ "type": "error", "error": format!("Invalid pattern: {}", e) });
+            let _ = socket.send(Message::text(error.to_string())).await;
+            let _ = socket.close().await;
+            return;
+        }
+    };
+    
+    let mut subscription = subscribe_to_changes(&hyperion, pattern, req.since);
+    
+    if subscription.missed_events() {
+        let reset = serde_json::json!({ "type": "reset" });
+        if socket.send(Message::text(reset.to_string())).await.is_err() {
+            return;
+        }
+    }
+    
+    loop {
+        tokio::select! {
+            event = subscription.recv() => {
+                let event = match event {
+                    Some(event) => event,
+                    None => break,
+                };
+                
+                let mut message = change_to_json(&event);
+                message["type"] = serde_json::Value::String("change".to_string());
+                
+                if socket.send(Message::text(message.to_string())).await.is_err() {
+                    break;
+                }
+            },
+            message = socket.next() => {
+                // Les messages du client sont ignorés, seule la fermeture compte
+                match message {
+                    Some(Ok(message)) if !message.is_close() => {},
+                    _ => break,
                 }
-            } else {
-                // Objet ordinaire, sérialiser en JSON
-                let json_str = serde_json::to_string(&json)
-                    .map_err(|e| format!("Failed to serialize JSON object: {}", e))?;
-                Ok(Value::String(json_str))
-            }
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+/// Convertit un événement de changement en JSON
+fn change_to_json(event: &ChangeEvent) -> serde_json::Value {
+    serde_json::json!({
+        "sequence": event.sequence,
+        "path": event.path.to_string(),
+        "operation": event.operation.as_str(),
+        "old_value": event.old_value.as_ref().map(value_to_json),
+        "new_value": event.new_value.as_ref().map(value_to_json),
+    })
+}
+
+/// Convertit une Value Hyperion en serde_json::Value
+fn value_to_json(value: &Value) -> serde_json::Value {
+    Entity::from(value.clone()).to_json()
+}
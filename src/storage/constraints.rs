@@ -0,0 +1,380 @@
+//! Unique constraints for the persistent store
+//!
+//! A unique constraint on a pattern (`users.*.email`) records, for every
+//! value held by a path matching the pattern, the path holding it. Values
+//! are keyed like in the value index. The entries are written in the same
+//! transaction as the data, so a write giving a value already held by
+//! another path is rejected atomically.
+//!
+//! The memory store keeps no entries: it checks the values it will hold
+//! with `check_distinct` before applying a batch.
+
+use std::collections::HashMap;
+
+use bincode::{serialize, deserialize};
+use sled::Db;
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use crate::core::index::value_index::ValueIndex;
+use super::keys::{decode_path, decode_value};
+
+/// Unique constraints of a persistent store
+pub struct UniqueConstraints {
+    /// Holder of each constrained value, keyed by (constraint id, value key)
+    tree: sled::Tree,
+    /// Constrained patterns, keyed by constraint id
+    metadata: sled::Tree,
+    /// Cache of the constrained patterns, with their id
+    patterns: Vec<(u32, Path)>,
+}
+
+impl UniqueConstraints {
+    /// Open the constraint trees
+    pub fn open(db: &Db) -> Result<Self> {
+        let tree = db.open_tree("unique_constraints")
+            .map_err(|e| StoreError::Internal(format!("Failed to open constraints tree: {}", e)))?;
+        let metadata = db.open_tree("unique_constraints_metadata")
+            .map_err(|e| StoreError::Internal(format!("Failed to open constraints metadata tree: {}", e)))?;
+
+        let mut patterns = Vec::new();
+        for item in metadata.iter() {
+            let (id, pattern) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to iterate constraints: {}", e)))?;
+            let id = id.as_ref().try_into()
+                .map_err(|_| StoreError::DeserializationError("Invalid constraint id".to_string()))?;
+            let pattern = deserialize(&pattern)
+                .map_err(|e| StoreError::DeserializationError(format!("Failed to deserialize constraint: {}", e)))?;
+            patterns.push((u32::from_be_bytes(id), pattern));
+        }
+
+        Ok(UniqueConstraints { tree, metadata, patterns })
+    }
+
+    /// Get the tree written in the same transaction as the data
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+
+    /// Check whether there is no constraint to enforce
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// List the constrained patterns
+    pub fn patterns(&self) -> Vec<Path> {
+        self.patterns.iter().map(|(_, pattern)| pattern.clone()).collect()
+    }
+
+    /// Add a unique constraint, after checking that the existing values
+    /// satisfy it
+    ///
+    /// Returns `false` if the constraint already exists. The caller must
+    /// not write to the data while the existing values are read.
+    pub fn add(&mut self, db: &Db, pattern: &Path) -> Result<bool> {
+        if self.patterns.iter().any(|(_, existing)| existing == pattern) {
+            return Ok(false);
+        }
+
+        let id = self.patterns.iter().map(|(id, _)| id + 1).max().unwrap_or(0);
+        let mut holders: HashMap<Vec<u8>, Path> = HashMap::new();
+
+        for item in db.iter() {
+            let (key, value) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to iterate database: {}", e)))?;
+            let path = decode_path(&key)?;
+            if !path.matches(pattern) {
+                continue;
+            }
+
+            let value = decode_value(&value)?;
+            if let Some(value_key) = ValueIndex::indexable_key(&value) {
+                let entry_key = Self::entry_key(id, &value_key);
+                if let Some(holder) = holders.get(&entry_key) {
+                    return Err(violation(pattern, &path, &value, holder));
+                }
+                holders.insert(entry_key, path);
+            }
+        }
+
+        let mut batch = sled::Batch::default();
+        for (entry_key, path) in &holders {
+            batch.insert(entry_key.as_slice(), serialize(path)
+                .map_err(|e| StoreError::SerializationError(format!("Failed to serialize path: {}", e)))?);
+        }
+        self.tree.apply_batch(batch)
+            .map_err(|e| StoreError::Internal(format!("Failed to write constraint entries: {}", e)))?;
+
+        // The constraint is only enforced once its entries are written
+        let encoded = serialize(pattern)
+            .map_err(|e| StoreError::SerializationError(format!("Failed to serialize pattern: {}", e)))?;
+        self.metadata.insert(id.to_be_bytes(), encoded)
+            .map_err(|e| StoreError::Internal(format!("Failed to record constraint: {}", e)))?;
+        self.patterns.push((id, pattern.clone()));
+
+        Ok(true)
+    }
+
+    /// Remove a unique constraint and its entries
+    ///
+    /// Returns `false` if the constraint does not exist.
+    pub fn remove(&mut self, pattern: &Path) -> Result<bool> {
+        let id = match self.patterns.iter().find(|(_, existing)| existing == pattern) {
+            Some((id, _)) => *id,
+            None => return Ok(false),
+        };
+
+        self.metadata.remove(id.to_be_bytes())
+            .map_err(|e| StoreError::Internal(format!("Failed to remove constraint: {}", e)))?;
+        self.patterns.retain(|(existing, _)| *existing != id);
+
+        for key in self.tree.scan_prefix(id.to_be_bytes()).keys() {
+            let key = key.map_err(|e| StoreError::Internal(format!("Failed to scan constraints tree: {}", e)))?;
+            self.tree.remove(key)
+                .map_err(|e| StoreError::Internal(format!("Failed to remove constraint entry: {}", e)))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Release the value a path held, inside a data transaction
+    pub fn stage_release(
+        &self,
+        tree: &TransactionalTree,
+        path: &Path,
+        value: &Value,
+    ) -> std::result::Result<(), ConflictableTransactionError<StoreError>> {
+        let value_key = match ValueIndex::indexable_key(value) {
+            Some(value_key) => value_key,
+            None => return Ok(()),
+        };
+
+        for (id, pattern) in &self.patterns {
+            if !path.matches(pattern) {
+                continue;
+            }
+
+            let entry_key = Self::entry_key(*id, &value_key);
+            if Self::holder(tree, &entry_key)?.as_ref() == Some(path) {
+                tree.remove(entry_key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Claim a value for a path, inside a data transaction
+    ///
+    /// Aborts the transaction with a `ConstraintViolation` if another path
+    /// matching a constrained pattern holds the value.
+    pub fn stage_claim(
+        &self,
+        tree: &TransactionalTree,
+        path: &Path,
+        value: &Value,
+    ) -> std::result::Result<(), ConflictableTransactionError<StoreError>> {
+        let value_key = match ValueIndex::indexable_key(value) {
+            Some(value_key) => value_key,
+            None => return Ok(()),
+        };
+
+        for (id, pattern) in &self.patterns {
+            if !path.matches(pattern) {
+                continue;
+            }
+
+            let entry_key = Self::entry_key(*id, &value_key);
+            match Self::holder(tree, &entry_key)? {
+                Some(holder) if holder != *path => {
+                    return Err(ConflictableTransactionError::Abort(violation(pattern, path, value, &holder)));
+                },
+                Some(_) => {},
+                None => {
+                    let encoded = serialize(path).map_err(|e| ConflictableTransactionError::Abort(
+                        StoreError::SerializationError(format!("Failed to serialize path: {}", e))
+                    ))?;
+                    tree.insert(entry_key, encoded)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the path holding a constrained value
+    fn holder(
+        tree: &TransactionalTree,
+        entry_key: &[u8],
+    ) -> std::result::Result<Option<Path>, ConflictableTransactionError<StoreError>> {
+        match tree.get(entry_key)? {
+            Some(encoded) => deserialize(&encoded).map(Some).map_err(|e| ConflictableTransactionError::Abort(
+                StoreError::DeserializationError(format!("Failed to deserialize constraint entry: {}", e))
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Build the key of the entry of a value
+    fn entry_key(id: u32, value_key: &[u8]) -> Vec<u8> {
+        let mut entry_key = id.to_be_bytes().to_vec();
+        entry_key.extend_from_slice(value_key);
+        entry_key
+    }
+}
+
+/// Check that the paths matching a pattern hold distinct values
+///
+/// Used by stores without a constraint tree, which check the values they
+/// will hold instead. When a value is held twice, the error names the
+/// later path, so the paths already stored should come first.
+pub fn check_distinct<'a, I>(pattern: &Path, endpoints: I) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a Path, &'a Value)>,
+{
+    let mut holders: HashMap<Vec<u8>, &Path> = HashMap::new();
+
+    for (path, value) in endpoints {
+        if !path.matches(pattern) {
+            continue;
+        }
+
+        if let Some(value_key) = ValueIndex::indexable_key(value) {
+            if let Some(holder) = holders.insert(value_key, path) {
+                return Err(violation(pattern, path, value, holder));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the error of a value held twice
+fn violation(pattern: &Path, path: &Path, value: &Value, holder: &Path) -> StoreError {
+    StoreError::ConstraintViolation(format!(
+        "{} cannot hold {}: the value is already held by {} (unique {})",
+        path, value, holder, pattern
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::store::{Store, WriteOp};
+    use crate::core::transaction::Transaction;
+    use crate::storage::{MemoryStore, PersistentStore};
+
+    fn path(s: &str) -> Path {
+        Path::from_str(s).unwrap()
+    }
+
+    fn email(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    /// Run a check on a memory store, then on a persistent store
+    async fn on_each_store(check: fn(&mut dyn Store)) {
+        check(&mut MemoryStore::new());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+        check(&mut store);
+    }
+
+    fn rejects_existing_duplicates(store: &mut dyn Store) {
+        let pattern = path("users.*.email");
+        store.set(path("users.a.email"), email("a@x")).unwrap();
+        store.set(path("users.b.email"), email("a@x")).unwrap();
+
+        let result = store.add_unique_constraint(&pattern);
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+        assert!(store.unique_constraints().unwrap().is_empty());
+
+        store.set(path("users.b.email"), email("b@x")).unwrap();
+        assert!(store.add_unique_constraint(&pattern).unwrap());
+        assert!(!store.add_unique_constraint(&pattern).unwrap());
+        assert_eq!(store.unique_constraints().unwrap(), vec![pattern.clone()]);
+
+        // A conflicting write is rejected and leaves the data untouched,
+        // paths outside the pattern are not constrained
+        let result = store.set(path("users.c.email"), email("a@x"));
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+        assert!(!store.exists(&path("users.c.email")).unwrap());
+        store.set(path("users.c.backup"), email("a@x")).unwrap();
+
+        assert!(store.drop_unique_constraint(&pattern).unwrap());
+        assert!(!store.drop_unique_constraint(&pattern).unwrap());
+        store.set(path("users.c.email"), email("a@x")).unwrap();
+    }
+
+    fn swaps_values_in_one_transaction(store: &mut dyn Store) {
+        store.set(path("users.a.email"), email("a@x")).unwrap();
+        store.set(path("users.b.email"), email("b@x")).unwrap();
+        store.add_unique_constraint(&path("users.*.email")).unwrap();
+
+        // Rewriting a value is allowed
+        store.set(path("users.a.email"), email("a@x")).unwrap();
+
+        let mut tx = Transaction::new(&mut *store);
+        tx.set(path("users.a.email"), email("b@x")).unwrap();
+        tx.set(path("users.b.email"), email("a@x")).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(store.get(&path("users.a.email")).unwrap(), email("b@x"));
+        assert_eq!(store.get(&path("users.b.email")).unwrap(), email("a@x"));
+
+        // A batch ending with a value held twice is rejected as a whole
+        let result = store.apply_batch(vec![
+            WriteOp::Set(path("users.a.email"), email("c@x")),
+            WriteOp::Set(path("users.c.email"), email("a@x")),
+        ]);
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+        assert_eq!(store.get(&path("users.a.email")).unwrap(), email("b@x"));
+    }
+
+    fn releases_deleted_values(store: &mut dyn Store) {
+        store.set(path("users.a.email"), email("a@x")).unwrap();
+        store.add_unique_constraint(&path("users.*.email")).unwrap();
+
+        store.delete(&path("users.a.email")).unwrap();
+        store.set(path("users.b.email"), email("a@x")).unwrap();
+
+        // In a single batch too
+        store.apply_batch(vec![
+            WriteOp::Delete(path("users.b.email")),
+            WriteOp::Set(path("users.c.email"), email("a@x")),
+        ]).unwrap();
+        let result = store.set(path("users.a.email"), email("a@x"));
+        assert!(matches!(result, Err(StoreError::ConstraintViolation(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_add_rejects_existing_duplicates() {
+        on_each_store(rejects_existing_duplicates).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_values_can_be_swapped_in_one_transaction() {
+        on_each_store(swaps_values_in_one_transaction).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deleted_values_can_be_claimed_again() {
+        on_each_store(releases_deleted_values).await;
+    }
+
+    #[test]
+    fn test_constraints_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let pattern = path("users.*.email");
+
+        let mut constraints = UniqueConstraints::open(&db).unwrap();
+        assert!(constraints.add(&db, &pattern).unwrap());
+        assert_eq!(UniqueConstraints::open(&db).unwrap().patterns(), vec![pattern.clone()]);
+
+        assert!(constraints.remove(&pattern).unwrap());
+        assert!(UniqueConstraints::open(&db).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+//! Version history for the persistent store
+//!
+//! Each version of a path is stored under a key made of the path key,
+//! the time of the write and its sequence number, so the versions of a
+//! path are contiguous and sorted from oldest to newest.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use sled::{Db, IVec};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use crate::core::version::{Version, RetentionPolicy};
+use super::keys::{decode_path, decode_value, length_prefixed, split_length_prefixed, encode_optional, decode_optional};
+
+/// Recorded versions of the paths of a persistent store
+pub struct HistoryLog {
+    /// Versions keyed by (path key, timestamp, sequence)
+    tree: sled::Tree,
+}
+
+impl HistoryLog {
+    /// Open the history tree
+    pub fn open(db: &Db) -> Result<Self> {
+        let tree = db.open_tree("history")
+            .map_err(|e| StoreError::Internal(format!("Failed to open history tree: {}", e)))?;
+
+        Ok(HistoryLog { tree })
+    }
+
+    /// Get the tree written in the same transaction as the data
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+
+    /// Record a new version of a path, inside a data transaction
+    pub fn record(
+        tree: &TransactionalTree,
+        key: &[u8],
+        timestamp: DateTime<Utc>,
+        sequence: u64,
+        value: Option<&[u8]>,
+    ) -> std::result::Result<(), ConflictableTransactionError<StoreError>> {
+        tree.insert(Self::version_key(key, timestamp, sequence), encode_optional(value))?;
+        Ok(())
+    }
+
+    /// Build the key of a version
+    fn version_key(key: &[u8], timestamp: DateTime<Utc>, sequence: u64) -> Vec<u8> {
+        // Flipping the sign bit keeps negative timestamps ordered
+        let micros = (timestamp.timestamp_micros() as u64) ^ (1 << 63);
+
+        let mut version_key = length_prefixed(key);
+        version_key.extend_from_slice(&micros.to_be_bytes());
+        version_key.extend_from_slice(&sequence.to_be_bytes());
+        version_key
+    }
+
+    /// Split a version key into the path key and the timestamp
+    fn split_version_key(version_key: &[u8]) -> Result<(&[u8], DateTime<Utc>)> {
+        let (key, rest) = split_length_prefixed(version_key)?;
+        if rest.len() != 16 {
+            return Err(StoreError::DeserializationError("Invalid history key".to_string()));
+        }
+
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(&rest[..8]);
+        let micros = (u64::from_be_bytes(raw) ^ (1 << 63)) as i64;
+
+        let timestamp = DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| StoreError::DeserializationError("Invalid history timestamp".to_string()))?;
+
+        Ok((key, timestamp))
+    }
+
+    /// Decode a stored version
+    fn decode_version(timestamp: DateTime<Utc>, entry: &[u8]) -> Result<Version> {
+        let value = match decode_optional(entry) {
+            Some(bytes) => Some(decode_value(bytes)?),
+            None => None,
+        };
+
+        Ok(Version { timestamp, value })
+    }
+
+    /// List the raw versions of a path key, oldest first
+    fn raw_versions(&self, key: &[u8]) -> Result<Vec<(IVec, DateTime<Utc>, IVec)>> {
+        let mut versions = Vec::new();
+
+        for item in self.tree.scan_prefix(length_prefixed(key)) {
+            let (version_key, entry) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read history: {}", e)))?;
+            let (_, timestamp) = Self::split_version_key(&version_key)?;
+            versions.push((version_key, timestamp, entry));
+        }
+
+        Ok(versions)
+    }
+
+    /// List the versions of a path key, oldest first
+    pub fn versions(&self, key: &[u8]) -> Result<Vec<Version>> {
+        self.raw_versions(key)?
+            .into_iter()
+            .map(|(_, timestamp, entry)| Self::decode_version(timestamp, &entry))
+            .collect()
+    }
+
+    /// Get the values the paths under a prefix had at the given time
+    pub fn prefix_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        // Versions are sorted by time within a path, so the last one
+        // seen before `as_of` is the value the path had then
+        let mut latest: HashMap<Vec<u8>, IVec> = HashMap::new();
+
+        for item in self.tree.iter() {
+            let (version_key, entry) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read history: {}", e)))?;
+            let (key, timestamp) = Self::split_version_key(&version_key)?;
+
+            if timestamp <= as_of {
+                latest.insert(key.to_vec(), entry);
+            }
+        }
+
+        let mut results = Vec::new();
+        for (key, entry) in latest {
+            let bytes = match decode_optional(&entry) {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+
+            let path = decode_path(&key)?;
+            if path.starts_with(prefix) {
+                results.push((path, decode_value(bytes)?));
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Drop the versions of a path key the retention policy does not keep
+    pub fn prune(&self, key: &[u8], policy: &RetentionPolicy, now: DateTime<Utc>) -> Result<()> {
+        if *policy == RetentionPolicy::KeepAll {
+            return Ok(());
+        }
+
+        let versions = self.raw_versions(key)?;
+        let len = versions.len();
+
+        for (i, (version_key, _, _)) in versions.iter().enumerate() {
+            let replaced_at = versions.get(i + 1).map(|(_, timestamp, _)| *timestamp);
+
+            if !policy.keeps(len - 1 - i, replaced_at, now) {
+                self.tree.remove(version_key)
+                    .map_err(|e| StoreError::Internal(format!("Failed to prune history: {}", e)))?;
+            }
+        }
+
+        Ok(())
+    }
+}
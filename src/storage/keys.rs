@@ -0,0 +1,282 @@
+//! Key and value encoding for the persistent store
+//!
+//! Every tree written by the persistent store goes through these
+//! helpers, so the on-disk format is defined in a single place.
+//!
+//! Path keys are order-preserving: each segment is a tag byte followed
+//! by its content, array indices as big-endian integers and names as
+//! escaped, terminated bytes. Keys therefore sort segment by segment,
+//! a path sorts right before its children, and the key of a prefix is
+//! a byte prefix of the keys of exactly the paths below it, so sled
+//! `scan_prefix` and `range` can answer prefix and range queries.
+
+use bincode::{serialize, deserialize};
+use sled::Db;
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+
+/// Version of the path key encoding written by this build
+///
+/// Version 1 keyed paths with bincode; databases written with it have no
+/// version marker and must be migrated.
+pub const KEY_FORMAT_VERSION: u32 = 2;
+
+/// Tree holding the metadata of the database
+const META_TREE: &str = "meta";
+/// Key of the key format version in the metadata tree
+const KEY_FORMAT_KEY: &[u8] = b"key_format";
+
+/// Tag of an array index segment (sorts before names)
+const INDEX_TAG: u8 = 0x01;
+/// Tag of a named segment
+const NAME_TAG: u8 = 0x02;
+/// Escape byte of names: `0x00` is written `0x00 0xFF`, and the end of
+/// a name `0x00 0x01`, so a name sorts before its extensions
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xFF;
+const NAME_END: u8 = 0x01;
+
+/// Encode a path into the key used in the main tree
+pub fn encode_path(path: &Path) -> Result<Vec<u8>> {
+    let mut key = Vec::new();
+
+    for segment in path.segments() {
+        if segment.is_wildcard() {
+            return Err(StoreError::SerializationError(format!("Cannot encode a path with wildcards: {}", path)));
+        }
+
+        match segment.as_index() {
+            Some(index) => {
+                key.push(INDEX_TAG);
+                key.extend_from_slice(&(index as u64).to_be_bytes());
+            },
+            None => {
+                key.push(NAME_TAG);
+                for byte in segment.as_str().bytes() {
+                    if byte == ESCAPE {
+                        key.extend_from_slice(&[ESCAPE, ESCAPED_ZERO]);
+                    } else {
+                        key.push(byte);
+                    }
+                }
+                key.extend_from_slice(&[ESCAPE, NAME_END]);
+            },
+        }
+    }
+
+    Ok(key)
+}
+
+/// Decode a key of the main tree back into a path
+pub fn decode_path(bytes: &[u8]) -> Result<Path> {
+    let invalid = || StoreError::DeserializationError("Invalid path key".to_string());
+    let mut path = Path::new();
+    let mut rest = bytes;
+
+    while let Some((&tag, content)) = rest.split_first() {
+        match tag {
+            INDEX_TAG => {
+                if content.len() < 8 {
+                    return Err(invalid());
+                }
+                let mut raw = [0u8; 8];
+                raw.copy_from_slice(&content[..8]);
+                path.push(format!("[{}]", u64::from_be_bytes(raw)));
+                rest = &content[8..];
+            },
+            NAME_TAG => {
+                let mut name = Vec::new();
+                let mut i = 0;
+                loop {
+                    match (content.get(i), content.get(i + 1)) {
+                        (Some(&ESCAPE), Some(&NAME_END)) => break,
+                        (Some(&ESCAPE), Some(&ESCAPED_ZERO)) => {
+                            name.push(ESCAPE);
+                            i += 2;
+                        },
+                        (Some(&ESCAPE), _) | (None, _) => return Err(invalid()),
+                        (Some(&byte), _) => {
+                            name.push(byte);
+                            i += 1;
+                        },
+                    }
+                }
+                path.push(String::from_utf8(name).map_err(|_| invalid())?);
+                rest = &content[i + 2..];
+            },
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(path)
+}
+
+/// Decode a path key written with the version 1 (bincode) encoding
+pub fn decode_legacy_path(bytes: &[u8]) -> Result<Path> {
+    deserialize(bytes).map_err(|e| StoreError::DeserializationError(e.to_string()))
+}
+
+/// Open the tree holding the metadata of the database
+pub fn meta_tree(db: &Db) -> Result<sled::Tree> {
+    db.open_tree(META_TREE)
+        .map_err(|e| StoreError::Internal(format!("Failed to open metadata tree: {}", e)))
+}
+
+/// Read the key format version of a database
+///
+/// A database without marker is new if it holds no data, and uses the
+/// version 1 encoding otherwise.
+pub fn stored_key_format(db: &Db) -> Result<u32> {
+    let marker = meta_tree(db)?.get(KEY_FORMAT_KEY)
+        .map_err(|e| StoreError::Internal(format!("Failed to read key format: {}", e)))?;
+
+    match marker {
+        Some(bytes) if bytes.len() == 4 => {
+            let mut raw = [0u8; 4];
+            raw.copy_from_slice(&bytes);
+            Ok(u32::from_be_bytes(raw))
+        },
+        Some(_) => Err(StoreError::DeserializationError("Invalid key format marker".to_string())),
+        None if db.is_empty() => Ok(KEY_FORMAT_VERSION),
+        None => Ok(1),
+    }
+}
+
+/// Encode the key format marker, to be written in the metadata tree
+pub fn key_format_entry(version: u32) -> (&'static [u8], [u8; 4]) {
+    (KEY_FORMAT_KEY, version.to_be_bytes())
+}
+
+/// Check that a database uses the key format of this build, marking new
+/// databases with it
+pub fn check_key_format(db: &Db) -> Result<()> {
+    let version = stored_key_format(db)?;
+
+    if version < KEY_FORMAT_VERSION {
+        return Err(StoreError::InvalidOperation(format!(
+            "Database uses key format {}, expected {}: migrate it first (hyperion-server --migrate)",
+            version, KEY_FORMAT_VERSION
+        )));
+    }
+    if version > KEY_FORMAT_VERSION {
+        return Err(StoreError::InvalidOperation(format!(
+            "Database uses key format {}, which is newer than this build supports ({})",
+            version, KEY_FORMAT_VERSION
+        )));
+    }
+
+    let (key, value) = key_format_entry(version);
+    meta_tree(db)?.insert(key, &value)
+        .map_err(|e| StoreError::Internal(format!("Failed to write key format: {}", e)))?;
+
+    Ok(())
+}
+
+/// Encode a value for storage
+pub fn encode_value(value: &Value) -> Result<Vec<u8>> {
+    serialize(value).map_err(|e| StoreError::SerializationError(e.to_string()))
+}
+
+/// Decode a stored value
+pub fn decode_value(bytes: &[u8]) -> Result<Value> {
+    deserialize(bytes).map_err(|e| StoreError::DeserializationError(e.to_string()))
+}
+
+/// Prefix a key with its length
+///
+/// Keys built by appending to a length-prefixed key never interleave
+/// with the ones built from a longer key that extends it.
+pub fn length_prefixed(key: &[u8]) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(key.len() + 4);
+    prefixed.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    prefixed.extend_from_slice(key);
+    prefixed
+}
+
+/// Split a length-prefixed key into the key and what follows it
+pub fn split_length_prefixed(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    if bytes.len() < 4 {
+        return Err(StoreError::DeserializationError("Truncated key".to_string()));
+    }
+
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&bytes[..4]);
+    let len = u32::from_be_bytes(raw) as usize;
+
+    if bytes.len() < 4 + len {
+        return Err(StoreError::DeserializationError("Truncated key".to_string()));
+    }
+
+    Ok((&bytes[4..4 + len], &bytes[4 + len..]))
+}
+
+/// Encode an optional encoded value (`None` for an absent endpoint)
+pub fn encode_optional(bytes: Option<&[u8]>) -> Vec<u8> {
+    // A leading byte distinguishes "absent" from an encoded value
+    match bytes {
+        Some(bytes) => {
+            let mut entry = Vec::with_capacity(bytes.len() + 1);
+            entry.push(1);
+            entry.extend_from_slice(bytes);
+            entry
+        },
+        None => vec![0],
+    }
+}
+
+/// Decode an optional encoded value
+pub fn decode_optional(entry: &[u8]) -> Option<&[u8]> {
+    match entry.first() {
+        Some(1) => Some(&entry[1..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn key(s: &str) -> Vec<u8> {
+        encode_path(&Path::from_str(s).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_path_keys_round_trip() {
+        for path in ["users.u-1.name", "users.u-1.tags.[12]", "a"] {
+            let path = Path::from_str(path).unwrap();
+            assert_eq!(decode_path(&encode_path(&path).unwrap()).unwrap(), path);
+        }
+
+        let mut path = Path::new();
+        path.push("with\0zero");
+        assert_eq!(decode_path(&encode_path(&path).unwrap()).unwrap(), path);
+    }
+
+    #[test]
+    fn test_path_keys_sort_by_segment() {
+        // A path sorts before its children, and names before their extensions
+        assert!(key("users.u-1") < key("users.u-1.name"));
+        assert!(key("users.u-1.name") < key("users.u-10"));
+        assert!(key("users.u-1.zzz") < key("users.u-10"));
+
+        // Array indices sort numerically, and before names
+        assert!(key("tags.[2]") < key("tags.[10]"));
+        assert!(key("tags.[10]") < key("tags.a"));
+
+        // Keys sort as the paths they encode
+        let mut paths: Vec<Path> = ["a.b", "a.[1]", "a", "a.[0].x", "a.\u{0}", "a.b.[3]", "ab", "a.b.c"]
+            .iter()
+            .map(|s| Path::from_str(s).unwrap())
+            .collect();
+        paths.sort();
+        let keys: Vec<Vec<u8>> = paths.iter().map(|path| encode_path(path).unwrap()).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // The key of a prefix only prefixes the keys of the paths below it
+        assert!(key("users.u-1.name").starts_with(&key("users.u-1")));
+        assert!(!key("users.u-10.name").starts_with(&key("users.u-1")));
+    }
+}
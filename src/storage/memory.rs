@@ -5,58 +5,119 @@
 
 use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
 use crate::core::path::Path;
 use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
-use crate::core::store::Store;
+use crate::core::store::{ReadStore, Store, WriteOp};
+use crate::core::snapshot::Snapshot;
+use crate::core::version::{self, Version, RetentionPolicy};
+use crate::core::changes::{ChangeEvent, ChangeOperation, ChangeFeed};
+use super::constraints::check_distinct;
 
 /// An in-memory store for the database
 #[derive(Debug, Default)]
 pub struct MemoryStore {
-    /// Map of paths to values
-    data: HashMap<Path, Value>,
+    /// Map of paths to values (shared with snapshots, copied on write)
+    data: Arc<HashMap<Path, Value>>,
+    /// Sequence number of the last write
+    sequence: u64,
+    /// Recorded versions of each path, oldest first
+    versions: Arc<HashMap<Path, Vec<Version>>>,
+    /// Retention policy of the versions (`None` if versioning is disabled)
+    retention: Option<RetentionPolicy>,
+    /// Feed publishing the writes
+    changes: ChangeFeed,
+    /// Patterns under a unique constraint
+    constraints: Vec<Path>,
 }
 
 impl MemoryStore {
     /// Create a new empty memory store
     pub fn new() -> Self {
         MemoryStore {
-            data: HashMap::new(),
+            data: Arc::new(HashMap::new()),
+            sequence: 0,
+            versions: Arc::new(HashMap::new()),
+            retention: None,
+            changes: ChangeFeed::new(),
+            constraints: Vec::new(),
         }
     }
-}
-
-impl Store for MemoryStore {
-    fn set(&mut self, path: Path, value: Value) -> Result<()> {
-        if path.is_empty() {
-            return Err(StoreError::InvalidOperation("Cannot set value at empty path".to_string()));
+    
+    /// Create a new empty memory store recording versions of every write
+    pub fn with_versioning(policy: RetentionPolicy) -> Self {
+        MemoryStore {
+            retention: Some(policy),
+            ..Self::new()
         }
+    }
+    
+    /// Record a new version of a path, if versioning is enabled
+    fn record_version(&mut self, path: &Path, value: Option<&Value>, now: DateTime<Utc>) {
+        let policy = match &self.retention {
+            Some(policy) => policy,
+            None => return,
+        };
         
-        self.data.insert(path, value);
-        Ok(())
+        let versions = Arc::make_mut(&mut self.versions)
+            .entry(path.clone())
+            .or_default();
+        versions.push(Version { timestamp: now, value: value.cloned() });
+        policy.prune(versions, now);
     }
     
-    fn get(&self, path: &Path) -> Result<Value> {
-        if path.is_empty() {
-            return Err(StoreError::InvalidOperation("Cannot get value at empty path".to_string()));
+    /// Get the recorded versions of a path, failing if versioning is disabled
+    fn versions_of(&self, path: &Path) -> Result<&[Version]> {
+        if self.retention.is_none() {
+            return Err(version::versioning_disabled());
         }
         
-        self.data.get(path)
-            .cloned()
-            .ok_or_else(|| StoreError::NotFound(path.clone()))
+        Ok(self.versions.get(path).map(Vec::as_slice).unwrap_or(&[]))
     }
     
-    fn delete(&mut self, path: &Path) -> Result<()> {
-        if path.is_empty() {
-            return Err(StoreError::InvalidOperation("Cannot delete value at empty path".to_string()));
+    /// Check that a batch leaves distinct values at the paths of every
+    /// unique constraint
+    fn check_constraints(&self, ops: &[WriteOp]) -> Result<()> {
+        if self.constraints.is_empty() {
+            return Ok(());
         }
         
-        if self.data.remove(path).is_none() {
-            return Err(StoreError::NotFound(path.clone()));
+        // Value held by each written path once the batch is applied
+        let mut written: HashMap<&Path, Option<&Value>> = HashMap::new();
+        for op in ops {
+            match op {
+                WriteOp::Set(path, value) => written.insert(path, Some(value)),
+                WriteOp::Delete(path) => written.insert(path, None),
+            };
+        }
+        
+        for pattern in &self.constraints {
+            // Deletes only release values
+            if !written.iter().any(|(path, value)| value.is_some() && path.matches(pattern)) {
+                continue;
+            }
+            
+            let kept = self.data.iter().filter(|(path, _)| !written.contains_key(path));
+            let claimed = written.iter().filter_map(|(path, value)| value.map(|value| (*path, value)));
+            check_distinct(pattern, kept.chain(claimed))?;
         }
         
         Ok(())
     }
+}
+
+impl ReadStore for MemoryStore {
+    fn get(&self, path: &Path) -> Result<Value> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot get value at empty path".to_string()));
+        }
+        
+        self.data.get(path)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(path.clone()))
+    }
     
     fn exists(&self, path: &Path) -> Result<bool> {
         if path.is_empty() {
@@ -114,6 +175,152 @@ impl Store for MemoryStore {
         Ok(count)
     }
     
+    fn history(&self, path: &Path, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<Version>> {
+        let versions = self.versions_of(path)?.to_vec();
+        Ok(version::in_range(versions, from, to))
+    }
+    
+    fn get_prefix_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        self.versions_of(prefix)?;
+        
+        let pairs = self.versions.iter()
+            .filter(|(p, _)| p.starts_with(prefix))
+            .filter_map(|(p, versions)| {
+                version::value_as_of(versions, as_of).map(|v| (p.clone(), v.clone()))
+            })
+            .collect();
+        
+        Ok(pairs)
+    }
+}
+
+impl Store for MemoryStore {
+    fn set(&mut self, path: Path, value: Value) -> Result<()> {
+        self.apply_batch(vec![WriteOp::Set(path, value)])
+    }
+    
+    fn delete(&mut self, path: &Path) -> Result<()> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete value at empty path".to_string()));
+        }
+        
+        self.apply_batch(vec![WriteOp::Delete(path.clone())])
+    }
+    
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        // Validate the whole batch first so that a failing operation
+        // leaves the store untouched (and a shared map is not copied)
+        let mut present: HashMap<&Path, bool> = HashMap::new();
+        for op in &ops {
+            match op {
+                WriteOp::Set(path, _) => {
+                    if path.is_empty() {
+                        return Err(StoreError::InvalidOperation("Cannot set value at empty path".to_string()));
+                    }
+                    present.insert(path, true);
+                },
+                WriteOp::Delete(path) => {
+                    let exists = present.get(path)
+                        .copied()
+                        .unwrap_or_else(|| self.data.contains_key(path));
+                    if !exists {
+                        return Err(StoreError::NotFound(path.clone()));
+                    }
+                    present.insert(path, false);
+                },
+            }
+        }
+        self.check_constraints(&ops)?;
+        
+        // All the writes of a batch share the same timestamp
+        let now = Utc::now();
+        let publish = self.changes.is_active();
+        let mut events = Vec::new();
+        
+        for op in ops {
+            self.sequence += 1;
+            
+            match op {
+                WriteOp::Set(path, value) => {
+                    self.record_version(&path, Some(&value), now);
+                    let new_value = publish.then(|| value.clone());
+                    let old_value = Arc::make_mut(&mut self.data).insert(path.clone(), value);
+                    
+                    if publish {
+                        events.push(ChangeEvent {
+                            sequence: self.sequence,
+                            path,
+                            old_value,
+                            new_value,
+                            operation: ChangeOperation::Set,
+                        });
+                    }
+                },
+                WriteOp::Delete(path) => {
+                    self.record_version(&path, None, now);
+                    let old_value = Arc::make_mut(&mut self.data).remove(&path);
+                    
+                    if publish {
+                        events.push(ChangeEvent {
+                            sequence: self.sequence,
+                            path,
+                            old_value,
+                            new_value: None,
+                            operation: ChangeOperation::Delete,
+                        });
+                    }
+                },
+            }
+        }
+        
+        self.changes.publish(events);
+        
+        Ok(())
+    }
+    
+    fn snapshot(&self) -> Result<Snapshot> {
+        // Sharing the map is enough: the next write copies it
+        let view = MemoryStore {
+            data: Arc::clone(&self.data),
+            sequence: self.sequence,
+            versions: Arc::clone(&self.versions),
+            retention: self.retention.clone(),
+            changes: ChangeFeed::new(),
+            constraints: self.constraints.clone(),
+        };
+        
+        Ok(Snapshot::new(Box::new(view), self.sequence))
+    }
+    
+    fn set_versioning(&mut self, policy: Option<RetentionPolicy>) -> Result<()> {
+        self.retention = policy;
+        Ok(())
+    }
+    
+    fn change_feed(&self) -> &ChangeFeed {
+        &self.changes
+    }
+    
+    fn add_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        if self.constraints.contains(pattern) {
+            return Ok(false);
+        }
+        
+        check_distinct(pattern, self.data.iter())?;
+        self.constraints.push(pattern.clone());
+        Ok(true)
+    }
+    
+    fn drop_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        let count = self.constraints.len();
+        self.constraints.retain(|existing| existing != pattern);
+        Ok(self.constraints.len() < count)
+    }
+    
+    fn unique_constraints(&self) -> Result<Vec<Path>> {
+        Ok(self.constraints.clone())
+    }
+    
     fn flush(&self) -> Result<()> {
         // No-op for in-memory store
         Ok(())
@@ -0,0 +1,118 @@
+//! Offline migration of persistent databases
+//!
+//! Databases written before the order-preserving key encoding keyed
+//! paths with bincode. Migrating re-encodes the keys of the main tree and
+//! of the version history, then writes the key format marker, all in a
+//! single sled transaction: an interrupted migration leaves the database
+//! in its original format. Legacy databases have no persisted index
+//! state, so their indexes are rebuilt on open, and the snapshot undo log
+//! is cleared on open, so neither needs migrating.
+
+use sled::{Db, Transactional};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+
+use crate::core::errors::{Result, StoreError};
+use super::keys::{
+    encode_path, decode_legacy_path, length_prefixed, split_length_prefixed,
+    meta_tree, stored_key_format, key_format_entry, KEY_FORMAT_VERSION,
+};
+
+/// Migrate a database to the current key format
+///
+/// Must run before a store is opened on the database. Returns the number
+/// of migrated endpoints (0 if the database was already up to date).
+pub fn migrate(db: &Db) -> Result<usize> {
+    let version = stored_key_format(db)?;
+    if version == KEY_FORMAT_VERSION {
+        return Ok(0);
+    }
+    if version > KEY_FORMAT_VERSION {
+        return Err(StoreError::InvalidOperation(format!(
+            "Database uses key format {}, which is newer than this build supports ({})",
+            version, KEY_FORMAT_VERSION
+        )));
+    }
+
+    let history = db.open_tree("history")
+        .map_err(|e| StoreError::Internal(format!("Failed to open history tree: {}", e)))?;
+    let meta = meta_tree(db)?;
+
+    // Re-encode every key up front, so an undecodable key aborts the
+    // migration before anything is written
+    let mut data_keys = Vec::new();
+    for item in db.iter() {
+        let (key, value) = item
+            .map_err(|e| StoreError::Internal(format!("Failed to iterate database: {}", e)))?;
+        let new_key = encode_path(&decode_legacy_path(&key)?)?;
+        data_keys.push((key, new_key, value));
+    }
+
+    let mut history_keys = Vec::new();
+    for item in history.iter() {
+        let (version_key, entry) = item
+            .map_err(|e| StoreError::Internal(format!("Failed to read history: {}", e)))?;
+        let (key, rest) = split_length_prefixed(&version_key)?;
+
+        let mut new_key = length_prefixed(&encode_path(&decode_legacy_path(key)?)?);
+        new_key.extend_from_slice(rest);
+        history_keys.push((version_key.clone(), new_key, entry));
+    }
+
+    (&**db, &history, &meta).transaction(|(data, history, meta)| {
+        // Old keys are all removed before new ones are written, so an old
+        // key can never overwrite a migrated one
+        for (old_key, _, _) in &data_keys {
+            data.remove(old_key)?;
+        }
+        for (_, new_key, value) in &data_keys {
+            data.insert(new_key.as_slice(), value)?;
+        }
+
+        for (old_key, _, _) in &history_keys {
+            history.remove(old_key)?;
+        }
+        for (_, new_key, entry) in &history_keys {
+            history.insert(new_key.as_slice(), entry)?;
+        }
+
+        let (key, value) = key_format_entry(KEY_FORMAT_VERSION);
+        meta.insert(key, &value)?;
+
+        Ok::<(), ConflictableTransactionError<StoreError>>(())
+    }).map_err(|e| match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => StoreError::Internal(format!("Failed to migrate database: {}", e)),
+    })?;
+
+    db.flush()
+        .map_err(|e| StoreError::Internal(format!("Failed to flush database: {}", e)))?;
+
+    Ok(data_keys.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use bincode::serialize;
+    use crate::core::path::Path;
+    use crate::storage::keys::{decode_path, check_key_format};
+
+    #[test]
+    fn test_migrate_legacy_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let path = Path::from_str("users.u-1.tags.[0]").unwrap();
+
+        db.insert(serialize(&path).unwrap(), b"value".to_vec()).unwrap();
+        assert!(check_key_format(&db).is_err());
+
+        assert_eq!(migrate(&db).unwrap(), 1);
+        assert_eq!(migrate(&db).unwrap(), 0);
+        check_key_format(&db).unwrap();
+
+        let (key, value) = db.iter().next().unwrap().unwrap();
+        assert_eq!(decode_path(&key).unwrap(), path);
+        assert_eq!(&*value, b"value");
+    }
+}
@@ -1,5 +1,10 @@
 pub mod memory;
 pub mod persistent;
+pub mod keys;
+pub mod snapshot;
+pub mod history;
+pub mod migration;
+pub mod constraints;
 
 pub use memory::MemoryStore;
 pub use persistent::PersistentStore;
\ No newline at end of file
@@ -3,17 +3,31 @@
 use std::any::Any;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
+use chrono::{DateTime, Utc};
 use sled::Db;
-use bincode::{serialize, deserialize};
+use sled::Transactional;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
 use tokio::sync::OnceCell;
 
 use crate::core::path::Path;
 use crate::core::value::Value;
 use crate::core::errors::{Result, StoreError};
-use crate::core::store::Store;
-use crate::core::index::{IndexSystem, IndexStats};
+use crate::core::store::{ReadStore, ScannedEndpoints, Store, WriteOp};
+use crate::core::snapshot::Snapshot;
+use crate::core::version::{self, Version, RetentionPolicy};
+use crate::core::changes::{ChangeEvent, ChangeOperation, ChangeFeed};
+use crate::core::index::{
+    IndexSystem, IndexStats, IndexOp, IndexConsistency, IndexReport, ValueRange, VectorQuery, GeoArea,
+    IndexDefinition, IndexInfo,
+};
+use crate::core::index::state::IndexState;
+use crate::core::index::tree::IndexTree;
+use super::keys::{encode_path, decode_path, encode_value, decode_value, check_key_format, KEY_FORMAT_VERSION};
+use super::migration;
+use super::snapshot::{PersistentSnapshot, UndoLog};
+use super::history::HistoryLog;
+use super::constraints::UniqueConstraints;
 
 /// A persistent store for the database using sled
 pub struct PersistentStore {
@@ -23,27 +37,84 @@ pub struct PersistentStore {
     index_system: IndexSystem,
     /// Statistics (cached to avoid async calls in sync contexts)
     cached_stats: OnceCell<IndexStats>,
+    /// Undo log backing snapshots
+    undo_log: Arc<UndoLog>,
+    /// Recorded versions of the paths
+    history: HistoryLog,
+    /// Retention policy of the versions (`None` if versioning is disabled)
+    retention: Option<RetentionPolicy>,
+    /// Feed publishing the writes
+    changes: ChangeFeed,
+    /// Whether the indexes are updated with the data or in the background
+    consistency: IndexConsistency,
+    /// Unique constraints checked with the writes
+    constraints: UniqueConstraints,
 }
 
 impl PersistentStore {
     /// Open a persistent store at the given path
+    ///
+    /// Databases written with an older key format are refused; they must
+    /// be opened with `open_with_migration_async` once.
     pub async fn open_async<P: Into<PathBuf>>(path: P) -> Result<Self> {
-        // Open the sled database
-        let db = sled::open(path.into())
-            .map_err(|e| StoreError::Internal(format!("Failed to open database: {}", e)))?;
+        Self::open_db(Self::open_sled(path)?).await
+    }
+    
+    /// Open a persistent store, migrating the database to the current key
+    /// format first if needed
+    pub async fn open_with_migration_async<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let db = Self::open_sled(path)?;
+        
+        let migrated = migration::migrate(&db)?;
+        if migrated > 0 {
+            log::info!("Migrated {} endpoints to key format {}", migrated, KEY_FORMAT_VERSION);
+        }
+        
+        Self::open_db(db).await
+    }
+    
+    /// Open the sled database
+    fn open_sled<P: Into<PathBuf>>(path: P) -> Result<Db> {
+        sled::open(path.into())
+            .map_err(|e| StoreError::Internal(format!("Failed to open database: {}", e)))
+    }
+    
+    /// Open a persistent store on an open sled database
+    async fn open_db(db: Db) -> Result<Self> {
+        check_key_format(&db)?;
         let db_arc = Arc::new(db);
         
         // Create the index system
         let index_system = IndexSystem::new(Arc::clone(&db_arc))?;
         
+        // Open the undo log used by snapshots
+        let undo_log = Arc::new(UndoLog::open(&db_arc)?);
+        
+        // Open the version history
+        let history = HistoryLog::open(&db_arc)?;
+        
+        // Open the unique constraints
+        let constraints = UniqueConstraints::open(&db_arc)?;
+        
         let store = PersistentStore {
             db: db_arc,
             index_system,
             cached_stats: OnceCell::new(),
+            undo_log,
+            history,
+            retention: None,
+            changes: ChangeFeed::new(),
+            consistency: IndexConsistency::default(),
+            constraints,
         };
         
-        // Build initial indexes if the database already contains data
-        store.rebuild_indexes_async().await?;
+        // Bring the indexes up to date with the data
+        store.restore_indexes_async().await?;
+        
+        // Resume the index builds interrupted by a shutdown
+        for definition in store.index_system.pending_builds()? {
+            store.spawn_build(definition);
+        }
         
         Ok(store)
     }
@@ -60,30 +131,131 @@ impl PersistentStore {
         rt.block_on(Self::open_async(path))
     }
     
+    /// Bring the indexes up to date with the data
+    ///
+    /// Only the writes journaled after the index watermark are replayed.
+    /// The indexes are rebuilt from scratch if they were built with
+    /// another format, or if their state cannot be read.
+    async fn restore_indexes_async(&self) -> Result<()> {
+        let state = self.index_system.state();
+        if !state.is_current()? {
+            return self.rebuild_indexes_async().await;
+        }
+        
+        let pending = match state.pending() {
+            Ok(pending) => pending,
+            Err(e) => {
+                log::warn!("Unreadable index journal ({}), rebuilding indexes", e);
+                return self.rebuild_indexes_async().await;
+            }
+        };
+        
+        if !pending.is_empty() {
+            log::info!("Replaying {} writes into the indexes", pending.len());
+        }
+        
+        // The journal only holds the path: its current value (or its
+        // absence) is what the indexes must reflect
+        let mut last = None;
+        for (sequence, key) in pending {
+            let path = match decode_path(&key) {
+                Ok(path) => path,
+                Err(e) => {
+                    log::warn!("Unreadable index journal entry ({}), rebuilding indexes", e);
+                    return self.rebuild_indexes_async().await;
+                }
+            };
+            
+            let current = self.db.get(&key)
+                .map_err(|e| StoreError::Internal(format!("Failed to retrieve data: {}", e)))?;
+            match current {
+                Some(bytes) => self.index_system.submit(IndexOp::AddWithValue(path, decode_value(&bytes)?))?,
+                None => self.index_system.submit(IndexOp::Remove(path))?,
+            }
+            last = Some(sequence);
+        }
+        
+        if let Some(sequence) = last {
+            self.index_system.submit(IndexOp::Watermark(sequence))?;
+        }
+        
+        self.index_system.flush().await?;
+        Ok(())
+    }
+    
     /// Rebuild all indexes from scratch
     async fn rebuild_indexes_async(&self) -> Result<()> {
-        println!("Rebuilding indexes from existing data...");
+        log::info!("Rebuilding indexes from existing data");
+        
+        // An interrupted rebuild must be started over on the next open
+        self.index_system.state().invalidate()?;
+        self.index_system.clear()?;
+        
+        // Every write journaled so far has a smaller sequence
+        let sequence = self.next_sequence()?;
         
         // Iterate through all paths in the database and add them to indexes
         for item in self.db.iter() {
-            let (key_bytes, _) = item
+            let (key_bytes, value_bytes) = item
                 .map_err(|e| StoreError::Internal(format!("Failed to iterate database: {}", e)))?;
             
-            // Deserialize the path
-            let path: Path = deserialize(&key_bytes)
-                .map_err(|e| StoreError::DeserializationError(e.to_string()))?;
+            let path = decode_path(&key_bytes)?;
+            let value = decode_value(&value_bytes)?;
             
             // Add to indexes asynchronously
-            self.index_system.add_path(path).await?;
+            self.index_system.submit(IndexOp::AddWithValue(path, value))?;
         }
         
+        // The watermark marks the indexes as complete once every path
+        // above has been applied
+        self.index_system.submit(IndexOp::Watermark(sequence))?;
+        
         // Flush the indexes to ensure all operations are complete
         self.index_system.flush().await?;
-        println!("Index rebuilding complete.");
+        log::info!("Index rebuilding complete");
         
         Ok(())
     }
     
+    /// Index the existing endpoints of an index being built
+    ///
+    /// Runs in the background when a Tokio runtime is available, and
+    /// synchronously otherwise.
+    fn spawn_build(&self, definition: IndexDefinition) {
+        let db = Arc::clone(&self.db);
+        let index_system = self.index_system.clone();
+        let build = move || {
+            log::info!("Building {}...", definition);
+            match build_index(&db, &index_system, &definition) {
+                Ok(total) => log::info!("Built {} ({} endpoints)", definition, total),
+                // The build is resumed on the next open
+                Err(e) => log::warn!("Failed to build {}: {}", definition, e),
+            }
+        };
+        
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(build);
+            },
+            Err(_) => build(),
+        }
+    }
+    
+    /// Check that the indexes match the data, repairing any divergence
+    ///
+    /// Scans every endpoint of the database once per index, without
+    /// loading them in memory; meant for maintenance rather than for the
+    /// hot path. The report lists the repaired divergences.
+    pub fn verify_indexes(&self) -> Result<IndexReport> {
+        self.index_system.verify(|| {
+            self.db.iter().map(|item| {
+                let (key, value) = item
+                    .map_err(|e| StoreError::Internal(format!("Failed to iterate database: {}", e)))?;
+                Ok((decode_path(&key)?, decode_value(&value)?))
+            })
+        })
+    }
+    
     /// Get index statistics
     pub async fn index_stats_async(&self) -> Result<IndexStats> {
         // Get combined stats from the index system
@@ -105,54 +277,126 @@ impl PersistentStore {
         // Otherwise, return the current stats
         Ok(self.index_system.stats().clone())
     }
-}
-
-impl Store for PersistentStore {
-    fn set(&mut self, path: Path, value: Value) -> Result<()> {
-        if path.is_empty() {
-            return Err(StoreError::InvalidOperation("Cannot set value at empty path".to_string()));
+    
+    /// Generate the next sequence number (monotonic across restarts)
+    ///
+    /// Sequence numbers start at 1, so that resuming a change feed from 0
+    /// replays every write.
+    fn next_sequence(&self) -> Result<u64> {
+        self.db.generate_id()
+            .map(|id| id + 1)
+            .map_err(|e| StoreError::Internal(format!("Failed to generate sequence number: {}", e)))
+    }
+    
+    /// Scan the endpoints under a prefix, in path order
+    fn scan_prefix(&self, prefix: &Path) -> Result<sled::Iter> {
+        // The key of a prefix is a byte prefix of the keys below it
+        Ok(self.db.scan_prefix(encode_path(prefix)?))
+    }
+    
+    /// Get the versions of a path key, failing if versioning is disabled
+    fn versions_of(&self, path: &Path) -> Result<Vec<Version>> {
+        if self.retention.is_none() {
+            return Err(version::versioning_disabled());
         }
         
-        println!("PersistentStore: Setting value at path: {:?}", path);
-        
-        // Serialize the path and value
-        let path_bytes = serialize(&path)
-            .map_err(|e| StoreError::SerializationError(e.to_string()))?;
-        
-        let value_bytes = serialize(&value)
-            .map_err(|e| StoreError::SerializationError(e.to_string()))?;
-        
-        // Store in the database
-        self.db.insert(path_bytes, value_bytes)
-            .map_err(|e| StoreError::Internal(format!("Failed to insert data: {}", e)))?;
-        
-        // Flush to ensure data is persisted
-        self.db.flush()
-            .map_err(|e| StoreError::Internal(format!("Failed to flush database: {}", e)))?;
-        
-        // Update indexes asynchronously
-        let path_clone = path.clone();
-        let value_clone = value.clone();
-        let index_system = self.index_system.clone();
-        
-        // Spawn a task to handle indexing
-        tokio::spawn(async move {
-            if let Err(e) = index_system.add_path_with_value(path_clone, value_clone).await {
-                println!("Error updating value index: {:?}", e);
+        self.history.versions(&encode_path(path)?)
+    }
+    
+    /// Queue the index updates of a batch, in order
+    ///
+    /// The writes are already journaled, so an update lost here (e.g. on
+    /// a crash) is replayed on the next open. `replaced` holds the values
+    /// the writes replaced, whose value index entries are removed.
+    fn schedule_index_updates(&self, ops: Vec<WriteOp>, replaced: Vec<Option<sled::IVec>>, last_sequence: u64) {
+        for (op, previous) in ops.into_iter().zip(replaced) {
+            let result = match op {
+                WriteOp::Set(path, value) => {
+                    let removed = match previous.map(|bytes| decode_value(&bytes)) {
+                        Some(Ok(old_value)) => self.index_system.submit(IndexOp::RemoveValue(path.clone(), old_value)),
+                        _ => Ok(()),
+                    };
+                    removed.and_then(|_| self.index_system.submit(IndexOp::AddWithValue(path, value)))
+                },
+                WriteOp::Delete(path) => self.index_system.submit(IndexOp::Remove(path)),
+            };
+            
+            if let Err(e) = result {
+                log::error!("Error updating indexes: {}", e);
+                return;
             }
-        });
+        }
         
-        Ok(())
+        // Once the updates above are applied, the batch needs no replay
+        if let Err(e) = self.index_system.submit(IndexOp::Watermark(last_sequence)) {
+            log::error!("Error updating indexes: {}", e);
+        }
+    }
+}
+
+/// Number of endpoints indexed per transaction when building an index
+const BUILD_BATCH_SIZE: usize = 256;
+
+/// Index the existing endpoints covered by an index, then mark it ready
+///
+/// Each batch of endpoints is read and indexed in a single sled
+/// transaction, so a concurrent write is never indexed with a stale
+/// value. Returns the number of indexed endpoints.
+fn build_index(db: &Db, index_system: &IndexSystem, definition: &IndexDefinition) -> Result<usize> {
+    // Collect the keys first: the batches read the values again
+    let mut keys = Vec::new();
+    for item in db.iter().keys() {
+        let key = item
+            .map_err(|e| StoreError::Internal(format!("Failed to iterate database: {}", e)))?;
+        let path = decode_path(&key)?;
+        if definition.indexes(&path) {
+            keys.push((key, path));
+        }
+    }
+    
+    let total = keys.len();
+    index_system.set_build_progress(definition, 0, total);
+    
+    let data: &sled::Tree = db;
+    let mut trees = vec![data];
+    trees.extend(index_system.build_trees(&definition.kind));
+    
+    let mut indexed = 0;
+    for batch in keys.chunks(BUILD_BATCH_SIZE) {
+        let writer = index_system.writer()?;
+        trees.as_slice().transaction(|views| {
+            let index_trees: Vec<&dyn IndexTree> = views[1..].iter().map(|tree| tree as &dyn IndexTree).collect();
+            
+            for (key, path) in batch {
+                // The endpoint may have been deleted since the scan
+                if let Some(bytes) = views[0].get(key)? {
+                    let value = decode_value(&bytes).map_err(ConflictableTransactionError::Abort)?;
+                    writer.stage_build(definition, &index_trees, path, &value)?;
+                }
+            }
+            
+            Ok(())
+        }).map_err(|e: TransactionError<StoreError>| match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => StoreError::Internal(format!("Failed to build index: {}", e)),
+        })?;
+        
+        indexed += batch.len();
+        index_system.set_build_progress(definition, indexed, total);
     }
     
+    index_system.finish_build(definition)?;
+    Ok(total)
+}
+
+impl ReadStore for PersistentStore {
     fn get(&self, path: &Path) -> Result<Value> {
         if path.is_empty() {
             return Err(StoreError::InvalidOperation("Cannot get value at empty path".to_string()));
         }
         
         // Serialize the path to use as key
-        let path_bytes = serialize(path)
-            .map_err(|e| StoreError::Internal(format!("Failed to serialize path: {}", e)))?;
+        let path_bytes = encode_path(path)?;
         
         // Retrieve from the database
         let value_bytes = self.db.get(path_bytes)
@@ -160,55 +404,16 @@ impl Store for PersistentStore {
             .ok_or_else(|| StoreError::NotFound(path.clone()))?;
         
         // Deserialize the value
-        let value: Value = deserialize(&value_bytes)
-            .map_err(|e| StoreError::Internal(format!("Failed to deserialize value: {}", e)))?;
-        
-        Ok(value)
+        decode_value(&value_bytes)
     }
     
-    fn delete(&mut self, path: &Path) -> Result<()> {
-        if path.is_empty() {
-            return Err(StoreError::InvalidOperation("Cannot delete value at empty path".to_string()));
-        }
-        
-        // Serialize the path to use as key
-        let path_bytes = serialize(path)
-            .map_err(|e| StoreError::SerializationError(e.to_string()))?;
-        
-        // Remove from the database
-        let result = self.db.remove(path_bytes)
-            .map_err(|e| StoreError::Internal(format!("Failed to delete data: {}", e)))?;
-        
-        if result.is_none() {
-            return Err(StoreError::NotFound(path.clone()));
-        }
-        
-        // Flush to ensure data removal is persisted
-        self.db.flush()
-            .map_err(|e| StoreError::Internal(format!("Failed to flush database: {}", e)))?;
-        
-        // Update indexes asynchronously
-        let path_clone = path.clone();
-        let index_system = self.index_system.clone();
-        
-        // Spawn a task to handle index removal
-        tokio::spawn(async move {
-            if let Err(e) = index_system.remove_path(path_clone).await {
-                println!("Error removing from index: {:?}", e);
-            }
-        });
-        
-        Ok(())
-    }
-
     fn exists(&self, path: &Path) -> Result<bool> {
         if path.is_empty() {
             return Err(StoreError::InvalidOperation("Cannot check empty path".to_string()));
         }
         
         // Serialize the path to use as key
-        let path_bytes = serialize(path)
-            .map_err(|e| StoreError::Internal(format!("Failed to serialize path: {}", e)))?;
+        let path_bytes = encode_path(path)?;
         
         // Check if the key exists
         let result = self.db.contains_key(path_bytes)
@@ -218,36 +423,38 @@ impl Store for PersistentStore {
     }
     
     fn list_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
-        println!("PersistentStore: Listing paths with prefix: {:?}", prefix);
-        
-        // Use the index system to find paths by prefix
-        let paths = self.index_system.find_by_prefix(prefix)?;
+        log::debug!("PersistentStore: Listing paths with prefix: {:?}", prefix);
+        
+        // Keys are sorted by path, so the paths come out in order
+        let mut paths = Vec::new();
+        for item in self.scan_prefix(prefix)? {
+            let (key, _) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to scan database: {}", e)))?;
+            paths.push(decode_path(&key)?);
+        }
         
-        println!("PersistentStore: Found {} paths with prefix", paths.len());
+        log::debug!("PersistentStore: Found {} paths with prefix", paths.len());
         Ok(paths)
     }
     
     fn get_prefix(&self, prefix: &Path) -> Result<Vec<(Path, Value)>> {
-        println!("PersistentStore: Getting all values with prefix: {:?}", prefix);
+        log::debug!("PersistentStore: Getting all values with prefix: {:?}", prefix);
         
         let mut results = Vec::new();
         
-        // Find all paths with this prefix
-        let paths = self.list_prefix(prefix)?;
-        
-        // Get the value for each path
-        for path in paths {
-            if let Ok(value) = self.get(&path) {
-                results.push((path, value));
-            }
+        // Read the paths and their values in a single scan
+        for item in self.scan_prefix(prefix)? {
+            let (key, value) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to scan database: {}", e)))?;
+            results.push((decode_path(&key)?, decode_value(&value)?));
         }
         
-        println!("PersistentStore: Found {} value pairs", results.len());
+        log::debug!("PersistentStore: Found {} value pairs", results.len());
         Ok(results)
     }
     
     fn query(&self, pattern: &Path) -> Result<Vec<(Path, Value)>> {
-        println!("PersistentStore: Querying with pattern: {:?}", pattern);
+        log::debug!("PersistentStore: Querying with pattern: {:?}", pattern);
         
         let mut results = Vec::new();
         
@@ -269,18 +476,339 @@ impl Store for PersistentStore {
             }
         }
         
-        println!("PersistentStore: Found {} matches", results.len());
+        log::debug!("PersistentStore: Found {} matches", results.len());
         Ok(results)
     }
 
+    fn scan_matching(&self, pattern: &Path) -> Result<ScannedEndpoints<'_>> {
+        // Only the keys under the segments before the first wildcard can
+        // match
+        let fixed = pattern.segments().iter().take_while(|segment| !segment.is_wildcard()).cloned().collect();
+        let pattern = pattern.clone();
+        
+        let pairs = self.scan_prefix(&Path::from_segments(fixed))?.filter_map(move |item| {
+            let decoded = item
+                .map_err(|e| StoreError::Internal(format!("Failed to scan database: {}", e)))
+                .and_then(|(key, value)| Ok((decode_path(&key)?, value)));
+            match decoded {
+                Ok((path, value)) if path.matches(&pattern) => Some(decode_value(&value).map(|value| (path, value))),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        
+        Ok(Box::new(pairs))
+    }
+
     fn count(&self) -> Result<usize> {
-        let count = self.db.len();
-        Ok(count as usize)
+        Ok(self.db.len())
     }
     
     fn count_prefix(&self, prefix: &Path) -> Result<usize> {
-        let paths = self.list_prefix(prefix)?;
-        Ok(paths.len())
+        let mut count = 0;
+        for item in self.scan_prefix(prefix)? {
+            item.map_err(|e| StoreError::Internal(format!("Failed to scan database: {}", e)))?;
+            count += 1;
+        }
+        
+        Ok(count)
+    }
+    
+    fn find_by_value_range(&self, pattern: &Path, range: &ValueRange) -> Result<Option<Vec<Path>>> {
+        self.index_system.find_by_range(pattern, range)
+    }
+    
+    fn find_by_fields(
+        &self,
+        pattern: &Path,
+        equals: &[(Path, Value)],
+        range: Option<(&Path, &ValueRange)>,
+    ) -> Result<Option<Vec<Path>>> {
+        self.index_system.find_by_fields(pattern, equals, range)
+    }
+
+    fn search_text(&self, pattern: &Path, query: &str, limit: usize) -> Result<Option<Vec<(Path, f64)>>> {
+        self.index_system.search(pattern, query, limit)
+    }
+
+    fn search_vectors(&self, pattern: &Path, query: &VectorQuery) -> Result<Option<Vec<(Path, f64)>>> {
+        self.index_system.search_vectors(pattern, query)
+    }
+
+    fn find_in_area(&self, pattern: &Path, area: &GeoArea) -> Result<Option<Vec<(Path, f64)>>> {
+        self.index_system.find_in_area(pattern, area)
+    }
+    
+    fn history(&self, path: &Path, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Vec<Version>> {
+        Ok(version::in_range(self.versions_of(path)?, from, to))
+    }
+    
+    fn get_prefix_as_of(&self, prefix: &Path, as_of: DateTime<Utc>) -> Result<Vec<(Path, Value)>> {
+        if self.retention.is_none() {
+            return Err(version::versioning_disabled());
+        }
+        
+        self.history.prefix_as_of(prefix, as_of)
+    }
+}
+
+impl Store for PersistentStore {
+    fn set(&mut self, path: Path, value: Value) -> Result<()> {
+        log::debug!("PersistentStore: Setting value at path: {:?}", path);
+        
+        self.apply_batch(vec![WriteOp::Set(path, value)])
+    }
+    
+    fn delete(&mut self, path: &Path) -> Result<()> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot delete value at empty path".to_string()));
+        }
+        
+        self.apply_batch(vec![WriteOp::Delete(path.clone())])
+    }
+    
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        // Encode everything up front, the transaction closure may be retried
+        let mut encoded = Vec::with_capacity(ops.len());
+        for op in &ops {
+            match op {
+                WriteOp::Set(path, value) => {
+                    if path.is_empty() {
+                        return Err(StoreError::InvalidOperation("Cannot set value at empty path".to_string()));
+                    }
+                    encoded.push((encode_path(path)?, Some(encode_value(value)?)));
+                },
+                WriteOp::Delete(path) => {
+                    encoded.push((encode_path(path)?, None));
+                },
+            }
+        }
+        
+        // Live snapshots need the replaced values, tagged with a sequence
+        // number greater than the one they were taken at. Every write is
+        // also journaled for the indexes under its sequence number.
+        let record_undo = self.undo_log.is_recording();
+        let record_version = self.retention.is_some();
+        let publish = self.changes.is_active();
+        let mut sequences = Vec::with_capacity(ops.len());
+        for _ in &ops {
+            sequences.push(self.next_sequence()?);
+        }
+        
+        // All the writes of a batch share the same timestamp
+        let now = Utc::now();
+        
+        // The unique constraints are checked against the final value of
+        // each path, once every replaced value has been released, so that
+        // a batch can swap values between paths
+        let check_constraints = !self.constraints.is_empty();
+        let mut claims: Vec<(&Path, &Value)> = Vec::new();
+        if check_constraints {
+            for (i, op) in ops.iter().enumerate() {
+                if let WriteOp::Set(path, value) = op {
+                    if !ops[i + 1..].iter().any(|later| later.path() == path) {
+                        claims.push((path, value));
+                    }
+                }
+            }
+        }
+        let constraints = &self.constraints;
+        
+        // Apply all writes (with their undo entries, versions and index
+        // journal entries, or the index entries themselves in synchronous
+        // mode) in a single sled transaction
+        let (undo_by_path, undo_by_sequence) = self.undo_log.trees();
+        let data: &sled::Tree = &self.db;
+        let mut trees = vec![
+            data, undo_by_path, undo_by_sequence, self.history.tree(),
+            self.index_system.state().journal(), self.constraints.tree(),
+        ];
+        let writer = match self.consistency {
+            IndexConsistency::Synchronous => {
+                trees.extend(self.index_system.trees());
+                Some(self.index_system.writer()?)
+            },
+            IndexConsistency::Eventual => None,
+        };
+        
+        let replaced = trees.as_slice().transaction(|views| {
+            let (data, undo_by_path, undo_by_sequence, history, journal) = (&views[0], &views[1], &views[2], &views[3], &views[4]);
+            let unique = &views[5];
+            let index_trees: Vec<&dyn IndexTree> = views[6..].iter().map(|tree| tree as &dyn IndexTree).collect();
+            let mut replaced = Vec::with_capacity(encoded.len());
+            
+            for (i, (key, value)) in encoded.iter().enumerate() {
+                let previous = match value {
+                    Some(value) => data.insert(key.as_slice(), value.as_slice())?,
+                    None => {
+                        let previous = data.remove(key.as_slice())?;
+                        if previous.is_none() {
+                            let path = ops[i].path().clone();
+                            return Err(ConflictableTransactionError::Abort(StoreError::NotFound(path)));
+                        }
+                        previous
+                    },
+                };
+                
+                if record_undo {
+                    UndoLog::record(undo_by_path, undo_by_sequence, key, sequences[i], previous.as_ref())?;
+                }
+                
+                if record_version {
+                    HistoryLog::record(history, key, now, sequences[i], value.as_deref())?;
+                }
+                
+                let old_value = match &previous {
+                    Some(bytes) if writer.is_some() || check_constraints => {
+                        Some(decode_value(bytes).map_err(ConflictableTransactionError::Abort)?)
+                    },
+                    _ => None,
+                };
+                
+                if let Some(old_value) = &old_value {
+                    if check_constraints {
+                        constraints.stage_release(unique, ops[i].path(), old_value)?;
+                    }
+                }
+                
+                if let Some(writer) = &writer {
+                    let new_value = match &ops[i] {
+                        WriteOp::Set(_, value) => Some(value),
+                        WriteOp::Delete(_) => None,
+                    };
+                    writer.stage_write(&index_trees, ops[i].path(), old_value.as_ref(), new_value)?;
+                } else {
+                    IndexState::record(journal, sequences[i], key)?;
+                }
+                
+                replaced.push(previous);
+            }
+            
+            for (path, value) in &claims {
+                constraints.stage_claim(unique, path, value)?;
+            }
+            Ok(replaced)
+        }).map_err(|e| match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => StoreError::Internal(format!("Failed to apply batch: {}", e)),
+        })?;
+        let synchronous = writer.is_some();
+        drop(writer);
+        
+        // Drop the versions that fell out of the retention policy
+        if let Some(policy) = &self.retention {
+            for (key, _) in &encoded {
+                self.history.prune(key, policy, now)?;
+            }
+        }
+        
+        // Flush to ensure the batch is persisted
+        self.db.flush()
+            .map_err(|e| StoreError::Internal(format!("Failed to flush database: {}", e)))?;
+        
+        // Publish the writes to the subscribers
+        if publish {
+            let mut events = Vec::with_capacity(ops.len());
+            for ((op, previous), sequence) in ops.iter().zip(&replaced).zip(sequences.iter().copied()) {
+                let old_value = match previous {
+                    Some(bytes) => Some(decode_value(bytes)?),
+                    None => None,
+                };
+                
+                events.push(match op {
+                    WriteOp::Set(path, value) => ChangeEvent {
+                        sequence,
+                        path: path.clone(),
+                        old_value,
+                        new_value: Some(value.clone()),
+                        operation: ChangeOperation::Set,
+                    },
+                    WriteOp::Delete(path) => ChangeEvent {
+                        sequence,
+                        path: path.clone(),
+                        old_value,
+                        new_value: None,
+                        operation: ChangeOperation::Delete,
+                    },
+                });
+            }
+            self.changes.publish(events);
+        }
+        
+        // Update indexes asynchronously (synchronous updates are already
+        // committed with the data)
+        if let (false, Some(&last_sequence)) = (synchronous, sequences.last()) {
+            self.schedule_index_updates(ops, replaced, last_sequence);
+        }
+        
+        Ok(())
+    }
+    
+    fn snapshot(&self) -> Result<Snapshot> {
+        // Every write committed from now on gets a greater sequence number
+        let sequence = self.next_sequence()?;
+        
+        let view = PersistentSnapshot::new(
+            Arc::clone(&self.db),
+            self.index_system.clone(),
+            Arc::clone(&self.undo_log),
+            sequence,
+        );
+        
+        Ok(Snapshot::new(Box::new(view), sequence))
+    }
+
+    fn set_versioning(&mut self, policy: Option<RetentionPolicy>) -> Result<()> {
+        self.retention = policy;
+        Ok(())
+    }
+
+    fn change_feed(&self) -> &ChangeFeed {
+        &self.changes
+    }
+
+    fn create_index(&mut self, definition: IndexDefinition) -> Result<bool> {
+        if !self.index_system.create_index(&definition)? {
+            return Ok(false);
+        }
+        
+        self.spawn_build(definition);
+        Ok(true)
+    }
+    
+    fn drop_index(&mut self, definition: &IndexDefinition) -> Result<bool> {
+        self.index_system.drop_index(definition)
+    }
+    
+    fn indexes(&self) -> Result<Vec<IndexInfo>> {
+        self.index_system.indexes()
+    }
+
+    fn add_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        // Writes need `&mut self`, so no write happens while the existing
+        // values are checked
+        self.constraints.add(&self.db, pattern)
+    }
+
+    fn drop_unique_constraint(&mut self, pattern: &Path) -> Result<bool> {
+        self.constraints.remove(pattern)
+    }
+
+    fn unique_constraints(&self) -> Result<Vec<Path>> {
+        Ok(self.constraints.patterns())
+    }
+
+    fn set_index_consistency(&mut self, consistency: IndexConsistency) -> Result<()> {
+        // Updates still queued could be applied after a synchronous write
+        // of the same path, and undo it
+        if consistency == IndexConsistency::Synchronous && !self.index_system.is_idle() {
+            return Err(StoreError::InvalidOperation(
+                "Index updates are still pending; flush the indexes before switching to synchronous mode".to_string()
+            ));
+        }
+        
+        self.consistency = consistency;
+        Ok(())
     }
 
     fn flush(&self) -> Result<()> {
@@ -310,4 +838,166 @@ impl Drop for PersistentStore {
             let _ = index_system.shutdown().await;
         });
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::core::index::{IndexKind, IndexStatus};
+    use crate::core::transaction::Transaction;
+    use crate::storage::MemoryStore;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_synchronous_indexes_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+        store.set_index_consistency(IndexConsistency::Synchronous).unwrap();
+
+        let path = |s: &str| Path::from_str(s).unwrap();
+        store.set(path("users.u-1.name"), Value::String("Ada".to_string())).unwrap();
+        store.set(path("users.u-2.name"), Value::String("Alan".to_string())).unwrap();
+        store.delete(&path("users.u-2.name")).unwrap();
+
+        // The indexes are committed with the data
+        let found = store.query(&path("users.*.name")).unwrap();
+        assert_eq!(found, vec![(path("users.u-1.name"), Value::String("Ada".to_string()))]);
+        assert!(store.verify_indexes().unwrap().is_consistent());
+
+        // Lose an index entry and add a stale one
+        let prefix_tree = store.db.open_tree("prefix_index").unwrap();
+        prefix_tree.clear().unwrap();
+        prefix_tree.insert(b"users:u-3", b"stale".to_vec()).unwrap();
+
+        let report = store.verify_indexes().unwrap();
+        assert_eq!(report.checked_paths, 1);
+        assert_eq!(report.missing_entries, 1);
+        assert_eq!(report.stale_entries, 1);
+        assert!(store.verify_indexes().unwrap().is_consistent());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_synchronous_writes_are_read_back_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+        store.set_index_consistency(IndexConsistency::Synchronous).unwrap();
+        store.index_system.add_value_indexed_pattern(&Path::from_str("users.*.age").unwrap()).unwrap();
+
+        // Each read follows its write without waiting for the worker
+        for i in 0..50 {
+            let path = Path::from_str(&format!("users.u-{}.age", i)).unwrap();
+            store.set(path.clone(), Value::Integer(i)).unwrap();
+
+            let found = store.query(&Path::from_str("users.*.age").unwrap()).unwrap();
+            assert_eq!(found.len(), i as usize + 1);
+            assert_eq!(store.list_prefix(&Path::from_str("users").unwrap()).unwrap().len(), i as usize + 1);
+
+            let range = ValueRange::new().above(Value::Integer(i), true);
+            let found = store.find_by_value_range(&Path::from_str("users.*.age").unwrap(), &range).unwrap().unwrap();
+            assert_eq!(found, vec![path]);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pattern_scans_share_the_path_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+        let mut memory = MemoryStore::new();
+
+        let path = |s: &str| Path::from_str(s).unwrap();
+        for (i, s) in ["lists.a.b", "lists.a.[10]", "lists.b.a", "lists.a.[2]", "lists.[0].a"].iter().enumerate() {
+            store.set(path(s), Value::Integer(i as i64)).unwrap();
+            memory.set(path(s), Value::Integer(i as i64)).unwrap();
+        }
+
+        fn scan<S: ReadStore>(store: &S, pattern: &Path) -> Vec<String> {
+            store.scan_matching(pattern).unwrap()
+                .map(|item| item.unwrap().0.to_string())
+                .collect()
+        }
+
+        // Array indices come before names, whichever store is read
+        let pattern = path("lists.*.*");
+        let expected = ["lists.[0].a", "lists.a.[2]", "lists.a.[10]", "lists.a.b", "lists.b.a"];
+        assert_eq!(scan(&store, &pattern), expected);
+        assert_eq!(scan(&memory, &pattern), expected);
+
+        // Staged writes are merged into the scan in the same order
+        let expected = ["lists.[0].a", "lists.[1].a", "lists.a.[2]", "lists.a.[10]", "lists.a.b", "lists.b.a"];
+        let mut tx = Transaction::new(&mut store);
+        tx.set(path("lists.[1].a"), Value::Integer(5)).unwrap();
+        assert_eq!(scan(&tx, &pattern), expected);
+        tx.rollback();
+
+        let mut tx = Transaction::new(&mut memory);
+        tx.set(path("lists.[1].a"), Value::Integer(5)).unwrap();
+        assert_eq!(scan(&tx, &pattern), expected);
+        tx.rollback();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_value_range_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+        store.set_index_consistency(IndexConsistency::Synchronous).unwrap();
+
+        let path = |s: &str| Path::from_str(s).unwrap();
+        assert_eq!(store.find_by_value_range(&path("users.*.age"), &ValueRange::new().above(Value::Integer(0), false)).unwrap(), None);
+        store.index_system.add_value_indexed_pattern(&path("users.*.age")).unwrap();
+
+        let ages = [("u-1", Value::Integer(-3)), ("u-2", Value::Integer(30)), ("u-3", Value::Float(30.5)), ("u-4", Value::Integer(41)), ("u-5", Value::String("old".to_string()))];
+        for (id, age) in ages {
+            store.set(path(&format!("users.{}.age", id)), age).unwrap();
+        }
+        store.set(path("users.u-4.age"), Value::Integer(35)).unwrap();
+
+        let range = ValueRange::new().above(Value::Integer(30), false).below(Value::Integer(40), true);
+        let mut found = store.find_by_value_range(&path("users.*.age"), &range).unwrap().unwrap();
+        found.sort();
+        assert_eq!(found, vec![path("users.u-3.age"), path("users.u-4.age")]);
+
+        let below = ValueRange::new().below(Value::Integer(30), true);
+        let mut found = store.find_by_value_range(&path("users.*.age"), &below).unwrap().unwrap();
+        found.sort();
+        assert_eq!(found, vec![path("users.u-1.age"), path("users.u-2.age")]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_declared_index_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PersistentStore::open_async(dir.path()).await.unwrap();
+
+        let path = |s: &str| Path::from_str(s).unwrap();
+        for i in 0..600 {
+            store.set(path(&format!("users.u-{}.age", i)), Value::Integer(i)).unwrap();
+            store.set(path(&format!("users.u-{}.name", i)), Value::String(format!("user {}", i))).unwrap();
+        }
+
+        let definition = IndexDefinition::new(path("users.*.age"), IndexKind::Value);
+        assert!(store.create_index(definition.clone()).unwrap());
+        assert!(!store.create_index(definition.clone()).unwrap());
+
+        // Wait for the background build
+        let info = loop {
+            let info = store.indexes().unwrap().remove(0);
+            if info.status == IndexStatus::Ready {
+                break info;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!(info.definition, definition);
+        assert_eq!(info.entries, 600);
+
+        // Writes after the build are indexed, other fields are not
+        store.set(path("users.u-600.age"), Value::Integer(600)).unwrap();
+        store.index_system.flush().await.unwrap();
+        let range = ValueRange::new().above(Value::Integer(598), true);
+        let mut found = store.find_by_value_range(&path("users.*.age"), &range).unwrap().unwrap();
+        found.sort();
+        assert_eq!(found, vec![path("users.u-598.age"), path("users.u-599.age"), path("users.u-600.age")]);
+        assert_eq!(store.find_by_value_range(&path("users.*.name"), &range).unwrap(), None);
+
+        assert!(store.drop_index(&definition).unwrap());
+        assert!(store.indexes().unwrap().is_empty());
+        assert_eq!(store.find_by_value_range(&path("users.*.age"), &range).unwrap(), None);
+    }
+}
@@ -0,0 +1,307 @@
+//! Point-in-time snapshots for the persistent store
+//!
+//! sled has no native snapshots, so the persistent store keeps an undo
+//! log while at least one snapshot is alive: every write records the
+//! value it replaced, tagged with the write's sequence number. A
+//! snapshot taken at sequence `S` reads the current value of a path,
+//! unless the path was written after `S`, in which case the first undo
+//! entry after `S` holds the value the path had when the snapshot was
+//! taken.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use sled::{Db, IVec};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::core::path::Path;
+use crate::core::value::Value;
+use crate::core::errors::{Result, StoreError};
+use crate::core::store::ReadStore;
+use crate::core::index::IndexSystem;
+use super::keys::{encode_path, decode_path, decode_value, length_prefixed, encode_optional, decode_optional};
+
+/// Undo log shared by a persistent store and its snapshots
+pub struct UndoLog {
+    /// Undo entries keyed by (path key, sequence)
+    by_path: sled::Tree,
+    /// Path keys keyed by sequence, to find what changed after a snapshot
+    by_sequence: sled::Tree,
+    /// Sequence numbers of the live snapshots, with their reference count
+    active: Mutex<BTreeMap<u64, usize>>,
+}
+
+impl UndoLog {
+    /// Open the undo log trees, dropping entries left over from a previous run
+    pub fn open(db: &Db) -> Result<Self> {
+        let by_path = db.open_tree("snapshot_undo")
+            .map_err(|e| StoreError::Internal(format!("Failed to open undo tree: {}", e)))?;
+        let by_sequence = db.open_tree("snapshot_undo_sequence")
+            .map_err(|e| StoreError::Internal(format!("Failed to open undo tree: {}", e)))?;
+
+        // Snapshots do not survive a restart, so old entries are useless
+        by_path.clear()
+            .map_err(|e| StoreError::Internal(format!("Failed to clear undo tree: {}", e)))?;
+        by_sequence.clear()
+            .map_err(|e| StoreError::Internal(format!("Failed to clear undo tree: {}", e)))?;
+
+        Ok(UndoLog {
+            by_path,
+            by_sequence,
+            active: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Get the trees written in the same transaction as the data
+    pub fn trees(&self) -> (&sled::Tree, &sled::Tree) {
+        (&self.by_path, &self.by_sequence)
+    }
+
+    /// Check whether writes need to record undo entries
+    pub fn is_recording(&self) -> bool {
+        !self.active.lock().unwrap().is_empty()
+    }
+
+    /// Record the value replaced by a write, inside a data transaction
+    pub fn record(
+        by_path: &TransactionalTree,
+        by_sequence: &TransactionalTree,
+        key: &[u8],
+        sequence: u64,
+        previous: Option<&IVec>,
+    ) -> std::result::Result<(), ConflictableTransactionError<StoreError>> {
+        by_path.insert(Self::undo_key(key, sequence), encode_optional(previous.map(|p| p.as_ref())))?;
+        by_sequence.insert(&sequence.to_be_bytes(), key)?;
+
+        Ok(())
+    }
+
+    /// Build the undo key for a path key and a sequence number
+    fn undo_key(key: &[u8], sequence: u64) -> Vec<u8> {
+        let mut undo_key = length_prefixed(key);
+        undo_key.extend_from_slice(&sequence.to_be_bytes());
+        undo_key
+    }
+
+    /// Find the value a path had at the given sequence, if it changed since
+    ///
+    /// Returns `None` if the path was not written after the sequence,
+    /// `Some(None)` if it did not exist at that point.
+    fn value_before(&self, key: &[u8], sequence: u64) -> Result<Option<Option<IVec>>> {
+        let start = Self::undo_key(key, sequence + 1);
+        let end = Self::undo_key(key, u64::MAX);
+
+        let first = self.by_path.range(start..=end)
+            .next()
+            .transpose()
+            .map_err(|e| StoreError::Internal(format!("Failed to read undo log: {}", e)))?;
+
+        Ok(first.map(|(_, entry)| decode_optional(&entry).map(IVec::from)))
+    }
+
+    /// List the keys of the paths written after the given sequence
+    fn changed_since(&self, sequence: u64) -> Result<HashSet<IVec>> {
+        let mut keys = HashSet::new();
+
+        for item in self.by_sequence.range((sequence + 1).to_be_bytes()..) {
+            let (_, key) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read undo log: {}", e)))?;
+            keys.insert(key);
+        }
+
+        Ok(keys)
+    }
+
+    /// Register a live snapshot
+    fn acquire(&self, sequence: u64) {
+        *self.active.lock().unwrap().entry(sequence).or_insert(0) += 1;
+    }
+
+    /// Unregister a snapshot and drop the entries no snapshot needs anymore
+    fn release(&self, sequence: u64) -> Result<()> {
+        let mut active = self.active.lock().unwrap();
+
+        if let Some(count) = active.get_mut(&sequence) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&sequence);
+            }
+        }
+
+        // A snapshot at S only reads entries written after S
+        let oldest = active.keys().next().copied().unwrap_or(u64::MAX);
+
+        for item in self.by_sequence.range(..oldest.to_be_bytes()) {
+            let (sequence_bytes, key) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to read undo log: {}", e)))?;
+
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(&sequence_bytes);
+            let entry_sequence = u64::from_be_bytes(raw);
+
+            self.by_path.remove(Self::undo_key(&key, entry_sequence))
+                .map_err(|e| StoreError::Internal(format!("Failed to prune undo log: {}", e)))?;
+            self.by_sequence.remove(sequence_bytes)
+                .map_err(|e| StoreError::Internal(format!("Failed to prune undo log: {}", e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A read-only, point-in-time view of a persistent store
+pub struct PersistentSnapshot {
+    /// The underlying sled database
+    db: Arc<Db>,
+    /// Index system of the store, used to find current paths
+    index_system: IndexSystem,
+    /// Undo log of the store
+    undo_log: Arc<UndoLog>,
+    /// Sequence number the snapshot was taken at
+    sequence: u64,
+}
+
+impl PersistentSnapshot {
+    /// Take a snapshot at the given sequence number
+    pub fn new(db: Arc<Db>, index_system: IndexSystem, undo_log: Arc<UndoLog>, sequence: u64) -> Self {
+        undo_log.acquire(sequence);
+
+        PersistentSnapshot {
+            db,
+            index_system,
+            undo_log,
+            sequence,
+        }
+    }
+
+    /// Read the raw value stored under a key at the snapshot's sequence
+    fn raw_value_at(&self, key: &[u8]) -> Result<Option<IVec>> {
+        // Read the current value first: a write landing between the two
+        // reads leaves an undo entry that takes precedence
+        let current = self.db.get(key)
+            .map_err(|e| StoreError::Internal(format!("Failed to retrieve data: {}", e)))?;
+
+        match self.undo_log.value_before(key, self.sequence)? {
+            Some(previous) => Ok(previous),
+            None => Ok(current),
+        }
+    }
+
+    /// Read the value of a path at the snapshot's sequence
+    fn value_at(&self, path: &Path) -> Result<Option<Value>> {
+        let key = encode_path(path)?;
+
+        match self.raw_value_at(&key)? {
+            Some(bytes) => Ok(Some(decode_value(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Collect the endpoints visible in the snapshot among candidate paths
+    fn collect<F>(&self, current: Vec<Path>, filter: F) -> Result<Vec<(Path, Value)>>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        // Paths changed since the snapshot may have been deleted or
+        // created, so they are considered on top of the current ones.
+        // Keys sort by path, so the results come out in path order.
+        let mut candidates: BTreeMap<Vec<u8>, Path> = BTreeMap::new();
+        for path in current {
+            candidates.insert(encode_path(&path)?, path);
+        }
+        for key in self.undo_log.changed_since(self.sequence)? {
+            let path = decode_path(&key)?;
+            if filter(&path) {
+                candidates.insert(key.to_vec(), path);
+            }
+        }
+
+        let mut results = Vec::with_capacity(candidates.len());
+        for (key, path) in candidates {
+            if let Some(bytes) = self.raw_value_at(&key)? {
+                results.push((path, decode_value(&bytes)?));
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+impl ReadStore for PersistentSnapshot {
+    fn get(&self, path: &Path) -> Result<Value> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot get value at empty path".to_string()));
+        }
+
+        self.value_at(path)?
+            .ok_or_else(|| StoreError::NotFound(path.clone()))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        if path.is_empty() {
+            return Err(StoreError::InvalidOperation("Cannot check empty path".to_string()));
+        }
+
+        Ok(self.raw_value_at(&encode_path(path)?)?.is_some())
+    }
+
+    fn list_prefix(&self, prefix: &Path) -> Result<Vec<Path>> {
+        let paths = self.get_prefix(prefix)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+
+        Ok(paths)
+    }
+
+    fn get_prefix(&self, prefix: &Path) -> Result<Vec<(Path, Value)>> {
+        // The key of a prefix is a byte prefix of the keys below it
+        let mut current = Vec::new();
+        for item in self.db.scan_prefix(encode_path(prefix)?) {
+            let (key, _) = item
+                .map_err(|e| StoreError::Internal(format!("Failed to scan database: {}", e)))?;
+            current.push(decode_path(&key)?);
+        }
+
+        self.collect(current, |path| path.starts_with(prefix))
+    }
+
+    fn query(&self, pattern: &Path) -> Result<Vec<(Path, Value)>> {
+        if !pattern.has_wildcards() {
+            return Ok(self.value_at(pattern)?
+                .map(|value| vec![(pattern.clone(), value)])
+                .unwrap_or_default());
+        }
+
+        let current = self.index_system.find_by_pattern(pattern)?;
+        self.collect(current, |path| path.matches(pattern))
+    }
+
+    fn count(&self) -> Result<usize> {
+        let mut count = self.db.len() as i64;
+
+        // Adjust the current count for paths created or deleted since
+        for key in self.undo_log.changed_since(self.sequence)? {
+            let exists_now = self.db.contains_key(&key)
+                .map_err(|e| StoreError::Internal(format!("Failed to check key: {}", e)))?;
+            let existed = self.raw_value_at(&key)?.is_some();
+            count += existed as i64 - exists_now as i64;
+        }
+
+        Ok(count.max(0) as usize)
+    }
+
+    fn count_prefix(&self, prefix: &Path) -> Result<usize> {
+        Ok(self.list_prefix(prefix)?.len())
+    }
+}
+
+impl Drop for PersistentSnapshot {
+    fn drop(&mut self) {
+        // Entries left behind are pruned when the next snapshot is released
+        if let Err(e) = self.undo_log.release(self.sequence) {
+            log::warn!("Failed to prune the snapshot undo log: {}", e);
+        }
+    }
+}
+